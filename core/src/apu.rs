@@ -0,0 +1,906 @@
+use crate::region::Region;
+use crate::savestate::{StateReader, StateWriter};
+
+pub const SAMPLE_RATE: u32 = 44100;
+
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14,
+    12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+const TRIANGLE_TABLE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0,
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+
+// The tables that depend on the CPU clock. The Dendy uses the NTSC ones.
+struct Timing {
+    // In CPU cycles
+    noise_periods: [u16; 16],
+    dmc_rates: [u16; 16],
+
+    // Frame counter steps, in CPU cycles since the sequence started
+    quarter_frames: [u32; 4],
+    four_step_length: u32,
+    five_step_quarter_frames: [u32; 4],
+    five_step_length: u32,
+}
+
+const NTSC_TIMING: Timing = Timing {
+    noise_periods: [4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068],
+    dmc_rates: [428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54],
+    quarter_frames: [7457, 14913, 22371, 29829],
+    four_step_length: 29830,
+    five_step_quarter_frames: [7457, 14913, 22371, 37281],
+    five_step_length: 37282,
+};
+
+const PAL_TIMING: Timing = Timing {
+    noise_periods: [4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778],
+    dmc_rates: [398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50],
+    quarter_frames: [8313, 16627, 24939, 33253],
+    four_step_length: 33254,
+    five_step_quarter_frames: [8313, 16627, 24939, 41565],
+    five_step_length: 41566,
+};
+
+#[derive(Clone, Copy, Default)]
+struct Envelope {
+    start: bool,
+    looping: bool,
+    constant: bool,
+    volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Pulse {
+    // Pulse 1 negates its sweep with ones' complement, pulse 2 with two's complement
+    ones_complement: bool,
+    enabled: bool,
+    duty: u8,
+    step: u8,
+    timer: u16,
+    period: u16,
+    length: u8,
+    envelope: Envelope,
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_divider: u8,
+    sweep_reload: bool,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Triangle {
+    enabled: bool,
+    control: bool,
+    step: u8,
+    timer: u16,
+    period: u16,
+    length: u8,
+    linear_reload_value: u8,
+    linear: u8,
+    linear_reload: bool,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Noise {
+    enabled: bool,
+    mode: bool,
+    shift: u16,
+    timer: u16,
+    period: u16,
+    length: u8,
+    envelope: Envelope,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Dmc {
+    irq_enabled: bool,
+    looping: bool,
+    period: u16,
+    timer: u16,
+    output: u8,
+    sample_address: u16,
+    sample_length: u16,
+    address: u16,
+    bytes_remaining: u16,
+    buffer: Option<u8>,
+    shift: u8,
+    bits_remaining: u8,
+    silence: bool,
+    irq: bool,
+}
+
+// The 2A03 sound hardware: two pulse channels, a triangle, noise and the DMC sample channel,
+// mixed down and resampled to SAMPLE_RATE.
+pub struct APU {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    cycle: u64,
+    frame_cycle: u32,
+    five_step: bool,
+    irq_inhibit: bool,
+    frame_irq: bool,
+    sample_clock: f64,
+    sample_sum: f32,
+    sample_count: u32,
+    high_pass_in: f32,
+    high_pass_out: f32,
+    samples: Vec<f32>,
+    timing: &'static Timing,
+    cpu_clock: f64,
+}
+
+impl Envelope {
+    fn write(&mut self, value: u8) {
+        self.looping = value & 0x20 != 0;
+        self.constant = value & 0x10 != 0;
+        self.volume = value & 0x0F;
+    }
+
+    fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.constant { self.volume } else { self.decay }
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.start);
+        writer.write_bool(self.looping);
+        writer.write_bool(self.constant);
+        writer.write_u8(self.volume);
+        writer.write_u8(self.divider);
+        writer.write_u8(self.decay);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        self.start = reader.read_bool()?;
+        self.looping = reader.read_bool()?;
+        self.constant = reader.read_bool()?;
+        self.volume = reader.read_u8()?;
+        self.divider = reader.read_u8()?;
+        self.decay = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+impl Pulse {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.duty = value >> 6;
+                self.envelope.write(value);
+            }
+            1 => {
+                self.sweep_enabled = value & 0x80 != 0;
+                self.sweep_period = (value >> 4) & 0x07;
+                self.sweep_negate = value & 0x08 != 0;
+                self.sweep_shift = value & 0x07;
+                self.sweep_reload = true;
+            }
+            2 => self.period = (self.period & 0x0700) | value as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | (((value & 0x07) as u16) << 8);
+                if self.enabled {
+                    self.length = LENGTH_TABLE[(value >> 3) as usize];
+                }
+                self.step = 0;
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            self.step = (self.step + 1) & 7;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn sweep_target(&self) -> u16 {
+        let change = self.period >> self.sweep_shift;
+        if self.sweep_negate {
+            let change = if self.ones_complement { change + 1 } else { change };
+            self.period.saturating_sub(change)
+        } else {
+            self.period + change
+        }
+    }
+
+    fn clock_sweep(&mut self) {
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.muted() {
+            self.period = self.sweep_target();
+        }
+
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    fn clock_length(&mut self) {
+        if !self.envelope.looping && self.length > 0 {
+            self.length -= 1;
+        }
+    }
+
+    fn muted(&self) -> bool {
+        self.period < 8 || self.sweep_target() > 0x7FF
+    }
+
+    fn output(&self) -> u8 {
+        if self.length == 0 || self.muted() || DUTY_TABLE[self.duty as usize][self.step as usize] == 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.enabled);
+        writer.write_u8(self.duty);
+        writer.write_u8(self.step);
+        writer.write_u16(self.timer);
+        writer.write_u16(self.period);
+        writer.write_u8(self.length);
+        self.envelope.save_state(writer);
+        writer.write_bool(self.sweep_enabled);
+        writer.write_u8(self.sweep_period);
+        writer.write_bool(self.sweep_negate);
+        writer.write_u8(self.sweep_shift);
+        writer.write_u8(self.sweep_divider);
+        writer.write_bool(self.sweep_reload);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        self.enabled = reader.read_bool()?;
+        self.duty = reader.read_u8()? & 3;
+        self.step = reader.read_u8()? & 7;
+        self.timer = reader.read_u16()?;
+        self.period = reader.read_u16()?;
+        self.length = reader.read_u8()?;
+        self.envelope.load_state(reader)?;
+        self.sweep_enabled = reader.read_bool()?;
+        self.sweep_period = reader.read_u8()?;
+        self.sweep_negate = reader.read_bool()?;
+        self.sweep_shift = reader.read_u8()?;
+        self.sweep_divider = reader.read_u8()?;
+        self.sweep_reload = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+impl Triangle {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.control = value & 0x80 != 0;
+                self.linear_reload_value = value & 0x7F;
+            }
+            2 => self.period = (self.period & 0x0700) | value as u16,
+            3 => {
+                self.period = (self.period & 0x00FF) | (((value & 0x07) as u16) << 8);
+                if self.enabled {
+                    self.length = LENGTH_TABLE[(value >> 3) as usize];
+                }
+                self.linear_reload = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.length > 0 && self.linear > 0 {
+                self.step = (self.step + 1) & 31;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_linear(&mut self) {
+        if self.linear_reload {
+            self.linear = self.linear_reload_value;
+        } else if self.linear > 0 {
+            self.linear -= 1;
+        }
+
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    fn clock_length(&mut self) {
+        if !self.control && self.length > 0 {
+            self.length -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        TRIANGLE_TABLE[self.step as usize]
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.enabled);
+        writer.write_bool(self.control);
+        writer.write_u8(self.step);
+        writer.write_u16(self.timer);
+        writer.write_u16(self.period);
+        writer.write_u8(self.length);
+        writer.write_u8(self.linear_reload_value);
+        writer.write_u8(self.linear);
+        writer.write_bool(self.linear_reload);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        self.enabled = reader.read_bool()?;
+        self.control = reader.read_bool()?;
+        self.step = reader.read_u8()? & 31;
+        self.timer = reader.read_u16()?;
+        self.period = reader.read_u16()?;
+        self.length = reader.read_u8()?;
+        self.linear_reload_value = reader.read_u8()?;
+        self.linear = reader.read_u8()?;
+        self.linear_reload = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+impl Noise {
+    fn write(&mut self, register: u16, value: u8, periods: &[u16; 16]) {
+        match register {
+            0 => self.envelope.write(value),
+            2 => {
+                self.mode = value & 0x80 != 0;
+                self.period = periods[(value & 0x0F) as usize];
+            }
+            3 => {
+                if self.enabled {
+                    self.length = LENGTH_TABLE[(value >> 3) as usize];
+                }
+                self.envelope.start = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period.saturating_sub(1);
+            let tap = if self.mode { 6 } else { 1 };
+            let feedback = (self.shift ^ (self.shift >> tap)) & 1;
+            self.shift = (self.shift >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_length(&mut self) {
+        if !self.envelope.looping && self.length > 0 {
+            self.length -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.length == 0 || self.shift & 1 == 1 { 0 } else { self.envelope.output() }
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.enabled);
+        writer.write_bool(self.mode);
+        writer.write_u16(self.shift);
+        writer.write_u16(self.timer);
+        writer.write_u16(self.period);
+        writer.write_u8(self.length);
+        self.envelope.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        self.enabled = reader.read_bool()?;
+        self.mode = reader.read_bool()?;
+        self.shift = reader.read_u16()?;
+        self.timer = reader.read_u16()?;
+        self.period = reader.read_u16()?;
+        self.length = reader.read_u8()?;
+        self.envelope.load_state(reader)
+    }
+}
+
+impl Dmc {
+    fn write(&mut self, register: u16, value: u8, rates: &[u16; 16]) {
+        match register {
+            0 => {
+                self.irq_enabled = value & 0x80 != 0;
+                self.looping = value & 0x40 != 0;
+                self.period = rates[(value & 0x0F) as usize];
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+            }
+            1 => self.output = value & 0x7F,
+            2 => self.sample_address = 0xC000 | ((value as u16) << 6),
+            _ => self.sample_length = ((value as u16) << 4) | 1,
+        }
+    }
+
+    fn restart(&mut self) {
+        self.address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    fn clock_timer<F: Fn(u16) -> u8>(&mut self, read: F) {
+        if self.buffer.is_none() && self.bytes_remaining > 0 {
+            self.buffer = Some(read(self.address));
+            self.address = if self.address == 0xFFFF { 0x8000 } else { self.address + 1 };
+            self.bytes_remaining -= 1;
+
+            if self.bytes_remaining == 0 {
+                if self.looping {
+                    self.restart();
+                } else if self.irq_enabled {
+                    self.irq = true;
+                }
+            }
+        }
+
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period.saturating_sub(1);
+
+        if !self.silence {
+            if self.shift & 1 == 1 {
+                if self.output <= 125 {
+                    self.output += 2;
+                }
+            } else if self.output >= 2 {
+                self.output -= 2;
+            }
+        }
+        self.shift >>= 1;
+
+        if self.bits_remaining > 0 {
+            self.bits_remaining -= 1;
+        }
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.buffer.take() {
+                Some(value) => {
+                    self.shift = value;
+                    self.silence = false;
+                }
+                None => self.silence = true
+            }
+        }
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.irq_enabled);
+        writer.write_bool(self.looping);
+        writer.write_u16(self.period);
+        writer.write_u16(self.timer);
+        writer.write_u8(self.output);
+        writer.write_u16(self.sample_address);
+        writer.write_u16(self.sample_length);
+        writer.write_u16(self.address);
+        writer.write_u16(self.bytes_remaining);
+        writer.write_bool(self.buffer.is_some());
+        writer.write_u8(self.buffer.unwrap_or(0));
+        writer.write_u8(self.shift);
+        writer.write_u8(self.bits_remaining);
+        writer.write_bool(self.silence);
+        writer.write_bool(self.irq);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        self.irq_enabled = reader.read_bool()?;
+        self.looping = reader.read_bool()?;
+        self.period = reader.read_u16()?;
+        self.timer = reader.read_u16()?;
+        self.output = reader.read_u8()?;
+        self.sample_address = reader.read_u16()?;
+        self.sample_length = reader.read_u16()?;
+        self.address = reader.read_u16()?;
+        self.bytes_remaining = reader.read_u16()?;
+        let has_buffer = reader.read_bool()?;
+        let buffer = reader.read_u8()?;
+        self.buffer = if has_buffer { Some(buffer) } else { None };
+        self.shift = reader.read_u8()?;
+        self.bits_remaining = reader.read_u8()?;
+        self.silence = reader.read_bool()?;
+        self.irq = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+impl APU {
+    pub fn new(region: Region) -> APU {
+        let timing = if region == Region::Pal { &PAL_TIMING } else { &NTSC_TIMING };
+        APU {
+            pulse1: Pulse { ones_complement: true, ..Pulse::default() },
+            pulse2: Pulse::default(),
+            triangle: Triangle::default(),
+            noise: Noise { shift: 1, period: timing.noise_periods[0], ..Noise::default() },
+            dmc: Dmc { period: timing.dmc_rates[0], bits_remaining: 8, silence: true, ..Dmc::default() },
+            cycle: 0,
+            frame_cycle: 0,
+            five_step: false,
+            irq_inhibit: false,
+            frame_irq: false,
+            sample_clock: 0.0,
+            sample_sum: 0.0,
+            sample_count: 0,
+            high_pass_in: 0.0,
+            high_pass_out: 0.0,
+            samples: vec![],
+            timing,
+            cpu_clock: region.cpu_clock(),
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x4000..=0x4003 => self.pulse1.write(address - 0x4000, value),
+            0x4004..=0x4007 => self.pulse2.write(address - 0x4004, value),
+            0x4008..=0x400B => self.triangle.write(address - 0x4008, value),
+            0x400C..=0x400F => self.noise.write(address - 0x400C, value, &self.timing.noise_periods),
+            0x4010..=0x4013 => self.dmc.write(address - 0x4010, value, &self.timing.dmc_rates),
+            0x4015 => {
+                self.pulse1.enabled = value & 0x01 != 0;
+                self.pulse2.enabled = value & 0x02 != 0;
+                self.triangle.enabled = value & 0x04 != 0;
+                self.noise.enabled = value & 0x08 != 0;
+                if !self.pulse1.enabled { self.pulse1.length = 0; }
+                if !self.pulse2.enabled { self.pulse2.length = 0; }
+                if !self.triangle.enabled { self.triangle.length = 0; }
+                if !self.noise.enabled { self.noise.length = 0; }
+
+                if value & 0x10 == 0 {
+                    self.dmc.bytes_remaining = 0;
+                } else if self.dmc.bytes_remaining == 0 {
+                    self.dmc.restart();
+                }
+                self.dmc.irq = false;
+            }
+            0x4017 => {
+                self.five_step = value & 0x80 != 0;
+                self.irq_inhibit = value & 0x40 != 0;
+                if self.irq_inhibit {
+                    self.frame_irq = false;
+                }
+
+                self.frame_cycle = 0;
+                if self.five_step {
+                    self.quarter_frame();
+                    self.half_frame();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // $4015, reading it acknowledges the frame IRQ
+    pub fn read_status(&mut self) -> u8 {
+        let status = self.peek_status();
+        self.frame_irq = false;
+        status
+    }
+
+    pub fn peek_status(&self) -> u8 {
+        (self.pulse1.length > 0) as u8
+            | ((self.pulse2.length > 0) as u8) << 1
+            | ((self.triangle.length > 0) as u8) << 2
+            | ((self.noise.length > 0) as u8) << 3
+            | ((self.dmc.bytes_remaining > 0) as u8) << 4
+            | (self.frame_irq as u8) << 6
+            | (self.dmc.irq as u8) << 7
+    }
+
+    pub fn irq_pending(&self) -> bool {
+        self.frame_irq || self.dmc.irq
+    }
+
+    // Runs the APU for a number of CPU cycles. `read` is how the DMC fetches its samples.
+    pub fn clock<F: Fn(u16) -> u8>(&mut self, cpu_cycles: i32, read: F) {
+        for _ in 0..cpu_cycles {
+            self.step(&read);
+        }
+    }
+
+    // Everything that was output since the last call, as mono samples at SAMPLE_RATE
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+
+    fn step<F: Fn(u16) -> u8>(&mut self, read: &F) {
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer(read);
+        if self.cycle % 2 == 1 {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+
+        self.frame_cycle += 1;
+        if self.five_step {
+            if let Some(step) = self.timing.five_step_quarter_frames.iter().position(|c| *c == self.frame_cycle) {
+                self.quarter_frame();
+                if step == 1 || step == 3 {
+                    self.half_frame();
+                }
+            }
+            if self.frame_cycle >= self.timing.five_step_length {
+                self.frame_cycle = 0;
+            }
+        } else {
+            if let Some(step) = self.timing.quarter_frames.iter().position(|c| *c == self.frame_cycle) {
+                self.quarter_frame();
+                if step == 1 || step == 3 {
+                    self.half_frame();
+                }
+            }
+            if self.frame_cycle >= self.timing.quarter_frames[3] - 1 && !self.irq_inhibit {
+                self.frame_irq = true;
+            }
+            if self.frame_cycle >= self.timing.four_step_length {
+                self.frame_cycle = 0;
+            }
+        }
+
+        self.cycle += 1;
+        self.sample_sum += self.mix();
+        self.sample_count += 1;
+        self.sample_clock += SAMPLE_RATE as f64;
+        if self.sample_clock >= self.cpu_clock {
+            self.sample_clock -= self.cpu_clock;
+            let sample = self.sample_sum / self.sample_count as f32;
+            self.sample_sum = 0.0;
+            self.sample_count = 0;
+
+            // Take the DC offset out, the mixer only ever outputs positive levels
+            let filtered = 0.996 * (self.high_pass_out + sample - self.high_pass_in);
+            self.high_pass_in = sample;
+            self.high_pass_out = filtered;
+            self.samples.push(filtered);
+        }
+    }
+
+    fn quarter_frame(&mut self) {
+        self.pulse1.envelope.clock();
+        self.pulse2.envelope.clock();
+        self.noise.envelope.clock();
+        self.triangle.clock_linear();
+    }
+
+    fn half_frame(&mut self) {
+        self.pulse1.clock_length();
+        self.pulse1.clock_sweep();
+        self.pulse2.clock_length();
+        self.pulse2.clock_sweep();
+        self.triangle.clock_length();
+        self.noise.clock_length();
+    }
+
+    // The non-linear DAC, see https://www.nesdev.org/wiki/APU_Mixer
+    fn mix(&self) -> f32 {
+        let pulse = (self.pulse1.output() + self.pulse2.output()) as f32;
+        let pulse_out = if pulse == 0.0 { 0.0 } else { 95.88 / (8128.0 / pulse + 100.0) };
+
+        let triangle = self.triangle.output() as f32 / 8227.0;
+        let noise = self.noise.output() as f32 / 12241.0;
+        let dmc = self.dmc.output as f32 / 22638.0;
+        let tnd = triangle + noise + dmc;
+        let tnd_out = if tnd == 0.0 { 0.0 } else { 159.79 / (1.0 / tnd + 100.0) };
+
+        pulse_out + tnd_out
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        self.pulse1.save_state(writer);
+        self.pulse2.save_state(writer);
+        self.triangle.save_state(writer);
+        self.noise.save_state(writer);
+        self.dmc.save_state(writer);
+        writer.write_u64(self.cycle);
+        writer.write_u32(self.frame_cycle);
+        writer.write_bool(self.five_step);
+        writer.write_bool(self.irq_inhibit);
+        writer.write_bool(self.frame_irq);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        self.pulse1.load_state(reader)?;
+        self.pulse2.load_state(reader)?;
+        self.triangle.load_state(reader)?;
+        self.noise.load_state(reader)?;
+        self.dmc.load_state(reader)?;
+        self.cycle = reader.read_u64()?;
+        self.frame_cycle = reader.read_u32()?;
+        self.five_step = reader.read_bool()?;
+        self.irq_inhibit = reader.read_bool()?;
+        self.frame_irq = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apu() -> APU {
+        APU::new(Region::Ntsc)
+    }
+
+    fn run(apu: &mut APU, cycles: i32) {
+        apu.clock(cycles, |_| 0);
+    }
+
+    #[test]
+    fn length_counters_load_count_down_on_half_frames_and_halt() {
+        let mut apu = apu();
+        // Loading while the channel is disabled does nothing
+        apu.write(0x4003, 0x08);
+        assert_eq!(apu.pulse1.length, 0);
+
+        apu.write(0x4015, 0x0F);
+        apu.write(0x4003, 0x08);
+        apu.write(0x4007, 0x08);
+        apu.write(0x4004, 0x20);
+        assert_eq!((apu.pulse1.length, apu.pulse2.length), (254, 254));
+        assert_eq!(apu.peek_status() & 0x03, 0x03);
+
+        // Two half frames in every four step sequence, the halted pulse 2 keeps its count
+        run(&mut apu, 14913);
+        assert_eq!(apu.pulse1.length, 253);
+        run(&mut apu, 29830 - 14913);
+        assert_eq!((apu.pulse1.length, apu.pulse2.length), (252, 254));
+
+        // The five step mode clocks a half frame as soon as it is written
+        apu.write(0x4017, 0xC0);
+        assert_eq!(apu.pulse1.length, 251);
+
+        apu.write(0x400B, 0x18);
+        assert_eq!(apu.triangle.length, 2);
+        apu.write(0x4017, 0xC0);
+        apu.write(0x4017, 0xC0);
+        assert_eq!(apu.triangle.length, 0);
+        assert_eq!(apu.peek_status() & 0x04, 0);
+
+        apu.write(0x4015, 0x00);
+        assert_eq!(apu.peek_status() & 0x0F, 0);
+    }
+
+    #[test]
+    fn the_envelope_decays_once_per_period_and_loops() {
+        let mut envelope = Envelope::default();
+        envelope.write(0x03);
+        envelope.start = true;
+        envelope.clock();
+        assert_eq!(envelope.output(), 15);
+
+        // A divider of 3 counts four clocks per step
+        for _ in 0..4 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.output(), 14);
+        for _ in 0..4 * 14 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.output(), 0);
+        for _ in 0..8 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.output(), 0);
+
+        envelope.write(0x23);
+        for _ in 0..4 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.output(), 15);
+
+        envelope.write(0x17);
+        assert_eq!(envelope.output(), 7);
+    }
+
+    #[test]
+    fn the_sweep_negates_differently_on_each_pulse_and_mutes_out_of_range() {
+        let mut apu = apu();
+        apu.write(0x4001, 0x89);
+        apu.write(0x4005, 0x89);
+        apu.write(0x4002, 0x00);
+        apu.write(0x4003, 0x01);
+        apu.write(0x4006, 0x00);
+        apu.write(0x4007, 0x01);
+        assert_eq!(apu.pulse1.sweep_target(), 0x7F);
+        assert_eq!(apu.pulse2.sweep_target(), 0x80);
+
+        // Sweep period 0 updates on every half frame
+        apu.write(0x4017, 0x80);
+        assert_eq!((apu.pulse1.period, apu.pulse2.period), (0x7F, 0x80));
+        apu.write(0x4017, 0x80);
+        assert_eq!((apu.pulse1.period, apu.pulse2.period), (0x3F, 0x40));
+
+        apu.write(0x4001, 0x01);
+        apu.write(0x4002, 0x00);
+        apu.write(0x4003, 0x06);
+        assert_eq!(apu.pulse1.sweep_target(), 0x900);
+        assert!(apu.pulse1.muted());
+        // A muting sweep leaves the period alone even when enabled
+        apu.write(0x4001, 0x81);
+        apu.write(0x4017, 0x80);
+        apu.write(0x4017, 0x80);
+        assert_eq!(apu.pulse1.period, 0x600);
+
+        apu.write(0x4002, 0x07);
+        apu.write(0x4003, 0x00);
+        assert!(apu.pulse1.muted());
+    }
+
+    #[test]
+    fn the_frame_irq_fires_at_the_end_of_the_four_step_sequence() {
+        let mut apu = apu();
+        run(&mut apu, 29827);
+        assert!(!apu.irq_pending());
+        run(&mut apu, 1);
+        assert!(apu.irq_pending());
+        assert_eq!(apu.read_status() & 0x40, 0x40);
+        assert!(!apu.irq_pending());
+
+        // Inhibiting it clears a pending one too
+        run(&mut apu, 29830);
+        assert!(apu.irq_pending());
+        apu.write(0x4017, 0x40);
+        assert!(!apu.irq_pending());
+        run(&mut apu, 2 * 29830);
+        assert!(!apu.irq_pending());
+
+        apu.write(0x4017, 0x80);
+        run(&mut apu, 2 * 37282);
+        assert!(!apu.irq_pending());
+    }
+
+    #[test]
+    fn pal_runs_a_longer_frame_sequence() {
+        let mut apu = APU::new(Region::Pal);
+        run(&mut apu, 29830);
+        assert!(!apu.irq_pending());
+        run(&mut apu, 33252 - 29830);
+        assert!(apu.irq_pending());
+    }
+}
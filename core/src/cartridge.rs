@@ -0,0 +1,265 @@
+use crate::region::Region;
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+use crate::hash;
+
+pub struct Cartridge {
+    prg_rom_banks: Vec<PrgRomBank>,
+    chr_rom_banks: Vec<ChrRomBank>,
+    header: [u8; 16],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    FourScreen,
+}
+
+pub struct PrgRomBank {
+    data: [u8; 0x4000],
+    number: usize,
+}
+
+pub struct ChrRomBank {
+    data: [u8; 0x2000]
+}
+
+impl Cartridge {
+    pub fn load(path: &str) -> Result<Cartridge, String> {
+        let file = File::open(path).map_err(|why| format!("Couldn't open rom file {}: {}", path, why))?;
+        Cartridge::read(BufReader::new(file))
+    }
+
+    // An iNES image that is already in memory
+    pub fn from_bytes(data: &[u8]) -> Result<Cartridge, String> {
+        Cartridge::read(Cursor::new(data))
+    }
+
+    fn read<R: Read + Seek>(mut file: R) -> Result<Cartridge, String> {
+        let truncated = |_| String::from("ROM file is truncated");
+
+        let mut header = [0u8; 16];
+        file.read_exact(&mut header).map_err(truncated)?;
+
+        if &header[0..3] != b"NES" {
+            return Err(String::from("Invalid header in ROM"));
+        }
+
+        let prg_rom_bank_count = header[4];
+        let chr_rom_bank_count = header[5];
+        let flags6 = header[6];
+
+        if prg_rom_bank_count == 0 {
+            return Err(String::from("ROM has no PRG ROM"));
+        }
+
+        let has_trainer_mask = 0b00000100;
+
+        if (flags6 & has_trainer_mask) == has_trainer_mask {
+            // There are 512 bytes of trainer data before the prg rom, so we skip past it for now
+            file.seek(SeekFrom::Current(512)).map_err(truncated)?;
+        }
+
+        let mut prg_rom_banks = Vec::new();
+        for number in 0..prg_rom_bank_count as usize {
+            let mut buffer = [0u8; 0x4000];
+            file.read_exact(&mut buffer).map_err(truncated)?;
+
+            prg_rom_banks.push(PrgRomBank::new(buffer, number));
+        }
+
+        let mut chr_rom_banks = Vec::new();
+        for _ in 0..chr_rom_bank_count {
+            let mut buffer = [0u8; 0x2000];
+            file.read_exact(&mut buffer).map_err(truncated)?;
+
+            chr_rom_banks.push(ChrRomBank::new(buffer));
+        }
+
+        Ok(Cartridge {
+            prg_rom_banks,
+            chr_rom_banks,
+            header
+        })
+    }
+
+    pub fn prg_rom_banks(&self) -> &Vec<PrgRomBank> {
+        &self.prg_rom_banks
+    }
+    pub fn chr_rom_banks(&self) -> &Vec<ChrRomBank> { &self.chr_rom_banks }
+
+    pub fn header(&self) -> &[u8; 16] {
+        &self.header
+    }
+
+    // NES 2.0 headers are iNES headers with bit 3 of flags 7 set (and bit 2 clear)
+    pub fn is_nes2(&self) -> bool {
+        self.header[7] & 0b00001100 == 0b00001000
+    }
+
+    // The low nibble is in flags 6, the high one in flags 7 and NES 2.0 adds four more bits in byte 8
+    pub fn mapper(&self) -> u16 {
+        let mapper = (self.header[6] >> 4) as u16 | (self.header[7] & 0xF0) as u16;
+        if self.is_nes2() {
+            mapper | ((self.header[8] & 0x0F) as u16) << 8
+        } else {
+            mapper
+        }
+    }
+
+    pub fn has_battery(&self) -> bool {
+        self.header[6] & 0b00000010 != 0
+    }
+
+    pub fn has_trainer(&self) -> bool {
+        self.header[6] & 0b00000100 != 0
+    }
+
+    // How the nametables are wired up, from bit 0 and 3 of flags 6
+    pub fn mirroring(&self) -> Mirroring {
+        let flags6 = self.header[6];
+        if flags6 & 0b00001000 != 0 {
+            Mirroring::FourScreen
+        } else if flags6 & 0b00000001 != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        }
+    }
+
+    // NES 2.0 has the timing in byte 12, where multi-region ROMs run as NTSC. iNES only has a PAL bit in
+    // byte 9, which is only trusted when the end of the header is clean rather than a ripper's signature.
+    pub fn region(&self) -> Region {
+        if self.is_nes2() {
+            match self.header[12] & 0b11 {
+                1 => Region::Pal,
+                3 => Region::Dendy,
+                _ => Region::Ntsc,
+            }
+        } else if self.header[9] & 1 != 0 && self.header[12..].iter().all(|b| *b == 0) {
+            Region::Pal
+        } else {
+            Region::Ntsc
+        }
+    }
+
+    // Only NROM is emulated: one or two 16KB PRG ROM banks and one 8KB CHR ROM bank, or CHR RAM
+    pub fn check_supported(&self) -> Result<(), String> {
+        if self.mapper() != 0 {
+            return Err(format!("Mapper {} is not supported, only NROM (mapper 0) is", self.mapper()));
+        }
+        if self.prg_rom_banks.len() > 2 {
+            return Err(format!("NROM has at most two 16KB PRG ROM banks, this ROM has {}", self.prg_rom_banks.len()));
+        }
+        if self.chr_rom_banks.len() > 1 {
+            return Err(format!("NROM has at most one 8KB CHR ROM bank, this ROM has {}", self.chr_rom_banks.len()));
+        }
+
+        Ok(())
+    }
+
+    // All PRG ROM followed by all CHR ROM, which is what FCEUX checksums a ROM by
+    pub fn rom_data(&self) -> Vec<u8> {
+        let prg = self.prg_rom_banks.iter().flat_map(|bank| bank.get_data().iter());
+        let chr = self.chr_rom_banks.iter().flat_map(|bank| bank.get_data().iter());
+        prg.chain(chr).cloned().collect()
+    }
+
+    // CRC-32 of the ROM data, which save states use to tell ROMs apart
+    pub fn checksum(&self) -> u32 {
+        hash::crc32(&self.rom_data())
+    }
+}
+
+impl PrgRomBank {
+    pub fn new(data: [u8; 0x4000], number: usize) -> PrgRomBank {
+        PrgRomBank {
+            data,
+            number
+        }
+    }
+
+    pub fn get_data(&self) -> &[u8; 0x4000] {
+        &self.data
+    }
+
+    // Position of the bank in the PRG ROM, counted in 16KB banks
+    pub fn number(&self) -> usize {
+        self.number
+    }
+}
+
+impl ChrRomBank {
+    pub fn new(data: [u8; 0x2000]) -> ChrRomBank {
+        ChrRomBank {
+            data
+        }
+    }
+
+    pub fn get_data(&self) -> &[u8; 0x2000] { &self.data }
+}
+#[cfg(test)]
+impl Cartridge {
+    // NROM for tests, with each (address, code) in place and the NMI, reset and IRQ vectors set.
+    // Code at $C000 and up gets a second PRG bank, otherwise the one bank is mirrored.
+    pub(crate) fn nrom(code: &[(u16, &[u8])], vectors: [u16; 3]) -> Cartridge {
+        let banks = if code.iter().any(|(address, _)| *address >= 0xC000) { 2 } else { 1 };
+        let mut prg = vec![0u8; banks * 0x4000];
+        for (address, bytes) in code {
+            let start = (*address - 0x8000) as usize;
+            prg[start..start + bytes.len()].copy_from_slice(bytes);
+        }
+
+        let vector_start = prg.len() - 6;
+        for (i, vector) in vectors.iter().enumerate() {
+            prg[vector_start + i * 2] = *vector as u8;
+            prg[vector_start + i * 2 + 1] = (*vector >> 8) as u8;
+        }
+
+        let mut prg_rom_banks = Vec::new();
+        for (number, bank) in prg.chunks(0x4000).enumerate() {
+            let mut data = [0u8; 0x4000];
+            data.copy_from_slice(bank);
+            prg_rom_banks.push(PrgRomBank::new(data, number));
+        }
+
+        let mut header = [0u8; 16];
+        header[..4].copy_from_slice(b"NES\x1A");
+        header[4] = banks as u8;
+
+        Cartridge {
+            prg_rom_banks,
+            chr_rom_banks: vec![],
+            header
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nes::Nes;
+
+    // An iNES image with the given PRG and CHR bank counts and mapper
+    fn image(prg_banks: u8, chr_banks: u8, mapper: u8) -> Vec<u8> {
+        let mut image = vec![b'N', b'E', b'S', 0x1A, prg_banks, chr_banks, mapper << 4, mapper & 0xF0];
+        image.resize(16 + prg_banks as usize * 0x4000 + chr_banks as usize * 0x2000, 0);
+        image
+    }
+
+    #[test]
+    fn only_nrom_is_supported() {
+        assert_eq!(Cartridge::from_bytes(&image(2, 1, 0)).unwrap().check_supported(), Ok(()));
+        assert_eq!(Cartridge::from_bytes(&image(1, 0, 0)).unwrap().check_supported(), Ok(()));
+
+        // They still load, so that `rustnes info` can show them
+        let mmc3 = Cartridge::from_bytes(&image(2, 1, 4)).unwrap();
+        assert_eq!(mmc3.check_supported(), Err(String::from("Mapper 4 is not supported, only NROM (mapper 0) is")));
+        assert_eq!(Cartridge::from_bytes(&image(4, 1, 0)).unwrap().check_supported(),
+                   Err(String::from("NROM has at most two 16KB PRG ROM banks, this ROM has 4")));
+        assert_eq!(Cartridge::from_bytes(&image(2, 2, 0)).unwrap().check_supported(),
+                   Err(String::from("NROM has at most one 8KB CHR ROM bank, this ROM has 2")));
+        assert!(Nes::new(mmc3).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+use crate::savestate::{StateReader, StateWriter};
+
+// Bits of the button byte, in the order the controller shifts them out
+pub const BUTTON_A: u8 = 0b00000001;
+pub const BUTTON_B: u8 = 0b00000010;
+pub const BUTTON_SELECT: u8 = 0b00000100;
+pub const BUTTON_START: u8 = 0b00001000;
+pub const BUTTON_UP: u8 = 0b00010000;
+pub const BUTTON_DOWN: u8 = 0b00100000;
+pub const BUTTON_LEFT: u8 = 0b01000000;
+pub const BUTTON_RIGHT: u8 = 0b10000000;
+
+// A standard controller. Writing 1 to $4016 keeps reloading the shift register from the buttons,
+// writing 0 freezes it so that $4016/$4017 reads shift out one button at a time.
+#[derive(Clone, Copy, Debug)]
+pub struct Controller {
+    buttons: u8,
+    shift: u8,
+    reads: u8,
+    strobe: bool,
+}
+
+impl Controller {
+    pub fn new() -> Controller {
+        Controller {
+            buttons: 0,
+            shift: 0,
+            reads: 0,
+            strobe: false,
+        }
+    }
+
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.buttons = buttons;
+        if self.strobe {
+            self.reload();
+        }
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.strobe = value & 1 == 1;
+        if self.strobe {
+            self.reload();
+        }
+    }
+
+    // Only bit 0 comes from the controller, the upper bits are open bus and usually read as $40
+    pub fn read(&mut self) -> u8 {
+        let value = self.peek();
+
+        if !self.strobe {
+            self.shift >>= 1;
+            self.reads = self.reads.saturating_add(1);
+        }
+
+        value
+    }
+
+    pub fn peek(&self) -> u8 {
+        let bit = if self.strobe {
+            self.buttons & 1
+        } else if self.reads >= 8 {
+            // An official controller returns 1 once all buttons have been read
+            1
+        } else {
+            self.shift & 1
+        };
+
+        0x40 | bit
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.buttons);
+        writer.write_u8(self.shift);
+        writer.write_u8(self.reads);
+        writer.write_bool(self.strobe);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        self.buttons = reader.read_u8()?;
+        self.shift = reader.read_u8()?;
+        self.reads = reader.read_u8()?;
+        self.strobe = reader.read_bool()?;
+
+        Ok(())
+    }
+
+    fn reload(&mut self) {
+        self.shift = self.buttons;
+        self.reads = 0;
+    }
+}
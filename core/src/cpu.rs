@@ -0,0 +1,118 @@
+use crate::cpuregisters::{CPUFlags, CPURegisters};
+use crate::ram_controller::RamController;
+use crate::{instructions, stack};
+
+const NMI_VECTOR: u16 = 0xFFFA;
+const RESET_VECTOR: u16 = 0xFFFC;
+const IRQ_VECTOR: u16 = 0xFFFE;
+
+// An opcode the CPU can not run. The JAM opcodes lock up a real 6502 and the unstable ones are
+// not emulated, either way the CPU stops there until it is reset.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Jam {
+    pub address: u16,
+    pub opcode: u8,
+}
+
+pub struct CPU {
+    pub registers: CPURegisters,
+    memory: RamController,
+    jam: Option<Jam>,
+}
+
+impl CPU {
+    pub fn new(mem: RamController) -> CPU {
+        CPU {
+            memory: mem,
+            registers: CPURegisters::new(),
+            jam: None,
+        }
+    }
+    pub fn reset(&mut self) {
+        self.registers = CPURegisters::new();
+        self.jam = None;
+
+        let address = self.memory.read16(RESET_VECTOR);
+        self.registers.set_pc(address);
+
+        // Only used with nestest
+        // self.registers.set_pc(0xC000);
+    }
+
+    // Pushes the return address and status the way RTI expects them, and jumps through the NMI vector.
+    // Returns the cycles it took.
+    pub fn trigger_nmi(&mut self) -> i32 {
+        if self.jam.is_some() {
+            return 0;
+        }
+
+        let pc = self.registers.pc();
+        stack::push(&mut self.registers, &mut self.memory, ((pc >> 8) & 0xFF) as u8);
+        stack::push(&mut self.registers, &mut self.memory, (pc & 0xFF) as u8);
+        let status = (self.registers.status() | CPUFlags::Unused as u8) & !(CPUFlags::BreakCommand as u8);
+        stack::push(&mut self.registers, &mut self.memory, status);
+        self.registers.set_flag(CPUFlags::InterruptDisable);
+
+        let address = self.memory.read16(NMI_VECTOR);
+        self.registers.set_pc(address);
+
+        7
+    }
+
+    // Services a pending IRQ (i.e from the APU) unless interrupts are disabled. Returns the cycles it took.
+    pub fn poll_irq(&mut self) -> i32 {
+        if self.jam.is_some() || self.registers.flag(CPUFlags::InterruptDisable) || !self.memory.apu_irq_pending() {
+            return 0;
+        }
+
+        let pc = self.registers.pc();
+        stack::push(&mut self.registers, &mut self.memory, ((pc >> 8) & 0xFF) as u8);
+        stack::push(&mut self.registers, &mut self.memory, (pc & 0xFF) as u8);
+        // Bit 4 is clear when the push comes from the interrupt line rather than BRK
+        let status = (self.registers.status() | CPUFlags::Unused as u8) & !(CPUFlags::BreakCommand as u8);
+        stack::push(&mut self.registers, &mut self.memory, status);
+        self.registers.set_flag(CPUFlags::InterruptDisable);
+
+        let address = self.memory.read16(IRQ_VECTOR);
+        self.registers.set_pc(address);
+
+        7
+    }
+
+    pub fn memory(&self) -> &RamController {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut RamController {
+        &mut self.memory
+    }
+
+    // Where the CPU stopped, if it ran into an opcode it can not run
+    pub fn jam(&self) -> Option<Jam> {
+        self.jam
+    }
+
+    // A state loaded into a jammed CPU gets it going again
+    pub(crate) fn clear_jam(&mut self) {
+        self.jam = None;
+    }
+
+    pub fn process_instruction(&mut self) -> i32 {
+        let address = self.registers.pc();
+        if self.jam.is_some() {
+            // Stuck on the opcode, the clock keeps running
+            return 2;
+        }
+
+        let opcode = self.memory.read8(self.registers.increment_pc());
+        let instruction = instructions::lookup(opcode);
+        match instruction.execute {
+            Some(execute) => execute(&mut self.registers, &mut self.memory),
+            None => {
+                self.registers.set_pc(address);
+                self.jam = Some(Jam { address, opcode });
+                instruction.cycles
+            }
+        }
+    }
+}
\ No newline at end of file
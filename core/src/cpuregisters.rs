@@ -1,3 +1,4 @@
+use crate::savestate::{StateReader, StateWriter};
 
 #[derive(Clone, Debug)]
 pub struct CPURegisters {
@@ -42,6 +43,19 @@ impl CPURegisters {
         }
     }
 
+    // Sets every register as is, without touching any flags
+    pub fn from_raw(accumulator: u8, x: u8, y: u8, status: u8, stack: u8, pc: u16) -> CPURegisters {
+        CPURegisters {
+            accumulator,
+            x,
+            y,
+            status,
+            pc,
+            stack: 0x0100 | stack as u16,
+            rts_counter: 0
+        }
+    }
+
     pub fn accumulator(&self) -> u8 {
         self.accumulator
     }
@@ -119,7 +133,7 @@ impl CPURegisters {
         self.pc = ((self.pc as i32) + value as i32) as u16;
         let to_page = self.pc / 0x100;
 
-        return from_page != to_page; // True if we have crossed page boundaries
+        from_page != to_page // True if we have crossed page boundaries
     }
 
     pub fn set_stack(&mut self, value: u16) {
@@ -140,7 +154,7 @@ impl CPURegisters {
     }
 
     pub(crate) fn clear_flag(&mut self, flag: CPUFlags) {
-        self.status = self.status & !(flag as u8);
+        self.status &= !(flag as u8);
     }
 
     pub fn set_flag_if(&mut self, flag: CPUFlags, set: bool) {
@@ -150,4 +164,26 @@ impl CPURegisters {
             self.clear_flag(flag);
         }
     }
-}
\ No newline at end of file
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.accumulator);
+        writer.write_u8(self.x);
+        writer.write_u8(self.y);
+        writer.write_u8(self.status);
+        writer.write_u16(self.pc);
+        writer.write_u16(self.stack);
+        writer.write_u32(self.rts_counter);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        self.accumulator = reader.read_u8()?;
+        self.x = reader.read_u8()?;
+        self.y = reader.read_u8()?;
+        self.status = reader.read_u8()?;
+        self.pc = reader.read_u16()?;
+        self.stack = reader.read_u16()?;
+        self.rts_counter = reader.read_u32()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,267 @@
+use crate::debugger::{parse_number, parse_scanline, Condition, Interrupt, Watchpoint};
+use crate::ram_controller::{AccessKind, Bus, RamController};
+use crate::symbols::SymbolTable;
+
+// One line typed at the debugger console. Addresses can be given as numbers or as labels that
+// are mapped in at the moment, so they are resolved while parsing.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Continue,
+    StepInto,
+    StepOver,
+    StepOut,
+    RunToScanline(i32),
+    Break { address: Option<u16>, condition: Option<Condition> },
+    Watch(Watchpoint),
+    BreakOnInterrupt(Interrupt, bool),
+    List,
+    DeleteBreakpoint(usize),
+    DeleteWatchpoint(usize),
+    Registers,
+    // From the PC when no address is given
+    Disassemble { start: Option<u16>, count: u16 },
+    DisassembleRange(u16, u16),
+    Memory { bus: Bus, start: u16, length: u16 },
+    Help,
+    Quit,
+}
+
+pub const HELP: &str = "\
+c                          continue
+s                          step into
+n                          step over (runs a JSR until it returns)
+o                          step out (runs until the current subroutine returns)
+scanline <n>               run until scanline <n> starts, -1 is the pre-render line
+b <addr> [if <cond>]       break at <addr>, optionally only when <cond> holds
+                           addresses can also be given as labels, e.g. b update_player
+b if <cond>                break whenever <cond> holds, e.g. b if A == $10 && X > 3
+w <r|w|x> <cpu|ppu> <addr>[-<end>]
+                           watch reads, writes or execution of an address range
+nmi <on|off>, irq <on|off> break when an interrupt is taken
+l                          list breakpoints and watchpoints
+del b <n>, del w <n>       delete a breakpoint or watchpoint
+r                          show registers
+d [addr] [count], d <start>-<end>
+                           disassemble
+m [cpu|ppu] <addr> [len]   dump memory
+q                          quit";
+
+pub fn parse(line: &str, mem: &RamController, symbols: &SymbolTable) -> Result<Command, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+
+    match words.first() {
+        None => Err(String::from("No command, try 'help'")),
+        Some(&"c") | Some(&"continue") => Ok(Command::Continue),
+        Some(&"s") | Some(&"step") => Ok(Command::StepInto),
+        Some(&"n") | Some(&"next") => Ok(Command::StepOver),
+        Some(&"o") | Some(&"out") | Some(&"finish") => Ok(Command::StepOut),
+        Some(&"scanline") => match words.get(1) {
+            Some(word) => Ok(Command::RunToScanline(parse_scanline(word)?)),
+            None => Err(String::from("Usage: scanline <n>"))
+        },
+        Some(&"q") | Some(&"quit") => Ok(Command::Quit),
+        Some(&"b") | Some(&"break") => parse_breakpoint(mem, symbols, line, &words),
+        Some(&"w") | Some(&"watch") => parse_watchpoint(mem, symbols, &words),
+        Some(&"nmi") => Ok(Command::BreakOnInterrupt(Interrupt::Nmi, parse_on_off(&words)?)),
+        Some(&"irq") => Ok(Command::BreakOnInterrupt(Interrupt::Irq, parse_on_off(&words)?)),
+        Some(&"l") | Some(&"list") => Ok(Command::List),
+        Some(&"del") | Some(&"delete") => parse_delete(&words),
+        Some(&"r") | Some(&"regs") => Ok(Command::Registers),
+        Some(&"d") | Some(&"disassemble") => parse_disassemble(mem, symbols, &words),
+        Some(&"m") | Some(&"memory") => parse_memory(mem, symbols, &words),
+        Some(&"h") | Some(&"help") | Some(&"?") => Ok(Command::Help),
+        Some(other) => Err(format!("Unknown command '{}', try 'help'", other))
+    }
+}
+
+// A number or a label that is currently mapped in
+fn parse_address(mem: &RamController, symbols: &SymbolTable, text: &str) -> Result<u16, String> {
+    parse_number(text).or_else(|e| symbols.address_of(mem, text).ok_or(e))
+}
+
+fn parse_range(mem: &RamController, symbols: &SymbolTable, text: &str) -> Result<(u16, u16), String> {
+    match text.find('-') {
+        Some(index) => Ok((parse_address(mem, symbols, &text[..index])?, parse_address(mem, symbols, &text[index + 1..])?)),
+        None => {
+            let address = parse_address(mem, symbols, text)?;
+            Ok((address, address))
+        }
+    }
+}
+
+fn parse_breakpoint(mem: &RamController, symbols: &SymbolTable, line: &str, words: &[&str]) -> Result<Command, String> {
+    let condition = match line.find(" if ") {
+        Some(index) => Some(Condition::parse(&line[index + 4..])?),
+        None => None
+    };
+
+    let address = match words.get(1) {
+        Some(&"if") | None => None,
+        Some(word) => Some(parse_address(mem, symbols, word)?)
+    };
+
+    if address.is_none() && condition.is_none() {
+        return Err(String::from("Usage: b <addr> [if <cond>] or b if <cond>"));
+    }
+
+    Ok(Command::Break { address, condition })
+}
+
+fn parse_watchpoint(mem: &RamController, symbols: &SymbolTable, words: &[&str]) -> Result<Command, String> {
+    if words.len() < 4 {
+        return Err(String::from("Usage: w <r|w|x> <cpu|ppu> <addr>[-<end>]"));
+    }
+
+    let kind = match words[1] {
+        "r" => AccessKind::Read,
+        "w" => AccessKind::Write,
+        "x" => AccessKind::Execute,
+        other => return Err(format!("Unknown access kind '{}'", other))
+    };
+
+    let bus = match words[2] {
+        "cpu" => Bus::Cpu,
+        "ppu" => Bus::Ppu,
+        other => return Err(format!("Unknown bus '{}'", other))
+    };
+
+    let (start, end) = parse_range(mem, symbols, words[3])?;
+    Ok(Command::Watch(Watchpoint { bus, kind, start, end }))
+}
+
+fn parse_on_off(words: &[&str]) -> Result<bool, String> {
+    match words.get(1) {
+        Some(&"on") => Ok(true),
+        Some(&"off") => Ok(false),
+        _ => Err(String::from("Expected on or off"))
+    }
+}
+
+fn parse_delete(words: &[&str]) -> Result<Command, String> {
+    let index = match words.get(2) {
+        Some(word) => parse_number(word)? as usize,
+        None => return Err(String::from("Usage: del <b|w> <n>"))
+    };
+
+    match words[1] {
+        "b" => Ok(Command::DeleteBreakpoint(index)),
+        "w" => Ok(Command::DeleteWatchpoint(index)),
+        other => Err(format!("Unknown kind '{}'", other))
+    }
+}
+
+fn parse_disassemble(mem: &RamController, symbols: &SymbolTable, words: &[&str]) -> Result<Command, String> {
+    if let Some(word) = words.get(1).filter(|word| word.contains('-')) {
+        let (start, end) = parse_range(mem, symbols, word)?;
+        return Ok(Command::DisassembleRange(start, end));
+    }
+
+    let start = match words.get(1) {
+        Some(word) => Some(parse_address(mem, symbols, word)?),
+        None => None
+    };
+    let count = match words.get(2) {
+        Some(word) => parse_number(word)?,
+        None => 10
+    };
+
+    Ok(Command::Disassemble { start, count })
+}
+
+fn parse_memory(mem: &RamController, symbols: &SymbolTable, words: &[&str]) -> Result<Command, String> {
+    let (bus, arguments) = match words.get(1) {
+        Some(&"cpu") => (Bus::Cpu, &words[2..]),
+        Some(&"ppu") => (Bus::Ppu, &words[2..]),
+        _ => (Bus::Cpu, &words[1..])
+    };
+
+    let start = match arguments.first() {
+        Some(word) => parse_address(mem, symbols, word)?,
+        None => return Err(String::from("Usage: m [cpu|ppu] <addr> [len]"))
+    };
+    let length = match arguments.get(1) {
+        Some(word) => parse_number(word)?,
+        None => 0x40
+    };
+
+    Ok(Command::Memory { bus, start, length })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Cartridge;
+    use crate::nes::Nes;
+
+    fn parse_line(line: &str) -> Result<Command, String> {
+        let nes = Nes::new(Cartridge::nrom(&[], [0x8000, 0x8000, 0x8000])).unwrap();
+        let mut symbols = SymbolTable::new();
+        symbols.parse_fceux_nl("$0010#player_x#\n$0020#buffer#\n", None).unwrap();
+        parse(line, nes.cpu().memory(), &symbols)
+    }
+
+    #[test]
+    fn run_control_commands_and_their_aliases() {
+        assert_eq!(parse_line("c"), Ok(Command::Continue));
+        assert_eq!(parse_line("  continue  "), Ok(Command::Continue));
+        assert_eq!(parse_line("s"), Ok(Command::StepInto));
+        assert_eq!(parse_line("next"), Ok(Command::StepOver));
+        assert_eq!(parse_line("finish"), Ok(Command::StepOut));
+        assert_eq!(parse_line("scanline 241"), Ok(Command::RunToScanline(241)));
+        assert_eq!(parse_line("scanline -1"), Ok(Command::RunToScanline(-1)));
+        assert_eq!(parse_line("q"), Ok(Command::Quit));
+        assert_eq!(parse_line("?"), Ok(Command::Help));
+        assert!(parse_line("scanline").is_err());
+        assert!(parse_line("").is_err());
+        assert_eq!(parse_line("jump"), Err(String::from("Unknown command 'jump', try 'help'")));
+    }
+
+    #[test]
+    fn breakpoints_take_addresses_labels_and_conditions() {
+        assert_eq!(parse_line("b $C000"), Ok(Command::Break { address: Some(0xC000), condition: None }));
+        assert_eq!(parse_line("b player_x"), Ok(Command::Break { address: Some(0x10), condition: None }));
+        assert_eq!(parse_line("b $C000 if A == $10 && X > 3"), Ok(Command::Break {
+            address: Some(0xC000),
+            condition: Some(Condition::parse("A == $10 && X > 3").unwrap()),
+        }));
+        assert_eq!(parse_line("break if Y != 0"), Ok(Command::Break {
+            address: None,
+            condition: Some(Condition::parse("Y != 0").unwrap()),
+        }));
+        assert!(parse_line("b").is_err());
+        assert!(parse_line("b nowhere").is_err());
+        assert!(parse_line("b if Q == 1").is_err());
+    }
+
+    #[test]
+    fn watchpoints_take_a_kind_a_bus_and_a_range() {
+        assert_eq!(parse_line("w w ppu $2000-$23FF"), Ok(Command::Watch(Watchpoint {
+            bus: Bus::Ppu, kind: AccessKind::Write, start: 0x2000, end: 0x23FF,
+        })));
+        assert_eq!(parse_line("watch r cpu buffer"), Ok(Command::Watch(Watchpoint {
+            bus: Bus::Cpu, kind: AccessKind::Read, start: 0x20, end: 0x20,
+        })));
+        assert!(parse_line("w x cpu").is_err());
+        assert!(parse_line("w q cpu $10").is_err());
+        assert!(parse_line("w r apu $10").is_err());
+    }
+
+    #[test]
+    fn the_remaining_commands() {
+        assert_eq!(parse_line("nmi on"), Ok(Command::BreakOnInterrupt(Interrupt::Nmi, true)));
+        assert_eq!(parse_line("irq off"), Ok(Command::BreakOnInterrupt(Interrupt::Irq, false)));
+        assert!(parse_line("irq").is_err());
+        assert_eq!(parse_line("l"), Ok(Command::List));
+        assert_eq!(parse_line("del b 2"), Ok(Command::DeleteBreakpoint(2)));
+        assert_eq!(parse_line("delete w 0"), Ok(Command::DeleteWatchpoint(0)));
+        assert!(parse_line("del x 0").is_err());
+        assert!(parse_line("del b").is_err());
+        assert_eq!(parse_line("r"), Ok(Command::Registers));
+        assert_eq!(parse_line("d"), Ok(Command::Disassemble { start: None, count: 10 }));
+        assert_eq!(parse_line("d $8000 4"), Ok(Command::Disassemble { start: Some(0x8000), count: 4 }));
+        assert_eq!(parse_line("d $8000-$8010"), Ok(Command::DisassembleRange(0x8000, 0x8010)));
+        assert_eq!(parse_line("m buffer"), Ok(Command::Memory { bus: Bus::Cpu, start: 0x20, length: 0x40 }));
+        assert_eq!(parse_line("m ppu $3F00 32"), Ok(Command::Memory { bus: Bus::Ppu, start: 0x3F00, length: 32 }));
+        assert!(parse_line("m cpu").is_err());
+    }
+}
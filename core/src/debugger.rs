@@ -0,0 +1,587 @@
+use crate::cpu::CPU;
+use crate::cpuregisters::CPURegisters;
+use crate::ram_controller::{AccessKind, Bus, MemoryAccess};
+use crate::region::Region;
+
+const JSR: u8 = 0x20;
+const RTS: u8 = 0x60;
+const RTI: u8 = 0x40;
+const BRK: u8 = 0x00;
+
+// What the emulator should do once a debugger front end lets go of a paused machine
+#[derive(PartialEq)]
+pub enum PauseResult {
+    Resume,
+    Quit,
+    // The gdb client went away, the emulator runs on without it
+    Detach,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interrupt {
+    Nmi,
+    Irq,
+}
+
+#[derive(Clone, Debug)]
+pub enum BreakReason {
+    Requested,
+    Step,
+    Breakpoint(u16),
+    Condition(String),
+    Watchpoint(MemoryAccess),
+    Scanline(i32),
+    Interrupt(Interrupt),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operand {
+    A,
+    X,
+    Y,
+    P,
+    SP,
+    PC,
+    Scanline,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Term {
+    operand: Operand,
+    comparison: Comparison,
+    value: i32,
+}
+
+// A register expression such as `A == $10 && X > 3 || PC == $C000`. && binds harder than ||.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Condition {
+    source: String,
+    any_of: Vec<Vec<Term>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Breakpoint {
+    pub address: Option<u16>,
+    pub condition: Option<Condition>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Watchpoint {
+    pub bus: Bus,
+    pub kind: AccessKind,
+    pub start: u16,
+    pub end: u16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RunMode {
+    Run,
+    StepInto,
+    StepOver { return_address: u16, depth: i32 },
+    StepOut { depth: i32 },
+    RunToScanline(i32),
+}
+
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    break_on_nmi: bool,
+    break_on_irq: bool,
+    mode: RunMode,
+    break_requested: bool,
+    pending: Option<BreakReason>,
+    call_depth: i32,
+    last_opcode: Option<u8>,
+    last_scanline: i32,
+    // The last scanline of the frame, which the debugger also takes as -1
+    pre_render_scanline: i32,
+}
+
+impl Debugger {
+    pub fn new(region: Region) -> Debugger {
+        Debugger {
+            breakpoints: vec![],
+            watchpoints: vec![],
+            break_on_nmi: false,
+            break_on_irq: false,
+            mode: RunMode::Run,
+            break_requested: false,
+            pending: None,
+            call_depth: 0,
+            last_opcode: None,
+            last_scanline: 0,
+            pre_render_scanline: region.scanlines() - 1,
+        }
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn add_breakpoint(&mut self, address: Option<u16>, condition: Option<Condition>) {
+        let condition = condition.map(|c| c.with_pre_render_scanline(self.pre_render_scanline));
+        self.breakpoints.push(Breakpoint { address, condition });
+    }
+
+    pub fn remove_breakpoint(&mut self, index: usize) -> bool {
+        if index < self.breakpoints.len() {
+            self.breakpoints.remove(index);
+            return true;
+        }
+
+        false
+    }
+
+    pub fn remove_breakpoint_at(&mut self, address: u16) -> bool {
+        match self.breakpoints.iter().position(|b| b.address == Some(address) && b.condition.is_none()) {
+            Some(index) => self.remove_breakpoint(index),
+            None => false
+        }
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    pub fn remove_watchpoint(&mut self, index: usize) -> bool {
+        if index < self.watchpoints.len() {
+            self.watchpoints.remove(index);
+            return true;
+        }
+
+        false
+    }
+
+    pub fn remove_watchpoint_at(&mut self, bus: Bus, kind: AccessKind, start: u16, end: u16) -> bool {
+        match self.watchpoints.iter().position(|w| w.bus == bus && w.kind == kind && w.start == start && w.end == end) {
+            Some(index) => self.remove_watchpoint(index),
+            None => false
+        }
+    }
+
+    pub fn set_break_on_interrupt(&mut self, interrupt: Interrupt, enabled: bool) {
+        match interrupt {
+            Interrupt::Nmi => self.break_on_nmi = enabled,
+            Interrupt::Irq => self.break_on_irq = enabled,
+        }
+    }
+
+    // Whether the memory controller has to record accesses for the watchpoints to work
+    pub fn wants_accesses(&self) -> bool {
+        self.watchpoints.iter().any(|w| w.kind != AccessKind::Execute)
+    }
+
+    pub fn request_break(&mut self) {
+        self.break_requested = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.mode = RunMode::Run;
+    }
+
+    pub fn step_into(&mut self) {
+        self.mode = RunMode::StepInto;
+    }
+
+    // Runs a JSR at the current PC until it returns, anything else is a plain step
+    pub fn step_over(&mut self, cpu: &CPU) {
+        let pc = cpu.registers.pc();
+        self.mode = if cpu.memory().peek8(pc) == JSR {
+            RunMode::StepOver { return_address: pc.wrapping_add(3), depth: self.call_depth }
+        } else {
+            RunMode::StepInto
+        };
+    }
+
+    pub fn step_out(&mut self) {
+        self.mode = RunMode::StepOut { depth: self.call_depth };
+    }
+
+    // -1 is the pre-render scanline
+    pub fn run_to_scanline(&mut self, scanline: i32) -> Result<(), String> {
+        if !(-1..=self.pre_render_scanline).contains(&scanline) {
+            return Err(format!("Scanline {} does not exist, they go from -1 to {}", scanline, self.pre_render_scanline));
+        }
+
+        self.mode = RunMode::RunToScanline(if scanline == -1 { self.pre_render_scanline } else { scanline });
+        Ok(())
+    }
+
+    // Has to be called when the CPU is interrupted, since it enters a new call level
+    pub fn interrupt(&mut self, interrupt: Interrupt) {
+        self.call_depth += 1;
+
+        if (interrupt == Interrupt::Nmi && self.break_on_nmi) || (interrupt == Interrupt::Irq && self.break_on_irq) {
+            self.pending = Some(BreakReason::Interrupt(interrupt));
+        }
+    }
+
+    // Called before each instruction. Accounts for what the previous instruction did, then decides
+    // whether execution should stop before the instruction at the current PC. `accesses` are the
+    // previous instruction's, from RamController::take_accesses.
+    pub fn check(&mut self, cpu: &CPU, scanline: i32, accesses: &[MemoryAccess]) -> Option<BreakReason> {
+        match self.last_opcode.take() {
+            Some(JSR) => self.call_depth += 1,
+            Some(RTS) | Some(RTI) => self.call_depth -= 1,
+            // BRK is a software IRQ
+            Some(BRK) => self.interrupt(Interrupt::Irq),
+            _ => {}
+        }
+
+        let pc = cpu.registers.pc();
+        self.last_opcode = Some(cpu.memory().peek8(pc));
+
+        let scanline_changed = scanline != self.last_scanline;
+        self.last_scanline = scanline;
+
+        if let Some(reason) = self.pending.take() {
+            return self.stop(reason);
+        }
+
+        if self.break_requested {
+            self.break_requested = false;
+            return self.stop(BreakReason::Requested);
+        }
+
+        match self.mode {
+            RunMode::StepInto => return self.stop(BreakReason::Step),
+            RunMode::StepOver { return_address, depth } if pc == return_address && self.call_depth <= depth => {
+                return self.stop(BreakReason::Step);
+            }
+            RunMode::StepOut { depth } if self.call_depth < depth => return self.stop(BreakReason::Step),
+            RunMode::RunToScanline(target) if scanline_changed && scanline == target => {
+                return self.stop(BreakReason::Scanline(scanline));
+            }
+            _ => {}
+        }
+
+        if let Some(access) = accesses.iter().find(|a| self.watched(a)) {
+            return self.stop(BreakReason::Watchpoint(*access));
+        }
+
+        let execute = MemoryAccess { bus: Bus::Cpu, kind: AccessKind::Execute, address: pc, value: self.last_opcode.unwrap() };
+        if self.watched(&execute) {
+            return self.stop(BreakReason::Watchpoint(execute));
+        }
+
+        for breakpoint in &self.breakpoints {
+            if breakpoint.address.is_some() && breakpoint.address != Some(pc) {
+                continue;
+            }
+
+            match &breakpoint.condition {
+                Some(condition) if condition.evaluate(&cpu.registers, scanline) => {
+                    let reason = BreakReason::Condition(condition.source.clone());
+                    return self.stop(reason);
+                }
+                None => return self.stop(BreakReason::Breakpoint(pc)),
+                _ => {}
+            }
+        }
+
+        None
+    }
+
+    fn stop(&mut self, reason: BreakReason) -> Option<BreakReason> {
+        self.mode = RunMode::Run;
+        Some(reason)
+    }
+
+    fn watched(&self, access: &MemoryAccess) -> bool {
+        self.watchpoints.iter().any(|w| {
+            w.bus == access.bus && w.kind == access.kind && access.address >= w.start && access.address <= w.end
+        })
+    }
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Condition, String> {
+        let mut any_of = vec![];
+
+        for alternative in source.split("||") {
+            let mut all_of = vec![];
+            for term in alternative.split("&&") {
+                all_of.push(Term::parse(term.trim())?);
+            }
+            any_of.push(all_of);
+        }
+
+        Ok(Condition {
+            source: source.trim().to_string(),
+            any_of,
+        })
+    }
+
+    // Conditions are parsed without knowing the region, so a scanline of -1 is kept until the
+    // debugger they are added to fills in its pre-render scanline
+    fn with_pre_render_scanline(mut self, pre_render: i32) -> Condition {
+        for term in self.any_of.iter_mut().flatten() {
+            if term.operand == Operand::Scanline && term.value == -1 {
+                term.value = pre_render;
+            }
+        }
+        self
+    }
+
+    pub fn evaluate(&self, regs: &CPURegisters, scanline: i32) -> bool {
+        self.any_of.iter().any(|all_of| all_of.iter().all(|term| term.evaluate(regs, scanline)))
+    }
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl Term {
+    fn parse(source: &str) -> Result<Term, String> {
+        // Two character operators first, so that <= is not taken for <
+        let operators = [
+            ("==", Comparison::Equal),
+            ("!=", Comparison::NotEqual),
+            ("<=", Comparison::LessOrEqual),
+            (">=", Comparison::GreaterOrEqual),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+        ];
+
+        for (token, comparison) in operators.iter() {
+            if let Some(index) = source.find(token) {
+                let operand = match source[..index].trim().to_uppercase().as_str() {
+                    "A" => Operand::A,
+                    "X" => Operand::X,
+                    "Y" => Operand::Y,
+                    "P" => Operand::P,
+                    "SP" => Operand::SP,
+                    "PC" => Operand::PC,
+                    "SCANLINE" => Operand::Scanline,
+                    other => return Err(format!("Unknown register '{}'", other))
+                };
+
+                let value = source[index + token.len()..].trim();
+                let value = match operand {
+                    Operand::Scanline => parse_scanline(value)?,
+                    _ => parse_number(value)? as i32,
+                };
+
+                return Ok(Term {
+                    operand,
+                    comparison: *comparison,
+                    value,
+                });
+            }
+        }
+
+        Err(format!("Expected a comparison in '{}'", source))
+    }
+
+    fn evaluate(&self, regs: &CPURegisters, scanline: i32) -> bool {
+        let left = match self.operand {
+            Operand::A => regs.accumulator() as i32,
+            Operand::X => regs.x() as i32,
+            Operand::Y => regs.y() as i32,
+            Operand::P => regs.status() as i32,
+            Operand::SP => (regs.stack() & 0xFF) as i32,
+            Operand::PC => regs.pc() as i32,
+            Operand::Scanline => scanline,
+        };
+        let right = self.value;
+
+        match self.comparison {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+        }
+    }
+}
+
+// Accepts $C000, 0xC000 and plain decimal numbers
+pub fn parse_number(text: &str) -> Result<u16, String> {
+    let result = if let Some(hex) = text.strip_prefix('$') {
+        u16::from_str_radix(hex, 16)
+    } else if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        u16::from_str_radix(hex, 16)
+    } else {
+        text.parse::<u16>()
+    };
+
+    result.map_err(|_| format!("Invalid number '{}'", text))
+}
+
+// A number, or -1 for the pre-render scanline
+pub fn parse_scanline(text: &str) -> Result<i32, String> {
+    match text {
+        "-1" => Ok(-1),
+        _ => parse_number(text).map(|scanline| scanline as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Cartridge;
+    use crate::nes::Nes;
+
+    // Runs the way main.rs does, but steps before the first check, as main.rs resumes
+    fn run(nes: &mut Nes, debugger: &mut Debugger) -> Option<BreakReason> {
+        nes.cpu().memory().set_access_logging(debugger.wants_accesses());
+        for _ in 0..100_000 {
+            if nes.step().nmi {
+                debugger.interrupt(Interrupt::Nmi);
+            }
+            let accesses = nes.cpu().memory().take_accesses();
+            if let Some(reason) = debugger.check(nes.cpu(), nes.ppu().scanline(), &accesses) {
+                return Some(reason);
+            }
+        }
+        None
+    }
+
+    // Turns NMIs on and calls a subroutine that counts X down from 256 over and over, the NMI
+    // handler at $8100 counts NMIs in $10
+    fn nmi_program() -> Cartridge {
+        Cartridge::nrom(&[
+            (0x8000, &[0xA9, 0x80, 0x8D, 0x00, 0x20, 0x20, 0x20, 0x80, 0x4C, 0x05, 0x80]),
+            (0x8020, &[0xA2, 0x00, 0xCA, 0xD0, 0xFD, 0x60]),
+            (0x8100, &[0xE6, 0x10, 0x40]),
+        ], [0x8100, 0x8000, 0x8000])
+    }
+
+    #[test]
+    fn step_over_returns_after_the_jsr_even_with_an_nmi_in_between() {
+        let mut nes = Nes::new(nmi_program()).unwrap();
+        let mut debugger = Debugger::new(Region::Ntsc);
+        debugger.add_breakpoint(Some(0x8005), None);
+        assert!(matches!(run(&mut nes, &mut debugger), Some(BreakReason::Breakpoint(0x8005))));
+        assert!(debugger.remove_breakpoint(0));
+
+        // Each call takes about 11 scanlines, so an NMI lands in one of the first few
+        let mut interrupted = false;
+        for _ in 0..40 {
+            let nmis = nes.cpu().memory().peek8(0x10);
+            debugger.step_over(nes.cpu());
+            assert!(matches!(run(&mut nes, &mut debugger), Some(BreakReason::Step)));
+            assert_eq!(nes.cpu().registers.pc(), 0x8008);
+            interrupted = nes.cpu().memory().peek8(0x10) != nmis;
+
+            debugger.step_into();
+            assert!(matches!(run(&mut nes, &mut debugger), Some(BreakReason::Step)));
+            assert_eq!(nes.cpu().registers.pc(), 0x8005);
+            if interrupted {
+                break;
+            }
+        }
+        assert!(interrupted);
+
+        // Into the subroutine and back out of it
+        debugger.step_into();
+        run(&mut nes, &mut debugger);
+        assert_eq!(nes.cpu().registers.pc(), 0x8020);
+        debugger.step_out();
+        assert!(matches!(run(&mut nes, &mut debugger), Some(BreakReason::Step)));
+        assert_eq!(nes.cpu().registers.pc(), 0x8008);
+    }
+
+    #[test]
+    fn conditional_breakpoints_stop_once_the_condition_holds() {
+        let mut nes = Nes::new(nmi_program()).unwrap();
+        let mut debugger = Debugger::new(Region::Ntsc);
+        debugger.add_breakpoint(None, Some(Condition::parse("X == $80 && PC == $8022").unwrap()));
+        match run(&mut nes, &mut debugger) {
+            Some(BreakReason::Condition(source)) => assert_eq!(source, "X == $80 && PC == $8022"),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!((nes.cpu().registers.x(), nes.cpu().registers.pc()), (0x80, 0x8022));
+
+        // Only at its address
+        let mut debugger = Debugger::new(Region::Ntsc);
+        debugger.add_breakpoint(Some(0x8025), Some(Condition::parse("X != 0 || scanline >= 300").unwrap()));
+        debugger.add_breakpoint(Some(0x8022), Some(Condition::parse("X < 3").unwrap()));
+        assert!(matches!(run(&mut nes, &mut debugger), Some(BreakReason::Condition(_))));
+        assert_eq!((nes.cpu().registers.x(), nes.cpu().registers.pc()), (2, 0x8022));
+
+        assert!(Condition::parse("Q == 1").is_err());
+        assert!(Condition::parse("A 1").is_err());
+        assert!(Condition::parse("A == -1").is_err());
+    }
+
+    #[test]
+    fn the_pre_render_scanline_is_also_minus_one() {
+        let mut nes = Nes::new(nmi_program()).unwrap();
+        let mut debugger = Debugger::new(Region::Ntsc);
+        debugger.add_breakpoint(None, Some(Condition::parse("scanline == -1").unwrap()));
+        assert!(matches!(run(&mut nes, &mut debugger), Some(BreakReason::Condition(_))));
+        assert_eq!(nes.ppu().scanline(), 261);
+
+        let mut debugger = Debugger::new(Region::Ntsc);
+        debugger.run_to_scanline(-1).unwrap();
+        assert!(matches!(run(&mut nes, &mut debugger), Some(BreakReason::Scanline(261))));
+        debugger.run_to_scanline(20).unwrap();
+        assert!(matches!(run(&mut nes, &mut debugger), Some(BreakReason::Scanline(20))));
+
+        let mut debugger = Debugger::new(Region::Ntsc);
+        assert!(debugger.run_to_scanline(261).is_ok());
+        assert!(debugger.run_to_scanline(262).is_err());
+        assert!(debugger.run_to_scanline(-2).is_err());
+        for region in [Region::Pal, Region::Dendy] {
+            let mut debugger = Debugger::new(region);
+            assert!(debugger.run_to_scanline(311).is_ok());
+            assert!(debugger.run_to_scanline(312).is_err());
+        }
+        assert_eq!(parse_scanline("-1"), Ok(-1));
+        assert!(parse_scanline("-2").is_err());
+    }
+
+    #[test]
+    fn watchpoints_catch_reads_writes_and_execution_on_both_buses() {
+        // PPUADDR = $2100, PPUDATA = $55, then $55 to $0300 and back, then spin
+        let program = [
+            0xA9, 0x21, 0x8D, 0x06, 0x20, 0xA9, 0x00, 0x8D, 0x06, 0x20,
+            0xA9, 0x55, 0x8D, 0x07, 0x20, 0x8D, 0x00, 0x03, 0xAD, 0x00, 0x03, 0x4C, 0x15, 0x80,
+        ];
+        let watch = |bus, kind, start, end| Watchpoint { bus, kind, start, end };
+
+        let mut nes = Nes::new(Cartridge::nrom(&[(0x8000, &program)], [0x8000, 0x8000, 0x8000])).unwrap();
+        let mut debugger = Debugger::new(Region::Ntsc);
+        debugger.add_watchpoint(watch(Bus::Ppu, AccessKind::Write, 0x2000, 0x23FF));
+        debugger.add_watchpoint(watch(Bus::Cpu, AccessKind::Write, 0x0300, 0x0300));
+        debugger.add_watchpoint(watch(Bus::Cpu, AccessKind::Read, 0x0300, 0x0300));
+        debugger.add_watchpoint(watch(Bus::Cpu, AccessKind::Execute, 0x8015, 0x8015));
+        assert!(debugger.wants_accesses());
+
+        let mut hits = vec![];
+        while let Some(BreakReason::Watchpoint(access)) = run(&mut nes, &mut debugger) {
+            hits.push((access.bus, access.kind, access.address, access.value));
+            if access.kind == AccessKind::Execute {
+                break;
+            }
+        }
+        assert_eq!(hits, vec![
+            (Bus::Ppu, AccessKind::Write, 0x2100, 0x55),
+            (Bus::Cpu, AccessKind::Write, 0x0300, 0x55),
+            (Bus::Cpu, AccessKind::Read, 0x0300, 0x55),
+            (Bus::Cpu, AccessKind::Execute, 0x8015, 0x4C),
+        ]);
+
+        assert!(debugger.remove_watchpoint(3));
+        assert!(!debugger.remove_watchpoint(3));
+    }
+}
@@ -0,0 +1,288 @@
+// Just enough DEFLATE (RFC 1951) for PNG: compression with the fixed Huffman codes,
+// decompression of everything, since reference images may have been saved by other tools.
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+// The order the code length code lengths of a dynamic block come in
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+const WINDOW: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+// How far back along the hash chain to look for a longer match
+const MAX_CHAIN: usize = 64;
+
+struct BitWriter {
+    data: Vec<u8>,
+    bits: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, count: u32) {
+        self.bits |= value << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.data.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes go most significant bit first, everything else least significant bit first
+    fn write_code(&mut self, code: u32, count: u32) {
+        let reversed = code.reverse_bits() >> (32 - count);
+        self.write(reversed, count);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.data.push(self.bits as u8);
+        }
+        self.data
+    }
+}
+
+fn write_literal(writer: &mut BitWriter, symbol: u16) {
+    let symbol = symbol as u32;
+    match symbol {
+        0..=143 => writer.write_code(0x30 + symbol, 8),
+        144..=255 => writer.write_code(0x190 + symbol - 144, 9),
+        256..=279 => writer.write_code(symbol - 256, 7),
+        _ => writer.write_code(0xC0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let code = LENGTH_BASE.iter().rposition(|base| *base as usize <= length).unwrap();
+    write_literal(writer, 257 + code as u16);
+    writer.write((length - LENGTH_BASE[code] as usize) as u32, LENGTH_EXTRA[code] as u32);
+
+    let code = DISTANCE_BASE.iter().rposition(|base| *base as usize <= distance).unwrap();
+    writer.write_code(code as u32, 5);
+    writer.write((distance - DISTANCE_BASE[code] as usize) as u32, DISTANCE_EXTRA[code] as u32);
+}
+
+fn hash(data: &[u8]) -> usize {
+    ((data[0] as usize) << 10 ^ (data[1] as usize) << 5 ^ data[2] as usize) & (WINDOW - 1)
+}
+
+// A single fixed Huffman block, with matches found through hash chains of 3 byte prefixes
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter { data: vec![], bits: 0, count: 0 };
+    writer.write(1, 1); // last block
+    writer.write(1, 2); // fixed Huffman codes
+
+    let mut head = vec![usize::MAX; WINDOW];
+    let mut previous = vec![usize::MAX; data.len()];
+    let insert = |position: usize, head: &mut Vec<usize>, previous: &mut Vec<usize>| {
+        if position + MIN_MATCH <= data.len() {
+            let h = hash(&data[position..]);
+            previous[position] = head[h];
+            head[h] = position;
+        }
+    };
+
+    let mut position = 0;
+    while position < data.len() {
+        let mut best = (0, 0);
+        if position + MIN_MATCH <= data.len() {
+            let longest = MAX_MATCH.min(data.len() - position);
+            let mut candidate = head[hash(&data[position..])];
+            let mut chain = 0;
+            while candidate != usize::MAX && position - candidate <= WINDOW && chain < MAX_CHAIN {
+                let length = (0..longest).take_while(|i| data[candidate + i] == data[position + i]).count();
+                if length > best.0 {
+                    best = (length, position - candidate);
+                }
+                candidate = previous[candidate];
+                chain += 1;
+            }
+        }
+
+        if best.0 >= MIN_MATCH {
+            write_match(&mut writer, best.0, best.1);
+            for i in position..position + best.0 {
+                insert(i, &mut head, &mut previous);
+            }
+            position += best.0;
+        } else {
+            write_literal(&mut writer, data[position] as u16);
+            insert(position, &mut head, &mut previous);
+            position += 1;
+        }
+    }
+
+    write_literal(&mut writer, 256);
+    writer.finish()
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn bits(&mut self, count: u32) -> Result<u32, String> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = *self.data.get(self.position).ok_or("Compressed data ends early")?;
+            value |= (((byte >> self.bit) & 1) as u32) << i;
+            self.bit += 1;
+            if self.bit == 8 {
+                self.bit = 0;
+                self.position += 1;
+            }
+        }
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.position += 1;
+        }
+    }
+}
+
+// A canonical Huffman code, as the number of codes of each length and the symbols in code order
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut symbols = vec![];
+        for length in 1..16 {
+            symbols.extend((0..lengths.len()).filter(|s| lengths[*s] as usize == length).map(|s| s as u16));
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, String> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(String::from("Invalid Huffman code"))
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), String> {
+    let literals = reader.bits(5)? as usize + 257;
+    let distances = reader.bits(5)? as usize + 1;
+    let code_lengths = reader.bits(4)? as usize + 4;
+
+    let mut lengths = [0u8; 19];
+    for index in CODE_LENGTH_ORDER.iter().take(code_lengths) {
+        lengths[*index] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&lengths);
+
+    let mut lengths = vec![];
+    while lengths.len() < literals + distances {
+        let (length, repeat) = match code_length_code.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last().ok_or("Repeated code length with nothing to repeat")?, 3 + reader.bits(2)?),
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(length, repeat as usize));
+    }
+    if lengths.len() != literals + distances {
+        return Err(String::from("Code lengths run past the end"));
+    }
+
+    Ok((Huffman::new(&lengths[..literals]), Huffman::new(&lengths[literals..])))
+}
+
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut reader = BitReader { data, position: 0, bit: 0 };
+    let mut output: Vec<u8> = vec![];
+
+    loop {
+        let last = reader.bits(1)? == 1;
+        let (literals, distances) = match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let header = data.get(reader.position..reader.position + 4).ok_or("Compressed data ends early")?;
+                let length = u16::from_le_bytes([header[0], header[1]]) as usize;
+                let start = reader.position + 4;
+                output.extend_from_slice(data.get(start..start + length).ok_or("Compressed data ends early")?);
+                reader.position = start + length;
+                if last {
+                    return Ok(output);
+                }
+                continue;
+            }
+            1 => fixed_codes(),
+            2 => dynamic_codes(&mut reader)?,
+            _ => return Err(String::from("Invalid block type")),
+        };
+
+        loop {
+            let symbol = literals.decode(&mut reader)? as usize;
+            if symbol < 256 {
+                output.push(symbol as u8);
+                continue;
+            }
+            if symbol == 256 {
+                break;
+            }
+
+            let code = symbol - 257;
+            if code >= LENGTH_BASE.len() {
+                return Err(String::from("Invalid length code"));
+            }
+            let length = LENGTH_BASE[code] as usize + reader.bits(LENGTH_EXTRA[code] as u32)? as usize;
+            let code = distances.decode(&mut reader)? as usize;
+            if code >= DISTANCE_BASE.len() {
+                return Err(String::from("Invalid distance code"));
+            }
+            let distance = DISTANCE_BASE[code] as usize + reader.bits(DISTANCE_EXTRA[code] as u32)? as usize;
+            if distance > output.len() {
+                return Err(String::from("Distance reaches before the start"));
+            }
+
+            let start = output.len() - distance;
+            for i in 0..length {
+                output.push(output[start + i]);
+            }
+        }
+
+        if last {
+            return Ok(output);
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use crate::cpuregisters::CPURegisters;
+use crate::instructions::{self, AddressingMode};
+use crate::ram_controller::RamController;
+use crate::symbols::SymbolTable;
+
+pub struct DisassembledInstruction {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub text: String,
+}
+
+impl DisassembledInstruction {
+    pub fn length(&self) -> u16 {
+        self.bytes.len() as u16
+    }
+}
+
+// Disassembles the instruction at `address` without looking at any register or memory values.
+// Operand addresses that have a label are shown by name.
+pub fn disassemble(mem: &RamController, symbols: &SymbolTable, address: u16) -> DisassembledInstruction {
+    disassemble_with(mem, symbols, address, None)
+}
+
+// Disassembles the instruction about to be executed, resolving effective addresses and the
+// values stored there the way nestest.log does (`LDA $0200,X @ 0205 = 3F`).
+pub fn disassemble_at(mem: &RamController, symbols: &SymbolTable, regs: &CPURegisters) -> DisassembledInstruction {
+    disassemble_with(mem, symbols, regs.pc(), Some(regs))
+}
+
+// Disassembles every instruction that starts in [start, end].
+pub fn disassemble_range(mem: &RamController, symbols: &SymbolTable, start: u16, end: u16) -> Vec<DisassembledInstruction> {
+    let mut result = Vec::new();
+    let mut address = start as u32;
+
+    while address <= end as u32 {
+        let instruction = disassemble(mem, symbols, address as u16);
+        address += instruction.length() as u32;
+        result.push(instruction);
+    }
+
+    result
+}
+
+fn disassemble_with(mem: &RamController, symbols: &SymbolTable, address: u16, regs: Option<&CPURegisters>) -> DisassembledInstruction {
+    let opcode = mem.peek8(address);
+    let instruction = instructions::lookup(opcode);
+    let bytes: Vec<u8> = (0..instruction.length())
+        .map(|i| mem.peek8(address.wrapping_add(i)))
+        .collect();
+
+    let operand8 = if bytes.len() > 1 { bytes[1] } else { 0 };
+    let operand16 = if bytes.len() > 2 { operand8 as u16 | ((bytes[2] as u16) << 8) } else { operand8 as u16 };
+
+    let zero_page = symbols.label(mem, operand8 as u16).map(String::from).unwrap_or(format!("${:02X}", operand8));
+    let absolute = |address: u16| symbols.label(mem, address).map(String::from).unwrap_or(format!("${:04X}", address));
+
+    let operand = match instruction.mode {
+        AddressingMode::Implied => String::new(),
+        AddressingMode::Accumulator => String::from("A"),
+        AddressingMode::Immediate => format!("#${:02X}", operand8),
+        AddressingMode::ZeroPage => zero_page,
+        AddressingMode::ZeroPageX => format!("{},X", zero_page),
+        AddressingMode::ZeroPageY => format!("{},Y", zero_page),
+        AddressingMode::Relative => absolute(address.wrapping_add(2).wrapping_add(operand8 as i8 as u16)),
+        AddressingMode::Absolute => absolute(operand16),
+        AddressingMode::AbsoluteX => format!("{},X", absolute(operand16)),
+        AddressingMode::AbsoluteY => format!("{},Y", absolute(operand16)),
+        AddressingMode::Indirect => format!("({})", absolute(operand16)),
+        AddressingMode::IndexedIndirect => format!("({},X)", zero_page),
+        AddressingMode::IndirectIndexed => format!("({}),Y", zero_page),
+    };
+
+    let annotation = match regs {
+        Some(regs) => annotate(mem, regs, instruction.mnemonic, instruction.mode, operand8, operand16),
+        None => String::new()
+    };
+
+    let prefix = if instruction.official { "" } else { "*" };
+    let text = if operand.is_empty() {
+        format!("{}{}", prefix, instruction.mnemonic)
+    } else {
+        format!("{}{} {}{}", prefix, instruction.mnemonic, operand, annotation)
+    };
+
+    DisassembledInstruction {
+        address,
+        bytes,
+        text,
+    }
+}
+
+fn annotate(mem: &RamController, regs: &CPURegisters, mnemonic: &str, mode: AddressingMode, operand8: u8, operand16: u16) -> String {
+    let read16_zero_page = |address: u8| {
+        mem.peek8(address as u16) as u16 | ((mem.peek8(address.wrapping_add(1) as u16) as u16) << 8)
+    };
+
+    match mode {
+        AddressingMode::ZeroPage => format!(" = {:02X}", mem.peek8(operand8 as u16)),
+        AddressingMode::ZeroPageX | AddressingMode::ZeroPageY => {
+            let index = if mode == AddressingMode::ZeroPageX { regs.x() } else { regs.y() };
+            let address = operand8.wrapping_add(index);
+            format!(" @ {:02X} = {:02X}", address, mem.peek8(address as u16))
+        }
+        AddressingMode::Absolute if mnemonic == "JMP" || mnemonic == "JSR" => String::new(),
+        AddressingMode::Absolute => format!(" = {:02X}", mem.peek8(operand16)),
+        AddressingMode::AbsoluteX | AddressingMode::AbsoluteY => {
+            let index = if mode == AddressingMode::AbsoluteX { regs.x() } else { regs.y() };
+            let address = operand16.wrapping_add(index as u16);
+            format!(" @ {:04X} = {:02X}", address, mem.peek8(address))
+        }
+        AddressingMode::Indirect => {
+            // Same page wrap bug as the CPU, see addressing_mode::indirect
+            let high_address = if operand16 & 0xFF == 0xFF { operand16 & 0xFF00 } else { operand16 + 1 };
+            let target = mem.peek8(operand16) as u16 | ((mem.peek8(high_address) as u16) << 8);
+            format!(" = {:04X}", target)
+        }
+        AddressingMode::IndexedIndirect => {
+            let pointer = operand8.wrapping_add(regs.x());
+            let address = read16_zero_page(pointer);
+            format!(" @ {:02X} = {:04X} = {:02X}", pointer, address, mem.peek8(address))
+        }
+        AddressingMode::IndirectIndexed => {
+            let base = read16_zero_page(operand8);
+            let address = base.wrapping_add(regs.y() as u16);
+            format!(" = {:04X} @ {:04X} = {:02X}", base, address, mem.peek8(address))
+        }
+        _ => String::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppu_registers::PPURegisters;
+    use crate::vram_controller::VRAMController;
+    use crate::region::Region;
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    // RAM with `code` at $0200
+    fn memory(code: &[u8]) -> RamController {
+        let mut mem = RamController::new(Rc::new(Cell::new(PPURegisters::new())), Rc::new(RefCell::new(VRAMController::new())), Region::Ntsc);
+        for (i, byte) in code.iter().enumerate() {
+            mem.write8(0x0200 + i as u16, *byte);
+        }
+        mem
+    }
+
+    fn disassemble_code(code: &[u8]) -> DisassembledInstruction {
+        disassemble(&memory(code), &SymbolTable::new(), 0x0200)
+    }
+
+    fn text(code: &[u8]) -> String {
+        disassemble_code(code).text
+    }
+
+    #[test]
+    fn every_addressing_mode_formats_its_operand() {
+        assert_eq!(text(&[0x18]), "CLC");
+        assert_eq!(text(&[0x0A]), "ASL A");
+        assert_eq!(text(&[0xA9, 0x10]), "LDA #$10");
+        assert_eq!(text(&[0xA5, 0x10]), "LDA $10");
+        assert_eq!(text(&[0xB5, 0x10]), "LDA $10,X");
+        assert_eq!(text(&[0xB6, 0x10]), "LDX $10,Y");
+        assert_eq!(text(&[0xAD, 0x34, 0x12]), "LDA $1234");
+        assert_eq!(text(&[0xBD, 0x34, 0x12]), "LDA $1234,X");
+        assert_eq!(text(&[0xB9, 0x34, 0x12]), "LDA $1234,Y");
+        assert_eq!(text(&[0x6C, 0x34, 0x12]), "JMP ($1234)");
+        assert_eq!(text(&[0xA1, 0x10]), "LDA ($10,X)");
+        assert_eq!(text(&[0xB1, 0x10]), "LDA ($10),Y");
+    }
+
+    #[test]
+    fn branch_targets_are_relative_to_the_next_instruction() {
+        assert_eq!(text(&[0xD0, 0x05]), "BNE $0207");
+        assert_eq!(text(&[0xD0, 0xFE]), "BNE $0200");
+        assert_eq!(text(&[0x10, 0x80]), "BPL $0182");
+    }
+
+    #[test]
+    fn unofficial_and_unsupported_opcodes_are_marked() {
+        assert_eq!(text(&[0xA7, 0x10]), "*LAX $10");
+        let jam = disassemble_code(&[0x02]);
+        assert_eq!((jam.text.as_str(), jam.length()), ("*JAM", 1));
+        assert_eq!(text(&[0x93, 0x10]), "*SHA ($10),Y");
+        assert_eq!(disassemble_code(&[0x9C, 0x34, 0x12]).length(), 3);
+    }
+
+    #[test]
+    fn the_instruction_about_to_run_shows_its_effective_address() {
+        // LDA ($10),Y with $10 pointing at $0300, and JMP ($02FF) wrapping within the page
+        let mut mem = memory(&[0xB1, 0x10]);
+        mem.write8(0x10, 0x00);
+        mem.write8(0x11, 0x03);
+        mem.write8(0x0302, 0x7F);
+        let mut regs = CPURegisters::new();
+        regs.set_y(2);
+        regs.set_pc(0x0200);
+        assert_eq!(disassemble_at(&mem, &SymbolTable::new(), &regs).text, "LDA ($10),Y = 0300 @ 0302 = 7F");
+
+        mem.write8(0x0210, 0x6C);
+        mem.write8(0x0211, 0xFF);
+        mem.write8(0x0212, 0x02);
+        mem.write8(0x02FF, 0x34);
+        mem.write8(0x0300, 0x56);
+        mem.write8(0x0200, 0x12);
+        regs.set_pc(0x0210);
+        assert_eq!(disassemble_at(&mem, &SymbolTable::new(), &regs).text, "JMP ($02FF) = 1234");
+    }
+}
@@ -0,0 +1,571 @@
+use crate::cpu::CPU;
+use crate::cpuregisters::CPURegisters;
+use crate::debugger::{BreakReason, Debugger, PauseResult, Watchpoint};
+use crate::hex;
+use crate::ram_controller::{AccessKind, Bus};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+// GDB has no built in 6502 target, so the client gets the register layout from this description.
+// Registers are sent in this order, little endian: a, x, y, p, sp (8 bits each) and pc (16 bits).
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.rustnes.mos6502">
+    <reg name="a" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="x" bitsize="8" type="uint8" regnum="1"/>
+    <reg name="y" bitsize="8" type="uint8" regnum="2"/>
+    <reg name="p" bitsize="8" type="uint8" regnum="3"/>
+    <reg name="sp" bitsize="8" type="uint8" regnum="4"/>
+    <reg name="pc" bitsize="16" type="code_ptr" regnum="5"/>
+  </feature>
+</target>"#;
+
+const REGISTER_SIZES: [usize; 6] = [1, 1, 1, 1, 1, 2];
+
+// The largest packet we take or send, as advertised in the qSupported reply
+const PACKET_SIZE: usize = 0x1000;
+
+// Remote serial protocol stub. The emulator keeps running its own loop and hands control to
+// the stub whenever the debugger stops, the same way it does for the stdin console.
+pub struct GdbStub {
+    stream: TcpStream,
+    // Stop replies are only sent for a c or s the client is waiting on
+    running: bool,
+    // What the client has set, so that it can all be taken out again when it detaches. Watchpoints
+    // keep the stop reason of the Z type they came from, as an access watchpoint is a read and a write one.
+    breakpoints: Vec<u16>,
+    watchpoints: Vec<(Watchpoint, &'static str)>,
+}
+
+impl GdbStub {
+    // Blocks until a client connects to localhost:port
+    pub fn listen(port: u16) -> Result<GdbStub, String> {
+        let listener = TcpListener::bind(("127.0.0.1", port)).map_err(|e| e.to_string())?;
+        GdbStub::accept(&listener)
+    }
+
+    // Blocks until a client connects to a listener that is already bound
+    pub fn accept(listener: &TcpListener) -> Result<GdbStub, String> {
+        let (stream, _) = listener.accept().map_err(|e| e.to_string())?;
+        stream.set_nodelay(true).map_err(|e| e.to_string())?;
+
+        Ok(GdbStub { stream, running: false, breakpoints: vec![], watchpoints: vec![] })
+    }
+
+    // Called while the machine runs, picks up the Ctrl-C byte gdb sends to interrupt the target
+    pub fn poll_interrupt(&mut self, debugger: &mut Debugger) {
+        let mut buffer = [0u8; 1];
+        if self.stream.set_nonblocking(true).is_err() {
+            return;
+        }
+
+        if let Ok(1) = self.stream.read(&mut buffer) {
+            if buffer[0] == 0x03 {
+                debugger.request_break();
+            }
+        }
+
+        let _ = self.stream.set_nonblocking(false);
+    }
+
+    // Reports why the machine stopped and serves requests until the client resumes it
+    pub fn pause(&mut self, debugger: &mut Debugger, cpu: &mut CPU, reason: &BreakReason) -> PauseResult {
+        if self.running {
+            self.running = false;
+            let reply = self.stop_reply(reason);
+            if self.send(&reply).is_err() {
+                return PauseResult::Quit;
+            }
+        }
+
+        loop {
+            let packet = match self.receive() {
+                Ok(Some(packet)) => packet,
+                Ok(None) => continue,
+                Err(_) => return PauseResult::Quit
+            };
+
+            let response = match packet.as_bytes().first() {
+                Some(b'?') => self.stop_reply(reason),
+                Some(b'g') => read_registers(&cpu.registers),
+                Some(b'G') => write_registers(cpu, &packet[1..]),
+                Some(b'p') => read_register(&cpu.registers, &packet[1..]),
+                Some(b'P') => write_register(cpu, &packet[1..]),
+                Some(b'm') => read_memory(cpu, &packet[1..]),
+                Some(b'M') => write_memory(cpu, &packet[1..]),
+                Some(b'Z') => self.update_breakpoint(debugger, &packet[1..], true),
+                Some(b'z') => self.update_breakpoint(debugger, &packet[1..], false),
+                Some(b'c') => {
+                    debugger.resume();
+                    self.running = true;
+                    return PauseResult::Resume;
+                }
+                Some(b's') => {
+                    debugger.step_into();
+                    self.running = true;
+                    return PauseResult::Resume;
+                }
+                Some(b'D') => {
+                    let _ = self.send("OK");
+                    self.remove_breakpoints(debugger);
+                    debugger.resume();
+                    return PauseResult::Detach;
+                }
+                Some(b'k') => return PauseResult::Quit,
+                Some(b'H') => String::from("OK"),
+                Some(b'q') => query(&packet),
+                _ => String::new()
+            };
+
+            if self.send(&response).is_err() {
+                return PauseResult::Quit;
+            }
+        }
+    }
+
+    // Z/z packets: type 0/1 are breakpoints, 2 write, 3 read and 4 access watchpoints
+    fn update_breakpoint(&mut self, debugger: &mut Debugger, data: &str, insert: bool) -> String {
+        let parts: Vec<&str> = data.split(',').collect();
+        if parts.len() < 3 {
+            return String::from("E01");
+        }
+
+        let address = match u32::from_str_radix(parts[1], 16) {
+            Ok(address) if address <= 0xFFFF => address as u16,
+            _ => return String::from("E01")
+        };
+
+        let (kinds, name): (&[AccessKind], &'static str) = match parts[0] {
+            "0" | "1" => {
+                if insert {
+                    debugger.add_breakpoint(Some(address), None);
+                    self.breakpoints.push(address);
+                } else if debugger.remove_breakpoint_at(address) {
+                    if let Some(index) = self.breakpoints.iter().position(|b| *b == address) {
+                        self.breakpoints.remove(index);
+                    }
+                }
+                return String::from("OK");
+            }
+            "2" => (&[AccessKind::Write], "watch"),
+            "3" => (&[AccessKind::Read], "rwatch"),
+            "4" => (&[AccessKind::Read, AccessKind::Write], "awatch"),
+            _ => return String::new()
+        };
+
+        // The watched range has to fit below $10000
+        let end = match u32::from_str_radix(parts[2], 16) {
+            Ok(length) if length >= 1 && length <= 0x10000 - address as u32 => address + (length - 1) as u16,
+            _ => return String::from("E01")
+        };
+
+        for kind in kinds {
+            let watchpoint = Watchpoint { bus: Bus::Cpu, kind: *kind, start: address, end };
+            if insert {
+                debugger.add_watchpoint(watchpoint);
+                self.watchpoints.push((watchpoint, name));
+            } else if debugger.remove_watchpoint_at(Bus::Cpu, *kind, address, end) {
+                if let Some(index) = self.watchpoints.iter().position(|(w, _)| *w == watchpoint) {
+                    self.watchpoints.remove(index);
+                }
+            }
+        }
+
+        String::from("OK")
+    }
+
+    // Watchpoints report the Z type the client set them with, those from the console go by the access
+    fn stop_reply(&self, reason: &BreakReason) -> String {
+        match reason {
+            BreakReason::Watchpoint(access) => {
+                let set = self.watchpoints.iter().find(|(w, _)| {
+                    w.bus == access.bus && w.kind == access.kind && access.address >= w.start && access.address <= w.end
+                });
+                let name = match (set, access.kind) {
+                    (Some((_, name)), _) => name,
+                    (None, AccessKind::Read) => "rwatch",
+                    (None, _) => "watch"
+                };
+                format!("T05{}:{:04x};", name, access.address)
+            }
+            // SIGINT for a Ctrl-C, SIGTRAP for everything else
+            BreakReason::Requested => String::from("S02"),
+            _ => String::from("S05")
+        }
+    }
+
+    fn remove_breakpoints(&mut self, debugger: &mut Debugger) {
+        for address in self.breakpoints.drain(..) {
+            debugger.remove_breakpoint_at(address);
+        }
+        for (w, _) in self.watchpoints.drain(..) {
+            debugger.remove_watchpoint_at(w.bus, w.kind, w.start, w.end);
+        }
+    }
+
+    // Returns Ok(None) for anything that is not a complete packet, such as acks
+    fn receive(&mut self) -> Result<Option<String>, String> {
+        let mut byte = [0u8; 1];
+
+        loop {
+            self.read_byte(&mut byte)?;
+            match byte[0] {
+                b'$' => break,
+                // Ctrl-C while already stopped, nothing to do
+                0x03 => return Ok(None),
+                _ => continue
+            }
+        }
+
+        let mut data = Vec::new();
+        loop {
+            self.read_byte(&mut byte)?;
+            if byte[0] == b'#' {
+                break;
+            }
+            data.push(byte[0]);
+        }
+
+        let mut checksum = [0u8; 2];
+        self.read_byte(&mut byte)?;
+        checksum[0] = byte[0];
+        self.read_byte(&mut byte)?;
+        checksum[1] = byte[0];
+
+        let expected = u8::from_str_radix(std::str::from_utf8(&checksum).unwrap_or("00"), 16).unwrap_or(0);
+        if expected != checksum_of(&data) {
+            self.stream.write_all(b"-").map_err(|e| e.to_string())?;
+            return Ok(None);
+        }
+
+        self.stream.write_all(b"+").map_err(|e| e.to_string())?;
+        Ok(Some(String::from_utf8_lossy(&data).into_owned()))
+    }
+
+    fn read_byte(&mut self, byte: &mut [u8; 1]) -> Result<(), String> {
+        loop {
+            match self.stream.read(byte) {
+                Ok(0) => return Err(String::from("Connection closed")),
+                Ok(_) => return Ok(()),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.to_string())
+            }
+        }
+    }
+
+    fn send(&mut self, data: &str) -> Result<(), String> {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.stream.write_all(packet.as_bytes()).map_err(|e| e.to_string())?;
+
+        // Wait for the ack, resending on a nack
+        let mut byte = [0u8; 1];
+        loop {
+            self.read_byte(&mut byte)?;
+            match byte[0] {
+                b'+' => return Ok(()),
+                b'-' => self.stream.write_all(packet.as_bytes()).map_err(|e| e.to_string())?,
+                _ => {}
+            }
+        }
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+fn register_values(regs: &CPURegisters) -> [u16; 6] {
+    [
+        regs.accumulator() as u16,
+        regs.x() as u16,
+        regs.y() as u16,
+        regs.status() as u16,
+        regs.stack() & 0xFF,
+        regs.pc(),
+    ]
+}
+
+fn set_register_values(cpu: &mut CPU, values: [u16; 6]) {
+    cpu.registers = CPURegisters::from_raw(
+        values[0] as u8, values[1] as u8, values[2] as u8, values[3] as u8, values[4] as u8, values[5]);
+}
+
+fn encode_register(value: u16, size: usize) -> String {
+    (0..size).map(|i| format!("{:02x}", (value >> (8 * i)) & 0xFF)).collect()
+}
+
+fn decode_register(data: &str) -> Option<u16> {
+    let bytes = hex::decode(data.as_bytes())?;
+    Some(bytes.iter().enumerate().fold(0u16, |value, (i, b)| value | ((*b as u16) << (8 * i))))
+}
+
+fn read_registers(regs: &CPURegisters) -> String {
+    register_values(regs).iter()
+        .zip(REGISTER_SIZES.iter())
+        .map(|(value, size)| encode_register(*value, *size))
+        .collect()
+}
+
+fn write_registers(cpu: &mut CPU, data: &str) -> String {
+    let mut values = register_values(&cpu.registers);
+    let mut offset = 0;
+
+    for (i, size) in REGISTER_SIZES.iter().enumerate() {
+        match data.get(offset..offset + size * 2).and_then(decode_register) {
+            Some(value) => values[i] = value,
+            None => return String::from("E01")
+        }
+        offset += size * 2;
+    }
+
+    set_register_values(cpu, values);
+    String::from("OK")
+}
+
+fn read_register(regs: &CPURegisters, data: &str) -> String {
+    match usize::from_str_radix(data, 16) {
+        Ok(index) if index < REGISTER_SIZES.len() => encode_register(register_values(regs)[index], REGISTER_SIZES[index]),
+        _ => String::from("E01")
+    }
+}
+
+fn write_register(cpu: &mut CPU, data: &str) -> String {
+    let mut parts = data.splitn(2, '=');
+    let index = parts.next().and_then(|i| usize::from_str_radix(i, 16).ok());
+    let value = parts.next().and_then(decode_register);
+
+    match (index, value) {
+        (Some(index), Some(value)) if index < REGISTER_SIZES.len() => {
+            let mut values = register_values(&cpu.registers);
+            values[index] = value;
+            set_register_values(cpu, values);
+            String::from("OK")
+        }
+        _ => String::from("E01")
+    }
+}
+
+fn parse_address_length(data: &str) -> Option<(u16, usize)> {
+    let mut parts = data.splitn(2, ',');
+    let address = u32::from_str_radix(parts.next()?, 16).ok()?;
+    let length = usize::from_str_radix(parts.next()?, 16).ok()?;
+
+    Some((address as u16, length))
+}
+
+// At most what fits in a reply, two hex digits a byte
+fn read_memory(cpu: &CPU, data: &str) -> String {
+    match parse_address_length(data) {
+        Some((address, length)) => (0..length.min(PACKET_SIZE / 2))
+            .map(|i| format!("{:02x}", cpu.memory().peek8(address.wrapping_add(i as u16))))
+            .collect(),
+        None => String::from("E01")
+    }
+}
+
+fn write_memory(cpu: &mut CPU, data: &str) -> String {
+    let mut parts = data.splitn(2, ':');
+    let target = parts.next().and_then(parse_address_length);
+    let bytes = parts.next().and_then(|data| hex::decode(data.as_bytes()));
+
+    match (target, bytes) {
+        (Some((address, length)), Some(bytes)) if bytes.len() == length => {
+            for (i, value) in bytes.iter().enumerate() {
+                cpu.memory_mut().write8(address.wrapping_add(i as u16), *value);
+            }
+            String::from("OK")
+        }
+        _ => String::from("E01")
+    }
+}
+
+
+fn query(packet: &str) -> String {
+    if packet.starts_with("qSupported") {
+        return format!("PacketSize={:x};qXfer:features:read+", PACKET_SIZE);
+    }
+
+    if packet == "qAttached" {
+        return String::from("1");
+    }
+
+    if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+        return match parse_address_length(range) {
+            Some((offset, length)) => {
+                let offset = (offset as usize).min(TARGET_XML.len());
+                let end = (offset + length).min(TARGET_XML.len());
+                let marker = if end == TARGET_XML.len() { "l" } else { "m" };
+                format!("{}{}", marker, &TARGET_XML[offset..end])
+            }
+            None => String::from("E01")
+        };
+    }
+
+    String::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Cartridge;
+    use crate::nes::Nes;
+    use crate::ram_controller::MemoryAccess;
+    use crate::region::Region;
+    use std::thread;
+
+    // LDA #$42, LDX #$07, NOP, then JMP to itself at $8005
+    fn cartridge() -> Cartridge {
+        Cartridge::nrom(&[(0x8000, &[0xA9, 0x42, 0xA2, 0x07, 0xEA, 0x4C, 0x05, 0x80])], [0x8000, 0x8000, 0x8000])
+    }
+
+    // Steps until the debugger stops, for at most `instructions` instructions
+    fn run(nes: &mut Nes, debugger: &mut Debugger, instructions: usize) -> Option<BreakReason> {
+        for _ in 0..instructions {
+            nes.step();
+            if let Some(reason) = debugger.check(nes.cpu(), nes.ppu().scanline(), &[]) {
+                return Some(reason);
+            }
+        }
+        None
+    }
+
+    // Accepts a client on loopback, which `client` then talks to
+    fn connect<R: Send + 'static>(client: impl FnOnce(TcpStream) -> R + Send + 'static) -> (GdbStub, thread::JoinHandle<R>) {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let client = thread::spawn(move || client(TcpStream::connect(("127.0.0.1", port)).unwrap()));
+        (GdbStub::accept(&listener).unwrap(), client)
+    }
+
+    fn send_packet(stream: &mut TcpStream, data: &[u8]) {
+        let mut packet = vec![b'$'];
+        packet.extend_from_slice(data);
+        packet.extend_from_slice(format!("#{:02x}", checksum_of(data)).as_bytes());
+        stream.write_all(&packet).unwrap();
+
+        let mut ack = [0u8; 1];
+        stream.read_exact(&mut ack).unwrap();
+        assert_eq!(ack[0], b'+');
+    }
+
+    fn receive_packet(stream: &mut TcpStream) -> String {
+        let mut byte = [0u8; 1];
+        stream.read_exact(&mut byte).unwrap();
+        assert_eq!(byte[0], b'$');
+
+        let mut data = Vec::new();
+        loop {
+            stream.read_exact(&mut byte).unwrap();
+            if byte[0] == b'#' {
+                break;
+            }
+            data.push(byte[0]);
+        }
+        let mut checksum = [0u8; 2];
+        stream.read_exact(&mut checksum).unwrap();
+        assert_eq!(std::str::from_utf8(&checksum).unwrap(), format!("{:02x}", checksum_of(&data)));
+
+        stream.write_all(b"+").unwrap();
+        String::from_utf8(data).unwrap()
+    }
+
+    fn exchange(stream: &mut TcpStream, data: &[u8]) -> String {
+        send_packet(stream, data);
+        receive_packet(stream)
+    }
+
+    #[test]
+    fn serves_a_client_over_loopback() {
+        let (mut stub, client) = connect(|mut stream| {
+            let mut replies = vec![
+                exchange(&mut stream, b"?"),
+                exchange(&mut stream, b"g"),
+                exchange(&mut stream, b"m8000,3"),
+                exchange(&mut stream, b"m8000,ffff").len().to_string(),
+                exchange(&mut stream, "M10,2:a\u{e9}0".as_bytes()),
+                exchange(&mut stream, b"Z0,8005,1"),
+            ];
+            // No reply until the machine stops again
+            send_packet(&mut stream, b"c");
+            replies.push(receive_packet(&mut stream));
+            send_packet(&mut stream, b"k");
+            replies
+        });
+
+        let mut nes = Nes::new(cartridge()).unwrap();
+        let mut debugger = Debugger::new(Region::Ntsc);
+        assert!(stub.pause(&mut debugger, nes.cpu_mut(), &BreakReason::Requested) == PauseResult::Resume);
+
+        let reason = run(&mut nes, &mut debugger, 10);
+        assert!(matches!(reason, Some(BreakReason::Breakpoint(0x8005))));
+        assert!(stub.pause(&mut debugger, nes.cpu_mut(), &reason.unwrap()) == PauseResult::Quit);
+
+        let replies = client.join().unwrap();
+        assert_eq!(replies[0], "S02");
+        assert_eq!(replies[1], "000000".to_string() + &format!("{:02x}", nes.cpu().registers.status()) + "fd0080");
+        assert_eq!(replies[2], "a942a2");
+        assert_eq!(replies[3], PACKET_SIZE.to_string());
+        assert_eq!(replies[4], "E01");
+        assert_eq!(replies[5], "OK");
+        assert_eq!(replies[6], "S05");
+        assert_eq!(nes.cpu().registers.accumulator(), 0x42);
+        assert_eq!(nes.cpu().registers.x(), 0x07);
+    }
+
+    #[test]
+    fn detaching_takes_out_what_the_client_set() {
+        let (mut stub, client) = connect(|mut stream| {
+            let mut replies = vec![
+                exchange(&mut stream, b"Z0,8005,1"),
+                exchange(&mut stream, b"Z2,0300,2"),
+            ];
+            send_packet(&mut stream, b"c");
+            replies.push(receive_packet(&mut stream));
+            replies.push(exchange(&mut stream, b"D"));
+            replies
+        });
+
+        let mut nes = Nes::new(cartridge()).unwrap();
+        let mut debugger = Debugger::new(Region::Ntsc);
+        debugger.add_breakpoint(Some(0x8000), None);
+        assert!(stub.pause(&mut debugger, nes.cpu_mut(), &BreakReason::Requested) == PauseResult::Resume);
+        assert_eq!((debugger.breakpoints().len(), debugger.watchpoints().len()), (2, 1));
+
+        let reason = run(&mut nes, &mut debugger, 10);
+        assert!(matches!(reason, Some(BreakReason::Breakpoint(0x8005))));
+        assert!(stub.pause(&mut debugger, nes.cpu_mut(), &reason.unwrap()) == PauseResult::Detach);
+        assert_eq!(client.join().unwrap(), vec!["OK", "OK", "S05", "OK"]);
+
+        // Only the breakpoint that was there before the client is left, and the loop at $8005 runs on
+        assert_eq!(debugger.breakpoints()[0].address, Some(0x8000));
+        assert_eq!((debugger.breakpoints().len(), debugger.watchpoints().len()), (1, 0));
+        assert!(run(&mut nes, &mut debugger, 1000).is_none());
+    }
+
+    #[test]
+    fn watchpoints_check_their_range_and_report_their_type() {
+        let (mut stub, client) = connect(|stream| stream);
+        let _stream = client.join().unwrap();
+        let mut debugger = Debugger::new(Region::Ntsc);
+
+        // The range has to be at least a byte and end by $FFFF
+        assert_eq!(stub.update_breakpoint(&mut debugger, "2,0,10000", true), "OK");
+        assert_eq!(stub.update_breakpoint(&mut debugger, "2,8000,8001", true), "E01");
+        assert_eq!(stub.update_breakpoint(&mut debugger, "2,8000,0", true), "E01");
+        assert_eq!(stub.update_breakpoint(&mut debugger, "3,ffff,2", true), "E01");
+        assert_eq!(stub.update_breakpoint(&mut debugger, "2,0,10000", false), "OK");
+        assert!(debugger.watchpoints().is_empty());
+
+        assert_eq!(stub.update_breakpoint(&mut debugger, "3,0300,1", true), "OK");
+        assert_eq!(stub.update_breakpoint(&mut debugger, "4,0400,2", true), "OK");
+        assert_eq!(debugger.watchpoints().len(), 3);
+
+        let hit = |kind, address| BreakReason::Watchpoint(MemoryAccess { bus: Bus::Cpu, kind, address, value: 0 });
+        assert_eq!(stub.stop_reply(&hit(AccessKind::Read, 0x0300)), "T05rwatch:0300;");
+        assert_eq!(stub.stop_reply(&hit(AccessKind::Read, 0x0401)), "T05awatch:0401;");
+        assert_eq!(stub.stop_reply(&hit(AccessKind::Write, 0x0400)), "T05awatch:0400;");
+        // Set from the console rather than by the client
+        assert_eq!(stub.stop_reply(&hit(AccessKind::Write, 0x0500)), "T05watch:0500;");
+    }
+}
@@ -0,0 +1,76 @@
+// Golden image tests: run a ROM headless and compare its last frame with a checked in reference PNG.
+// A missing reference is written out to be looked at and checked in, RUSTNES_BLESS=1 rewrites them all.
+// On a mismatch the frame and a diff are written next to the reference, as name.actual.png and name.diff.png.
+
+use crate::hash;
+use crate::headless::{self, HeadlessOptions};
+use crate::palette;
+use crate::png;
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Differing pixels are drawn in this, everything else dimmed
+const DIFF_COLOR: [u8; 3] = [0xFF, 0x00, 0xFF];
+
+pub fn check(options: &HeadlessOptions, reference: &Path) -> Result<(), String> {
+    let frame = headless::run(options)?.framebuffer;
+    let bless = std::env::var_os("RUSTNES_BLESS").is_some_and(|value| value != "0");
+
+    if bless || !reference.exists() {
+        write(reference, &headless::framebuffer_png(&frame))?;
+        return if bless {
+            Ok(())
+        } else {
+            Err(format!("{} did not exist, it has been written, check that it looks right and check it in", reference.display()))
+        };
+    }
+
+    let data = fs::read(reference).map_err(|e| format!("{}: {}", reference.display(), e))?;
+    let expected = png::decode_indexed(&data).map_err(|e| format!("{}: {}", reference.display(), e))?;
+    if (expected.width, expected.height) != (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32) {
+        return Err(format!("{} is {}x{}, not {}x{}", reference.display(), expected.width, expected.height, SCREEN_WIDTH, SCREEN_HEIGHT));
+    }
+
+    let differing = frame.iter().zip(&expected.pixels).filter(|(a, b)| (*a & 0x3F) != (*b & 0x3F)).count();
+    if differing == 0 {
+        return Ok(());
+    }
+
+    let actual_path = sibling(reference, "actual");
+    let diff_path = sibling(reference, "diff");
+    write(&actual_path, &headless::framebuffer_png(&frame))?;
+    write(&diff_path, &diff_png(&expected.pixels, &frame))?;
+
+    Err(format!(
+        "{} differs in {} pixels (frame CRC32 {:08X}, reference {:08X}), see {} and {}",
+        reference.display(),
+        differing,
+        hash::crc32(&frame),
+        hash::crc32(&expected.pixels),
+        actual_path.display(),
+        diff_path.display()
+    ))
+}
+
+fn diff_png(expected: &[u8], actual: &[u8]) -> Vec<u8> {
+    let mut rgb = vec![];
+    for (a, b) in expected.iter().zip(actual) {
+        if (a & 0x3F) == (b & 0x3F) {
+            rgb.extend(palette::NTSC[(a & 0x3F) as usize].iter().map(|c| c / 4));
+        } else {
+            rgb.extend_from_slice(&DIFF_COLOR);
+        }
+    }
+    png::encode_rgb(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32, &rgb)
+}
+
+// name.png to name.<suffix>.png
+fn sibling(reference: &Path, suffix: &str) -> PathBuf {
+    let stem = reference.file_stem().unwrap_or_default().to_string_lossy();
+    reference.with_file_name(format!("{}.{}.png", stem, suffix))
+}
+
+fn write(path: &Path, data: &[u8]) -> Result<(), String> {
+    fs::write(path, data).map_err(|e| format!("{}: {}", path.display(), e))
+}
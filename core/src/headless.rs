@@ -0,0 +1,188 @@
+use crate::cartridge::Cartridge;
+use crate::movie::{FrameInput, Movie, COMMAND_POWER, COMMAND_SOFT_RESET};
+use crate::golden;
+use crate::nes::Nes;
+use crate::ntsc_filter::{self, NtscFilter, NtscSetup};
+use crate::palette::{self, Palette};
+use crate::png;
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::recorder::{Recorder, WavWriter};
+use crate::region::Region;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+pub struct HeadlessOptions {
+    pub rom: String,
+    pub frames: u64,
+    // Input for each frame from the start, frames past the end have nothing pressed
+    pub input: Vec<FrameInput>,
+    // Where a movie that starts from a save state starts, power on when None
+    pub state: Option<Vec<u8>>,
+    // From the ROM header when not given
+    pub region: Option<Region>,
+}
+
+pub struct HeadlessResult {
+    // The last complete frame, one 6-bit palette index per pixel
+    pub framebuffer: Vec<u8>,
+    // The emphasis bits of each line of that frame, in palette::EMPHASIS_* order
+    pub emphasis: Vec<u8>,
+    // Every sample the APU put out, mono at apu::SAMPLE_RATE
+    pub audio: Vec<f32>,
+    // The 2KB of internal RAM
+    pub ram: Vec<u8>,
+}
+
+// Powers on the ROM, loads the starting state if there is one and runs it for `frames` frames,
+// without a window or any timing
+pub fn run(options: &HeadlessOptions) -> Result<HeadlessResult, String> {
+    run_with(options, |_, _| Ok(()))
+}
+
+// Like run(), calling `each_frame` after every frame with the console and the samples of that frame
+pub fn run_with(options: &HeadlessOptions, mut each_frame: impl FnMut(&Nes, &[f32]) -> Result<(), String>) -> Result<HeadlessResult, String> {
+    let cartridge = Cartridge::load(&options.rom)?;
+    let region = options.region.unwrap_or_else(|| cartridge.region());
+    let mut nes = Nes::with_region(cartridge, region)?;
+    if let Some(state) = &options.state {
+        nes.load_state(state)?;
+    }
+    let start_frame = nes.frame();
+    let mut audio = vec![];
+
+    while nes.frame() - start_frame < options.frames {
+        let input = options.input.get((nes.frame() - start_frame) as usize).copied().unwrap_or_default();
+        if input.commands & COMMAND_POWER != 0 {
+            nes.power_cycle();
+        } else if input.commands & COMMAND_SOFT_RESET != 0 {
+            nes.reset();
+        }
+        nes.set_input(0, input.ports[0]);
+        nes.set_input(1, input.ports[1]);
+
+        nes.run_frame();
+        if let Some(jam) = nes.cpu().jam() {
+            return Err(format!("The CPU jammed on opcode ${:02X} at ${:04X} in frame {}", jam.opcode, jam.address, nes.frame()));
+        }
+        let samples = nes.audio_samples();
+        each_frame(&nes, &samples)?;
+        audio.extend(samples);
+    }
+
+    Ok(HeadlessResult {
+        framebuffer: nes.framebuffer().to_vec(),
+        emphasis: nes.emphasis().to_vec(),
+        audio,
+        ram: (0..0x800).map(|address| nes.cpu().memory().peek8(address)).collect(),
+    })
+}
+
+// `rustnes headless <rom> [--frames N] [--region ntsc|pal|dendy] [--input movie.fm2] [--framebuffer file] [--png file.png] [--audio file.wav]
+//     [--ram file] [--golden reference.png] [--ntsc-filter composite|svideo|rgb|monochrome] [--video file.y4m] [--avi file.avi]`
+// The framebuffer is written as 256x240 raw palette indices, the RAM as its raw 2KB.
+// With --ntsc-filter the PNG is the 602 pixel wide filtered frame instead, and so is every frame of
+// the --video and --avi recordings.
+// --golden compares the last frame with a reference image instead, see golden::check.
+pub fn run_cli(args: &[String]) -> Result<(), String> {
+    let mut rom = None;
+    let mut frames = 60;
+    let mut input = vec![];
+    let mut state = None;
+    let mut region = None;
+    let mut framebuffer_path = None;
+    let mut png_path = None;
+    let mut audio_path = None;
+    let mut ram_path = None;
+    let mut golden_path = None;
+    let mut ntsc_setup = None;
+    let mut video_path = None;
+    let mut avi_path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--frames" => frames = value()?.parse().map_err(|_| String::from("--frames has to be a number"))?,
+            "--region" => region = Some(Region::from_name(&value()?)?),
+            "--input" => {
+                let movie = Movie::load_fm2(&value()?)?;
+                input = movie.frames;
+                state = movie.savestate;
+            }
+            "--framebuffer" => framebuffer_path = Some(value()?),
+            "--png" => png_path = Some(value()?),
+            "--audio" => audio_path = Some(value()?),
+            "--ram" => ram_path = Some(value()?),
+            "--golden" => golden_path = Some(value()?),
+            "--ntsc-filter" => ntsc_setup = Some(NtscSetup::from_name(&value()?)?),
+            "--video" => video_path = Some(value()?),
+            "--avi" => avi_path = Some(value()?),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ => rom = Some(arg.clone())
+        }
+    }
+
+    let rom = rom.ok_or("No ROM given")?;
+    let options = HeadlessOptions { rom, frames, input, state, region };
+    if let Some(path) = golden_path {
+        return golden::check(&options, Path::new(&path));
+    }
+    let result = if video_path.is_some() || avi_path.is_some() {
+        let region = match options.region {
+            Some(region) => region,
+            None => Cartridge::load(&options.rom)?.region(),
+        };
+        let width = if ntsc_setup.is_some() { ntsc_filter::OUTPUT_WIDTH } else { SCREEN_WIDTH } as u32;
+        let mut recorder = Recorder::create(video_path.as_deref(), None, avi_path.as_deref(), width, SCREEN_HEIGHT as u32, region)?;
+        let palette = Palette::new();
+        let mut filter = ntsc_setup.map(NtscFilter::new);
+
+        let result = run_with(&options, |nes, samples| {
+            let rgb = match filter.as_mut() {
+                Some(filter) => filter.apply(nes.framebuffer(), nes.emphasis(), nes.frame()),
+                None => palette.frame_to_rgb(nes.framebuffer(), nes.emphasis()),
+            };
+            recorder.frame(&rgb, samples)
+        })?;
+        recorder.finish()?;
+        result
+    } else {
+        run(&options)?
+    };
+
+    if let Some(path) = framebuffer_path {
+        fs::write(&path, &result.framebuffer).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    if let Some(path) = png_path {
+        let data = match ntsc_setup {
+            Some(setup) => {
+                let rgb = NtscFilter::new(setup).apply(&result.framebuffer, &result.emphasis, frames);
+                png::encode_rgb(ntsc_filter::OUTPUT_WIDTH as u32, SCREEN_HEIGHT as u32, &rgb)
+            }
+            None => framebuffer_png(&result.framebuffer),
+        };
+        fs::write(&path, data).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    if let Some(path) = audio_path {
+        fs::write(&path, wav(&result.audio)?).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    if let Some(path) = ram_path {
+        fs::write(&path, &result.ram).map_err(|e| format!("{}: {}", path, e))?;
+    }
+
+    Ok(())
+}
+
+// An indexed PNG that keeps the palette indices, with the NTSC colours to look at
+pub fn framebuffer_png(framebuffer: &[u8]) -> Vec<u8> {
+    let pixels: Vec<u8> = framebuffer.iter().map(|index| index & 0x3F).collect();
+    png::encode_indexed(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32, &palette::NTSC, &pixels)
+}
+
+// 16-bit mono PCM
+fn wav(samples: &[f32]) -> Result<Vec<u8>, String> {
+    let mut writer = WavWriter::new(Cursor::new(vec![]))?;
+    writer.write(samples)?;
+    Ok(writer.finish()?.into_inner())
+}
@@ -0,0 +1,27 @@
+// Two hex digits a byte, in either case. Works on bytes, so text with anything other than hex
+// digits in it is refused instead of being cut in the middle of a character.
+pub fn decode(hex: &[u8]) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    let digit = |byte: u8| (byte as char).to_digit(16);
+    hex.chunks(2)
+        .map(|pair| Some((digit(pair[0])? << 4 | digit(pair[1])?) as u8))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_pairs_of_digits() {
+        assert_eq!(decode(b"00ff7A"), Some(vec![0x00, 0xFF, 0x7A]));
+        assert_eq!(decode(b""), Some(vec![]));
+        assert_eq!(decode(b"abc"), None);
+        assert_eq!(decode(b"0g"), None);
+        assert_eq!(decode(b"+1"), None);
+        assert_eq!(decode("a\u{e9}0".as_bytes()), None);
+    }
+}
@@ -0,0 +1,326 @@
+use crate::cpuregisters::CPURegisters;
+use crate::opcodes;
+use crate::ram_controller::RamController;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AddressingMode {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Relative,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndexedIndirect,
+    IndirectIndexed,
+}
+
+type Execute = fn(&mut CPURegisters, &mut RamController) -> i32;
+
+// One entry per opcode. This is what the CPU dispatches on, and what the disassembler and
+// the debugging tools use to describe an instruction.
+pub struct Instruction {
+    pub mnemonic: &'static str,
+    pub mode: AddressingMode,
+    pub cycles: i32,
+    pub official: bool,
+    pub execute: Option<Execute>,
+}
+
+use AddressingMode::*;
+
+const fn official(mnemonic: &'static str, mode: AddressingMode, cycles: i32, execute: Execute) -> Instruction {
+    Instruction { mnemonic, mode, cycles, official: true, execute: Some(execute) }
+}
+
+const fn unofficial(mnemonic: &'static str, mode: AddressingMode, cycles: i32, execute: Execute) -> Instruction {
+    Instruction { mnemonic, mode, cycles, official: false, execute: Some(execute) }
+}
+
+// Unofficial opcodes that the CPU does not implement (yet)
+const fn unsupported(mnemonic: &'static str, mode: AddressingMode, cycles: i32) -> Instruction {
+    Instruction { mnemonic, mode, cycles, official: false, execute: None }
+}
+
+impl AddressingMode {
+    pub fn operand_length(&self) -> u16 {
+        match self {
+            Implied | Accumulator => 0,
+            Absolute | AbsoluteX | AbsoluteY | Indirect => 2,
+            _ => 1
+        }
+    }
+}
+
+impl Instruction {
+    pub fn length(&self) -> u16 {
+        1 + self.mode.operand_length()
+    }
+}
+
+pub fn lookup(opcode: u8) -> &'static Instruction {
+    &INSTRUCTIONS[opcode as usize]
+}
+
+static INSTRUCTIONS: [Instruction; 256] = [
+    /* 00 */ official("BRK", Implied, 7, opcodes::brk_implied),
+    /* 01 */ official("ORA", IndexedIndirect, 6, |regs, mem| opcodes::ora_indirect_x(regs, mem)),
+    /* 02 */ unsupported("JAM", Implied, 2),
+    /* 03 */ unofficial("SLO", IndexedIndirect, 8, opcodes::slo_indirect_x),
+    /* 04 */ unofficial("NOP", ZeroPage, 3, |regs, mem| opcodes::nop_zero_page(regs, mem)),
+    /* 05 */ official("ORA", ZeroPage, 3, |regs, mem| opcodes::ora_zero_page(regs, mem)),
+    /* 06 */ official("ASL", ZeroPage, 5, opcodes::asl_zero_page),
+    /* 07 */ unofficial("SLO", ZeroPage, 5, opcodes::slo_zero_page),
+    /* 08 */ official("PHP", Implied, 3, opcodes::php_implied),
+    /* 09 */ official("ORA", Immediate, 2, |regs, mem| opcodes::ora_immediate(regs, mem)),
+    /* 0A */ official("ASL", Accumulator, 2, |regs, _| opcodes::asl_accumulator(regs)),
+    /* 0B */ unsupported("ANC", Immediate, 2),
+    /* 0C */ unofficial("NOP", Absolute, 4, |regs, mem| opcodes::nop_absolute(regs, mem)),
+    /* 0D */ official("ORA", Absolute, 4, |regs, mem| opcodes::ora_absolute(regs, mem)),
+    /* 0E */ official("ASL", Absolute, 6, opcodes::asl_absolute),
+    /* 0F */ unofficial("SLO", Absolute, 6, opcodes::slo_absolute),
+    /* 10 */ official("BPL", Relative, 2, |regs, mem| opcodes::bpl_relative(regs, mem)),
+    /* 11 */ official("ORA", IndirectIndexed, 5, |regs, mem| opcodes::ora_indirect_y(regs, mem)),
+    /* 12 */ unsupported("JAM", Implied, 2),
+    /* 13 */ unofficial("SLO", IndirectIndexed, 8, opcodes::slo_indirect_y),
+    /* 14 */ unofficial("NOP", ZeroPageX, 4, |regs, mem| opcodes::nop_zero_page_x(regs, mem)),
+    /* 15 */ official("ORA", ZeroPageX, 4, |regs, mem| opcodes::ora_zero_page_x(regs, mem)),
+    /* 16 */ official("ASL", ZeroPageX, 6, opcodes::asl_zero_page_x),
+    /* 17 */ unofficial("SLO", ZeroPageX, 6, opcodes::slo_zero_page_x),
+    /* 18 */ official("CLC", Implied, 2, |regs, _| opcodes::clc_implied(regs)),
+    /* 19 */ official("ORA", AbsoluteY, 4, |regs, mem| opcodes::ora_absolute_y(regs, mem)),
+    /* 1A */ unofficial("NOP", Implied, 2, |_, _| opcodes::nop_implied()),
+    /* 1B */ unofficial("SLO", AbsoluteY, 7, opcodes::slo_absolute_y),
+    /* 1C */ unofficial("NOP", AbsoluteX, 4, |regs, mem| opcodes::nop_absolute_x(regs, mem)),
+    /* 1D */ official("ORA", AbsoluteX, 4, |regs, mem| opcodes::ora_absolute_x(regs, mem)),
+    /* 1E */ official("ASL", AbsoluteX, 7, opcodes::asl_absolute_x),
+    /* 1F */ unofficial("SLO", AbsoluteX, 7, opcodes::slo_absolute_x),
+    /* 20 */ official("JSR", Absolute, 6, opcodes::jsr_absolute),
+    /* 21 */ official("AND", IndexedIndirect, 6, |regs, mem| opcodes::and_indirect_x(regs, mem)),
+    /* 22 */ unsupported("JAM", Implied, 2),
+    /* 23 */ unofficial("RLA", IndexedIndirect, 8, opcodes::rla_indirect_x),
+    /* 24 */ official("BIT", ZeroPage, 3, |regs, mem| opcodes::bit_zero_page(regs, mem)),
+    /* 25 */ official("AND", ZeroPage, 3, |regs, mem| opcodes::and_zero_page(regs, mem)),
+    /* 26 */ official("ROL", ZeroPage, 5, opcodes::rol_zero_page),
+    /* 27 */ unofficial("RLA", ZeroPage, 5, opcodes::rla_zero_page),
+    /* 28 */ official("PLP", Implied, 4, opcodes::plp_implied),
+    /* 29 */ official("AND", Immediate, 2, |regs, mem| opcodes::and_immediate(regs, mem)),
+    /* 2A */ official("ROL", Accumulator, 2, |regs, _| opcodes::rol_accumulator(regs)),
+    /* 2B */ unsupported("ANC", Immediate, 2),
+    /* 2C */ official("BIT", Absolute, 4, |regs, mem| opcodes::bit_absolute(regs, mem)),
+    /* 2D */ official("AND", Absolute, 4, |regs, mem| opcodes::and_absolute(regs, mem)),
+    /* 2E */ official("ROL", Absolute, 6, opcodes::rol_absolute),
+    /* 2F */ unofficial("RLA", Absolute, 6, opcodes::rla_absolute),
+    /* 30 */ official("BMI", Relative, 2, |regs, mem| opcodes::bmi_relative(regs, mem)),
+    /* 31 */ official("AND", IndirectIndexed, 5, |regs, mem| opcodes::and_indirect_y(regs, mem)),
+    /* 32 */ unsupported("JAM", Implied, 2),
+    /* 33 */ unofficial("RLA", IndirectIndexed, 8, opcodes::rla_indirect_y),
+    /* 34 */ unofficial("NOP", ZeroPageX, 4, |regs, mem| opcodes::nop_zero_page_x(regs, mem)),
+    /* 35 */ official("AND", ZeroPageX, 4, |regs, mem| opcodes::and_zero_page_x(regs, mem)),
+    /* 36 */ official("ROL", ZeroPageX, 6, opcodes::rol_zero_page_x),
+    /* 37 */ unofficial("RLA", ZeroPageX, 6, opcodes::rla_zero_page_x),
+    /* 38 */ official("SEC", Implied, 2, |regs, _| opcodes::sec_implied(regs)),
+    /* 39 */ official("AND", AbsoluteY, 4, |regs, mem| opcodes::and_absolute_y(regs, mem)),
+    /* 3A */ unofficial("NOP", Implied, 2, |_, _| opcodes::nop_implied()),
+    /* 3B */ unofficial("RLA", AbsoluteY, 7, opcodes::rla_absolute_y),
+    /* 3C */ unofficial("NOP", AbsoluteX, 4, |regs, mem| opcodes::nop_absolute_x(regs, mem)),
+    /* 3D */ official("AND", AbsoluteX, 4, |regs, mem| opcodes::and_absolute_x(regs, mem)),
+    /* 3E */ official("ROL", AbsoluteX, 7, opcodes::rol_absolute_x),
+    /* 3F */ unofficial("RLA", AbsoluteX, 7, opcodes::rla_absolute_x),
+    /* 40 */ official("RTI", Implied, 6, |regs, mem| opcodes::rti_implied(regs, mem)),
+    /* 41 */ official("EOR", IndexedIndirect, 6, |regs, mem| opcodes::eor_indirect_x(regs, mem)),
+    /* 42 */ unsupported("JAM", Implied, 2),
+    /* 43 */ unofficial("SRE", IndexedIndirect, 8, opcodes::sre_indirect_x),
+    /* 44 */ unofficial("NOP", ZeroPage, 3, |regs, mem| opcodes::nop_zero_page(regs, mem)),
+    /* 45 */ official("EOR", ZeroPage, 3, |regs, mem| opcodes::eor_zero_page(regs, mem)),
+    /* 46 */ official("LSR", ZeroPage, 5, opcodes::lsr_zero_page),
+    /* 47 */ unofficial("SRE", ZeroPage, 5, opcodes::sre_zero_page),
+    /* 48 */ official("PHA", Implied, 3, opcodes::pha_implied),
+    /* 49 */ official("EOR", Immediate, 2, |regs, mem| opcodes::eor_immediate(regs, mem)),
+    /* 4A */ official("LSR", Accumulator, 2, |regs, _| opcodes::lsr_accumulator(regs)),
+    /* 4B */ unsupported("ALR", Immediate, 2),
+    /* 4C */ official("JMP", Absolute, 3, |regs, mem| opcodes::jmp_absolute(regs, mem)),
+    /* 4D */ official("EOR", Absolute, 4, |regs, mem| opcodes::eor_absolute(regs, mem)),
+    /* 4E */ official("LSR", Absolute, 6, opcodes::lsr_absolute),
+    /* 4F */ unofficial("SRE", Absolute, 6, opcodes::sre_absolute),
+    /* 50 */ official("BVC", Relative, 2, |regs, mem| opcodes::bvc_relative(regs, mem)),
+    /* 51 */ official("EOR", IndirectIndexed, 5, |regs, mem| opcodes::eor_indirect_y(regs, mem)),
+    /* 52 */ unsupported("JAM", Implied, 2),
+    /* 53 */ unofficial("SRE", IndirectIndexed, 8, opcodes::sre_indirect_y),
+    /* 54 */ unofficial("NOP", ZeroPageX, 4, |regs, mem| opcodes::nop_zero_page_x(regs, mem)),
+    /* 55 */ official("EOR", ZeroPageX, 4, |regs, mem| opcodes::eor_zero_page_x(regs, mem)),
+    /* 56 */ official("LSR", ZeroPageX, 6, opcodes::lsr_zero_page_x),
+    /* 57 */ unofficial("SRE", ZeroPageX, 6, opcodes::sre_zero_page_x),
+    /* 58 */ official("CLI", Implied, 2, |regs, _| opcodes::cli_implied(regs)),
+    /* 59 */ official("EOR", AbsoluteY, 4, |regs, mem| opcodes::eor_absolute_y(regs, mem)),
+    /* 5A */ unofficial("NOP", Implied, 2, |_, _| opcodes::nop_implied()),
+    /* 5B */ unofficial("SRE", AbsoluteY, 7, opcodes::sre_absolute_y),
+    /* 5C */ unofficial("NOP", AbsoluteX, 4, |regs, mem| opcodes::nop_absolute_x(regs, mem)),
+    /* 5D */ official("EOR", AbsoluteX, 4, |regs, mem| opcodes::eor_absolute_x(regs, mem)),
+    /* 5E */ official("LSR", AbsoluteX, 7, opcodes::lsr_absolute_x),
+    /* 5F */ unofficial("SRE", AbsoluteX, 7, opcodes::sre_absolute_x),
+    /* 60 */ official("RTS", Implied, 6, |regs, mem| opcodes::rts_implied(regs, mem)),
+    /* 61 */ official("ADC", IndexedIndirect, 6, |regs, mem| opcodes::adc_indirect_x(regs, mem)),
+    /* 62 */ unsupported("JAM", Implied, 2),
+    /* 63 */ unofficial("RRA", IndexedIndirect, 8, opcodes::rra_indirect_x),
+    /* 64 */ unofficial("NOP", ZeroPage, 3, |regs, mem| opcodes::nop_zero_page(regs, mem)),
+    /* 65 */ official("ADC", ZeroPage, 3, |regs, mem| opcodes::adc_zero_page(regs, mem)),
+    /* 66 */ official("ROR", ZeroPage, 5, opcodes::ror_zero_page),
+    /* 67 */ unofficial("RRA", ZeroPage, 5, opcodes::rra_zero_page),
+    /* 68 */ official("PLA", Implied, 4, |regs, mem| opcodes::pla_implied(regs, mem)),
+    /* 69 */ official("ADC", Immediate, 2, |regs, mem| opcodes::adc_immediate(regs, mem)),
+    /* 6A */ official("ROR", Accumulator, 2, |regs, _| opcodes::ror_accumulator(regs)),
+    /* 6B */ unsupported("ARR", Immediate, 2),
+    /* 6C */ official("JMP", Indirect, 5, |regs, mem| opcodes::jmp_indirect(regs, mem)),
+    /* 6D */ official("ADC", Absolute, 4, |regs, mem| opcodes::adc_absolute(regs, mem)),
+    /* 6E */ official("ROR", Absolute, 6, opcodes::ror_absolute),
+    /* 6F */ unofficial("RRA", Absolute, 6, opcodes::rra_absolute),
+    /* 70 */ official("BVS", Relative, 2, |regs, mem| opcodes::bvs_relative(regs, mem)),
+    /* 71 */ official("ADC", IndirectIndexed, 5, |regs, mem| opcodes::adc_indirect_y(regs, mem)),
+    /* 72 */ unsupported("JAM", Implied, 2),
+    /* 73 */ unofficial("RRA", IndirectIndexed, 8, opcodes::rra_indirect_y),
+    /* 74 */ unofficial("NOP", ZeroPageX, 4, |regs, mem| opcodes::nop_zero_page_x(regs, mem)),
+    /* 75 */ official("ADC", ZeroPageX, 4, |regs, mem| opcodes::adc_zero_page_x(regs, mem)),
+    /* 76 */ official("ROR", ZeroPageX, 6, opcodes::ror_zero_page_x),
+    /* 77 */ unofficial("RRA", ZeroPageX, 6, opcodes::rra_zero_page_x),
+    /* 78 */ official("SEI", Implied, 2, |regs, _| opcodes::sei_implied(regs)),
+    /* 79 */ official("ADC", AbsoluteY, 4, |regs, mem| opcodes::adc_absolute_y(regs, mem)),
+    /* 7A */ unofficial("NOP", Implied, 2, |_, _| opcodes::nop_implied()),
+    /* 7B */ unofficial("RRA", AbsoluteY, 7, opcodes::rra_absolute_y),
+    /* 7C */ unofficial("NOP", AbsoluteX, 4, |regs, mem| opcodes::nop_absolute_x(regs, mem)),
+    /* 7D */ official("ADC", AbsoluteX, 4, |regs, mem| opcodes::adc_absolute_x(regs, mem)),
+    /* 7E */ official("ROR", AbsoluteX, 7, opcodes::ror_absolute_x),
+    /* 7F */ unofficial("RRA", AbsoluteX, 7, opcodes::rra_absolute_x),
+    /* 80 */ unofficial("NOP", Immediate, 2, |regs, mem| opcodes::nop_immediate(regs, mem)),
+    /* 81 */ official("STA", IndexedIndirect, 6, opcodes::sta_indirect_x),
+    /* 82 */ unsupported("NOP", Immediate, 2),
+    /* 83 */ unofficial("SAX", IndexedIndirect, 6, opcodes::sax_indirect_x),
+    /* 84 */ official("STY", ZeroPage, 3, opcodes::sty_zero_page),
+    /* 85 */ official("STA", ZeroPage, 3, opcodes::sta_zero_page),
+    /* 86 */ official("STX", ZeroPage, 3, opcodes::stx_zero_page),
+    /* 87 */ unofficial("SAX", ZeroPage, 3, opcodes::sax_zero_page),
+    /* 88 */ official("DEY", Implied, 2, |regs, _| opcodes::dey_implied(regs)),
+    /* 89 */ unsupported("NOP", Immediate, 2),
+    /* 8A */ official("TXA", Implied, 2, |regs, _| opcodes::txa_implied(regs)),
+    /* 8B */ unsupported("ANE", Immediate, 2),
+    /* 8C */ official("STY", Absolute, 4, opcodes::sty_absolute),
+    /* 8D */ official("STA", Absolute, 4, opcodes::sta_absolute),
+    /* 8E */ official("STX", Absolute, 4, opcodes::stx_absolute),
+    /* 8F */ unofficial("SAX", Absolute, 4, opcodes::sax_absolute),
+    /* 90 */ official("BCC", Relative, 2, |regs, mem| opcodes::bcc_relative(regs, mem)),
+    /* 91 */ official("STA", IndirectIndexed, 6, opcodes::sta_indirect_y),
+    /* 92 */ unsupported("JAM", Implied, 2),
+    /* 93 */ unsupported("SHA", IndirectIndexed, 6),
+    /* 94 */ official("STY", ZeroPageX, 4, opcodes::sty_zero_page_x),
+    /* 95 */ official("STA", ZeroPageX, 4, opcodes::sta_zero_page_x),
+    /* 96 */ official("STX", ZeroPageY, 4, opcodes::stx_zero_page_y),
+    /* 97 */ unofficial("SAX", ZeroPageY, 4, opcodes::sax_zero_page_y),
+    /* 98 */ official("TYA", Implied, 2, |regs, _| opcodes::tya_implied(regs)),
+    /* 99 */ official("STA", AbsoluteY, 5, opcodes::sta_absolute_y),
+    /* 9A */ official("TXS", Implied, 2, |regs, _| opcodes::txs_implied(regs)),
+    /* 9B */ unsupported("TAS", AbsoluteY, 5),
+    /* 9C */ unsupported("SHY", AbsoluteX, 5),
+    /* 9D */ official("STA", AbsoluteX, 5, opcodes::sta_absolute_x),
+    /* 9E */ unsupported("SHX", AbsoluteY, 5),
+    /* 9F */ unsupported("SHA", AbsoluteY, 5),
+    /* A0 */ official("LDY", Immediate, 2, |regs, mem| opcodes::ldy_immediate(regs, mem)),
+    /* A1 */ official("LDA", IndexedIndirect, 6, |regs, mem| opcodes::lda_indirect_x(regs, mem)),
+    /* A2 */ official("LDX", Immediate, 2, |regs, mem| opcodes::ldx_immediate(regs, mem)),
+    /* A3 */ unofficial("LAX", IndexedIndirect, 6, |regs, mem| opcodes::lax_indirect_x(regs, mem)),
+    /* A4 */ official("LDY", ZeroPage, 3, |regs, mem| opcodes::ldy_zero_page(regs, mem)),
+    /* A5 */ official("LDA", ZeroPage, 3, |regs, mem| opcodes::lda_zero_page(regs, mem)),
+    /* A6 */ official("LDX", ZeroPage, 3, |regs, mem| opcodes::ldx_zero_page(regs, mem)),
+    /* A7 */ unofficial("LAX", ZeroPage, 3, |regs, mem| opcodes::lax_zero_page(regs, mem)),
+    /* A8 */ official("TAY", Implied, 2, |regs, _| opcodes::tay_implied(regs)),
+    /* A9 */ official("LDA", Immediate, 2, |regs, mem| opcodes::lda_immediate(regs, mem)),
+    /* AA */ official("TAX", Implied, 2, |regs, _| opcodes::tax_implied(regs)),
+    /* AB */ unsupported("LXA", Immediate, 2),
+    /* AC */ official("LDY", Absolute, 4, |regs, mem| opcodes::ldy_absolute(regs, mem)),
+    /* AD */ official("LDA", Absolute, 4, |regs, mem| opcodes::lda_absolute(regs, mem)),
+    /* AE */ official("LDX", Absolute, 4, |regs, mem| opcodes::ldx_absolute(regs, mem)),
+    /* AF */ unofficial("LAX", Absolute, 4, |regs, mem| opcodes::lax_absolute(regs, mem)),
+    /* B0 */ official("BCS", Relative, 2, |regs, mem| opcodes::bcs_relative(regs, mem)),
+    /* B1 */ official("LDA", IndirectIndexed, 5, |regs, mem| opcodes::lda_indirect_y(regs, mem)),
+    /* B2 */ unsupported("JAM", Implied, 2),
+    /* B3 */ unofficial("LAX", IndirectIndexed, 5, |regs, mem| opcodes::lax_indirect_y(regs, mem)),
+    /* B4 */ official("LDY", ZeroPageX, 4, |regs, mem| opcodes::ldy_zero_page_x(regs, mem)),
+    /* B5 */ official("LDA", ZeroPageX, 4, |regs, mem| opcodes::lda_zero_page_x(regs, mem)),
+    /* B6 */ official("LDX", ZeroPageY, 4, |regs, mem| opcodes::ldx_zero_page_y(regs, mem)),
+    /* B7 */ unofficial("LAX", ZeroPageY, 4, |regs, mem| opcodes::lax_zero_page_y(regs, mem)),
+    /* B8 */ official("CLV", Implied, 2, |regs, _| opcodes::clv_implied(regs)),
+    /* B9 */ official("LDA", AbsoluteY, 4, |regs, mem| opcodes::lda_absolute_y(regs, mem)),
+    /* BA */ official("TSX", Implied, 2, |regs, _| opcodes::tsx_implied(regs)),
+    /* BB */ unsupported("LAS", AbsoluteY, 4),
+    /* BC */ official("LDY", AbsoluteX, 4, |regs, mem| opcodes::ldy_absolute_x(regs, mem)),
+    /* BD */ official("LDA", AbsoluteX, 4, |regs, mem| opcodes::lda_absolute_x(regs, mem)),
+    /* BE */ official("LDX", AbsoluteY, 4, |regs, mem| opcodes::ldx_absolute_y(regs, mem)),
+    /* BF */ unofficial("LAX", AbsoluteY, 4, |regs, mem| opcodes::lax_absolute_y(regs, mem)),
+    /* C0 */ official("CPY", Immediate, 2, |regs, mem| opcodes::cpy_immediate(regs, mem)),
+    /* C1 */ official("CMP", IndexedIndirect, 6, |regs, mem| opcodes::cmp_indirect_x(regs, mem)),
+    /* C2 */ unsupported("NOP", Immediate, 2),
+    /* C3 */ unofficial("DCP", IndexedIndirect, 8, opcodes::dcp_indirect_x),
+    /* C4 */ official("CPY", ZeroPage, 3, |regs, mem| opcodes::cpy_zero_page(regs, mem)),
+    /* C5 */ official("CMP", ZeroPage, 3, |regs, mem| opcodes::cmp_zero_page(regs, mem)),
+    /* C6 */ official("DEC", ZeroPage, 5, opcodes::dec_zero_page),
+    /* C7 */ unofficial("DCP", ZeroPage, 5, opcodes::dcp_zero_page),
+    /* C8 */ official("INY", Implied, 2, |regs, _| opcodes::iny_implied(regs)),
+    /* C9 */ official("CMP", Immediate, 2, |regs, mem| opcodes::cmp_immediate(regs, mem)),
+    /* CA */ official("DEX", Implied, 2, |regs, _| opcodes::dex_implied(regs)),
+    /* CB */ unsupported("SBX", Immediate, 2),
+    /* CC */ official("CPY", Absolute, 4, |regs, mem| opcodes::cpy_absolute(regs, mem)),
+    /* CD */ official("CMP", Absolute, 4, |regs, mem| opcodes::cmp_absolute(regs, mem)),
+    /* CE */ official("DEC", Absolute, 6, opcodes::dec_absolute),
+    /* CF */ unofficial("DCP", Absolute, 6, opcodes::dcp_absolute),
+    /* D0 */ official("BNE", Relative, 2, |regs, mem| opcodes::bne_relative(regs, mem)),
+    /* D1 */ official("CMP", IndirectIndexed, 5, |regs, mem| opcodes::cmp_indirect_y(regs, mem)),
+    /* D2 */ unsupported("JAM", Implied, 2),
+    /* D3 */ unofficial("DCP", IndirectIndexed, 8, opcodes::dcp_indirect_y),
+    /* D4 */ unofficial("NOP", ZeroPageX, 4, |regs, mem| opcodes::nop_zero_page_x(regs, mem)),
+    /* D5 */ official("CMP", ZeroPageX, 4, |regs, mem| opcodes::cmp_zero_page_x(regs, mem)),
+    /* D6 */ official("DEC", ZeroPageX, 6, opcodes::dec_zero_page_x),
+    /* D7 */ unofficial("DCP", ZeroPageX, 6, opcodes::dcp_zero_page_x),
+    /* D8 */ official("CLD", Implied, 2, |regs, _| opcodes::cld_implied(regs)),
+    /* D9 */ official("CMP", AbsoluteY, 4, |regs, mem| opcodes::cmp_absolute_y(regs, mem)),
+    /* DA */ unofficial("NOP", Implied, 2, |_, _| opcodes::nop_implied()),
+    /* DB */ unofficial("DCP", AbsoluteY, 7, opcodes::dcp_absolute_y),
+    /* DC */ unofficial("NOP", AbsoluteX, 4, |regs, mem| opcodes::nop_absolute_x(regs, mem)),
+    /* DD */ official("CMP", AbsoluteX, 4, |regs, mem| opcodes::cmp_absolute_x(regs, mem)),
+    /* DE */ official("DEC", AbsoluteX, 7, opcodes::dec_absolute_x),
+    /* DF */ unofficial("DCP", AbsoluteX, 7, opcodes::dcp_absolute_x),
+    /* E0 */ official("CPX", Immediate, 2, |regs, mem| opcodes::cpx_immediate(regs, mem)),
+    /* E1 */ official("SBC", IndexedIndirect, 6, |regs, mem| opcodes::sbc_indirect_x(regs, mem)),
+    /* E2 */ unsupported("NOP", Immediate, 2),
+    /* E3 */ unofficial("ISB", IndexedIndirect, 8, opcodes::isc_indirect_x),
+    /* E4 */ official("CPX", ZeroPage, 3, |regs, mem| opcodes::cpx_zero_page(regs, mem)),
+    /* E5 */ official("SBC", ZeroPage, 3, |regs, mem| opcodes::sbc_zero_page(regs, mem)),
+    /* E6 */ official("INC", ZeroPage, 5, opcodes::inc_zero_page),
+    /* E7 */ unofficial("ISB", ZeroPage, 5, opcodes::isc_zero_page),
+    /* E8 */ official("INX", Implied, 2, |regs, _| opcodes::inx_implied(regs)),
+    /* E9 */ official("SBC", Immediate, 2, |regs, mem| opcodes::sbc_immediate(regs, mem)),
+    /* EA */ official("NOP", Implied, 2, |_, _| opcodes::nop_implied()),
+    /* EB */ unofficial("SBC", Immediate, 2, |regs, mem| opcodes::sbc_immediate(regs, mem)),
+    /* EC */ official("CPX", Absolute, 4, |regs, mem| opcodes::cpx_absolute(regs, mem)),
+    /* ED */ official("SBC", Absolute, 4, |regs, mem| opcodes::sbc_absolute(regs, mem)),
+    /* EE */ official("INC", Absolute, 6, opcodes::inc_absolute),
+    /* EF */ unofficial("ISB", Absolute, 6, opcodes::isc_absolute),
+    /* F0 */ official("BEQ", Relative, 2, |regs, mem| opcodes::beq_relative(regs, mem)),
+    /* F1 */ official("SBC", IndirectIndexed, 5, |regs, mem| opcodes::sbc_indirect_y(regs, mem)),
+    /* F2 */ unsupported("JAM", Implied, 2),
+    /* F3 */ unofficial("ISB", IndirectIndexed, 8, opcodes::isc_indirect_y),
+    /* F4 */ unofficial("NOP", ZeroPageX, 4, |regs, mem| opcodes::nop_zero_page_x(regs, mem)),
+    /* F5 */ official("SBC", ZeroPageX, 4, |regs, mem| opcodes::sbc_zero_page_x(regs, mem)),
+    /* F6 */ official("INC", ZeroPageX, 6, opcodes::inc_zero_page_x),
+    /* F7 */ unofficial("ISB", ZeroPageX, 6, opcodes::isc_zero_page_x),
+    /* F8 */ official("SED", Implied, 2, |regs, _| opcodes::sed_implied(regs)),
+    /* F9 */ official("SBC", AbsoluteY, 4, |regs, mem| opcodes::sbc_absolute_y(regs, mem)),
+    /* FA */ unofficial("NOP", Implied, 2, |_, _| opcodes::nop_implied()),
+    /* FB */ unofficial("ISB", AbsoluteY, 7, opcodes::isc_absolute_y),
+    /* FC */ unofficial("NOP", AbsoluteX, 4, |regs, mem| opcodes::nop_absolute_x(regs, mem)),
+    /* FD */ official("SBC", AbsoluteX, 4, |regs, mem| opcodes::sbc_absolute_x(regs, mem)),
+    /* FE */ official("INC", AbsoluteX, 7, opcodes::inc_absolute_x),
+    /* FF */ unofficial("ISB", AbsoluteX, 7, opcodes::isc_absolute_x),
+];
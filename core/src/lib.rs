@@ -0,0 +1,46 @@
+// The emulator core. None of it depends on SDL or GL, the SDL frontend is the rustnes binary.
+
+// Every component is built with new(), none of them have a meaningful default
+#![allow(clippy::new_without_default)]
+
+pub mod apu;
+pub mod cartridge;
+pub mod controller;
+pub mod cpu;
+pub mod cpuregisters;
+pub mod debug_command;
+pub mod debugger;
+pub mod disassembler;
+pub mod gdb_stub;
+pub mod golden;
+pub mod hash;
+pub mod headless;
+pub mod instructions;
+pub mod memory_viewer;
+pub mod movie;
+pub mod nes;
+pub mod ntsc_filter;
+pub mod palette;
+pub mod png;
+pub mod ppu;
+pub mod ppu_events;
+pub mod ppu_viewer;
+pub mod ppu_registers;
+pub mod ram_controller;
+pub mod recorder;
+pub mod region;
+pub mod rewind;
+pub mod savestate;
+pub mod scaler;
+pub mod screenshot;
+pub mod symbols;
+pub mod test_rom;
+pub mod trace;
+pub mod vram_controller;
+mod deflate;
+mod hex;
+mod opcodes;
+mod stack;
+
+pub use cartridge::Cartridge;
+pub use nes::Nes;
@@ -0,0 +1,499 @@
+// A hex view of any of the console's memories, for poking at a game while it runs: the CPU and
+// PPU address spaces, OAM, palette RAM, PRG RAM, and PRG and CHR ROM by their offset in the .nes
+// file. Bytes the CPU recently read light up green and written ones red, values can be frozen,
+// and byte sequences searched for. Everything goes through the side-effect-free peeks, so looking
+// at $2002 or $2007 does not disturb the PPU.
+
+use crate::hex;
+use crate::nes::Nes;
+use crate::ppu_viewer::Image;
+use crate::ram_controller::{AccessKind, Bus, MemoryAccess};
+
+pub const BYTES_PER_ROW: usize = 16;
+pub const ROWS: usize = 32;
+
+// Frames an access stays highlighted for, fading out as it goes
+const HEAT: u8 = 60;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MemorySpace {
+    Cpu,
+    Ppu,
+    Oam,
+    Palette,
+    PrgRam,
+    PrgRom,
+    ChrRom,
+}
+
+impl MemorySpace {
+    pub const ALL: [MemorySpace; 7] = [
+        MemorySpace::Cpu, MemorySpace::Ppu, MemorySpace::Oam, MemorySpace::Palette,
+        MemorySpace::PrgRam, MemorySpace::PrgRom, MemorySpace::ChrRom,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MemorySpace::Cpu => "CPU",
+            MemorySpace::Ppu => "PPU",
+            MemorySpace::Oam => "OAM",
+            MemorySpace::Palette => "palette RAM",
+            MemorySpace::PrgRam => "PRG RAM",
+            MemorySpace::PrgRom => "PRG ROM",
+            MemorySpace::ChrRom => "CHR ROM",
+        }
+    }
+
+    // The address the first byte is shown at. The ROMs are shown by file offset, so a change
+    // found here can be patched into the .nes file.
+    pub fn base(&self, nes: &Nes) -> usize {
+        let prg_rom = 16 + if nes.cartridge().has_trainer() { 512 } else { 0 };
+        match self {
+            MemorySpace::PrgRam => 0x6000,
+            MemorySpace::PrgRom => prg_rom,
+            MemorySpace::ChrRom => prg_rom + MemorySpace::PrgRom.len(nes),
+            _ => 0,
+        }
+    }
+
+    pub fn len(&self, nes: &Nes) -> usize {
+        match self {
+            MemorySpace::Cpu => 0x10000,
+            MemorySpace::Ppu => 0x4000,
+            MemorySpace::Oam => 0x100,
+            MemorySpace::Palette => 0x20,
+            MemorySpace::PrgRam => 0x2000,
+            MemorySpace::PrgRom => nes.cartridge().prg_rom_banks().len() * 0x4000,
+            MemorySpace::ChrRom => nes.cartridge().chr_rom_banks().len() * 0x2000,
+        }
+    }
+
+    // The byte at `offset` bytes into the space
+    pub fn peek(&self, nes: &Nes, offset: usize) -> u8 {
+        match self {
+            MemorySpace::Cpu => nes.cpu().memory().peek8(offset as u16),
+            MemorySpace::Ppu => nes.ppu().vram().borrow().peek8(offset as u16),
+            MemorySpace::Oam => nes.ppu().vram().borrow().read_oam(offset as u8),
+            MemorySpace::Palette => nes.ppu().vram().borrow().peek8(0x3F00 + offset as u16),
+            MemorySpace::PrgRam => nes.cpu().memory().peek8(0x6000 + offset as u16),
+            MemorySpace::PrgRom => nes.cartridge().prg_rom_banks()[offset / 0x4000].get_data()[offset % 0x4000],
+            MemorySpace::ChrRom => nes.cartridge().chr_rom_banks()[offset / 0x2000].get_data()[offset % 0x2000],
+        }
+    }
+
+    // Changes the byte at `offset` without anything else noticing, the registers and the ROMs can not be poked
+    pub fn poke(&self, nes: &mut Nes, offset: usize, value: u8) -> Result<(), String> {
+        match self {
+            MemorySpace::Cpu if (0x2000..0x4020).contains(&offset) => {
+                return Err(format!("${:04X} is an I/O register", offset));
+            }
+            // Left alone like a CPU write there, the PRG banks are copied in from the cartridge
+            MemorySpace::Cpu if offset >= 0x8000 => return Err(format!("${:04X} is PRG ROM, which is read-only", offset)),
+            MemorySpace::Cpu => nes.cpu_mut().memory_mut().poke8(offset as u16, value),
+            MemorySpace::PrgRam => nes.cpu_mut().memory_mut().poke8(0x6000 + offset as u16, value),
+            MemorySpace::Ppu => nes.ppu().vram().borrow_mut().write8(offset as u16, value),
+            MemorySpace::Oam => nes.ppu().vram().borrow_mut().write_oam(offset as u8, value),
+            MemorySpace::Palette => nes.ppu().vram().borrow_mut().write8(0x3F00 + offset as u16, value),
+            MemorySpace::PrgRom | MemorySpace::ChrRom => return Err(format!("{} is read-only", self.name())),
+        }
+        Ok(())
+    }
+
+    // Where in the space a CPU access landed, for the ones that can be told
+    fn offset_of(&self, nes: &Nes, access: &MemoryAccess) -> Option<usize> {
+        let address = access.address as usize;
+        match (self, access.bus) {
+            (MemorySpace::Cpu, Bus::Cpu) => Some(address),
+            (MemorySpace::PrgRam, Bus::Cpu) if (0x6000..0x8000).contains(&address) => Some(address - 0x6000),
+            (MemorySpace::PrgRom, Bus::Cpu) => nes.cpu().memory().prg_rom_offset(access.address),
+            (MemorySpace::Ppu, Bus::Ppu) => Some(address & 0x3FFF),
+            (MemorySpace::Palette, Bus::Ppu) if address & 0x3FFF >= 0x3F00 => Some(address & 0x1F),
+            (MemorySpace::ChrRom, Bus::Ppu) if address & 0x3FFF < 0x2000 && self.len(nes) > 0 => Some(address & 0x1FFF),
+            _ => None,
+        }
+    }
+}
+
+// A value written back after every instruction
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Freeze {
+    pub space: MemorySpace,
+    pub offset: usize,
+    pub value: u8,
+}
+
+// Parses a search pattern like "A9 00 8D" or "a9008d"
+pub fn parse_pattern(text: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<u8> = text.bytes().filter(|c| !c.is_ascii_whitespace()).collect();
+    hex::decode(&digits)
+        .filter(|pattern| !pattern.is_empty())
+        .ok_or_else(|| format!("{} is not a sequence of hex bytes", text))
+}
+
+pub struct MemoryViewer {
+    space: MemorySpace,
+    cursor: usize,
+    // Offset of the first row shown
+    top: usize,
+    // The first digit typed over the byte at the cursor, it is written once the second one is
+    high_nibble: Option<u8>,
+    // Frames left of the read and write highlights of each byte of the space
+    heat: Vec<(u8, u8)>,
+    freezes: Vec<Freeze>,
+    pattern: Vec<u8>,
+    found: Option<usize>,
+}
+
+impl MemoryViewer {
+    pub fn new(nes: &Nes) -> MemoryViewer {
+        let mut viewer = MemoryViewer {
+            space: MemorySpace::Cpu,
+            cursor: 0,
+            top: 0,
+            high_nibble: None,
+            heat: vec![],
+            freezes: vec![],
+            pattern: vec![],
+            found: None,
+        };
+        viewer.set_space(nes, MemorySpace::Cpu);
+        viewer
+    }
+
+    pub fn space(&self) -> MemorySpace {
+        self.space
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn freezes(&self) -> &[Freeze] {
+        &self.freezes
+    }
+
+    pub fn set_space(&mut self, nes: &Nes, space: MemorySpace) {
+        self.space = space;
+        self.cursor = 0;
+        self.top = 0;
+        self.high_nibble = None;
+        self.heat = vec![(0, 0); space.len(nes)];
+        self.found = None;
+    }
+
+    // The next or previous space in MemorySpace::ALL
+    pub fn cycle_space(&mut self, nes: &Nes, step: isize) {
+        let index = MemorySpace::ALL.iter().position(|space| *space == self.space).unwrap() as isize;
+        let next = MemorySpace::ALL[(index + step).rem_euclid(MemorySpace::ALL.len() as isize) as usize];
+        self.set_space(nes, next);
+    }
+
+    pub fn move_cursor(&mut self, nes: &Nes, delta: isize) {
+        let last = self.space.len(nes).saturating_sub(1) as isize;
+        self.set_cursor((self.cursor as isize + delta).clamp(0, last.max(0)) as usize);
+    }
+
+    fn set_cursor(&mut self, cursor: usize) {
+        self.cursor = cursor;
+        self.high_nibble = None;
+        let row = cursor - cursor % BYTES_PER_ROW;
+        if row < self.top {
+            self.top = row;
+        } else if row >= self.top + ROWS * BYTES_PER_ROW {
+            self.top = row + BYTES_PER_ROW - ROWS * BYTES_PER_ROW;
+        }
+    }
+
+    // Moves the cursor to an address as it is shown, i.e. a file offset for the ROMs
+    pub fn goto(&mut self, nes: &Nes, address: usize) -> Result<(), String> {
+        let base = self.space.base(nes);
+        if address < base || address >= base + self.space.len(nes) {
+            return Err(format!("${:X} is not in {}", address, self.space.name()));
+        }
+        self.set_cursor(address - base);
+        Ok(())
+    }
+
+    // Types one hex digit over the byte at the cursor, the byte is written and the cursor moves on after two
+    pub fn type_digit(&mut self, nes: &mut Nes, digit: u8) -> Result<(), String> {
+        if self.space.len(nes) == 0 {
+            return Ok(());
+        }
+        let high = match self.high_nibble.take() {
+            Some(high) => high,
+            None => {
+                self.high_nibble = Some(digit);
+                return Ok(());
+            }
+        };
+
+        let value = (high << 4) | digit;
+        self.space.poke(nes, self.cursor, value)?;
+        let (space, offset) = (self.space, self.cursor);
+        if let Some(freeze) = self.freezes.iter_mut().find(|f| f.space == space && f.offset == offset) {
+            freeze.value = value;
+        }
+        self.move_cursor(nes, 1);
+        Ok(())
+    }
+
+    // Freezes the byte at the cursor at its current value, or lets it go again
+    pub fn toggle_freeze(&mut self, nes: &mut Nes) -> Result<(), String> {
+        let (space, offset) = (self.space, self.cursor);
+        match self.freezes.iter().position(|f| f.space == space && f.offset == offset) {
+            Some(index) => {
+                self.freezes.remove(index);
+            }
+            None if offset < space.len(nes) => {
+                let value = space.peek(nes, offset);
+                space.poke(nes, offset, value)?;
+                self.freezes.push(Freeze { space, offset, value });
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    pub fn apply_freezes(&self, nes: &mut Nes) {
+        for freeze in &self.freezes {
+            // Only bytes that could be poked got frozen in the first place
+            freeze.space.poke(nes, freeze.offset, freeze.value).ok();
+        }
+    }
+
+    // Moves the cursor to the next place after it that holds `pattern`, wrapping around the end
+    pub fn find(&mut self, nes: &Nes, pattern: Vec<u8>) -> Result<usize, String> {
+        self.pattern = pattern;
+        self.find_next(nes)
+    }
+
+    pub fn find_next(&mut self, nes: &Nes) -> Result<usize, String> {
+        if self.pattern.is_empty() {
+            return Err(String::from("Nothing to search for"));
+        }
+        let contents: Vec<u8> = (0..self.space.len(nes)).map(|offset| self.space.peek(nes, offset)).collect();
+        let matches = |start: &usize| contents[*start..].starts_with(&self.pattern);
+        let found = (self.cursor + 1..contents.len()).find(matches).or_else(|| (0..=self.cursor.min(contents.len())).find(matches));
+
+        self.found = found;
+        match found {
+            Some(offset) => {
+                self.set_cursor(offset);
+                Ok(offset + self.space.base(nes))
+            }
+            None => {
+                let bytes: Vec<String> = self.pattern.iter().map(|b| format!("{:02X}", b)).collect();
+                Err(format!("{} is not in {}", bytes.join(" "), self.space.name()))
+            }
+        }
+    }
+
+    // Lights up the bytes the accesses touched, `accesses` are from RamController::take_accesses
+    pub fn watch(&mut self, nes: &Nes, accesses: &[MemoryAccess]) {
+        for access in accesses {
+            if let Some(offset) = self.space.offset_of(nes, access).filter(|offset| *offset < self.heat.len()) {
+                match access.kind {
+                    AccessKind::Write => self.heat[offset].1 = HEAT,
+                    _ => self.heat[offset].0 = HEAT,
+                }
+            }
+        }
+    }
+
+    // Called once a frame to fade the highlights
+    pub fn cool(&mut self) {
+        for (read, write) in &mut self.heat {
+            *read = read.saturating_sub(1);
+            *write = write.saturating_sub(1);
+        }
+    }
+
+    // Like "CPU $0300 = $1F, 2 frozen"
+    pub fn status(&self, nes: &Nes) -> String {
+        let mut status = match self.cursor < self.space.len(nes) {
+            true => format!("{} ${:04X} = ${:02X}", self.space.name(), self.space.base(nes) + self.cursor, self.space.peek(nes, self.cursor)),
+            false => format!("{} is empty", self.space.name()),
+        };
+        if !self.freezes.is_empty() {
+            status += &format!(", {} frozen", self.freezes.len());
+        }
+        status
+    }
+
+    // ROWS rows of an address and 16 bytes, in a 6x9 font
+    pub fn render(&self, nes: &Nes) -> Image {
+        const TEXT: [u8; 3] = [200, 200, 200];
+        const BACKGROUND: [u8; 3] = [16, 16, 16];
+
+        let columns = 6 + 2 + BYTES_PER_ROW * 3 + 1;
+        let mut image = Image::new((columns * CHAR_WIDTH + 8) as u32, (ROWS * CHAR_HEIGHT + 8) as u32);
+        image.fill(0, 0, image.width, image.height, BACKGROUND);
+
+        let (base, len) = (self.space.base(nes), self.space.len(nes));
+        let found = self.found.map_or(0..0, |offset| offset..offset + self.pattern.len());
+        for row in 0..ROWS {
+            let start = self.top + row * BYTES_PER_ROW;
+            if start >= len {
+                break;
+            }
+            let y = 4 + row * CHAR_HEIGHT;
+            draw_hex(&mut image, 4, y, (base + start) as u32, 6, [120, 120, 160], BACKGROUND);
+
+            for offset in start..(start + BYTES_PER_ROW).min(len) {
+                let column = offset - start;
+                let x = 4 + (8 + column * 3 + column / 8) * CHAR_WIDTH;
+                let (read, write) = self.heat[offset];
+                let frozen = self.freezes.iter().any(|f| f.space == self.space && f.offset == offset);
+
+                let mut background = if write > 0 {
+                    fade([220, 40, 40], write)
+                } else if read > 0 {
+                    fade([40, 200, 40], read)
+                } else {
+                    BACKGROUND
+                };
+                if frozen {
+                    background = [40, 80, 220];
+                }
+                if found.contains(&offset) {
+                    background = [150, 130, 0];
+                }
+                let (mut text, mut value) = (TEXT, self.space.peek(nes, offset));
+                if offset == self.cursor {
+                    (text, background) = (BACKGROUND, [230, 230, 230]);
+                    if let Some(high) = self.high_nibble {
+                        value = (high << 4) | (value & 0x0F);
+                    }
+                }
+                image.fill((x - CHAR_WIDTH / 2) as u32, (y - 1) as u32, (CHAR_WIDTH * 3) as u32, CHAR_HEIGHT as u32, background);
+                draw_hex(&mut image, x, y, value as u32, 2, text, background);
+            }
+        }
+        image
+    }
+}
+
+// Blends a highlight colour into the background as its heat runs out
+fn fade(color: [u8; 3], heat: u8) -> [u8; 3] {
+    color.map(|channel| (16 + (channel as u32 - 16) * heat as u32 / HEAT as u32) as u8)
+}
+
+const CHAR_WIDTH: usize = 6;
+const CHAR_HEIGHT: usize = 9;
+
+// 5x7 hex digits, one row per byte with the leftmost pixel in bit 4
+const DIGITS: [[u8; 7]; 16] = [
+    [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110],
+    [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+    [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111],
+    [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110],
+    [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010],
+    [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110],
+    [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110],
+    [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000],
+    [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110],
+    [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100],
+    [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001],
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110],
+    [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110],
+    [0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100],
+    [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111],
+    [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000],
+];
+
+fn draw_hex(image: &mut Image, x: usize, y: usize, value: u32, digits: usize, color: [u8; 3], background: [u8; 3]) {
+    for i in 0..digits {
+        let digit = (value >> ((digits - 1 - i) * 4)) & 0xF;
+        for (row, bits) in DIGITS[digit as usize].iter().enumerate() {
+            for column in 0..5 {
+                let lit = bits & (0b10000 >> column) != 0;
+                image.set((x + i * CHAR_WIDTH + column) as u32, (y + row) as u32, if lit { color } else { background });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Cartridge;
+
+    fn nes() -> Nes {
+        // LDA #$42; STA $0300; JMP $8005, with a bank of CHR ROM
+        let mut image = b"NES\x1A\x01\x01".to_vec();
+        image.resize(16, 0);
+        let mut prg = vec![0u8; 0x4000];
+        prg[..8].copy_from_slice(&[0xA9, 0x42, 0x8D, 0x00, 0x03, 0x4C, 0x05, 0x80]);
+        prg[0x3FFA..].copy_from_slice(&[0x05, 0x80, 0x00, 0x80, 0x05, 0x80]);
+        image.extend_from_slice(&prg);
+        image.extend((0..0x2000).map(|i| (i % 251) as u8));
+        Nes::new(Cartridge::from_bytes(&image).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn every_space_can_be_peeked_and_the_ram_ones_poked() {
+        let mut nes = nes();
+        assert_eq!(MemorySpace::PrgRom.base(&nes), 16);
+        assert_eq!(MemorySpace::ChrRom.base(&nes), 16 + 0x4000);
+        assert_eq!(MemorySpace::PrgRom.peek(&nes, 1), 0x42);
+        assert_eq!(MemorySpace::ChrRom.peek(&nes, 300), (300 % 251) as u8);
+
+        MemorySpace::Palette.poke(&mut nes, 0x10, 0x2A).unwrap();
+        assert_eq!(MemorySpace::Palette.peek(&nes, 0x00), 0x2A);
+        assert_eq!(MemorySpace::Ppu.peek(&nes, 0x3F00), 0x2A);
+        MemorySpace::Oam.poke(&mut nes, 0xFF, 7).unwrap();
+        assert_eq!(MemorySpace::Oam.peek(&nes, 0xFF), 7);
+        MemorySpace::PrgRam.poke(&mut nes, 0x10, 9).unwrap();
+        assert_eq!(MemorySpace::Cpu.peek(&nes, 0x6010), 9);
+
+        assert!(MemorySpace::Cpu.poke(&mut nes, 0x2007, 0).is_err());
+        assert!(MemorySpace::Cpu.poke(&mut nes, 0x8001, 0).is_err());
+        assert_eq!(MemorySpace::PrgRom.peek(&nes, 1), 0x42);
+        assert!(MemorySpace::PrgRom.poke(&mut nes, 0, 0).is_err());
+    }
+
+    #[test]
+    fn typed_values_are_written_and_frozen_ones_stay() {
+        let mut nes = nes();
+        let mut viewer = MemoryViewer::new(&nes);
+        viewer.goto(&nes, 0x0300).unwrap();
+        viewer.type_digit(&mut nes, 0x1).unwrap();
+        viewer.type_digit(&mut nes, 0xF).unwrap();
+        assert_eq!(MemorySpace::Cpu.peek(&nes, 0x0300), 0x1F);
+        assert_eq!(viewer.cursor(), 0x0301);
+
+        viewer.move_cursor(&nes, -1);
+        viewer.toggle_freeze(&mut nes).unwrap();
+        nes.step();
+        nes.step();
+        assert_eq!(MemorySpace::Cpu.peek(&nes, 0x0300), 0x42);
+        viewer.apply_freezes(&mut nes);
+        assert_eq!(MemorySpace::Cpu.peek(&nes, 0x0300), 0x1F);
+        assert_eq!(viewer.status(&nes), "CPU $0300 = $1F, 1 frozen");
+    }
+
+    #[test]
+    fn searches_wrap_around_and_accesses_light_up() {
+        let mut nes = nes();
+        let mut viewer = MemoryViewer::new(&nes);
+        viewer.set_space(&nes, MemorySpace::PrgRom);
+        assert_eq!(viewer.find(&nes, parse_pattern("8d 00 03").unwrap()), Ok(16 + 2));
+        assert_eq!(viewer.find_next(&nes), Ok(16 + 2));
+        assert!(viewer.find(&nes, vec![0xDE, 0xAD, 0xBE, 0xEF]).is_err());
+        assert!(parse_pattern("8D 0").is_err());
+        assert!(parse_pattern(" ").is_err());
+        assert_eq!(parse_pattern("a9 0 08D"), Ok(vec![0xA9, 0x00, 0x8D]));
+
+        viewer.set_space(&nes, MemorySpace::Cpu);
+        nes.cpu().memory().set_access_logging(true);
+        nes.step();
+        nes.step();
+        viewer.watch(&nes, &nes.cpu().memory().take_accesses());
+        assert_eq!(viewer.heat[0x0300], (0, HEAT));
+        viewer.cool();
+        assert_eq!(viewer.heat[0x0300], (0, HEAT - 1));
+
+        let image = viewer.render(&nes);
+        assert_eq!((image.width, image.height), (350, 296));
+    }
+}
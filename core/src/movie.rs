@@ -0,0 +1,366 @@
+use crate::hash::md5;
+use crate::hex;
+use crate::region::Region;
+use crate::controller::{BUTTON_A, BUTTON_B, BUTTON_DOWN, BUTTON_LEFT, BUTTON_RIGHT, BUTTON_SELECT, BUTTON_START, BUTTON_UP};
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// FM2 writes the buttons of each port as "RLDUTSBA", with a . for every button that is not held
+const FM2_BUTTONS: [(char, u8); 8] = [
+    ('R', BUTTON_RIGHT),
+    ('L', BUTTON_LEFT),
+    ('D', BUTTON_DOWN),
+    ('U', BUTTON_UP),
+    ('T', BUTTON_START),
+    ('S', BUTTON_SELECT),
+    ('B', BUTTON_B),
+    ('A', BUTTON_A),
+];
+
+pub const COMMAND_SOFT_RESET: u8 = 0b00000001;
+pub const COMMAND_POWER: u8 = 0b00000010;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MovieMode {
+    Recording,
+    Playing,
+    Finished,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameInput {
+    pub commands: u8,
+    pub ports: [u8; 2],
+}
+
+// Controller input for every frame since power on, or since the save state the movie starts from.
+// That state is one of ours, FCEUX puts its own in the same savestate header, so movies from either
+// emulator only play back in the one that made them. A read-only movie is only ever played back.
+// A read-write movie goes back to recording from wherever a save state is loaded, which counts as a re-record.
+pub struct Movie {
+    pub frames: Vec<FrameInput>,
+    pub rerecord_count: u32,
+    pub rom_filename: String,
+    pub rom_checksum: String,
+    pub guid: String,
+    pub region: Region,
+    pub comments: Vec<String>,
+    pub savestate: Option<Vec<u8>>,
+    start_frame: u64,
+    mode: MovieMode,
+    read_only: bool,
+}
+
+impl Movie {
+    // `start_frame` is the PPU frame the recording starts at, `savestate` the state it starts from
+    // when that is not power on
+    pub fn record(rom_filename: &str, rom_checksum: &str, region: Region, savestate: Option<Vec<u8>>, start_frame: u64) -> Movie {
+        Movie {
+            frames: vec![],
+            rerecord_count: 0,
+            rom_filename: rom_filename.to_string(),
+            rom_checksum: rom_checksum.to_string(),
+            guid: new_guid(),
+            region,
+            comments: vec![],
+            savestate,
+            start_frame,
+            mode: MovieMode::Recording,
+            read_only: false,
+        }
+    }
+
+    pub fn mode(&self) -> MovieMode {
+        self.mode
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    // Starts playback from the first frame, `start_frame` is the PPU frame the machine is at once
+    // it has been powered on or has loaded the movie's save state
+    pub fn play(&mut self, start_frame: u64) {
+        self.start_frame = start_frame;
+        self.mode = MovieMode::Playing;
+    }
+
+    // The input to use for `frame`. Recording stores the live input, playback replaces it.
+    pub fn input(&mut self, frame: u64, live: [u8; 2]) -> FrameInput {
+        let index = frame.saturating_sub(self.start_frame) as usize;
+
+        match self.mode {
+            MovieMode::Recording => {
+                let input = FrameInput { commands: 0, ports: live };
+                self.frames.resize(index, FrameInput::default());
+                self.frames.push(input);
+                input
+            }
+            MovieMode::Playing if index < self.frames.len() => self.frames[index],
+            MovieMode::Playing => {
+                self.mode = MovieMode::Finished;
+                FrameInput { commands: 0, ports: live }
+            }
+            MovieMode::Finished => FrameInput { commands: 0, ports: live }
+        }
+    }
+
+    // Has to be called after a save state was loaded while the movie is active
+    pub fn state_loaded(&mut self, frame: u64) {
+        if frame < self.start_frame {
+            return;
+        }
+
+        let index = (frame - self.start_frame) as usize;
+        if self.read_only {
+            if self.mode == MovieMode::Finished && index < self.frames.len() {
+                self.mode = MovieMode::Playing;
+            }
+            return;
+        }
+
+        // The input of the frame the state was saved in has already been read into the controllers
+        self.frames.truncate(index + 1);
+        self.mode = MovieMode::Recording;
+        self.rerecord_count += 1;
+    }
+
+    pub fn load_fm2(path: &str) -> Result<Movie, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Movie::parse_fm2(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    // FM2 has no Dendy flag, anything but palFlag 1 is NTSC
+    pub fn parse_fm2(text: &str) -> Result<Movie, String> {
+        let mut movie = Movie::record("", "", Region::Ntsc, None, 0);
+        movie.guid.clear();
+        movie.read_only = true;
+        movie.mode = MovieMode::Playing;
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim_end();
+            if line.starts_with('|') {
+                movie.frames.push(parse_fm2_frame(line).map_err(|e| format!("line {}: {}", number + 1, e))?);
+                continue;
+            }
+
+            let (key, value) = match line.split_once(' ') {
+                Some((key, value)) => (key, value),
+                None => (line, "")
+            };
+
+            match key {
+                "version" if value != "3" => return Err(format!("unsupported FM2 version {}", value)),
+                "binary" if value == "1" => return Err(String::from("binary FM2 movies are not supported")),
+                "fourscore" if value == "1" => return Err(String::from("Four Score movies are not supported")),
+                "rerecordCount" => movie.rerecord_count = value.parse().unwrap_or(0),
+                "palFlag" => movie.region = if value == "1" { Region::Pal } else { Region::Ntsc },
+                "romFilename" => movie.rom_filename = value.to_string(),
+                "romChecksum" => movie.rom_checksum = value.to_string(),
+                "guid" => movie.guid = value.to_string(),
+                "comment" => movie.comments.push(value.to_string()),
+                "savestate" => {
+                    let state = value.strip_prefix("0x").and_then(|hex| hex::decode(hex.as_bytes()));
+                    movie.savestate = Some(state.ok_or("the savestate has to be written in hex after 0x")?);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(movie)
+    }
+
+    pub fn save_fm2(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_fm2()).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn to_fm2(&self) -> String {
+        let mut text = String::new();
+        text.push_str("version 3\n");
+        text.push_str("emuVersion 20000\n");
+        text.push_str(&format!("rerecordCount {}\n", self.rerecord_count));
+        text.push_str(&format!("palFlag {}\n", (self.region == Region::Pal) as u8));
+        text.push_str(&format!("romFilename {}\n", self.rom_filename));
+        text.push_str(&format!("romChecksum {}\n", self.rom_checksum));
+        text.push_str(&format!("guid {}\n", self.guid));
+        text.push_str("fourscore 0\n");
+        text.push_str("microphone 0\n");
+        text.push_str("port0 1\n");
+        text.push_str("port1 1\n");
+        text.push_str("port2 0\n");
+        text.push_str("FDS 0\n");
+        text.push_str("NewPPU 0\n");
+        for comment in &self.comments {
+            text.push_str(&format!("comment {}\n", comment));
+        }
+        if let Some(state) = &self.savestate {
+            let hex: String = state.iter().map(|b| format!("{:02X}", b)).collect();
+            text.push_str(&format!("savestate 0x{}\n", hex));
+        }
+
+        for frame in &self.frames {
+            let ports: Vec<String> = frame.ports.iter()
+                .map(|buttons| FM2_BUTTONS.iter().map(|(c, b)| if buttons & b != 0 { *c } else { '.' }).collect())
+                .collect();
+            text.push_str(&format!("|{}|{}|{}||\n", frame.commands, ports[0], ports[1]));
+        }
+
+        text
+    }
+}
+
+fn parse_fm2_frame(line: &str) -> Result<FrameInput, String> {
+    let fields: Vec<&str> = line.split('|').collect();
+    if fields.len() < 3 {
+        return Err(format!("invalid input line '{}'", line));
+    }
+
+    let commands = fields[1].trim().parse::<u8>().map_err(|_| format!("invalid commands in '{}'", line))?;
+    let mut ports = [0u8; 2];
+    for (port, field) in fields[2..].iter().take(2).enumerate() {
+        // Any character other than a space or a . means the button is held
+        for ((_, button), c) in FM2_BUTTONS.iter().zip(field.chars()) {
+            if c != '.' && c != ' ' {
+                ports[port] |= button;
+            }
+        }
+    }
+
+    Ok(FrameInput { commands, ports })
+}
+
+fn new_guid() -> String {
+    // Not a proper random GUID, but unique enough to tell two movies apart
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+    let mut value = nanos ^ ((std::process::id() as u128) << 64);
+    let mut bytes = [0u8; 16];
+    for byte in bytes.iter_mut() {
+        value = value.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        *byte = (value >> 64) as u8;
+    }
+
+    let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+    let hex = hex.join("");
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+// FCEUX identifies a ROM by the MD5 of its PRG and CHR data, written as "base64:..."
+pub fn rom_checksum(data: &[u8]) -> String {
+    format!("base64:{}", base64(&md5(data)))
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut result = String::new();
+
+    for chunk in data.chunks(3) {
+        let value = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                result.push(ALPHABET[(value >> (18 - i * 6)) as usize & 0x3F] as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+
+    result
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_header_carries_the_movie_details() {
+        let mut movie = Movie::record("game", "base64:AAAA", Region::Pal, None, 0);
+        movie.rerecord_count = 3;
+        movie.comments.push(String::from("author someone"));
+        let text = movie.to_fm2();
+
+        let header: Vec<&str> = text.lines().collect();
+        assert_eq!(&header[..4], &["version 3", "emuVersion 20000", "rerecordCount 3", "palFlag 1"]);
+        assert!(header.contains(&"romFilename game"));
+        assert!(header.contains(&"romChecksum base64:AAAA"));
+        assert!(header.contains(&format!("guid {}", movie.guid).as_str()));
+        assert!(header.contains(&"comment author someone"));
+        assert!(!text.contains("savestate"));
+
+        let loaded = Movie::parse_fm2(&text).unwrap();
+        assert_eq!(loaded.region, Region::Pal);
+        assert_eq!(loaded.rerecord_count, 3);
+        assert_eq!(loaded.rom_filename, "game");
+        assert_eq!(loaded.rom_checksum, "base64:AAAA");
+        assert_eq!(loaded.guid, movie.guid);
+        assert_eq!(loaded.comments, movie.comments);
+        assert!(loaded.is_read_only());
+
+        let ntsc = Movie::record("game", "", Region::Dendy, None, 0);
+        assert!(ntsc.to_fm2().contains("palFlag 0\n"));
+        assert_eq!(Movie::parse_fm2(&ntsc.to_fm2()).unwrap().region, Region::Ntsc);
+    }
+
+    #[test]
+    fn unsupported_movies_are_refused() {
+        assert!(Movie::parse_fm2("version 2\n").is_err());
+        assert!(Movie::parse_fm2("version 3\nbinary 1\n").is_err());
+        assert!(Movie::parse_fm2("version 3\nfourscore 1\n").is_err());
+        assert_eq!(Movie::parse_fm2("version 3\nsavestate base64:AQ==\n").err(),
+                   Some(String::from("the savestate has to be written in hex after 0x")));
+        assert_eq!(Movie::parse_fm2("version 3\n|0|........|........||\n|x|||\n").err(),
+                   Some(String::from("line 3: invalid commands in '|x|||'")));
+    }
+
+    #[test]
+    fn input_lines_hold_the_commands_and_both_ports() {
+        assert_eq!(parse_fm2_frame("|0|........|........||"), Ok(FrameInput::default()));
+        assert_eq!(parse_fm2_frame("|2|RLDUTSBA|.......A||"), Ok(FrameInput { commands: COMMAND_POWER, ports: [0xFF, BUTTON_A] }));
+        // Any character marks a held button and short fields leave the rest released
+        assert_eq!(parse_fm2_frame("|1|R  xT|"), Ok(FrameInput {
+            commands: COMMAND_SOFT_RESET,
+            ports: [BUTTON_RIGHT | BUTTON_UP | BUTTON_START, 0],
+        }));
+        assert_eq!(parse_fm2_frame("|1|"), Ok(FrameInput { commands: 1, ports: [0, 0] }));
+        assert!(parse_fm2_frame("|").is_err());
+        assert!(parse_fm2_frame("|300|........|........||").is_err());
+    }
+
+    #[test]
+    fn the_starting_save_state_goes_in_the_header() {
+        let movie = Movie::record("game", "", Region::Ntsc, Some(vec![0x01, 0xAB, 0x00]), 0);
+        assert!(movie.to_fm2().lines().any(|line| line == "savestate 0x01AB00"));
+        assert_eq!(Movie::parse_fm2(&movie.to_fm2()).unwrap().savestate, Some(vec![0x01, 0xAB, 0x00]));
+        assert_eq!(Movie::parse_fm2("version 3\n").unwrap().savestate, None);
+    }
+
+    #[test]
+    fn a_recording_plays_back_the_same_input() {
+        let mut movie = Movie::record("game", "", Region::Ntsc, None, 100);
+        let live = [[BUTTON_A, 0], [BUTTON_A | BUTTON_LEFT, BUTTON_START], [0, 0], [BUTTON_SELECT, BUTTON_DOWN]];
+        for (frame, input) in live.iter().enumerate() {
+            assert_eq!(movie.input(100 + frame as u64, *input).ports, *input);
+        }
+        // A skipped frame is recorded as nothing held
+        movie.input(105, [BUTTON_B, BUTTON_B]);
+        assert_eq!(movie.frames.len(), 6);
+
+        let mut loaded = Movie::parse_fm2(&movie.to_fm2()).unwrap();
+        assert_eq!(loaded.frames, movie.frames);
+
+        loaded.play(10);
+        for (frame, input) in live.iter().enumerate() {
+            assert_eq!(loaded.input(10 + frame as u64, [BUTTON_UP, 0]).ports, *input);
+        }
+        assert_eq!(loaded.input(14, [BUTTON_UP, 0]).ports, [0, 0]);
+        assert_eq!(loaded.input(15, [BUTTON_UP, 0]).ports, [BUTTON_B, BUTTON_B]);
+        assert_eq!(loaded.mode(), MovieMode::Playing);
+        assert_eq!(loaded.input(16, [BUTTON_UP, 0]).ports, [BUTTON_UP, 0]);
+        assert_eq!(loaded.mode(), MovieMode::Finished);
+    }
+}
@@ -0,0 +1,284 @@
+use crate::cartridge::Cartridge;
+use crate::cpu::CPU;
+use crate::instructions;
+use crate::ppu::PPU;
+use crate::ppu_events::{PpuEvent, PpuEventKind, PpuEventLog};
+use crate::ppu_registers::PPURegisters;
+use crate::ram_controller::RamController;
+use crate::region::Region;
+use crate::savestate;
+use crate::vram_controller::VRAMController;
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::rc::Rc;
+
+// The reset sequence takes 7 cycles before the first instruction
+const RESET_CYCLES: u64 = 7;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StepResult {
+    pub cycles: i32,
+    pub nmi: bool,
+    pub irq: bool,
+}
+
+// A console with a cartridge inserted. Owns the whole machine, so it can be embedded anywhere.
+pub struct Nes {
+    cpu: CPU,
+    ppu: PPU,
+    cartridge: Cartridge,
+    // Save states carry it so that one made with another ROM is refused
+    rom_checksum: u32,
+    region: Region,
+    cycles: u64,
+    ppu_events: Option<PpuEventLog>,
+}
+
+impl Nes {
+    // Inserts the cartridge and powers the console on, as the region the ROM header asks for.
+    // Fails for a cartridge with a mapper that is not emulated.
+    pub fn new(cartridge: Cartridge) -> Result<Nes, String> {
+        let region = cartridge.region();
+        Nes::with_region(cartridge, region)
+    }
+
+    pub fn with_region(cartridge: Cartridge, region: Region) -> Result<Nes, String> {
+        cartridge.check_supported()?;
+        let (cpu, ppu) = Nes::power_on(&cartridge, region);
+
+        Ok(Nes {
+            cpu,
+            ppu,
+            rom_checksum: cartridge.checksum(),
+            cartridge,
+            region,
+            cycles: RESET_CYCLES,
+            ppu_events: None,
+        })
+    }
+
+    // A fresh CPU and PPU, with everything from RAM to the APU as it is at power on
+    fn power_on(cartridge: &Cartridge, region: Region) -> (CPU, PPU) {
+        let vram = Rc::new(RefCell::new(VRAMController::new()));
+        let ppu_regs = Rc::new(Cell::new(PPURegisters::new()));
+        let mut memory = RamController::new(ppu_regs.clone(), vram.clone(), region);
+
+        // With a single 16KB bank the same bank shows up at both $8000 and $C000
+        let prg_banks = cartridge.prg_rom_banks();
+        memory.load_prg_bank1(&prg_banks[0]);
+        memory.load_prg_bank2(&prg_banks[prg_banks.len() - 1]);
+
+        // Without CHR ROM the pattern tables are RAM
+        if let Some(chr_bank) = cartridge.chr_rom_banks().first() {
+            vram.borrow_mut().load_chr_rom(chr_bank);
+        }
+        vram.borrow_mut().set_mirroring(cartridge.mirroring());
+
+        let mut cpu = CPU::new(memory);
+        cpu.reset();
+
+        // The PPU runs through the reset sequence too, nestest.log starts at dot 21
+        let mut ppu = PPU::new(vram, ppu_regs, region);
+        ppu.process_cpu_cycles(RESET_CYCLES as i32);
+
+        (cpu, ppu)
+    }
+
+    // The reset button, only the CPU goes through its reset sequence and memory is left as it is
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+    }
+
+    // Switches the console off and on again. The frame and cycle counts carry on, so that movie
+    // input and the event log keep lining up with them.
+    pub fn power_cycle(&mut self) {
+        let (cpu, mut ppu) = Nes::power_on(&self.cartridge, self.region);
+        ppu.continue_from_frame(self.ppu.frame());
+        self.cpu = cpu;
+        self.ppu = ppu;
+        self.cycles += RESET_CYCLES;
+        self.cpu.memory().set_ppu_event_logging(self.ppu_events.is_some());
+    }
+
+    // Buttons held on controller port 0 or 1, see the controller::BUTTON_* bits
+    pub fn set_input(&mut self, port: usize, buttons: u8) {
+        self.cpu.memory().set_controller_buttons(port, buttons);
+    }
+
+    // Runs a single instruction, and the PPU and APU alongside it. The PPU is caught up to the
+    // instruction's last cycle before it runs, so register accesses see it at the right dot.
+    pub fn step(&mut self) -> StepResult {
+        let opcode = self.cpu.memory().peek8(self.cpu.registers.pc());
+        let before = instructions::lookup(opcode).cycles - 1;
+        self.ppu.process_cpu_cycles(before);
+
+        // The CPU looks at the NMI line on the next to last cycle, so an NMI raised later waits an instruction
+        let raised = self.ppu.registers().get().nmi_pending();
+
+        let mut cycles = self.cpu.process_instruction();
+        let events = self.cpu.memory().take_ppu_events();
+        self.record_ppu_events(events);
+        self.ppu.process_cpu_cycles(cycles - before);
+        self.cpu.memory().clock_apu(cycles);
+
+        let mut regs = self.ppu.registers().get();
+        let nmi = raised && regs.nmi_pending();
+        if nmi {
+            regs.acknowledge_nmi();
+            self.ppu.registers().set(regs);
+            cycles += self.interrupt(PpuEventKind::Nmi, |cpu| cpu.trigger_nmi());
+        }
+        let irq_cycles = self.interrupt(PpuEventKind::Irq, |cpu| cpu.poll_irq());
+        cycles += irq_cycles;
+
+        if let Some(log) = self.ppu_events.as_mut() {
+            log.frame(self.ppu.frame());
+        }
+        self.cycles += cycles as u64;
+        StepResult { cycles, nmi, irq: irq_cycles > 0 }
+    }
+
+    // Runs an interrupt sequence, with the PPU and APU clocked for the cycles it took
+    fn interrupt(&mut self, kind: PpuEventKind, service: impl FnOnce(&mut CPU) -> i32) -> i32 {
+        let cycles = service(&mut self.cpu);
+        if cycles > 0 {
+            self.record_ppu_events(vec![(kind, 0, 0)]);
+        }
+        self.ppu.process_cpu_cycles(cycles);
+        self.cpu.memory().clock_apu(cycles);
+        cycles
+    }
+
+    // Stamps the events with where the PPU is now
+    fn record_ppu_events(&mut self, events: Vec<(PpuEventKind, u16, u8)>) {
+        if let Some(log) = self.ppu_events.as_mut() {
+            log.frame(self.ppu.frame());
+            for (kind, address, value) in events {
+                log.push(PpuEvent { kind, address, value, scanline: self.ppu.scanline(), dot: self.ppu.pixel() });
+            }
+        }
+    }
+
+    // Keeps a log of the CPU's PPU register accesses and the interrupts, see ppu_events
+    pub fn set_ppu_event_logging(&mut self, enabled: bool) {
+        self.cpu.memory().set_ppu_event_logging(enabled);
+        self.ppu_events = if enabled { Some(PpuEventLog::new(self.ppu.frame())) } else { None };
+    }
+
+    pub fn ppu_event_log(&self) -> Option<&PpuEventLog> {
+        self.ppu_events.as_ref()
+    }
+
+    // Runs until the PPU has finished the current frame
+    pub fn run_frame(&mut self) {
+        let frame = self.ppu.frame();
+        while self.ppu.frame() == frame {
+            self.step();
+        }
+    }
+
+    // The last complete frame, 256x240 6-bit palette indices
+    pub fn framebuffer(&self) -> &[u8] {
+        self.ppu.framebuffer()
+    }
+
+    // The emphasis bits of each line of the last frame, in palette::EMPHASIS_* order
+    pub fn emphasis(&self) -> &[u8] {
+        self.ppu.emphasis()
+    }
+
+    // Every sample the APU has put out since the last call, mono at apu::SAMPLE_RATE
+    pub fn audio_samples(&mut self) -> Vec<f32> {
+        self.cpu.memory().take_audio_samples()
+    }
+
+    // Number of frames since power on
+    pub fn frame(&self) -> u64 {
+        self.ppu.frame()
+    }
+
+    // Number of CPU cycles since power on
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn cartridge(&self) -> &Cartridge {
+        &self.cartridge
+    }
+
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut CPU {
+        &mut self.cpu
+    }
+
+    pub fn ppu(&self) -> &PPU {
+        &self.ppu
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        savestate::save(&self.cpu, &self.ppu, self.rom_checksum, self.cycles)
+    }
+
+    // A state that cannot be loaded, or that was saved with another ROM, leaves the console as it was
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), String> {
+        self.cycles = savestate::load(&mut self.cpu, &mut self.ppu, self.rom_checksum, data)?;
+        Ok(())
+    }
+
+    pub fn save_state_to_file(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.save_state()).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn load_state_from_file(&mut self, path: &str) -> Result<(), String> {
+        let data = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        self.load_state(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes to RAM, PRG RAM and the nametables, then spins
+    fn cartridge() -> Cartridge {
+        let program: &[u8] = &[
+            0xA9, 0x55, 0x85, 0x10,       // LDA #$55, STA $10
+            0x8D, 0x00, 0x60,             // STA $6000
+            0xA9, 0x20, 0x8D, 0x06, 0x20, // LDA #$20, STA $2006
+            0xA9, 0x00, 0x8D, 0x06, 0x20, // LDA #$00, STA $2006
+            0xA9, 0x55, 0x8D, 0x07, 0x20, // LDA #$55, STA $2007
+            0x4C, 0x16, 0x80,             // JMP to itself
+        ];
+        Cartridge::nrom(&[(0x8000, program)], [0x8000; 3])
+    }
+
+    #[test]
+    fn a_power_cycle_clears_what_a_reset_leaves() {
+        let mut nes = Nes::new(cartridge()).unwrap();
+        nes.run_frame();
+        nes.run_frame();
+
+        nes.reset();
+        assert_eq!(nes.cpu().registers.pc(), 0x8000);
+        assert_eq!(nes.cpu().memory().peek8(0x10), 0x55);
+
+        let frame = nes.frame();
+        nes.power_cycle();
+        assert_eq!(nes.cpu().registers.pc(), 0x8000);
+        assert_eq!(nes.cpu().memory().peek8(0x10), 0);
+        assert_eq!(nes.cpu().memory().peek8(0x6000), 0);
+        assert_eq!(nes.ppu().vram().borrow().read8(0x2000), 0);
+        assert_eq!((nes.frame(), nes.ppu().scanline()), (frame, 0));
+
+        nes.run_frame();
+        assert_eq!(nes.frame(), frame + 1);
+        assert_eq!(nes.cpu().memory().peek8(0x10), 0x55);
+    }
+}
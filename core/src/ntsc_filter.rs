@@ -0,0 +1,254 @@
+// An NTSC composite video filter in the spirit of Blargg's nes_ntsc. Each line of palette indices is
+// turned into the signal the PPU puts out, eight samples per pixel at the master clock, and decoded
+// again the way a TV does, so colours bleed into each other, edges fringe and the dots crawl.
+// Every three pixels come out as seven, which gives a 602 pixel wide frame.
+
+use crate::palette::{self, NtscParameters};
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+pub const OUTPUT_WIDTH: usize = 602;
+
+// Master clock samples per pixel, and per colour clock
+const SAMPLES_PER_PIXEL: usize = 8;
+const SAMPLES_PER_CYCLE: usize = 12;
+const LINE_SAMPLES: usize = SCREEN_WIDTH * SAMPLES_PER_PIXEL;
+// A line is 341 dots, so the colour clock is four samples further along at the start of each line
+const LINE_PHASE_STEP: usize = (341 * SAMPLES_PER_PIXEL) % SAMPLES_PER_CYCLE;
+
+// The controls that are not part of the picture go from -1 to 1, with 0 the look of a composite cable
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NtscSetup {
+    pub picture: NtscParameters,
+    // Unsharp masking of luma, below 0 blurs it
+    pub sharpness: f64,
+    // How much luma detail survives separating it from chroma
+    pub resolution: f64,
+    // Chroma leaking into luma, the dot crawl and rainbow patterns
+    pub artifacts: f64,
+    // Luma edges leaking into chroma, the colour fringes around them
+    pub fringing: f64,
+    // How far colour spreads sideways
+    pub bleed: f64,
+}
+
+impl NtscSetup {
+    pub fn composite() -> NtscSetup {
+        NtscSetup { picture: NtscParameters::new(), sharpness: 0.0, resolution: 0.0, artifacts: 0.0, fringing: 0.0, bleed: 0.0 }
+    }
+
+    pub fn svideo() -> NtscSetup {
+        NtscSetup { sharpness: 0.2, resolution: 0.2, artifacts: -1.0, fringing: -1.0, ..NtscSetup::composite() }
+    }
+
+    pub fn rgb() -> NtscSetup {
+        NtscSetup { sharpness: 0.2, resolution: 0.7, artifacts: -1.0, fringing: -1.0, bleed: -1.0, ..NtscSetup::composite() }
+    }
+
+    pub fn monochrome() -> NtscSetup {
+        let picture = NtscParameters { saturation: 0.0, ..NtscParameters::new() };
+        NtscSetup { picture, sharpness: 0.2, resolution: 0.2, artifacts: -0.2, fringing: -0.2, bleed: -1.0 }
+    }
+
+    pub fn from_name(name: &str) -> Result<NtscSetup, String> {
+        match name.to_lowercase().as_str() {
+            "composite" => Ok(NtscSetup::composite()),
+            "svideo" | "s-video" => Ok(NtscSetup::svideo()),
+            "rgb" => Ok(NtscSetup::rgb()),
+            "monochrome" => Ok(NtscSetup::monochrome()),
+            _ => Err(format!("Unknown NTSC filter preset {}, it has to be composite, svideo, rgb or monochrome", name))
+        }
+    }
+}
+
+pub struct NtscFilter {
+    setup: NtscSetup,
+    // The signal of every colour and emphasis combination, at emphasis << 6 | index
+    levels: Vec<[f32; SAMPLES_PER_CYCLE]>,
+    // The average of each of those, which is its luma
+    luma: Vec<f32>,
+    // The subcarrier for demodulating, per phase
+    cos: [f32; SAMPLES_PER_CYCLE],
+    sin: [f32; SAMPLES_PER_CYCLE],
+    chroma_width: usize,
+
+    // Scratch space for a line
+    ideal_luma: Vec<f32>,
+    ideal_chroma: Vec<f32>,
+    line_luma: Vec<f32>,
+    line_i: Vec<f32>,
+    line_q: Vec<f32>,
+    filtered: Vec<f32>,
+}
+
+impl NtscFilter {
+    pub fn new(setup: NtscSetup) -> NtscFilter {
+        let mut levels = vec![];
+        let mut luma = vec![];
+        for color in 0..512u32 {
+            let signal = palette::composite_levels((color & 0x3F) as u8, (color >> 6) as u8);
+            levels.push(signal.map(|level| level as f32));
+            luma.push((signal.iter().sum::<f64>() / SAMPLES_PER_CYCLE as f64) as f32);
+        }
+
+        let mut cos = [0.0; SAMPLES_PER_CYCLE];
+        let mut sin = [0.0; SAMPLES_PER_CYCLE];
+        for phase in 0..SAMPLES_PER_CYCLE {
+            let angle = palette::phase_angle(phase as f64, &setup.picture);
+            cos[phase] = angle.cos() as f32;
+            sin[phase] = angle.sin() as f32;
+        }
+
+        // Averaging over whole half cycles keeps the demodulated carrier out of the colour
+        let half_cycles = 2 + ((setup.bleed.clamp(-1.0, 1.0) + 1.0) * 2.0).round() as usize;
+
+        NtscFilter {
+            setup,
+            levels,
+            luma,
+            cos,
+            sin,
+            chroma_width: half_cycles * SAMPLES_PER_CYCLE / 2,
+            ideal_luma: vec![0.0; LINE_SAMPLES],
+            ideal_chroma: vec![0.0; LINE_SAMPLES],
+            line_luma: vec![0.0; LINE_SAMPLES],
+            line_i: vec![0.0; LINE_SAMPLES],
+            line_q: vec![0.0; LINE_SAMPLES],
+            filtered: vec![0.0; LINE_SAMPLES],
+        }
+    }
+
+    pub fn setup(&self) -> &NtscSetup {
+        &self.setup
+    }
+
+    // A frame of palette indices and the emphasis of each line to OUTPUT_WIDTH x 240 RGB. The colour
+    // clock lines up differently every other frame, `frame` picks which, so the dots crawl.
+    pub fn apply(&mut self, framebuffer: &[u8], emphasis: &[u8], frame: u64) -> Vec<u8> {
+        let mut rgb = Vec::with_capacity(OUTPUT_WIDTH * SCREEN_HEIGHT * 3);
+        let frame_phase = (frame % 2) as usize * LINE_PHASE_STEP;
+        for (y, line) in framebuffer.chunks(SCREEN_WIDTH).take(SCREEN_HEIGHT).enumerate() {
+            let phase = (frame_phase + y * LINE_PHASE_STEP) % SAMPLES_PER_CYCLE;
+            self.filter_line(line, emphasis.get(y).copied().unwrap_or(0), phase, &mut rgb);
+        }
+        rgb
+    }
+
+    fn filter_line(&mut self, line: &[u8], emphasis: u8, phase: usize, rgb: &mut Vec<u8>) {
+        let setup = self.setup;
+        let amount = |control: f64| ((control.clamp(-1.0, 1.0) + 1.0) / 2.0) as f32;
+        let resolution = amount(setup.resolution);
+        let artifacts = amount(setup.artifacts) * 0.3;
+        let fringing = amount(setup.fringing);
+        let sharpness = setup.sharpness.clamp(-1.0, 1.0) as f32;
+
+        // The signal, split into the luma and chroma each pixel would have on its own
+        for (n, (luma, chroma)) in self.ideal_luma.iter_mut().zip(self.ideal_chroma.iter_mut()).enumerate() {
+            let color = ((emphasis as usize & 7) << 6) | (line[n / SAMPLES_PER_PIXEL] & 0x3F) as usize;
+            *luma = self.luma[color];
+            *chroma = self.levels[color][(n + phase) % SAMPLES_PER_CYCLE] - *luma;
+        }
+
+        // Separating luma from chroma blurs it over a colour clock, and lets some chroma through
+        box_filter(&self.ideal_luma, SAMPLES_PER_CYCLE, &mut self.filtered);
+        for n in 0..LINE_SAMPLES {
+            let blurred = self.filtered[n];
+            let ideal = self.ideal_luma[n];
+            self.line_luma[n] = blurred + (ideal - blurred) * resolution + self.ideal_chroma[n] * artifacts;
+
+            // What is left of a luma edge after separation ends up in chroma
+            let chroma = self.ideal_chroma[n] + (ideal - blurred) * fringing;
+            let carrier = (n + phase) % SAMPLES_PER_CYCLE;
+            self.line_i[n] = chroma * self.cos[carrier];
+            self.line_q[n] = chroma * self.sin[carrier];
+        }
+
+        if sharpness != 0.0 {
+            box_filter(&self.line_luma, SAMPLES_PER_PIXEL, &mut self.filtered);
+            for (luma, soft) in self.line_luma.iter_mut().zip(&self.filtered) {
+                *luma += (*luma - soft) * sharpness;
+            }
+        }
+
+        box_filter(&self.line_i, self.chroma_width, &mut self.filtered);
+        std::mem::swap(&mut self.line_i, &mut self.filtered);
+        box_filter(&self.line_q, self.chroma_width, &mut self.filtered);
+        std::mem::swap(&mut self.line_q, &mut self.filtered);
+
+        // Seven output pixels for every 24 samples, past the end of the line is the black border
+        for x in 0..OUTPUT_WIDTH {
+            let n = ((x as f64 + 0.5) * (3 * SAMPLES_PER_PIXEL) as f64 / 7.0) as usize;
+            let color = if n < LINE_SAMPLES {
+                palette::yiq_to_rgb(self.line_luma[n] as f64, self.line_i[n] as f64, self.line_q[n] as f64, &setup.picture)
+            } else {
+                palette::yiq_to_rgb(0.0, 0.0, 0.0, &setup.picture)
+            };
+            rgb.extend_from_slice(&color);
+        }
+    }
+}
+
+// A moving average of `width` samples centred on each one, with zeros past the ends
+fn box_filter(input: &[f32], width: usize, output: &mut [f32]) {
+    let before = width / 2;
+    let mut sum = 0.0;
+    for n in 0..input.len() + before {
+        if n < input.len() {
+            sum += input[n];
+        }
+        if n >= width {
+            sum -= input[n - width];
+        }
+        if n >= before {
+            output[n - before] = sum / width as f32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::Palette;
+
+    fn flat_frame(index: u8) -> Vec<u8> {
+        vec![index; SCREEN_WIDTH * SCREEN_HEIGHT]
+    }
+
+    // Composite puts some chroma in the luma, which shows up as dots even on a flat colour
+    #[test]
+    fn a_flat_colour_comes_out_as_the_generated_palette_has_it() {
+        let palette = Palette::generate(&NtscParameters::new());
+        let mut filter = NtscFilter::new(NtscSetup::rgb());
+        for index in [0x16, 0x2A, 0x11, 0x30] {
+            let rgb = filter.apply(&flat_frame(index), &[0; SCREEN_HEIGHT], 0);
+            assert_eq!(rgb.len(), OUTPUT_WIDTH * SCREEN_HEIGHT * 3);
+
+            let middle = (100 * OUTPUT_WIDTH + 300) * 3;
+            let expected = palette.rgb(index, 0);
+            for channel in 0..3 {
+                assert!((rgb[middle + channel] as i32 - expected[channel] as i32).abs() <= 2,
+                        "${:02X} came out as {:?}, not {:?}", index, &rgb[middle..middle + 3], expected);
+            }
+        }
+    }
+
+    #[test]
+    fn monochrome_has_no_colour() {
+        let mut filter = NtscFilter::new(NtscSetup::monochrome());
+        let frame: Vec<u8> = (0..SCREEN_WIDTH * SCREEN_HEIGHT).map(|i| ((i / 3) % 0x40) as u8).collect();
+        let rgb = filter.apply(&frame, &[0; SCREEN_HEIGHT], 1);
+        assert!(rgb.chunks(3).all(|pixel| pixel[0] == pixel[1] && pixel[1] == pixel[2]));
+    }
+
+    #[test]
+    fn composite_dots_crawl_between_frames() {
+        let mut filter = NtscFilter::new(NtscSetup::composite());
+        let frame: Vec<u8> = (0..SCREEN_WIDTH * SCREEN_HEIGHT).map(|i| if i % 2 == 0 { 0x30 } else { 0x0F }).collect();
+        assert!(filter.apply(&frame, &[0; SCREEN_HEIGHT], 0) != filter.apply(&frame, &[0; SCREEN_HEIGHT], 1));
+
+        // Away from the ends of the line, where the filters run into the border
+        let mut filter = NtscFilter::new(NtscSetup::rgb());
+        let frame = flat_frame(0x16);
+        let middle = |rgb: Vec<u8>| rgb.chunks(OUTPUT_WIDTH * 3).map(|line| line[30..OUTPUT_WIDTH * 3 - 60].to_vec()).collect::<Vec<_>>();
+        assert!(middle(filter.apply(&frame, &[0; SCREEN_HEIGHT], 0)) == middle(filter.apply(&frame, &[0; SCREEN_HEIGHT], 1)));
+    }
+}
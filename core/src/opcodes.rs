@@ -8,8 +8,6 @@ pub(crate) fn brk_implied(regs: &mut CPURegisters, mem: &mut RamController) -> i
     stack::push(regs, mem, (regs.pc() & 0xFF) as u8);
     stack::push(regs, mem, ((regs.pc() >> 8) & 0xFF) as u8);
 
-    print!("        ");
-
     // From the nesdev wiki:
     // In the byte pushed, bit 5 is always set to 1, and bit 4 is 1 if from an
     // instruction (PHP or BRK) or 0 if from an interrupt line being pulled low
@@ -29,7 +27,7 @@ fn ora(regs: &mut CPURegisters, value: u8) {
 }
 
 pub(crate) fn ora_immediate(regs: &mut CPURegisters, mem: &RamController) -> i32 {
-    let address = mode::immediate(regs, mem);
+    let address = mode::immediate(regs);
     ora(regs, mem.read8(address));
 
     2
@@ -99,8 +97,6 @@ pub(crate) fn asl_accumulator(regs: &mut CPURegisters) -> i32 {
     regs.set_flag_if(CPUFlags::Carry, (regs.accumulator() & 0x80) == 0x80);
     regs.set_accumulator(regs.accumulator() << 1);
 
-    print!("        ");
-
     2
 }
 
@@ -129,8 +125,6 @@ pub(crate) fn pla_implied(regs: &mut CPURegisters, mem: &RamController) -> i32 {
     let value = stack::pop(regs, mem);
     regs.set_accumulator(value);
 
-    print!("        ");
-
     4
 }
 
@@ -138,8 +132,6 @@ pub(crate) fn rts_implied(regs: &mut CPURegisters, mem: &RamController) -> i32 {
     let low = stack::pop(regs, mem);
     let high = stack::pop(regs, mem);
 
-    print!("        ");
-
     let address = low as u16 | ((high as u16) << 8);
     regs.set_pc(address + 1);
 
@@ -162,7 +154,7 @@ fn adc(regs: &mut CPURegisters, value: u8) {
 }
 
 pub(crate) fn adc_immediate(regs: &mut CPURegisters, mem: &RamController) -> i32 {
-    let address = mode::immediate(regs, mem);
+    let address = mode::immediate(regs);
     adc(regs, mem.read8(address));
 
     2
@@ -223,8 +215,6 @@ pub(crate) fn adc_indirect_y(regs: &mut CPURegisters, mem: &RamController) -> i3
 pub(crate) fn sei_implied(regs: &mut CPURegisters) -> i32 {
     regs.set_flag(CPUFlags::InterruptDisable);
 
-    print!("        ");
-
     2
 }
 
@@ -256,13 +246,11 @@ pub(crate) fn bne_relative(regs: &mut CPURegisters, mem: &RamController) -> i32
 pub(crate) fn cld_implied(regs: &mut CPURegisters) -> i32 {
     regs.clear_flag(CPUFlags::ClearDecimalMode);
 
-    print!("        ");
-
     2
 }
 
 pub(crate) fn ldy_immediate(regs: &mut CPURegisters, mem: &RamController) -> i32 {
-    let address = mode::immediate(regs, mem);
+    let address = mode::immediate(regs);
     regs.set_y(mem.read8(address));
 
     2
@@ -297,7 +285,7 @@ pub(crate) fn ldy_absolute_x(regs: &mut CPURegisters, mem: &RamController) -> i3
 }
 
 pub(crate) fn ldx_immediate(regs: &mut CPURegisters, mem: &RamController) -> i32 {
-    let address = mode::immediate(regs, mem);
+    let address = mode::immediate(regs);
     regs.set_x(mem.read8(address));
 
     2
@@ -386,48 +374,36 @@ pub(crate) fn sty_absolute(regs: &mut CPURegisters, mem: &mut RamController) ->
 pub(crate) fn tay_implied(regs: &mut CPURegisters) -> i32 {
     regs.set_y(regs.accumulator());
 
-    print!("        ");
-
     2
 }
 
 pub(crate) fn tax_implied(regs: &mut CPURegisters) -> i32 {
     regs.set_x(regs.accumulator());
 
-    print!("        ");
-
     2
 }
 
 pub(crate) fn tsx_implied(regs: &mut CPURegisters) -> i32 {
     regs.set_x(regs.stack() as u8);
 
-    print!("        ");
-
     2
 }
 
 pub(crate) fn txs_implied(regs: &mut CPURegisters) -> i32 {
     regs.set_stack(regs.x().into());
 
-    print!("        ");
-
     2
 }
 
 pub(crate) fn txa_implied(regs: &mut CPURegisters) -> i32 {
     regs.set_accumulator(regs.x());
 
-    print!("        ");
-
     2
 }
 
 pub(crate) fn tya_implied(regs: &mut CPURegisters) -> i32 {
     regs.set_accumulator(regs.y());
 
-    print!("        ");
-
     2
 }
 
@@ -530,7 +506,7 @@ pub(crate) fn lda_indirect_y(regs: &mut CPURegisters, mem: &RamController) -> i3
 }
 
 pub(crate) fn lda_immediate(regs: &mut CPURegisters, mem: &RamController) -> i32 {
-    let address = mode::immediate(regs, mem);
+    let address = mode::immediate(regs);
     regs.set_accumulator(mem.read8(address));
 
     2
@@ -586,8 +562,6 @@ pub(crate) fn bpl_relative(regs: &mut CPURegisters, mem: &RamController) -> i32
 pub(crate) fn clc_implied(regs: &mut CPURegisters) -> i32 {
     regs.clear_flag(CPUFlags::Carry);
 
-    print!("        ");
-
     2
 }
 
@@ -636,8 +610,6 @@ pub(crate) fn rol_accumulator(regs: &mut CPURegisters) -> i32 {
 
     regs.set_accumulator(result);
 
-    print!("        ");
-
     2
 }
 
@@ -685,8 +657,6 @@ pub(crate) fn ror_accumulator(regs: &mut CPURegisters) -> i32 {
 
     regs.set_accumulator(result);
 
-    print!("        ");
-
     2
 }
 
@@ -751,7 +721,7 @@ pub(crate) fn and_indirect_y(regs: &mut CPURegisters, mem: &RamController) -> i3
 }
 
 pub(crate) fn and_immediate(regs: &mut CPURegisters, mem: &RamController) -> i32 {
-    let address = mode::immediate(regs, mem);
+    let address = mode::immediate(regs);
     and(regs, mem, address);
 
     2
@@ -800,16 +770,12 @@ pub(crate) fn bmi_relative(regs: &mut CPURegisters, mem: &RamController) -> i32
 pub(crate) fn sec_implied(regs: &mut CPURegisters) -> i32 {
     regs.set_flag(CPUFlags::Carry);
 
-    print!("        ");
-
     2
 }
 
 pub(crate) fn pha_implied(regs: &mut CPURegisters, mem: &mut RamController) -> i32 {
     stack::push(regs, mem, regs.accumulator());
 
-    print!("        ");
-
     3
 }
 
@@ -818,8 +784,6 @@ pub(crate) fn lsr_accumulator(regs: &mut CPURegisters) -> i32 {
     regs.set_flag_if(CPUFlags::Carry, (old_value & 1) == 1);
     regs.set_accumulator(old_value >> 1);
 
-    print!("        ");
-
     2
 }
 
@@ -896,16 +860,12 @@ pub(crate) fn bvs_relative(regs: &mut CPURegisters, mem: &RamController) -> i32
 pub(crate) fn cli_implied(regs: &mut CPURegisters) -> i32 {
     regs.clear_flag(CPUFlags::InterruptDisable);
 
-    print!("        ");
-
     2
 }
 
 pub(crate) fn clv_implied(regs: &mut CPURegisters) -> i32 {
     regs.clear_flag(CPUFlags::Overflow);
 
-    print!("        ");
-
     2
 }
 
@@ -918,7 +878,7 @@ fn cmp(regs: &mut CPURegisters, register_value: u8, value: u8) {
 }
 
 pub(crate) fn cmp_immediate(regs: &mut CPURegisters, mem: &RamController) -> i32 {
-    let address = mode::immediate(regs, mem);
+    let address = mode::immediate(regs);
     cmp(regs, regs.accumulator(), mem.read8(address));
 
     2
@@ -974,7 +934,7 @@ pub(crate) fn cmp_indirect_y(regs: &mut CPURegisters, mem: &RamController) -> i3
 }
 
 pub(crate) fn cpx_immediate(regs: &mut CPURegisters, mem: &RamController) -> i32 {
-    let address= mode::immediate(regs, mem);
+    let address= mode::immediate(regs);
     cmp(regs, regs.x(), mem.read8(address));
 
     2
@@ -995,7 +955,7 @@ pub(crate) fn cpx_absolute(regs: &mut CPURegisters, mem: &RamController) -> i32
 }
 
 pub(crate) fn cpy_immediate(regs: &mut CPURegisters, mem: &RamController) -> i32 {
-    let address= mode::immediate(regs, mem);
+    let address= mode::immediate(regs);
     cmp(regs, regs.y(), mem.read8(address));
 
     2
@@ -1048,16 +1008,12 @@ pub(crate) fn dec_absolute_x(regs: &mut CPURegisters, mem: &mut RamController) -
 pub(crate) fn dex_implied(regs: &mut CPURegisters) -> i32 {
     regs.set_x(regs.x().wrapping_sub(1));
 
-    print!("        ");
-
     2
 }
 
 pub(crate) fn dey_implied(regs: &mut CPURegisters) -> i32 {
     regs.set_y(regs.y().wrapping_sub(1));
 
-    print!("        ");
-
     2
 }
 
@@ -1066,7 +1022,7 @@ pub(crate) fn eor(regs: &mut CPURegisters, value: u8) {
 }
 
 pub(crate) fn eor_immediate(regs: &mut CPURegisters, mem: &RamController) -> i32 {
-    let address = mode::immediate(regs, mem);
+    let address = mode::immediate(regs);
     eor(regs, mem.read8(address));
 
     2
@@ -1128,8 +1084,6 @@ pub(crate) fn rti_implied(regs: &mut CPURegisters, mem: &RamController) -> i32 {
     let new_pc = stack::pop(regs, mem) as u16 | ((stack::pop(regs, mem) as u16) << 8);
     regs.set_pc(new_pc);
 
-    print!("        ");
-
     6
 }
 
@@ -1166,16 +1120,12 @@ pub(crate) fn inc_absolute_x(regs: &mut CPURegisters, mem: &mut RamController) -
 pub(crate) fn inx_implied(regs: &mut CPURegisters) -> i32 {
     regs.set_x(regs.x().wrapping_add(1));
 
-    print!("        ");
-
     2
 }
 
 pub(crate) fn iny_implied(regs: &mut CPURegisters) -> i32 {
     regs.set_y(regs.y().wrapping_add(1));
 
-    print!("        ");
-
     2
 }
 
@@ -1184,7 +1134,7 @@ fn sbc(regs: &mut CPURegisters, value: u8) {
 }
 
 pub(crate) fn sbc_immediate(regs: &mut CPURegisters, mem: &RamController) -> i32 {
-    let address = mode::immediate(regs, mem);
+    let address = mode::immediate(regs);
     sbc(regs, mem.read8(address));
     2
 }
@@ -1237,13 +1187,10 @@ pub(crate) fn sbc_indirect_y(regs: &mut CPURegisters, mem: &RamController) -> i3
 pub(crate) fn sed_implied(regs: &mut CPURegisters) -> i32 {
     regs.set_flag(CPUFlags::ClearDecimalMode);
 
-    print!("        ");
-
     2
 }
 
 pub(crate) fn nop_implied() -> i32 {
-    print!("        ");
 
     2
 }
@@ -1252,7 +1199,7 @@ pub(crate) fn nop_immediate(regs: &mut CPURegisters, mem: &RamController) -> i32
     //TODO All the comments below about reads having no side effects are wrong. I dont know why i thought so a year ago. They all increment the program counter.
 
     // TODO: This is just here for the output log, can remove later as immediate reads have no side effects
-    let address = mode::immediate(regs, mem);
+    let address = mode::immediate(regs);
     mem.read8(address);
 
     2
@@ -1575,8 +1522,6 @@ pub(crate) fn php_implied(regs: &mut CPURegisters, mem: &mut RamController) -> i
     // (/IRQ or /NMI)
     stack::push(regs, mem, regs.status() | CPUFlags::Unused as u8 | CPUFlags::BreakCommand as u8);
 
-    print!("        ");
-
     3
 }
 
@@ -1586,8 +1531,6 @@ pub(crate) fn plp_implied(regs: &mut CPURegisters, mem: &mut RamController) -> i
 
     regs.set_status((status | CPUFlags::Unused as u8) & !(CPUFlags::BreakCommand as u8));
 
-    print!("        ");
-
     4
 }
 
@@ -1602,20 +1545,14 @@ mod addressing_mode
     use crate::cpuregisters::CPURegisters;
     use crate::ram_controller::RamController;
 
-    pub(crate) fn immediate(regs: &mut CPURegisters, mem: &RamController) -> u16 {
-        let address = regs.increment_pc();
-
-        print!("{:02X}      ", mem.read8(address));
-
-        address
+    pub(crate) fn immediate(regs: &mut CPURegisters) -> u16 {
+        regs.increment_pc()
     }
 
     pub(crate) fn absolute(regs: &mut CPURegisters, mem: &RamController) -> u16 {
         let low = mem.read8(regs.increment_pc());
         let high = mem.read8(regs.increment_pc());
 
-        print!("{:02X} {:02X}   ", low, high);
-
         low as u16 | ((high as u16) << 8)
     }
 
@@ -1624,8 +1561,6 @@ mod addressing_mode
         let mut low = mem.read8(regs.increment_pc());
         let mut high = mem.read8(regs.increment_pc());
 
-        print!("{:02X} {:02X}   ", low, high);
-
         low = low.wrapping_add(index);
 
         if low < index {
@@ -1641,25 +1576,21 @@ mod addressing_mode
 
     pub(crate) fn zero_page(regs: &mut CPURegisters, mem: &RamController) -> u16 {
         let address = mem.read8(regs.increment_pc());
-        print!("{:02X}      ", address);
         address as u16
     }
 
     pub(crate) fn zero_page_x(regs: &mut CPURegisters, mem: &RamController) -> u16 {
         let address = mem.read8(regs.increment_pc());
-        print!("{:02X}      ", address);
         address.wrapping_add(regs.x()) as u16
     }
 
     pub(crate) fn zero_page_y(regs: &mut CPURegisters, mem: &RamController) -> u16 {
         let address = mem.read8(regs.increment_pc());
-        print!("{:02X}      ", address);
         address.wrapping_add(regs.y()) as u16
     }
 
     pub(crate) fn relative(regs: &mut CPURegisters, mem: &RamController) -> i8 {
         let address = mem.read8(regs.increment_pc());
-        print!("{:02X}      ", address);
         address as i8
     }
 
@@ -1667,8 +1598,6 @@ mod addressing_mode
         let low = mem.read8(regs.increment_pc());
         let high = mem.read8(regs.increment_pc());
 
-        print!("{:02X} {:02X}   ", low, high);
-
         let address = low as u16 | ((high as u16) << 8);
 
         // from documentation at obelisk.me.uk:
@@ -1689,16 +1618,12 @@ mod addressing_mode
         let low = mem.read8(regs.increment_pc());
         let zero_page_address = low.wrapping_add(regs.x()); // TODO: Should I do wrapping_add here?
 
-        print!("{:02X}      ", low);
-
         mem.read8(zero_page_address as u16) as u16 | ((mem.read8(zero_page_address.wrapping_add(1) as u16) as u16) << 8)
     }
 
     pub(crate) fn indirect_indexed(regs: &mut CPURegisters, mem: &RamController) -> AddressingResult {
         let zero_page_address = mem.read8(regs.increment_pc());
 
-        print!("{:02X}      ", zero_page_address);
-
         let mut low = mem.read8(zero_page_address as u16);
         let mut high = mem.read8((zero_page_address.wrapping_add(1)) as u16); // TODO: Wrapping_add here?
 
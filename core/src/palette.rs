@@ -0,0 +1,257 @@
+// The colours of the 64 palette indices the PPU puts out, and the palette stage that turns a frame
+// of indices and emphasis bits into RGB
+
+use std::f64::consts::PI;
+use std::fs;
+
+// A 2C02 as captured from a composite NTSC console
+pub const NTSC: [[u8; 3]; 64] = [
+    [0x54, 0x54, 0x54], [0x00, 0x1E, 0x74], [0x08, 0x10, 0x90], [0x30, 0x00, 0x88],
+    [0x44, 0x00, 0x64], [0x5C, 0x00, 0x30], [0x54, 0x04, 0x00], [0x3C, 0x18, 0x00],
+    [0x20, 0x2A, 0x00], [0x08, 0x3A, 0x00], [0x00, 0x40, 0x00], [0x00, 0x3C, 0x00],
+    [0x00, 0x32, 0x3C], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0x98, 0x96, 0x98], [0x08, 0x4C, 0xC4], [0x30, 0x32, 0xEC], [0x5C, 0x1E, 0xE4],
+    [0x88, 0x14, 0xB0], [0xA0, 0x14, 0x64], [0x98, 0x22, 0x20], [0x78, 0x3C, 0x00],
+    [0x54, 0x5A, 0x00], [0x28, 0x72, 0x00], [0x08, 0x7C, 0x00], [0x00, 0x76, 0x28],
+    [0x00, 0x66, 0x78], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0xEC, 0xEE, 0xEC], [0x4C, 0x9A, 0xEC], [0x78, 0x7C, 0xEC], [0xB0, 0x62, 0xEC],
+    [0xE4, 0x54, 0xEC], [0xEC, 0x58, 0xB4], [0xEC, 0x6A, 0x64], [0xD4, 0x88, 0x20],
+    [0xA0, 0xAA, 0x00], [0x74, 0xC4, 0x00], [0x4C, 0xD0, 0x20], [0x38, 0xCC, 0x6C],
+    [0x38, 0xB4, 0xCC], [0x3C, 0x3C, 0x3C], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0xEC, 0xEE, 0xEC], [0xA8, 0xCC, 0xEC], [0xBC, 0xBC, 0xEC], [0xD4, 0xB2, 0xEC],
+    [0xEC, 0xAE, 0xEC], [0xEC, 0xAE, 0xD4], [0xEC, 0xB4, 0xB0], [0xE4, 0xC4, 0x90],
+    [0xCC, 0xD2, 0x78], [0xB4, 0xDE, 0x78], [0xA8, 0xE2, 0x90], [0x98, 0xE2, 0xB4],
+    [0xA0, 0xD6, 0xE4], [0xA0, 0xA2, 0xA0], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+];
+
+// Emphasis bits in red, green, blue order, which is how NTSC PPUMASK has them. The PPU swaps them on PAL.
+pub const EMPHASIS_RED: u8 = 0b001;
+pub const EMPHASIS_GREEN: u8 = 0b010;
+pub const EMPHASIS_BLUE: u8 = 0b100;
+
+// How much of a channel is left when another channel is emphasised, for palettes without emphasis colours
+const EMPHASIS_DIM: f64 = 0.816;
+
+// Composite signal levels in volts, from the NESdev wiki. The low and high half of the waveform for each luma level.
+const SIGNAL_LOW: [f64; 4] = [0.228, 0.312, 0.552, 0.880];
+const SIGNAL_HIGH: [f64; 4] = [0.616, 0.840, 1.100, 1.100];
+const SIGNAL_BLACK: f64 = 0.312;
+const SIGNAL_WHITE: f64 = 1.100;
+// An emphasis bit pulls the signal down to this during the phases of its colour
+const SIGNAL_EMPHASIS: f64 = 0.746;
+// Lines the colour phases up with the YIQ axes, in twelfths of a colour clock
+const PHASE_OFFSET: f64 = 4.0;
+
+// Knobs for generating a palette, like the ones on a TV
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NtscParameters {
+    // In degrees
+    pub hue: f64,
+    pub saturation: f64,
+    pub contrast: f64,
+    pub brightness: f64,
+    // Of the display, 2.2 leaves the decoded colours alone
+    pub gamma: f64,
+}
+
+impl NtscParameters {
+    pub fn new() -> NtscParameters {
+        NtscParameters { hue: 0.0, saturation: 1.0, contrast: 1.0, brightness: 0.0, gamma: 2.2 }
+    }
+
+    // "hue=-10,saturation=1.2" and so on, anything left out keeps its default. "default" is all defaults.
+    pub fn parse(text: &str) -> Result<NtscParameters, String> {
+        let mut parameters = NtscParameters::new();
+        for setting in text.split(',').map(|s| s.trim()).filter(|s| !s.is_empty() && *s != "default") {
+            let (name, value) = setting.split_once('=').ok_or(format!("Expected name=value, got {}", setting))?;
+            let value: f64 = value.trim().parse().map_err(|_| format!("{} has to be a number", name))?;
+            match name.trim() {
+                "hue" => parameters.hue = value,
+                "saturation" => parameters.saturation = value,
+                "contrast" => parameters.contrast = value,
+                "brightness" => parameters.brightness = value,
+                "gamma" if value > 0.0 => parameters.gamma = value,
+                "gamma" => return Err(String::from("gamma has to be above 0")),
+                other => return Err(format!("Unknown palette parameter {}, it has to be hue, saturation, contrast, brightness or gamma", other)),
+            }
+        }
+        Ok(parameters)
+    }
+}
+
+// RGB for every combination of the three emphasis bits and the 64 indices, at emphasis << 6 | index
+pub struct Palette {
+    colors: Vec<[u8; 3]>,
+}
+
+impl Palette {
+    // The built-in 2C02 colours
+    pub fn new() -> Palette {
+        Palette::with_emphasis(&NTSC)
+    }
+
+    // A .pal file, either 64 colours with emphasis worked out from them or all 512 combinations
+    pub fn from_pal(data: &[u8]) -> Result<Palette, String> {
+        let colors = || data.chunks(3).map(|c| [c[0], c[1], c[2]]).collect::<Vec<[u8; 3]>>();
+        match data.len() {
+            192 => Ok(Palette::with_emphasis(&colors())),
+            1536 => Ok(Palette { colors: colors() }),
+            length => Err(format!("A palette has 64 or 512 colours (192 or 1536 bytes), this one is {} bytes", length)),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Palette, String> {
+        let data = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        Palette::from_pal(&data).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    // Decodes the composite signal the PPU would put out for each colour, the way a TV does
+    pub fn generate(parameters: &NtscParameters) -> Palette {
+        let colors = (0..512).map(|i| generate_color((i & 0x3F) as u8, (i >> 6) as u8, parameters)).collect();
+        Palette { colors }
+    }
+
+    fn with_emphasis(base: &[[u8; 3]]) -> Palette {
+        let mut colors = Vec::with_capacity(512);
+        for emphasis in 0..8u8 {
+            for (index, color) in base.iter().enumerate() {
+                // The black columns stay black
+                if emphasis == 0 || index & 0x0F >= 0x0E {
+                    colors.push(*color);
+                    continue;
+                }
+                let mut emphasised = *color;
+                for (channel, value) in emphasised.iter_mut().enumerate() {
+                    if emphasis & !(1 << channel) != 0 {
+                        *value = (*value as f64 * EMPHASIS_DIM).round() as u8;
+                    }
+                }
+                colors.push(emphasised);
+            }
+        }
+        Palette { colors }
+    }
+
+    // `emphasis` is in EMPHASIS_* bits
+    pub fn rgb(&self, index: u8, emphasis: u8) -> [u8; 3] {
+        self.colors[((emphasis & 7) as usize) << 6 | (index & 0x3F) as usize]
+    }
+
+    // A frame of palette indices to RGB, with the emphasis each line was drawn with
+    pub fn frame_to_rgb(&self, framebuffer: &[u8], emphasis: &[u8]) -> Vec<u8> {
+        let width = framebuffer.len() / emphasis.len().max(1);
+        let mut rgb = Vec::with_capacity(framebuffer.len() * 3);
+        for (line, line_emphasis) in framebuffer.chunks(width).zip(emphasis) {
+            for index in line {
+                rgb.extend_from_slice(&self.rgb(*index, *line_emphasis));
+            }
+        }
+        rgb
+    }
+}
+
+// The signal of a colour over the twelve phases of the colour clock, 0 at black and 1 at white.
+// Phase `p` lines up with angle PI * (p + PHASE_OFFSET) / 6 when decoding.
+pub(crate) fn composite_levels(index: u8, emphasis: u8) -> [f64; 12] {
+    let color = (index & 0x0F) as i32;
+    // Columns $E and $F put out the same black as $1D
+    let level = if color >= 0x0E { 1 } else { ((index >> 4) & 3) as usize };
+    let (low, high) = match color {
+        0x00 => (SIGNAL_HIGH[level], SIGNAL_HIGH[level]),
+        0x0D..=0x0F => (SIGNAL_LOW[level], SIGNAL_LOW[level]),
+        _ => (SIGNAL_LOW[level], SIGNAL_HIGH[level]),
+    };
+    // Each colour is a square wave, high for six of the twelve phases
+    let in_phase = |color: i32, phase: i32| (color + phase) % 12 < 6;
+
+    let mut levels = [0.0; 12];
+    for (phase, level) in levels.iter_mut().enumerate() {
+        let phase = phase as i32;
+        let mut signal = if in_phase(color, phase) { high } else { low };
+        let attenuated = (emphasis & EMPHASIS_RED != 0 && in_phase(0x0C, phase))
+            || (emphasis & EMPHASIS_GREEN != 0 && in_phase(0x04, phase))
+            || (emphasis & EMPHASIS_BLUE != 0 && in_phase(0x08, phase));
+        if attenuated && color < 0x0E {
+            signal *= SIGNAL_EMPHASIS;
+        }
+        *level = (signal - SIGNAL_BLACK) / (SIGNAL_WHITE - SIGNAL_BLACK);
+    }
+    levels
+}
+
+pub(crate) fn phase_angle(phase: f64, parameters: &NtscParameters) -> f64 {
+    PI * (phase + PHASE_OFFSET) / 6.0 + parameters.hue.to_radians()
+}
+
+// Decoded luma and chroma to RGB, with the picture controls applied
+pub(crate) fn yiq_to_rgb(y: f64, i: f64, q: f64, parameters: &NtscParameters) -> [u8; 3] {
+    let y = y * parameters.contrast + parameters.brightness;
+    let i = i * parameters.contrast * parameters.saturation;
+    let q = q * parameters.contrast * parameters.saturation;
+
+    let rgb = [
+        y + 0.946882 * i + 0.623557 * q,
+        y - 0.274788 * i - 0.635691 * q,
+        y - 1.108545 * i + 1.709007 * q,
+    ];
+    rgb.map(|channel| (channel.clamp(0.0, 1.0).powf(2.2 / parameters.gamma) * 255.0).round() as u8)
+}
+
+fn generate_color(index: u8, emphasis: u8, parameters: &NtscParameters) -> [u8; 3] {
+    let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+    for (phase, level) in composite_levels(index, emphasis).iter().enumerate() {
+        let angle = phase_angle(phase as f64, parameters);
+        y += level;
+        i += level * angle.cos();
+        q += level * angle.sin();
+    }
+    yiq_to_rgb(y / 12.0, i / 12.0, q / 12.0, parameters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pal_files_have_64_or_512_colours() {
+        let small: Vec<u8> = NTSC.concat();
+        let palette = Palette::from_pal(&small).unwrap();
+        assert_eq!(palette.rgb(0x16, 0), NTSC[0x16]);
+        // Red emphasis dims green and blue, and leaves the black columns alone
+        let [r, g, b] = palette.rgb(0x30, EMPHASIS_RED);
+        assert!(r == NTSC[0x30][0] && g < NTSC[0x30][1] && b < NTSC[0x30][2]);
+        assert_eq!(palette.rgb(0x0F, 7), [0, 0, 0]);
+
+        let full: Vec<u8> = (0..1536).map(|i| (i / 3) as u8).collect();
+        let palette = Palette::from_pal(&full).unwrap();
+        assert_eq!(palette.rgb(0x05, 3), [0xC5, 0xC5, 0xC5]);
+
+        assert!(Palette::from_pal(&small[..190]).is_err());
+    }
+
+    #[test]
+    fn a_generated_palette_looks_like_a_2c02() {
+        let palette = Palette::generate(&NtscParameters::new());
+        assert_eq!(palette.rgb(0x0F, 0), [0, 0, 0]);
+        assert_eq!(palette.rgb(0x30, 0), [255, 255, 255]);
+
+        let [r, g, b] = palette.rgb(0x16, 0);
+        assert!(r > g && r > b, "$16 should be red, not {:?}", [r, g, b]);
+        let [r, g, b] = palette.rgb(0x12, 0);
+        assert!(b > r && b > g, "$12 should be blue, not {:?}", [r, g, b]);
+        let [r, g, b] = palette.rgb(0x1A, 0);
+        assert!(g > r && g > b, "$1A should be green, not {:?}", [r, g, b]);
+
+        let [r, g, b] = palette.rgb(0x20, EMPHASIS_BLUE);
+        assert!(b > r && b > g);
+    }
+
+    #[test]
+    fn ntsc_parameters_parse() {
+        assert_eq!(NtscParameters::parse("default").unwrap(), NtscParameters::new());
+        let parameters = NtscParameters::parse("hue=-10, saturation=1.5").unwrap();
+        assert_eq!((parameters.hue, parameters.saturation, parameters.gamma), (-10.0, 1.5, 2.2));
+        assert!(NtscParameters::parse("tint=3").is_err());
+        assert!(NtscParameters::parse("gamma=0").is_err());
+    }
+}
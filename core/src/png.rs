@@ -0,0 +1,227 @@
+// PNG files, written for screenshots and golden images and read back for comparing against references
+
+use crate::deflate;
+use crate::hash::crc32;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+const COLOR_RGB: u8 = 2;
+const COLOR_INDEXED: u8 = 3;
+
+// An 8-bit indexed image, one palette index per pixel
+pub struct IndexedImage {
+    pub width: u32,
+    pub height: u32,
+    pub palette: Vec<[u8; 3]>,
+    pub pixels: Vec<u8>,
+    // The tEXt chunks, as (keyword, text)
+    pub text: Vec<(String, String)>,
+}
+
+pub fn encode_rgb(width: u32, height: u32, rgb: &[u8]) -> Vec<u8> {
+    encode(width, height, COLOR_RGB, None, &[], rgb)
+}
+
+pub fn encode_indexed(width: u32, height: u32, palette: &[[u8; 3]], pixels: &[u8]) -> Vec<u8> {
+    encode(width, height, COLOR_INDEXED, Some(palette), &[], pixels)
+}
+
+// With tEXt chunks of (keyword, text), like ("Title", "Super Mario Bros.")
+pub fn encode_rgb_with_text(width: u32, height: u32, rgb: &[u8], text: &[(&str, &str)]) -> Vec<u8> {
+    encode(width, height, COLOR_RGB, None, text, rgb)
+}
+
+pub fn encode_indexed_with_text(width: u32, height: u32, palette: &[[u8; 3]], pixels: &[u8], text: &[(&str, &str)]) -> Vec<u8> {
+    encode(width, height, COLOR_INDEXED, Some(palette), text, pixels)
+}
+
+fn encode(width: u32, height: u32, color_type: u8, palette: Option<&[[u8; 3]]>, text: &[(&str, &str)], pixels: &[u8]) -> Vec<u8> {
+    let mut data = SIGNATURE.to_vec();
+
+    let mut header = vec![];
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&[8, color_type, 0, 0, 0]);
+    write_chunk(&mut data, b"IHDR", &header);
+
+    if let Some(palette) = palette {
+        write_chunk(&mut data, b"PLTE", &palette.concat());
+    }
+
+    for (keyword, text) in text {
+        // tEXt is Latin-1, anything outside it becomes a question mark
+        let mut contents: Vec<u8> = keyword.chars().take(79).map(latin1).collect();
+        contents.push(0);
+        contents.extend(text.chars().map(latin1));
+        write_chunk(&mut data, b"tEXt", &contents);
+    }
+
+    // Every row with filter 0, which is what indexed images compress best with anyway
+    let stride = pixels.len() / height as usize;
+    let mut rows = vec![];
+    for row in pixels.chunks(stride) {
+        rows.push(0);
+        rows.extend_from_slice(row);
+    }
+    write_chunk(&mut data, b"IDAT", &zlib(&rows));
+
+    write_chunk(&mut data, b"IEND", &[]);
+    data
+}
+
+fn latin1(c: char) -> u8 {
+    if (c as u32) < 0x100 { c as u8 } else { b'?' }
+}
+
+fn write_chunk(data: &mut Vec<u8>, kind: &[u8; 4], contents: &[u8]) {
+    data.extend_from_slice(&(contents.len() as u32).to_be_bytes());
+    let start = data.len();
+    data.extend_from_slice(kind);
+    data.extend_from_slice(contents);
+    let crc = crc32(&data[start..]);
+    data.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut compressed = vec![0x78, 0x01];
+    compressed.extend(deflate::compress(data));
+    compressed.extend_from_slice(&adler32(data).to_be_bytes());
+    compressed
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+// Only reads non-interlaced 8-bit indexed images, which is what encode_indexed writes
+pub fn decode_indexed(data: &[u8]) -> Result<IndexedImage, String> {
+    if !data.starts_with(&SIGNATURE) {
+        return Err(String::from("Not a PNG file"));
+    }
+
+    let mut image = IndexedImage { width: 0, height: 0, palette: vec![], pixels: vec![], text: vec![] };
+    let mut compressed = vec![];
+    let mut position = SIGNATURE.len();
+    while position + 12 <= data.len() {
+        let length = u32::from_be_bytes([data[position], data[position + 1], data[position + 2], data[position + 3]]) as usize;
+        let chunk = data.get(position + 4..position + 8 + length).ok_or("PNG chunk runs past the end of the file")?;
+        let (kind, contents) = chunk.split_at(4);
+        position += 12 + length;
+
+        match kind {
+            b"IHDR" => {
+                if contents.len() != 13 {
+                    return Err(String::from("Invalid PNG header"));
+                }
+                image.width = u32::from_be_bytes([contents[0], contents[1], contents[2], contents[3]]);
+                image.height = u32::from_be_bytes([contents[4], contents[5], contents[6], contents[7]]);
+                if contents[8] != 8 || contents[9] != COLOR_INDEXED || contents[12] != 0 {
+                    return Err(String::from("Only 8-bit indexed PNGs without interlacing are supported"));
+                }
+            }
+            b"PLTE" => image.palette = contents.chunks(3).map(|c| [c[0], c[1], c[2]]).collect(),
+            b"IDAT" => compressed.extend_from_slice(contents),
+            b"tEXt" => {
+                let (keyword, text) = contents.split_at(contents.iter().position(|b| *b == 0).unwrap_or(contents.len()));
+                let latin1 = |bytes: &[u8]| bytes.iter().map(|b| *b as char).collect::<String>();
+                image.text.push((latin1(keyword), latin1(text.get(1..).unwrap_or(&[]))));
+            }
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+
+    if compressed.len() < 2 {
+        return Err(String::from("PNG has no image data"));
+    }
+    // Past the zlib header, the checksum at the end is never reached
+    let rows = deflate::decompress(&compressed[2..])?;
+
+    let width = image.width as usize;
+    if rows.len() < (width + 1) * image.height as usize {
+        return Err(String::from("PNG image data is too short"));
+    }
+
+    let mut previous = vec![0u8; width];
+    for row in rows.chunks(width + 1).take(image.height as usize) {
+        let mut line = row[1..].to_vec();
+        unfilter(row[0], &mut line, &previous)?;
+        image.pixels.extend_from_slice(&line);
+        previous = line;
+    }
+    Ok(image)
+}
+
+// With one byte per pixel, so the pixel to the left is always one byte back
+fn unfilter(filter: u8, line: &mut [u8], previous: &[u8]) -> Result<(), String> {
+    for x in 0..line.len() {
+        let left = if x > 0 { line[x - 1] } else { 0 };
+        let up = previous[x];
+        let up_left = if x > 0 { previous[x - 1] } else { 0 };
+
+        let predicted = match filter {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((left as u16 + up as u16) / 2) as u8,
+            4 => paeth(left, up, up_left),
+            _ => return Err(format!("Unknown PNG filter {}", filter)),
+        };
+        line[x] = line[x].wrapping_add(predicted);
+    }
+    Ok(())
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette;
+
+    #[test]
+    fn an_indexed_image_reads_back_the_same() {
+        let pixels: Vec<u8> = (0..256 * 240).map(|i: u32| ((i / 7) ^ (i / 256 / 3)) as u8 & 0x3F).collect();
+        let image = decode_indexed(&encode_indexed(256, 240, &palette::NTSC, &pixels)).unwrap();
+
+        assert_eq!((image.width, image.height), (256, 240));
+        assert_eq!(image.palette, palette::NTSC.to_vec());
+        assert!(image.pixels == pixels);
+    }
+
+    #[test]
+    fn compressed_data_reads_back_the_same() {
+        let data: Vec<u8> = (0..100000u32).map(|i| if i % 1000 < 500 { (i % 13) as u8 } else { ((i * 7919) >> 5) as u8 }).collect();
+        let compressed = deflate::compress(&data);
+        assert!(compressed.len() < data.len());
+        assert!(deflate::decompress(&compressed).unwrap() == data);
+    }
+
+    #[test]
+    fn stored_and_dynamic_blocks_decompress() {
+        let stored = [0x01, 0x03, 0x00, 0xFC, 0xFF, b'a', b'b', b'c'];
+        assert_eq!(deflate::decompress(&stored).unwrap(), b"abc");
+
+        // A string zlib -9 compressed with Huffman codes of its own
+        let dynamic = [
+            0x1D, 0x88, 0xC1, 0x11, 0x00, 0x30, 0x0C, 0x40, 0x66, 0x25, 0xF6, 0x9F, 0xA1, 0x69, 0x1E, 0xEE,
+            0x20, 0x03, 0xF2, 0x59, 0x09, 0x26, 0xDB, 0xD6, 0xFB, 0xA9, 0xE1, 0xF4, 0x00,
+        ];
+        assert_eq!(deflate::decompress(&dynamic).unwrap(), b"bacaabaaabacaadaacdbdbaabbcaabadbbbdabcd");
+    }
+}
@@ -0,0 +1,484 @@
+use crate::vram_controller::VRAMController;
+use crate::ppu_registers::{PPURegisters, VBlankRace};
+use crate::region::Region;
+use crate::savestate::{StateReader, StateWriter};
+use std::cell::{RefCell, Cell};
+use std::rc::Rc;
+
+pub struct PPU {
+    frame: u64,
+    scanline_cycle: i32,
+    scanline: i32,
+    // Left over from converting CPU cycles to dots, a PAL PPU runs 3.2 per cycle
+    dot_fraction: i32,
+    region: Region,
+    vram: Rc<RefCell<VRAMController>>,
+    ppu_regs: Rc<Cell<PPURegisters>>,
+    framebuffer: Vec<u8>,
+    // The PPUMASK emphasis bits of each line, in palette::EMPHASIS_* order
+    emphasis: Vec<u8>,
+}
+
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
+
+impl PPU {
+
+    // The dot within the current scanline, 0-340
+    pub fn pixel(&self) -> i32 {
+        self.scanline_cycle
+    }
+
+    pub fn scanline(&self) -> i32 {
+        self.scanline
+    }
+
+    // Number of frames since power on
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    // The last rendered frame, one 6-bit palette index per pixel
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    // The emphasis bits each line of the last frame was drawn with, see palette::Palette::frame_to_rgb
+    pub fn emphasis(&self) -> &[u8] {
+        &self.emphasis
+    }
+
+    pub fn registers(&self) -> &Cell<PPURegisters> {
+        &self.ppu_regs
+    }
+
+    pub fn vram(&self) -> &RefCell<VRAMController> {
+        &self.vram
+    }
+
+    // After a power cycle the frame count goes on from where the last PPU left it
+    pub fn continue_from_frame(&mut self, frame: u64) {
+        self.frame = frame;
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u64(self.frame);
+        writer.write_i32(self.scanline_cycle);
+        writer.write_i32(self.scanline);
+        writer.write_i32(self.dot_fraction);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        self.frame = reader.read_u64()?;
+        self.scanline_cycle = reader.read_i32()?;
+        self.scanline = reader.read_i32()?;
+        self.dot_fraction = reader.read_i32()?;
+
+        Ok(())
+    }
+
+    pub fn new(vram: Rc<RefCell<VRAMController>>, ppu_regs: Rc<Cell<PPURegisters>>, region: Region) -> PPU {
+        PPU {
+            frame: 0,
+            scanline_cycle: 0,
+            scanline: 0,
+            dot_fraction: 0,
+            region,
+            vram,
+            ppu_regs,
+            framebuffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            emphasis: vec![0; SCREEN_HEIGHT],
+        }
+    }
+
+    // Draws a whole visible scanline at once, from the registers as they are at the end of it
+    fn render_scanline(&mut self, y: usize) {
+        let regs = self.ppu_regs.get();
+        let ctrl = regs.ppuctrl();
+        let mask = regs.ppumask();
+        let vram = self.vram.borrow();
+
+        // Palette RAM offsets, 0 is transparent and shows the backdrop colour
+        let mut background = [0u8; SCREEN_WIDTH];
+        let mut sprites = [0u8; SCREEN_WIDTH];
+        let mut sprite_behind = [false; SCREEN_WIDTH];
+        let mut sprite_zero = [false; SCREEN_WIDTH];
+
+        if mask & 0b00001000 != 0 {
+            let (scroll_x, scroll_y) = regs.ppuscroll();
+            let pattern_table = if ctrl & 0b00010000 != 0 { 0x1000 } else { 0 };
+            let py = (((ctrl >> 1) & 1) as usize * SCREEN_HEIGHT + scroll_y as usize + y) % (SCREEN_HEIGHT * 2);
+
+            for (x, pixel) in background.iter_mut().enumerate() {
+                if x < 8 && mask & 0b00000010 == 0 {
+                    continue;
+                }
+
+                let px = ((ctrl & 1) as usize * SCREEN_WIDTH + scroll_x as usize + x) % (SCREEN_WIDTH * 2);
+                let nametable = 0x2000 + ((px / SCREEN_WIDTH) + (py / SCREEN_HEIGHT) * 2) * 0x400;
+                let tile_x = (px % SCREEN_WIDTH) / 8;
+                let tile_y = (py % SCREEN_HEIGHT) / 8;
+
+                let tile = vram.read8((nametable + tile_y * 32 + tile_x) as u16) as usize;
+                let attribute = vram.read8((nametable + 0x3C0 + (tile_y / 4) * 8 + tile_x / 4) as u16);
+                let palette = (attribute >> (((tile_y % 4) / 2) * 4 + ((tile_x % 4) / 2) * 2)) & 3;
+
+                let address = (pattern_table + tile * 16 + py % 8) as u16;
+                let bit = 7 - (px % 8);
+                let color = ((vram.read8(address) >> bit) & 1) | (((vram.read8(address + 8) >> bit) & 1) << 1);
+                if color != 0 {
+                    *pixel = (palette << 2) | color;
+                }
+            }
+        }
+
+        if mask & 0b00010000 != 0 {
+            let height = if ctrl & 0b00100000 != 0 { 16 } else { 8 };
+            let pattern_table = if ctrl & 0b00001000 != 0 { 0x1000 } else { 0 };
+            let mut count = 0;
+
+            for (index, sprite) in vram.oam.chunks(4).enumerate() {
+                // Sprites show up one line below their Y coordinate
+                let top = sprite[0] as usize + 1;
+                if y < top || y >= top + height {
+                    continue;
+                }
+
+                // Only eight sprites fit on a line
+                count += 1;
+                if count > 8 {
+                    break;
+                }
+
+                let attributes = sprite[2];
+                let row = if attributes & 0x80 != 0 { height - 1 - (y - top) } else { y - top };
+                let tile = sprite[1] as usize;
+                let address = if height == 16 {
+                    (tile & 1) * 0x1000 + ((tile & 0xFE) + row / 8) * 16 + row % 8
+                } else {
+                    pattern_table + tile * 16 + row
+                } as u16;
+                let low = vram.read8(address);
+                let high = vram.read8(address + 8);
+
+                for column in 0..8 {
+                    let x = sprite[3] as usize + column;
+                    if x >= SCREEN_WIDTH {
+                        break;
+                    }
+                    // A sprite earlier in OAM wins, even when it is behind the background
+                    if (x < 8 && mask & 0b00000100 == 0) || sprites[x] != 0 {
+                        continue;
+                    }
+
+                    let bit = if attributes & 0x40 != 0 { column } else { 7 - column };
+                    let color = ((low >> bit) & 1) | (((high >> bit) & 1) << 1);
+                    if color != 0 {
+                        sprites[x] = 0x10 | ((attributes & 3) << 2) | color;
+                        sprite_behind[x] = attributes & 0x20 != 0;
+                        sprite_zero[x] = index == 0;
+                    }
+                }
+            }
+        }
+
+        // Lines are drawn whole, so a hit is flagged at the end of its line instead of on its dot.
+        // There is never one at x = 255.
+        let hit = (0..SCREEN_WIDTH - 1).any(|x| sprite_zero[x] && background[x] != 0);
+
+        // Greyscale keeps only the luma bits of the index
+        let grey = if mask & 1 != 0 { 0x30 } else { 0x3F };
+        let line = &mut self.framebuffer[y * SCREEN_WIDTH..(y + 1) * SCREEN_WIDTH];
+        for (x, pixel) in line.iter_mut().enumerate() {
+            let index = if sprites[x] != 0 && (background[x] == 0 || !sprite_behind[x]) {
+                sprites[x]
+            } else {
+                background[x]
+            };
+            *pixel = vram.read8(0x3F00 + index as u16) & grey;
+        }
+
+        if hit {
+            let mut regs = self.ppu_regs.get();
+            regs.set_sprite_zero_hit();
+            self.ppu_regs.set(regs);
+        }
+
+        // Bits 5 and 6 are green and red on PAL and Dendy, red and green on NTSC
+        let emphasis = mask >> 5;
+        self.emphasis[y] = match self.region {
+            Region::Ntsc => emphasis,
+            Region::Pal | Region::Dendy => (emphasis & 4) | ((emphasis & 1) << 1) | ((emphasis >> 1) & 1),
+        };
+    }
+
+    // One dot. `scanline_cycle` is the dot about to be drawn on `scanline`.
+    fn tick(&mut self) {
+        let pre_render_scanline = self.region.scanlines() - 1;
+
+        if self.scanline_cycle == 1 && (self.scanline == self.region.vblank_scanline() || self.scanline == pre_render_scanline) {
+            let mut regs = self.ppu_regs.get();
+            if self.scanline == pre_render_scanline {
+                regs.end_vblank();
+            } else {
+                regs.start_vblank();
+            }
+            self.ppu_regs.set(regs);
+        }
+
+        self.scanline_cycle += 1;
+
+        // With rendering on, every other NTSC frame skips the last dot of the pre-render scanline
+        if self.scanline == pre_render_scanline && self.scanline_cycle == 340 && self.frame % 2 == 1
+            && self.region == Region::Ntsc && self.ppu_regs.get().ppumask() & 0b00011000 != 0 {
+            self.scanline_cycle = 341;
+        }
+
+        if self.scanline_cycle == 341 {
+            if self.scanline < SCREEN_HEIGHT as i32 {
+                self.render_scanline(self.scanline as usize);
+            }
+
+            self.scanline_cycle = 0;
+            self.scanline += 1;
+
+            if self.scanline == self.region.scanlines() {
+                self.scanline = 0;
+                self.frame += 1;
+            }
+        }
+    }
+
+    // Runs the PPU for as long as `cpu_cycles` CPU cycles take
+    pub fn process_cpu_cycles(&mut self, cpu_cycles: i32) {
+        let (dots, cycles) = self.region.dots_per_cycle();
+        let total = cpu_cycles * dots + self.dot_fraction;
+        self.dot_fraction = total % cycles;
+        self.process(total / cycles);
+    }
+
+    pub fn process(&mut self, dots: i32) {
+        for _ in 0..dots {
+            self.tick();
+        }
+
+        // For $2002 reads between now and the next call
+        let vblank_scanline = self.scanline == self.region.vblank_scanline();
+        let race = match self.scanline_cycle {
+            1 if vblank_scanline => VBlankRace::Before,
+            2 | 3 if vblank_scanline => VBlankRace::JustStarted,
+            _ => VBlankRace::None,
+        };
+        let mut regs = self.ppu_regs.get();
+        regs.set_vblank_race(race);
+        self.ppu_regs.set(regs);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ppu(region: Region, mask: u8) -> PPU {
+        let mut regs = PPURegisters::new();
+        regs.set_ppumask(mask);
+        PPU::new(Rc::new(RefCell::new(VRAMController::new())), Rc::new(Cell::new(regs)), region)
+    }
+
+    // Dots until the frame counter moves on
+    fn frame_length(ppu: &mut PPU) -> i32 {
+        let frame = ppu.frame();
+        let mut dots = 0;
+        while ppu.frame() == frame {
+            ppu.process(1);
+            dots += 1;
+        }
+        dots
+    }
+
+    #[test]
+    fn odd_ntsc_frames_skip_a_dot_with_rendering_on() {
+        let mut rendering = ppu(Region::Ntsc, 0x18);
+        frame_length(&mut rendering);
+        assert_eq!([frame_length(&mut rendering), frame_length(&mut rendering)], [341 * 262 - 1, 341 * 262]);
+
+        let mut off = ppu(Region::Ntsc, 0);
+        frame_length(&mut off);
+        assert_eq!([frame_length(&mut off), frame_length(&mut off)], [341 * 262, 341 * 262]);
+
+        let mut pal = ppu(Region::Pal, 0x18);
+        frame_length(&mut pal);
+        assert_eq!([frame_length(&mut pal), frame_length(&mut pal)], [341 * 312, 341 * 312]);
+    }
+
+    #[test]
+    fn reading_status_as_vblank_starts_suppresses_it() {
+        let mut ppu = ppu(Region::Ntsc, 0);
+        let mut regs = ppu.registers().get();
+        regs.set_ppuctrl(0x80);
+        ppu.registers().set(regs);
+
+        // Up to dot 1 of the VBlank scanline, the dot the flag gets set on
+        ppu.process(341 * 241 + 1);
+        let mut regs = ppu.registers().get();
+        assert_eq!(regs.status() & 0x80, 0);
+        ppu.registers().set(regs);
+
+        ppu.process(10);
+        let mut regs = ppu.registers().get();
+        assert!(!regs.nmi_pending());
+        assert_eq!(regs.status() & 0x80, 0);
+    }
+
+    #[test]
+    fn reading_status_just_after_vblank_starts_cancels_the_nmi() {
+        // Read on dot 2 and dot 3 of the VBlank scanline, then on dot 4 where it no longer races
+        for (dots, nmi) in [(1, false), (2, false), (3, true)] {
+            let mut ppu = ppu(Region::Ntsc, 0);
+            let mut regs = ppu.registers().get();
+            regs.set_ppuctrl(0x80);
+            ppu.registers().set(regs);
+
+            ppu.process(341 * 241 + 1 + dots);
+            let mut regs = ppu.registers().get();
+            assert_eq!(regs.status() & 0x80, 0x80);
+            assert_eq!(regs.nmi_pending(), nmi);
+            assert_eq!(regs.peek_status() & 0x80, 0);
+        }
+    }
+
+    #[test]
+    fn enabling_nmi_during_vblank_raises_one() {
+        let mut ppu = ppu(Region::Ntsc, 0);
+        ppu.process(341 * 245);
+        let mut regs = ppu.registers().get();
+        assert!(!regs.nmi_pending());
+
+        regs.set_ppuctrl(0x80);
+        assert!(regs.nmi_pending());
+        regs.acknowledge_nmi();
+
+        // Writing it again while it is on does not raise another, turning it off and on does
+        regs.set_ppuctrl(0x80);
+        assert!(!regs.nmi_pending());
+        regs.set_ppuctrl(0x00);
+        regs.set_ppuctrl(0x80);
+        assert!(regs.nmi_pending());
+        regs.acknowledge_nmi();
+
+        // Once $2002 has cleared the flag there is nothing left to raise one for
+        regs.status();
+        regs.set_ppuctrl(0x00);
+        regs.set_ppuctrl(0x80);
+        assert!(!regs.nmi_pending());
+    }
+
+    #[test]
+    fn turning_nmi_off_as_vblank_starts_takes_it_back() {
+        let mut ppu = ppu(Region::Ntsc, 0);
+        let mut regs = ppu.registers().get();
+        regs.set_ppuctrl(0x80);
+        ppu.registers().set(regs);
+
+        ppu.process(341 * 241 + 2);
+        let mut regs = ppu.registers().get();
+        assert!(regs.nmi_pending());
+        regs.set_ppuctrl(0x00);
+        assert!(!regs.nmi_pending());
+        assert_eq!(regs.peek_status() & 0x80, 0x80);
+    }
+
+    // Opaque background tiles at x 0, 16, 96 and 248 of lines 16 to 23, sprites use a solid tile 2
+    // and everything not given stays off screen
+    fn with_scene<R>(mask: u8, sprites: &[[u8; 4]], test: impl FnOnce(&mut PPU) -> R) -> R {
+        let vram = Rc::new(RefCell::new(VRAMController::new()));
+        {
+            let mut vram = vram.borrow_mut();
+            for row in 0..8 {
+                vram.write8(0x10 + row, 0xFF);
+                vram.write8(0x20 + row, 0xFF);
+                vram.write8(0x28 + row, 0xFF);
+            }
+            for tile_x in [0, 2, 12, 31] {
+                vram.write8(0x2040 + tile_x, 1);
+            }
+            for (address, value) in [(0x3F00, 0x0F), (0x3F01, 0x01), (0x3F13, 0x23), (0x3F17, 0x27)] {
+                vram.write8(address, value);
+            }
+            vram.oam = [0xFF; 0x100];
+            for (i, sprite) in sprites.iter().enumerate() {
+                vram.oam[i * 4..i * 4 + 4].copy_from_slice(sprite);
+            }
+        }
+
+        let mut regs = PPURegisters::new();
+        regs.set_ppumask(mask);
+        test(&mut PPU::new(vram, Rc::new(Cell::new(regs)), Region::Ntsc))
+    }
+
+    // Whether sprite 0 hit is set once line 16 has been drawn
+    fn sprite_zero_hit(mask: u8, sprites: &[[u8; 4]]) -> bool {
+        with_scene(mask, sprites, |ppu| {
+            ppu.process(341 * 17);
+            ppu.registers().get().peek_status() & 0x40 != 0
+        })
+    }
+
+    #[test]
+    fn sprites_in_front_cover_the_background_and_sprites_behind_only_the_backdrop() {
+        with_scene(0x1E, &[[15, 2, 0x00, 20], [15, 2, 0x21, 100], [15, 2, 0x00, 104]], |ppu| {
+            ppu.process(341 * 17);
+
+            let line = &ppu.framebuffer()[16 * SCREEN_WIDTH..17 * SCREEN_WIDTH];
+            assert_eq!(&line[14..30], &[0x0F, 0x0F, 0x01, 0x01, 0x01, 0x01, 0x23, 0x23, 0x23, 0x23, 0x23, 0x23, 0x23, 0x23, 0x0F, 0x0F]);
+            // The sprite behind the background still hides the one after it in OAM
+            assert_eq!(&line[98..110], &[0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x27, 0x27, 0x27, 0x27, 0x23, 0x23]);
+            assert!(ppu.framebuffer()[15 * SCREEN_WIDTH..16 * SCREEN_WIDTH].iter().all(|index| *index == 0x0F));
+        });
+    }
+
+    #[test]
+    fn sprite_zero_hit_needs_both_pixels_opaque() {
+        assert!(sprite_zero_hit(0x1E, &[[15, 2, 0x00, 20]]));
+        assert!(sprite_zero_hit(0x1E, &[[15, 2, 0x20, 20]]));
+        assert!(!sprite_zero_hit(0x1E, &[[15, 2, 0x00, 40]]));
+        assert!(!sprite_zero_hit(0x1E, &[[15, 2, 0x00, 40], [15, 2, 0x00, 20]]));
+        assert!(!sprite_zero_hit(0x14, &[[15, 2, 0x00, 20]]));
+
+        // Not in the clipped left column, and never on the last pixel
+        assert!(sprite_zero_hit(0x1E, &[[15, 2, 0x00, 0]]));
+        assert!(!sprite_zero_hit(0x18, &[[15, 2, 0x00, 0]]));
+        assert!(sprite_zero_hit(0x1E, &[[15, 2, 0x00, 248]]));
+        assert!(!sprite_zero_hit(0x1E, &[[15, 2, 0x00, 255]]));
+    }
+
+    #[test]
+    fn sprite_zero_hit_lasts_until_the_pre_render_line() {
+        with_scene(0x1E, &[[15, 2, 0x00, 20]], |ppu| {
+            ppu.process(341 * 17 - 1);
+            assert_eq!(ppu.registers().get().peek_status() & 0x40, 0);
+            ppu.process(1);
+            assert_eq!(ppu.registers().get().peek_status() & 0x40, 0x40);
+
+            // Reading the status leaves it alone, it is cleared on dot 1 of the pre-render line
+            let mut regs = ppu.registers().get();
+            regs.status();
+            ppu.registers().set(regs);
+            ppu.process(341 * (261 - 17) + 1);
+            assert_eq!(ppu.registers().get().peek_status() & 0x40, 0x40);
+            ppu.process(1);
+            assert_eq!(ppu.registers().get().peek_status() & 0x40, 0);
+        });
+    }
+
+    #[test]
+    fn greyscale_and_pal_emphasis_apply_to_the_line() {
+        // Greyscale and the PAL green emphasis bit
+        let mut ppu = ppu(Region::Pal, 0x21);
+        ppu.vram().borrow_mut().write8(0x3F00, 0x16);
+        frame_length(&mut ppu);
+
+        assert!(ppu.framebuffer().iter().all(|index| *index == 0x10));
+        assert!(ppu.emphasis().iter().all(|emphasis| *emphasis == crate::palette::EMPHASIS_GREEN));
+    }
+}
@@ -0,0 +1,147 @@
+// A log of what the CPU did to the PPU during a frame: every access to $2000-$2007 and $4014,
+// and the NMIs and IRQs, each with the scanline and dot the PPU was on. Plotted on a grid of
+// dots by scanlines it shows raster effects, like a split scroll written a few dots too late.
+
+use crate::ppu_viewer::Image;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PpuEventKind {
+    Read,
+    Write,
+    Nmi,
+    Irq,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PpuEvent {
+    pub kind: PpuEventKind,
+    // $2000-$2007 with the mirrors folded, or $4014. 0 for NMIs and IRQs.
+    pub address: u16,
+    pub value: u8,
+    pub scanline: i32,
+    pub dot: i32,
+}
+
+impl PpuEvent {
+    pub fn name(&self) -> &'static str {
+        match (self.kind, self.address) {
+            (PpuEventKind::Nmi, _) => "NMI",
+            (PpuEventKind::Irq, _) => "IRQ",
+            (_, 0x2000) => "PPUCTRL",
+            (_, 0x2001) => "PPUMASK",
+            (_, 0x2002) => "PPUSTATUS",
+            (_, 0x2003) => "OAMADDR",
+            (_, 0x2004) => "OAMDATA",
+            (_, 0x2005) => "PPUSCROLL",
+            (_, 0x2006) => "PPUADDR",
+            (_, 0x2007) => "PPUDATA",
+            _ => "OAMDMA",
+        }
+    }
+
+    pub fn color(&self) -> [u8; 3] {
+        match (self.kind, self.address) {
+            (PpuEventKind::Nmi, _) => [255, 255, 255],
+            (PpuEventKind::Irq, _) => [0, 255, 200],
+            (_, 0x2000) => [255, 80, 80],
+            (_, 0x2001) => [255, 170, 50],
+            (_, 0x2002) => [255, 255, 80],
+            (_, 0x2003) | (_, 0x2004) => [190, 120, 255],
+            (_, 0x2005) => [80, 255, 80],
+            (_, 0x2006) => [80, 200, 255],
+            (_, 0x2007) => [60, 90, 255],
+            _ => [255, 80, 255],
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let position = format!("scanline {:3} dot {:3}", self.scanline, self.dot);
+        match self.kind {
+            PpuEventKind::Read => format!("{}: read  {} ${:04X} = ${:02X}", position, self.name(), self.address, self.value),
+            PpuEventKind::Write => format!("{}: write {} ${:04X} = ${:02X}", position, self.name(), self.address, self.value),
+            PpuEventKind::Nmi | PpuEventKind::Irq => format!("{}: {}", position, self.name()),
+        }
+    }
+}
+
+// Collects the events of the frame being run, and keeps the last complete one
+pub struct PpuEventLog {
+    frame: u64,
+    current: Vec<PpuEvent>,
+    finished: Vec<PpuEvent>,
+}
+
+impl PpuEventLog {
+    pub fn new(frame: u64) -> PpuEventLog {
+        PpuEventLog { frame, current: vec![], finished: vec![] }
+    }
+
+    pub fn push(&mut self, event: PpuEvent) {
+        self.current.push(event);
+    }
+
+    // Called as the PPU moves on, `frame` is the one it is on now
+    pub fn frame(&mut self, frame: u64) {
+        if frame != self.frame {
+            self.finished = std::mem::take(&mut self.current);
+            self.frame = frame;
+        }
+    }
+
+    // The events of the last complete frame
+    pub fn events(&self) -> &[PpuEvent] {
+        &self.finished
+    }
+
+    // 341 dots by `scanlines`, the visible picture in dark grey and each event a dot of its colour
+    pub fn plot(&self, scanlines: i32) -> Image {
+        let mut image = Image::new(341, scanlines as u32);
+        image.fill(1, 0, 256, 240, [40, 40, 40]);
+        for event in &self.finished {
+            if (0..341).contains(&event.dot) && (0..scanlines).contains(&event.scanline) {
+                image.set(event.dot as u32, event.scanline as u32, event.color());
+            }
+        }
+        image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Cartridge;
+    use crate::nes::Nes;
+
+    #[test]
+    fn a_frame_of_events_is_kept_once_it_is_over() {
+        let mut log = PpuEventLog::new(0);
+        let write = PpuEvent { kind: PpuEventKind::Write, address: 0x2005, value: 0x40, scanline: 30, dot: 250 };
+        log.push(write);
+        log.frame(0);
+        assert!(log.events().is_empty());
+
+        log.frame(1);
+        assert_eq!(log.events(), &[write]);
+        assert_eq!(write.describe(), "scanline  30 dot 250: write PPUSCROLL $2005 = $40");
+
+        let plot = log.plot(262);
+        assert_eq!((plot.width, plot.height), (341, 262));
+        let pixel = (30 * 341 + 250) * 3;
+        assert_eq!(&plot.rgb[pixel..pixel + 3], &[80, 255, 80]);
+    }
+
+    #[test]
+    fn register_accesses_and_nmis_are_stamped_with_the_dot() {
+        // Turns NMIs on and spins, the NMI handler only returns
+        let program = [0xA9, 0x80, 0x8D, 0x00, 0x20, 0x4C, 0x05, 0x80];
+        let mut nes = Nes::new(Cartridge::nrom(&[(0x8000, &program), (0x8100, &[0x40])], [0x8100, 0x8000, 0x8000])).unwrap();
+        nes.set_ppu_event_logging(true);
+        nes.run_frame();
+
+        let events = nes.ppu_event_log().unwrap().events();
+        assert_eq!(events.len(), 2);
+        // The reset sequence, LDA and three cycles into STA, three dots each
+        assert_eq!(events[0], PpuEvent { kind: PpuEventKind::Write, address: 0x2000, value: 0x80, scanline: 0, dot: 36 });
+        assert_eq!((events[1].kind, events[1].scanline), (PpuEventKind::Nmi, 241));
+    }
+}
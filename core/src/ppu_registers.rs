@@ -0,0 +1,246 @@
+
+use crate::savestate::{StateReader, StateWriter};
+
+const STATUS_VBLANK: u8 = 0b10000000;
+const STATUS_SPRITE_ZERO_HIT: u8 = 0b01000000;
+const STATUS_SPRITE_OVERFLOW: u8 = 0b00100000;
+const CTRL_NMI: u8 = 0b10000000;
+
+// Where the PPU is relative to VBlank starting, as of the CPU's next register access.
+// Reading $2002 in either window races the flag being set.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VBlankRace {
+    None,
+    // VBlank starts on the very next dot. The read sees it clear and it does not get set this frame.
+    Before,
+    // VBlank started a dot or two ago. The read sees it set, but there is no NMI this frame.
+    JustStarted,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PPURegisters {
+    ppuaddr: u16,
+    ppuctrl: u8,
+    ppumask: u8,
+    oamaddr: u8,
+    ppuscroll_x: u8,
+    ppuscroll_y: u8,
+    ppustatus: u8,
+    ppuscroll_toggle: bool,
+    ppuaddr_toggle: bool,
+    // An NMI edge the CPU has not taken yet
+    nmi_pending: bool,
+    suppress_vblank: bool,
+    vblank_race: VBlankRace,
+    // What the last $2007 read fetched, handed out by the next one
+    read_buffer: u8,
+}
+
+impl PPURegisters {
+    pub fn new() -> PPURegisters {
+        PPURegisters {
+            ppuaddr: 0,
+            ppuctrl: 0,
+            ppumask: 0,
+            oamaddr: 0,
+            ppuscroll_x: 0,
+            ppuscroll_y: 0,
+            ppustatus: 0,
+            ppuscroll_toggle: false,
+            ppuaddr_toggle: false,
+            nmi_pending: false,
+            suppress_vblank: false,
+            vblank_race: VBlankRace::None,
+            read_buffer: 0,
+        }
+    }
+
+    fn set_last_written_value(&mut self, value: u8) {
+        let least_significant_bits = value & 0b00011111;
+        self.ppustatus = (self.ppustatus & 0b11100000) | least_significant_bits;
+    }
+
+    // Turning NMI on during VBlank raises one straight away, turning it off right as VBlank starts
+    // takes back the one that was just raised
+    pub fn set_ppuctrl(&mut self, value: u8) {
+        let enabled = self.ppuctrl & CTRL_NMI == 0 && value & CTRL_NMI != 0;
+        if enabled && self.ppustatus & STATUS_VBLANK != 0 {
+            self.nmi_pending = true;
+        }
+        if value & CTRL_NMI == 0 && self.vblank_race == VBlankRace::JustStarted {
+            self.nmi_pending = false;
+        }
+
+        self.ppuctrl = value;
+        self.set_last_written_value(value);
+    }
+
+    pub fn set_ppumask(&mut self, value: u8) {
+        self.ppumask = value;
+        self.set_last_written_value(value);
+    }
+
+    pub fn set_oamaddr(&mut self, value: u8) {
+        self.oamaddr = value;
+        self.set_last_written_value(value);
+    }
+
+    pub fn set_ppuscroll(&mut self, value: u8) {
+        if self.ppuscroll_toggle {
+            self.ppuscroll_y = value;
+        }
+        else {
+            self.ppuscroll_x = value;
+        }
+
+        self.ppuscroll_toggle = !self.ppuscroll_toggle;
+        self.set_last_written_value(value);
+    }
+
+    pub fn set_ppuaddr(&mut self, value: u8) {
+        if self.ppuaddr_toggle {
+            self.ppuaddr = (self.ppuaddr & 0xFF00) | value as u16;
+        }
+        else {
+            self.ppuaddr = (self.ppuaddr & 0x00FF) | ((value as u16) << 8);
+        }
+
+        self.ppuaddr_toggle = !self.ppuaddr_toggle;
+        self.set_last_written_value(value);
+    }
+
+    pub fn ppuctrl(&self) -> u8 {
+        self.ppuctrl
+    }
+
+    pub fn ppumask(&self) -> u8 {
+        self.ppumask
+    }
+
+    pub fn ppuscroll(&self) -> (u8, u8) {
+        (self.ppuscroll_x, self.ppuscroll_y)
+    }
+
+    pub fn oamaddr(&self) -> u8 {
+        self.oamaddr
+    }
+
+    pub fn ppuaddr(&self) -> u16 {
+        self.ppuaddr
+    }
+
+    pub fn read_buffer(&self) -> u8 {
+        self.read_buffer
+    }
+
+    pub fn set_read_buffer(&mut self, value: u8) {
+        self.read_buffer = value;
+    }
+
+    pub fn increment_ppuaddr(&mut self) {
+        if (self.ppuctrl & 0x4) == 0x4 {
+            self.ppuaddr += 0x20;
+        }
+        else {
+            self.ppuaddr += 1;
+        }
+    }
+
+    // Set by the PPU on the first dot of VBlank, unless a $2002 read got there first
+    pub fn start_vblank(&mut self) {
+        if self.suppress_vblank {
+            self.suppress_vblank = false;
+            return;
+        }
+
+        self.ppustatus |= STATUS_VBLANK;
+        if self.ppuctrl & CTRL_NMI != 0 {
+            self.nmi_pending = true;
+        }
+    }
+
+    // On the first dot of the pre-render scanline
+    pub fn end_vblank(&mut self) {
+        self.ppustatus &= !(STATUS_VBLANK | STATUS_SPRITE_ZERO_HIT | STATUS_SPRITE_OVERFLOW);
+    }
+
+    pub fn set_vblank_race(&mut self, race: VBlankRace) {
+        self.vblank_race = race;
+    }
+
+    // Set by the PPU once an opaque sprite 0 pixel lands on an opaque background pixel
+    pub fn set_sprite_zero_hit(&mut self) {
+        self.ppustatus |= STATUS_SPRITE_ZERO_HIT;
+    }
+
+    pub fn status(&mut self) -> u8 {
+        let result = self.ppustatus;
+        match self.vblank_race {
+            VBlankRace::Before => self.suppress_vblank = true,
+            VBlankRace::JustStarted => self.nmi_pending = false,
+            VBlankRace::None => {}
+        }
+
+        self.ppustatus &= !STATUS_VBLANK;
+        self.ppuscroll_toggle = false;
+        self.ppuaddr_toggle = false;
+
+        result
+    }
+
+    pub fn peek_status(&self) -> u8 {
+        self.ppustatus
+    }
+
+    pub fn nmi_pending(&self) -> bool {
+        self.nmi_pending
+    }
+
+    // The CPU is taking the NMI
+    pub fn acknowledge_nmi(&mut self) {
+        self.nmi_pending = false;
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.ppuaddr);
+        writer.write_u8(self.ppuctrl);
+        writer.write_u8(self.ppumask);
+        writer.write_u8(self.oamaddr);
+        writer.write_u8(self.ppuscroll_x);
+        writer.write_u8(self.ppuscroll_y);
+        writer.write_u8(self.ppustatus);
+        writer.write_bool(self.ppuscroll_toggle);
+        writer.write_bool(self.ppuaddr_toggle);
+        writer.write_bool(self.nmi_pending);
+        writer.write_bool(self.suppress_vblank);
+        writer.write_u8(match self.vblank_race {
+            VBlankRace::None => 0,
+            VBlankRace::Before => 1,
+            VBlankRace::JustStarted => 2,
+        });
+        writer.write_u8(self.read_buffer);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        self.ppuaddr = reader.read_u16()?;
+        self.ppuctrl = reader.read_u8()?;
+        self.ppumask = reader.read_u8()?;
+        self.oamaddr = reader.read_u8()?;
+        self.ppuscroll_x = reader.read_u8()?;
+        self.ppuscroll_y = reader.read_u8()?;
+        self.ppustatus = reader.read_u8()?;
+        self.ppuscroll_toggle = reader.read_bool()?;
+        self.ppuaddr_toggle = reader.read_bool()?;
+        self.nmi_pending = reader.read_bool()?;
+        self.suppress_vblank = reader.read_bool()?;
+        self.vblank_race = match reader.read_u8()? {
+            0 => VBlankRace::None,
+            1 => VBlankRace::Before,
+            2 => VBlankRace::JustStarted,
+            other => return Err(format!("Invalid VBlank race state {}", other))
+        };
+        self.read_buffer = reader.read_u8()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,286 @@
+// Debug views of the PPU's memory: both pattern tables, all four nametables with the scrolled
+// screen outlined, the sprites in OAM and the 32 bytes of palette RAM. They are drawn from a
+// snapshot taken at one scanline, since games change the scroll and even the palettes mid-frame.
+
+use crate::palette::Palette;
+use crate::ppu::{PPU, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::screenshot;
+
+// An RGB image
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub rgb: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32) -> Image {
+        Image { width, height, rgb: vec![0; width as usize * height as usize * 3] }
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, color: [u8; 3]) {
+        let start = (y as usize * self.width as usize + x as usize) * 3;
+        self.rgb[start..start + 3].copy_from_slice(&color);
+    }
+
+    fn invert(&mut self, x: u32, y: u32) {
+        let start = (y as usize * self.width as usize + x as usize) * 3;
+        for channel in &mut self.rgb[start..start + 3] {
+            *channel = !*channel;
+        }
+    }
+
+    pub fn fill(&mut self, x: u32, y: u32, width: u32, height: u32, color: [u8; 3]) {
+        for y in y..y + height {
+            for x in x..x + width {
+                self.set(x, y, color);
+            }
+        }
+    }
+
+    fn paste(&mut self, image: &Image, x: u32, y: u32) {
+        let row = image.width as usize * 3;
+        for (line, pixels) in image.rgb.chunks(row).enumerate() {
+            let start = ((y as usize + line) * self.width as usize + x as usize) * 3;
+            self.rgb[start..start + row].copy_from_slice(pixels);
+        }
+    }
+
+    pub fn png(&self, rom_name: &str) -> Vec<u8> {
+        screenshot::rgb_png(&self.rgb, self.width, self.height, rom_name)
+    }
+}
+
+// One entry of OAM
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprite {
+    pub index: usize,
+    pub x: u8,
+    pub y: u8,
+    pub tile: u8,
+    pub attributes: u8,
+}
+
+impl Sprite {
+    pub fn describe(&self) -> String {
+        format!(
+            "#{:02} x={:3} y={:3} tile=${:02X} palette={} {} {}{}",
+            self.index, self.x, self.y, self.tile, self.attributes & 3,
+            if self.attributes & 0x20 != 0 { "behind" } else { "front " },
+            if self.attributes & 0x40 != 0 { "H" } else { "-" },
+            if self.attributes & 0x80 != 0 { "V" } else { "-" },
+        )
+    }
+}
+
+// What the views are drawn from, copied out of the PPU
+pub struct PpuSnapshot {
+    // $0000-$2FFF as the PPU sees it, with the nametables mirrored
+    memory: Vec<u8>,
+    palette: [u8; 32],
+    oam: [u8; 0x100],
+    ctrl: u8,
+    scroll: (u8, u8),
+    pub scanline: i32,
+    pub frame: u64,
+}
+
+impl PpuSnapshot {
+    pub fn capture(ppu: &PPU) -> PpuSnapshot {
+        let vram = ppu.vram().borrow();
+        let regs = ppu.registers().get();
+        let mut palette = [0; 32];
+        for (i, color) in palette.iter_mut().enumerate() {
+            *color = vram.read8(0x3F00 + i as u16);
+        }
+        PpuSnapshot {
+            memory: (0..0x3000).map(|address| vram.read8(address)).collect(),
+            palette,
+            oam: vram.oam,
+            ctrl: regs.ppuctrl(),
+            scroll: regs.ppuscroll(),
+            scanline: ppu.scanline(),
+            frame: ppu.frame(),
+        }
+    }
+
+    // The 2-bit colours of one row of a tile
+    fn tile_row(&self, address: usize, row: usize) -> [u8; 8] {
+        let (low, high) = (self.memory[address + row], self.memory[address + row + 8]);
+        let mut colors = [0; 8];
+        for (column, color) in colors.iter_mut().enumerate() {
+            let bit = 7 - column;
+            *color = ((low >> bit) & 1) | (((high >> bit) & 1) << 1);
+        }
+        colors
+    }
+
+    // `palette` 0-3 are the background palettes and 4-7 the sprite ones
+    fn color(&self, colors: &Palette, palette: u8, color: u8) -> [u8; 3] {
+        let index = if color == 0 { 0 } else { (palette as usize) * 4 + color as usize };
+        colors.rgb(self.palette[index], 0)
+    }
+
+    // Both pattern tables side by side, 256x128
+    pub fn pattern_tables(&self, colors: &Palette, palette: u8) -> Image {
+        let mut image = Image::new(256, 128);
+        for tile in 0..512 {
+            let (x, y) = ((tile / 256) * 128 + (tile % 16) * 8, (tile % 256) / 16 * 8);
+            for row in 0..8 {
+                for (column, color) in self.tile_row(tile as usize * 16, row).iter().enumerate() {
+                    image.set(x + column as u32, y + row as u32, self.color(colors, palette, *color));
+                }
+            }
+        }
+        image
+    }
+
+    // The four nametables as 512x480, with the screen the scroll registers point at outlined
+    pub fn nametables(&self, colors: &Palette) -> Image {
+        let mut image = Image::new(SCREEN_WIDTH as u32 * 2, SCREEN_HEIGHT as u32 * 2);
+        let pattern_table = if self.ctrl & 0b00010000 != 0 { 0x1000 } else { 0 };
+        for table in 0..4 {
+            let base = 0x2000 + table * 0x400;
+            for tile_y in 0..30 {
+                for tile_x in 0..32 {
+                    let tile = self.memory[base + tile_y * 32 + tile_x] as usize;
+                    let attribute = self.memory[base + 0x3C0 + (tile_y / 4) * 8 + tile_x / 4];
+                    let palette = (attribute >> (((tile_y % 4) / 2) * 4 + ((tile_x % 4) / 2) * 2)) & 3;
+
+                    let x = (table % 2) * SCREEN_WIDTH + tile_x * 8;
+                    let y = (table / 2) * SCREEN_HEIGHT + tile_y * 8;
+                    for row in 0..8 {
+                        for (column, color) in self.tile_row(pattern_table + tile * 16, row).iter().enumerate() {
+                            image.set((x + column) as u32, (y + row) as u32, self.color(colors, palette, *color));
+                        }
+                    }
+                }
+            }
+        }
+
+        // Wrapping around the edges the way the scroll does
+        let left = (self.ctrl & 1) as u32 * SCREEN_WIDTH as u32 + self.scroll.0 as u32;
+        let top = ((self.ctrl >> 1) & 1) as u32 * SCREEN_HEIGHT as u32 + self.scroll.1 as u32;
+        let (width, height) = (image.width, image.height);
+        for i in 0..SCREEN_WIDTH as u32 {
+            image.invert((left + i) % width, top % height);
+            image.invert((left + i) % width, (top + SCREEN_HEIGHT as u32 - 1) % height);
+        }
+        for i in 1..SCREEN_HEIGHT as u32 - 1 {
+            image.invert(left % width, (top + i) % height);
+            image.invert((left + SCREEN_WIDTH as u32 - 1) % width, (top + i) % height);
+        }
+        image
+    }
+
+    pub fn sprites(&self) -> Vec<Sprite> {
+        self.oam.chunks(4).enumerate()
+            .map(|(index, sprite)| Sprite { index, y: sprite[0], tile: sprite[1], attributes: sprite[2], x: sprite[3] })
+            .collect()
+    }
+
+    // Every sprite at twice its size in an 8x8 grid, 128x256, 8x16 sprites when PPUCTRL says so
+    pub fn sprite_previews(&self, colors: &Palette) -> Image {
+        let mut image = Image::new(128, 256);
+        let height = if self.ctrl & 0b00100000 != 0 { 16 } else { 8 };
+        let pattern_table = if self.ctrl & 0b00001000 != 0 { 0x1000 } else { 0 };
+
+        for sprite in self.sprites() {
+            let (cell_x, cell_y) = ((sprite.index % 8) as u32 * 16, (sprite.index / 8) as u32 * 32);
+            let tile = sprite.tile as usize;
+            for row in 0..height {
+                let source_row = if sprite.attributes & 0x80 != 0 { height - 1 - row } else { row };
+                let address = if height == 16 {
+                    (tile & 1) * 0x1000 + ((tile & 0xFE) + source_row / 8) * 16
+                } else {
+                    pattern_table + tile * 16
+                };
+                let colors_row = self.tile_row(address, source_row % 8);
+                for column in 0..8 {
+                    let source_column = if sprite.attributes & 0x40 != 0 { 7 - column } else { column };
+                    let color = self.color(colors, 4 + (sprite.attributes & 3), colors_row[source_column]);
+                    image.fill(cell_x + column as u32 * 2, cell_y + row as u32 * 2, 2, 2, color);
+                }
+            }
+        }
+        image
+    }
+
+    // The 32 entries of palette RAM as 16x16 squares, background palettes on the top row
+    pub fn palette_ram(&self, colors: &Palette) -> Image {
+        let mut image = Image::new(256, 32);
+        for (i, index) in self.palette.iter().enumerate() {
+            image.fill((i % 16) as u32 * 16, (i / 16) as u32 * 16, 16, 16, colors.rgb(*index, 0));
+        }
+        image
+    }
+
+    // All of it in one 776x480 image: the nametables on the left, and on the right the pattern
+    // tables, the palette RAM and the sprites
+    pub fn overview(&self, colors: &Palette, pattern_palette: u8) -> Image {
+        let mut image = Image::new(776, 480);
+        image.paste(&self.nametables(colors), 0, 0);
+        image.paste(&self.pattern_tables(colors, pattern_palette), 520, 0);
+        image.paste(&self.palette_ram(colors), 520, 136);
+        image.paste(&self.sprite_previews(colors), 520, 176);
+        image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppu_registers::PPURegisters;
+    use crate::region::Region;
+    use crate::vram_controller::VRAMController;
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    fn snapshot(setup: impl FnOnce(&mut VRAMController, &mut PPURegisters)) -> PpuSnapshot {
+        let mut vram = VRAMController::new();
+        let mut regs = PPURegisters::new();
+        setup(&mut vram, &mut regs);
+        PpuSnapshot::capture(&PPU::new(Rc::new(RefCell::new(vram)), Rc::new(Cell::new(regs)), Region::Ntsc))
+    }
+
+    #[test]
+    fn pattern_tables_use_the_chosen_palette() {
+        let snapshot = snapshot(|vram, _| {
+            // Tile 1 of the right table, its top row colour 3 then colour 1
+            vram.write8(0x1010, 0xFF);
+            vram.write8(0x1018, 0xF0);
+            vram.write8(0x3F00, 0x0F);
+            vram.write8(0x3F15, 0x16);
+            vram.write8(0x3F17, 0x2A);
+        });
+        let colors = Palette::new();
+        let image = snapshot.pattern_tables(&colors, 5);
+        let pixel = |x: usize, y: usize| [image.rgb[(y * 256 + x) * 3], image.rgb[(y * 256 + x) * 3 + 1], image.rgb[(y * 256 + x) * 3 + 2]];
+
+        assert_eq!([pixel(136, 0), pixel(140, 0), pixel(136, 1)], [colors.rgb(0x2A, 0), colors.rgb(0x16, 0), colors.rgb(0x0F, 0)]);
+    }
+
+    #[test]
+    fn the_scrolled_screen_is_outlined_and_wraps() {
+        let snapshot = snapshot(|_, regs| {
+            regs.set_ppuctrl(0x01);
+            regs.set_ppuscroll(0x80);
+            regs.set_ppuscroll(0x10);
+        });
+        let image = snapshot.nametables(&Palette::new());
+        let inverted = |x: usize, y: usize| image.rgb[(y * 512 + x) * 3] != image.rgb[(300 * 512 + 300) * 3];
+
+        // From x 384 in the right hand table around to x 127 in the left hand one
+        assert!(inverted(384, 16) && inverted(511, 16) && inverted(0, 16) && inverted(127, 16));
+        assert!(!inverted(128, 16) && !inverted(383, 16));
+        assert!(inverted(384, 255) && !inverted(384, 256));
+    }
+
+    #[test]
+    fn sprites_are_listed_from_oam() {
+        let snapshot = snapshot(|vram, _| vram.write_oam_dma(0, &[0x20, 0x42, 0xE1, 0x08]));
+        let sprite = snapshot.sprites()[0];
+        assert_eq!(sprite, Sprite { index: 0, x: 8, y: 0x20, tile: 0x42, attributes: 0xE1 });
+        assert_eq!(sprite.describe(), "#00 x=  8 y= 32 tile=$42 palette=1 behind HV");
+    }
+}
@@ -0,0 +1,492 @@
+use crate::cartridge::PrgRomBank;
+use crate::ppu_registers::PPURegisters;
+use std::cell::{Cell, RefCell};
+use std::ops::Range;
+use std::rc::Rc;
+use crate::vram_controller::VRAMController;
+use crate::savestate::{StateReader, StateWriter};
+use crate::controller::Controller;
+use crate::apu::APU;
+use crate::ppu_events::PpuEventKind;
+use crate::region::Region;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bus {
+    Cpu,
+    Ppu,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AccessKind {
+    Read,
+    Write,
+    Execute,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryAccess {
+    pub bus: Bus,
+    pub kind: AccessKind,
+    pub address: u16,
+    pub value: u8,
+}
+
+pub struct RamController {
+    ppu_regs: Rc<Cell<PPURegisters>>,
+    vram: Rc<RefCell<VRAMController>>,
+    memory: [u8; 0x10000],
+    log_accesses: Cell<bool>,
+    accesses: RefCell<Vec<MemoryAccess>>,
+    prg_banks: [usize; 2],
+    controllers: Cell<[Controller; 2]>,
+    apu: RefCell<APU>,
+    log_ppu_events: Cell<bool>,
+    ppu_events: RefCell<Vec<(PpuEventKind, u16, u8)>>,
+}
+
+impl RamController {
+    const PRG_BANK1_LOCATION: usize = 0x8000;
+    const PRG_BANK2_LOCATION: usize = 0xC000;
+    const PRG_BANK_SIZE: usize = 0x4000;
+    const RAM_RANGE: Range<usize> = 0x0000..0x0800;
+    const PRG_RAM_RANGE: Range<usize> = 0x6000..0x8000;
+
+    // The PPU registers and VRAM are shared with the PPU
+    pub fn new(ppu_regs: Rc<Cell<PPURegisters>>, vram: Rc<RefCell<VRAMController>>, region: Region) -> RamController {
+        RamController {
+            ppu_regs,
+            vram,
+            memory: [0; 0x10000],
+            log_accesses: Cell::new(false),
+            accesses: RefCell::new(vec![]),
+            prg_banks: [0; 2],
+            controllers: Cell::new([Controller::new(); 2]),
+            apu: RefCell::new(APU::new(region)),
+            log_ppu_events: Cell::new(false),
+            ppu_events: RefCell::new(vec![]),
+        }
+    }
+    pub fn read8(&self, address: u16) -> u8 {
+        let translated_address = self.translate_address(address);
+
+        let value = self.read_ppu_registers(Self::fold_ppu_register(address))
+            .or_else(|| self.read_controllers(address))
+            .or_else(|| self.read_apu(address))
+            .unwrap_or(self.memory[translated_address]);
+        self.log_access(Bus::Cpu, AccessKind::Read, address, value);
+        self.log_ppu_event(PpuEventKind::Read, address, value);
+
+        value
+    }
+
+    // Reads a byte without triggering any of the side effects that a read of a PPU register has.
+    pub fn peek8(&self, address: u16) -> u8 {
+        let translated_address = self.translate_address(address);
+
+        self.peek_ppu_registers(Self::fold_ppu_register(address))
+            .or_else(|| self.peek_controllers(address))
+            .or_else(|| self.peek_apu(address))
+            .unwrap_or(self.memory[translated_address])
+    }
+
+    // Writes a byte straight into memory, for the memory viewer. Unlike write8 it does not reach
+    // the PPU, APU or controllers, and nothing is logged.
+    pub fn poke8(&mut self, address: u16, value: u8) {
+        self.memory[self.translate_address(address)] = value;
+    }
+
+    pub fn read16(&self, address: u16) -> u16 {
+        // I will assume that we will never attempt to read 16bit that crosses the
+        // border of two ranges i.e the range that (address) occupies is not the
+        // same as (address+1). Otherwise I would have to do
+        // translate_address(address) AND translate_address(address+1)
+
+        let real_address = self.translate_address(address);
+
+        self.memory[real_address] as u16 | ((self.memory[real_address + 1] as u16) << 8)
+    }
+
+    pub fn write8(&mut self, address: u16, value: u8) -> i32 {
+        // The PRG ROM is read-only, a write there would go to the copy of the bank and outlive a load_state
+        if (address as usize) < RamController::PRG_BANK1_LOCATION {
+            self.memory[self.translate_address(address)] = value;
+        }
+        self.log_access(Bus::Cpu, AccessKind::Write, address, value);
+        self.log_ppu_event(PpuEventKind::Write, address, value);
+
+        if address == 0x4016 {
+            let mut controllers = self.controllers.get();
+            controllers.iter_mut().for_each(|c| c.write(value));
+            self.controllers.set(controllers);
+        }
+
+        if let 0x4000..=0x4013 | 0x4015 | 0x4017 = address {
+            self.apu.borrow_mut().write(address, value);
+        }
+
+        self.write_ppu_registers(Self::fold_ppu_register(address), value)
+    }
+
+    // Runs the APU alongside the CPU, the DMC fetches its samples straight from memory
+    pub fn clock_apu(&self, cycles: i32) {
+        self.apu.borrow_mut().clock(cycles, |address| self.memory[self.translate_address(address)]);
+    }
+
+    pub fn apu_irq_pending(&self) -> bool {
+        self.apu.borrow().irq_pending()
+    }
+
+    pub fn take_audio_samples(&self) -> Vec<f32> {
+        self.apu.borrow_mut().take_samples()
+    }
+
+    // Port 0 is read at $4016, port 1 at $4017
+    pub fn set_controller_buttons(&self, port: usize, buttons: u8) {
+        let mut controllers = self.controllers.get();
+        controllers[port].set_buttons(buttons);
+        self.controllers.set(controllers);
+    }
+
+    // Memory accesses are only recorded while someone (i.e the debugger) is interested in them
+    pub fn set_access_logging(&self, enabled: bool) {
+        self.log_accesses.set(enabled);
+        if !enabled {
+            self.accesses.borrow_mut().clear();
+        }
+    }
+
+    pub fn take_accesses(&self) -> Vec<MemoryAccess> {
+        self.accesses.replace(vec![])
+    }
+
+    // PPU register accesses are only recorded while someone (i.e the event viewer) is interested in them
+    pub fn set_ppu_event_logging(&self, enabled: bool) {
+        self.log_ppu_events.set(enabled);
+        if !enabled {
+            self.ppu_events.borrow_mut().clear();
+        }
+    }
+
+    // The accesses since the last call, for Nes::step to stamp with the scanline and dot
+    pub fn take_ppu_events(&self) -> Vec<(PpuEventKind, u16, u8)> {
+        self.ppu_events.replace(vec![])
+    }
+
+    fn log_ppu_event(&self, kind: PpuEventKind, address: u16, value: u8) {
+        if !self.log_ppu_events.get() {
+            return;
+        }
+        let address = match address {
+            0x2000..=0x3FFF => 0x2000 | (address & 7),
+            0x4014 if kind == PpuEventKind::Write => address,
+            _ => return,
+        };
+        self.ppu_events.borrow_mut().push((kind, address, value));
+    }
+
+    fn log_access(&self, bus: Bus, kind: AccessKind, address: u16, value: u8) {
+        if self.log_accesses.get() {
+            self.accesses.borrow_mut().push(MemoryAccess { bus, kind, address, value });
+        }
+    }
+
+    pub fn load_prg_bank1(&mut self, rom: &PrgRomBank) {
+        let prg_bank1 = &mut self.memory[RamController::PRG_BANK1_LOCATION..RamController::PRG_BANK1_LOCATION + RamController::PRG_BANK_SIZE];
+        prg_bank1.copy_from_slice(rom.get_data());
+        self.prg_banks[0] = rom.number();
+    }
+
+    pub fn load_prg_bank2(&mut self, rom: &PrgRomBank) {
+        let prg_bank2 = &mut self.memory[RamController::PRG_BANK2_LOCATION..RamController::PRG_BANK2_LOCATION + RamController::PRG_BANK_SIZE];
+        prg_bank2.copy_from_slice(rom.get_data());
+        self.prg_banks[1] = rom.number();
+    }
+
+    // Offset into the PRG ROM of whatever is currently mapped at a CPU address in $8000-$FFFF
+    pub fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        if (address as usize) < RamController::PRG_BANK1_LOCATION {
+            return None;
+        }
+
+        let slot = (address as usize - RamController::PRG_BANK1_LOCATION) / RamController::PRG_BANK_SIZE;
+        Some(self.prg_banks[slot] * RamController::PRG_BANK_SIZE + (address as usize % RamController::PRG_BANK_SIZE))
+    }
+
+    // The CPU address a PRG ROM offset is visible at, if its bank is mapped in at all
+    pub fn cpu_address_of_prg_offset(&self, offset: usize) -> Option<u16> {
+        let bank = offset / RamController::PRG_BANK_SIZE;
+        self.prg_banks.iter()
+            .position(|b| *b == bank)
+            .map(|slot| (RamController::PRG_BANK1_LOCATION + slot * RamController::PRG_BANK_SIZE + offset % RamController::PRG_BANK_SIZE) as u16)
+    }
+
+    // Only the 2KB of RAM and the PRG RAM at $6000-$7FFF are saved, the PRG ROM comes from the
+    // cartridge. The PRG bank numbers are what a mapper would have in its registers.
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.memory[RamController::RAM_RANGE]);
+        writer.write_bytes(&self.memory[RamController::PRG_RAM_RANGE]);
+        for bank in self.prg_banks.iter() {
+            writer.write_u32(*bank as u32);
+        }
+        for controller in self.controllers.get().iter() {
+            controller.save_state(writer);
+        }
+    }
+
+    pub fn save_apu_state(&self, writer: &mut StateWriter) {
+        self.apu.borrow().save_state(writer);
+    }
+
+    pub fn load_apu_state(&self, reader: &mut StateReader) -> Result<(), String> {
+        self.apu.borrow_mut().load_state(reader)
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        reader.read_into(&mut self.memory[RamController::RAM_RANGE])?;
+        reader.read_into(&mut self.memory[RamController::PRG_RAM_RANGE])?;
+        for bank in self.prg_banks.iter_mut() {
+            *bank = reader.read_u32()? as usize;
+        }
+
+        let mut controllers = self.controllers.get();
+        for controller in controllers.iter_mut() {
+            controller.load_state(reader)?;
+        }
+        self.controllers.set(controllers);
+
+        Ok(())
+    }
+
+    fn is_lower_ram_range(&self, address: u16) -> bool {
+        (address & 0x1FFF) == address
+    }
+
+    fn is_io_mirror_range(&self, address: u16) -> bool {
+        (address & 0x3FFF) == address
+    }
+
+    fn translate_address(&self, address: u16) -> usize {
+        if self.is_lower_ram_range(address) {
+            // Address range $0000-$07FF is mirrored 3 times
+            return (address & 0x07FF) as usize;
+        } else if self.is_io_mirror_range(address) {
+            // Address range $2000-$2007 is mirrored multiple times
+            return (address & 0x2007) as usize;
+        }
+
+        address as usize
+    }
+
+    // $2000-$2007 repeat every 8 bytes up to $3FFF
+    fn fold_ppu_register(address: u16) -> u16 {
+        match address {
+            0x2000..=0x3FFF => 0x2000 | (address & 7),
+            _ => address
+        }
+    }
+
+    fn read_ppu_registers(&self, address: u16) -> Option<u8> {
+        match address {
+            0x2002 => {
+                let mut ppu_regs = self.ppu_regs.get();
+                let status = ppu_regs.status();
+                self.ppu_regs.set(ppu_regs);
+                Some(status)
+            },
+            0x2004 => {
+                Some(self.vram.borrow().read_oam(self.ppu_regs.get().oamaddr()))
+            },
+            0x2007 => {
+                let mut regs = self.ppu_regs.get();
+                let ppuaddr = regs.ppuaddr();
+                let value = self.read_ppudata(&regs);
+                let vram = self.vram.borrow();
+                let fetched = vram.read8(ppuaddr);
+                // A palette read still refills the buffer, with the nametable byte underneath it
+                regs.set_read_buffer(if ppuaddr & 0x3FFF >= 0x3F00 { vram.read8(ppuaddr & 0x2FFF) } else { fetched });
+                regs.increment_ppuaddr();
+                self.ppu_regs.set(regs);
+                self.log_access(Bus::Ppu, AccessKind::Read, ppuaddr, fetched);
+                Some(value)
+            }
+            _ => None
+        }
+    }
+
+    fn read_controllers(&self, address: u16) -> Option<u8> {
+        let port = match address {
+            0x4016 => 0,
+            0x4017 => 1,
+            _ => return None
+        };
+
+        let mut controllers = self.controllers.get();
+        let value = controllers[port].read();
+        self.controllers.set(controllers);
+        Some(value)
+    }
+
+    fn read_apu(&self, address: u16) -> Option<u8> {
+        match address {
+            0x4015 => Some(self.apu.borrow_mut().read_status()),
+            _ => None
+        }
+    }
+
+    fn peek_apu(&self, address: u16) -> Option<u8> {
+        match address {
+            0x4015 => Some(self.apu.borrow().peek_status()),
+            _ => None
+        }
+    }
+
+    fn peek_controllers(&self, address: u16) -> Option<u8> {
+        match address {
+            0x4016 => Some(self.controllers.get()[0].peek()),
+            0x4017 => Some(self.controllers.get()[1].peek()),
+            _ => None
+        }
+    }
+
+    fn peek_ppu_registers(&self, address: u16) -> Option<u8> {
+        match address {
+            0x2002 => Some(self.ppu_regs.get().peek_status()),
+            0x2004 => Some(self.vram.borrow().read_oam(self.ppu_regs.get().oamaddr())),
+            0x2007 => Some(self.read_ppudata(&self.ppu_regs.get())),
+            _ => None
+        }
+    }
+
+    // What a $2007 read returns: reads below the palette hand out the byte the previous read fetched
+    fn read_ppudata(&self, regs: &PPURegisters) -> u8 {
+        let ppuaddr = regs.ppuaddr();
+        if ppuaddr & 0x3FFF >= 0x3F00 {
+            self.vram.borrow().read8(ppuaddr)
+        } else {
+            regs.read_buffer()
+        }
+    }
+
+    fn write_ppu_registers(&mut self, address: u16, value: u8) -> i32 {
+        match address {
+            0x2000 => {
+                let mut regs = self.ppu_regs.get();
+                regs.set_ppuctrl(value);
+                self.ppu_regs.set(regs);
+
+                0
+            }
+            0x2001 => {
+                let mut regs = self.ppu_regs.get();
+                regs.set_ppumask(value);
+                self.ppu_regs.set(regs);
+
+                0
+            }
+            0x2003 => {
+                let mut regs = self.ppu_regs.get();
+                regs.set_oamaddr(value);
+                self.ppu_regs.set(regs);
+
+                0
+            },
+            0x2004 => {
+                let mut regs = self.ppu_regs.get();
+                self.vram.borrow_mut().write_oam(regs.oamaddr(), value);
+                regs.set_oamaddr(regs.oamaddr().wrapping_add(1));
+                self.ppu_regs.set(regs);
+
+                0
+            }
+            0x2005 => {
+                let mut regs = self.ppu_regs.get();
+                regs.set_ppuscroll(value);
+                self.ppu_regs.set(regs);
+
+                0
+            }
+            0x2006 => {
+                let mut regs = self.ppu_regs.get();
+                regs.set_ppuaddr(value);
+                self.ppu_regs.set(regs);
+
+                0
+            }
+            0x2007 => {
+                let mut regs = self.ppu_regs.get();
+                self.vram.borrow_mut().write8(regs.ppuaddr(), value);
+                self.log_access(Bus::Ppu, AccessKind::Write, regs.ppuaddr(), value);
+                regs.increment_ppuaddr();
+                self.ppu_regs.set(regs);
+
+                0
+            }
+            0x4014 => {
+                let cpu_page_address = (value as usize) << 8;
+                let cpu_page= &self.memory[cpu_page_address..(cpu_page_address + 0x100)];
+
+                self.vram.borrow_mut().write_oam_dma(self.ppu_regs.get().oamaddr(), cpu_page);
+
+                513
+            }
+            _ => 0
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::region::Region;
+
+    fn controller() -> RamController {
+        RamController::new(Rc::new(Cell::new(PPURegisters::new())), Rc::new(RefCell::new(VRAMController::new())), Region::Ntsc)
+    }
+
+    fn set_ppuaddr(ram: &mut RamController, register: u16, address: u16) {
+        ram.write8(register, (address >> 8) as u8);
+        ram.write8(register, address as u8);
+    }
+
+    #[test]
+    fn ppu_registers_are_mirrored_up_to_3fff() {
+        let mut ram = controller();
+        set_ppuaddr(&mut ram, 0x3FFE, 0x2400);
+        ram.write8(0x2FFF, 0x5A);
+        assert_eq!(ram.vram.borrow().read8(0x2400), 0x5A);
+
+        ram.write8(0x3FFB, 0x10);
+        ram.write8(0x200C, 0x77);
+        assert_eq!(ram.vram.borrow().read_oam(0x10), 0x77);
+        ram.write8(0x280B, 0x10);
+        assert_eq!(ram.peek8(0x3FF4), 0x77);
+
+        let mut regs = ram.ppu_regs.get();
+        regs.start_vblank();
+        ram.ppu_regs.set(regs);
+        assert_eq!(ram.read8(0x3FFA) & 0x80, 0x80);
+        assert_eq!(ram.read8(0x2002) & 0x80, 0);
+    }
+
+    #[test]
+    fn ppudata_reads_go_through_the_read_buffer() {
+        let mut ram = controller();
+        ram.vram.borrow_mut().write8(0x2000, 0x11);
+        ram.vram.borrow_mut().write8(0x2001, 0x22);
+        ram.vram.borrow_mut().write8(0x2F00, 0x33);
+        ram.vram.borrow_mut().write8(0x3F00, 0x0F);
+
+        set_ppuaddr(&mut ram, 0x2006, 0x2000);
+        assert_eq!(ram.peek8(0x2007), 0);
+        assert_eq!(ram.read8(0x2007), 0);
+        assert_eq!(ram.peek8(0x2007), 0x11);
+        assert_eq!(ram.read8(0x200F), 0x11);
+        assert_eq!(ram.read8(0x2007), 0x22);
+
+        // Palette reads come back straight away and leave the nametable byte below in the buffer
+        set_ppuaddr(&mut ram, 0x2006, 0x3F00);
+        assert_eq!(ram.read8(0x2007), 0x0F);
+        set_ppuaddr(&mut ram, 0x2006, 0x2001);
+        assert_eq!(ram.read8(0x2007), 0x33);
+    }
+
+}
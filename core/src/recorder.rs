@@ -0,0 +1,453 @@
+// Recording every emulated frame and its audio, for bug reports and longplays. Video goes to Y4M
+// (uncompressed 4:4:4 YCbCr that ffmpeg and mpv read), audio to a 16-bit mono WAV, and both can be
+// interleaved into an uncompressed AVI. The frame rate is the region's exact one, and each frame is
+// followed by exactly the samples the APU put out during it, so the streams never drift apart.
+
+use crate::apu::SAMPLE_RATE;
+use crate::region::Region;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+
+// The frame rate as numerator / denominator, 60.0988 is 600988 / 10000
+fn frame_rate(region: Region) -> (u32, u32) {
+    ((region.frame_rate() * 10000.0).round() as u32, 10000)
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+fn create(path: &str) -> Result<BufWriter<File>, String> {
+    File::create(path).map(BufWriter::new).map_err(|e| format!("{}: {}", path, e))
+}
+
+fn to_pcm(sample: f32) -> [u8; 2] {
+    ((sample.clamp(-1.0, 1.0) * 32767.0) as i16).to_le_bytes()
+}
+
+pub struct Y4mWriter<W: Write> {
+    out: W,
+    width: u32,
+    height: u32,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    // The pixel aspect ratio makes players show a 256 or 602 pixel wide picture 8:7 wide per NES pixel
+    pub fn new(mut out: W, width: u32, height: u32, region: Region) -> Result<Y4mWriter<W>, String> {
+        let (rate, scale) = frame_rate(region);
+        let (aspect_x, aspect_y) = (256 * 8, 7 * width);
+        let divisor = gcd(aspect_x, aspect_y);
+        writeln!(out, "YUV4MPEG2 W{} H{} F{}:{} Ip A{}:{} C444", width, height, rate, scale, aspect_x / divisor, aspect_y / divisor)
+            .map_err(|e| e.to_string())?;
+        Ok(Y4mWriter { out, width, height })
+    }
+
+    pub fn frame(&mut self, rgb: &[u8]) -> Result<(), String> {
+        if rgb.len() != self.width as usize * self.height as usize * 3 {
+            return Err(format!("Every frame of the video has to be {}x{}", self.width, self.height));
+        }
+
+        // BT.601 with the studio range, which is what players assume without being told
+        let mut planes = vec![0u8; rgb.len()];
+        let pixels = rgb.len() / 3;
+        for (i, pixel) in rgb.chunks(3).enumerate() {
+            let (r, g, b) = (pixel[0] as f32, pixel[1] as f32, pixel[2] as f32);
+            planes[i] = (16.0 + (65.481 * r + 128.553 * g + 24.966 * b) / 255.0).round() as u8;
+            planes[pixels + i] = (128.0 + (-37.797 * r - 74.203 * g + 112.0 * b) / 255.0).round() as u8;
+            planes[pixels * 2 + i] = (128.0 + (112.0 * r - 93.786 * g - 18.214 * b) / 255.0).round() as u8;
+        }
+
+        self.out.write_all(b"FRAME\n").map_err(|e| e.to_string())?;
+        self.out.write_all(&planes).map_err(|e| e.to_string())
+    }
+
+    pub fn finish(mut self) -> Result<W, String> {
+        self.out.flush().map_err(|e| e.to_string())?;
+        Ok(self.out)
+    }
+}
+
+// The lengths in the header are filled in by finish()
+pub struct WavWriter<W: Write + Seek> {
+    out: W,
+    samples: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut out: W) -> Result<WavWriter<W>, String> {
+        out.write_all(&wav_header(0)?).map_err(|e| e.to_string())?;
+        Ok(WavWriter { out, samples: 0 })
+    }
+
+    pub fn write(&mut self, samples: &[f32]) -> Result<(), String> {
+        // Refused before anything is written, so that finish() still leaves a valid file
+        let total = u32::try_from(samples.len()).ok()
+            .and_then(|count| self.samples.checked_add(count))
+            .filter(|total| wav_lengths(*total).is_some())
+            .ok_or(WAV_FULL)?;
+
+        let data: Vec<u8> = samples.iter().flat_map(|sample| to_pcm(*sample)).collect();
+        self.samples = total;
+        self.out.write_all(&data).map_err(|e| e.to_string())
+    }
+
+    pub fn finish(mut self) -> Result<W, String> {
+        self.out.seek(SeekFrom::Start(0)).map_err(|e| e.to_string())?;
+        self.out.write_all(&wav_header(self.samples)?).map_err(|e| e.to_string())?;
+        self.out.seek(SeekFrom::End(0)).map_err(|e| e.to_string())?;
+        self.out.flush().map_err(|e| e.to_string())?;
+        Ok(self.out)
+    }
+}
+
+const WAV_FULL: &str = "WAV is full at 4GB";
+
+// The RIFF and data chunk lengths, None once they no longer fit in 32 bits
+fn wav_lengths(samples: u32) -> Option<(u32, u32)> {
+    let data_length = samples.checked_mul(2)?;
+    Some((data_length.checked_add(36)?, data_length))
+}
+
+fn wav_header(samples: u32) -> Result<Vec<u8>, String> {
+    let (riff_length, data_length) = wav_lengths(samples).ok_or(WAV_FULL)?;
+    let mut data = vec![];
+    data.extend_from_slice(b"RIFF");
+    data.extend_from_slice(&riff_length.to_le_bytes());
+    data.extend_from_slice(b"WAVE");
+    chunk(&mut data, b"fmt ", &wave_format());
+    data.extend_from_slice(b"data");
+    data.extend_from_slice(&data_length.to_le_bytes());
+    Ok(data)
+}
+
+// WAVEFORMATEX without the extra size field: PCM, mono, 16 bits
+fn wave_format() -> Vec<u8> {
+    let mut format = vec![];
+    format.extend_from_slice(&1u16.to_le_bytes());
+    format.extend_from_slice(&1u16.to_le_bytes());
+    format.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    format.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    format.extend_from_slice(&2u16.to_le_bytes());
+    format.extend_from_slice(&16u16.to_le_bytes());
+    format
+}
+
+fn chunk(data: &mut Vec<u8>, kind: &[u8; 4], contents: &[u8]) {
+    data.extend_from_slice(kind);
+    data.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+    data.extend_from_slice(contents);
+    if contents.len() % 2 == 1 {
+        data.push(0);
+    }
+}
+
+fn list(data: &mut Vec<u8>, kind: &[u8; 4], contents: &[u8]) {
+    data.extend_from_slice(b"LIST");
+    data.extend_from_slice(&(contents.len() as u32 + 4).to_le_bytes());
+    data.extend_from_slice(kind);
+    data.extend_from_slice(contents);
+}
+
+// An AVI 1.0 file with a 24-bit DIB video stream and a PCM audio stream, a frame and then its audio.
+// The header is written again with the counts by finish(). Plain AVI can not go past 4GB, about six
+// minutes of 256x240, so longer recordings have to be Y4M and WAV.
+pub struct AviWriter<W: Write + Seek> {
+    out: W,
+    width: u32,
+    height: u32,
+    region: Region,
+    frames: u32,
+    samples: u32,
+    // Bytes of chunks after the movi list's type
+    movi_length: u32,
+    index: Vec<u8>,
+}
+
+impl<W: Write + Seek> AviWriter<W> {
+    pub fn new(out: W, width: u32, height: u32, region: Region) -> Result<AviWriter<W>, String> {
+        let mut writer = AviWriter { out, width, height, region, frames: 0, samples: 0, movi_length: 0, index: vec![] };
+        let header = writer.header(0);
+        writer.out.write_all(&header).map_err(|e| e.to_string())?;
+        Ok(writer)
+    }
+
+    fn header(&self, riff_length: u32) -> Vec<u8> {
+        let (rate, scale) = frame_rate(self.region);
+        let frame_size = self.row_length() * self.height;
+        let dword = |data: &mut Vec<u8>, value: u32| data.extend_from_slice(&value.to_le_bytes());
+
+        let mut avih = vec![];
+        dword(&mut avih, (1_000_000.0 / self.region.frame_rate()).round() as u32);
+        dword(&mut avih, (frame_size as f64 * self.region.frame_rate()) as u32 + SAMPLE_RATE * 2);
+        dword(&mut avih, 0);
+        // Has an index, and the streams are interleaved
+        dword(&mut avih, 0x110);
+        dword(&mut avih, self.frames);
+        dword(&mut avih, 0);
+        dword(&mut avih, 2);
+        dword(&mut avih, frame_size);
+        dword(&mut avih, self.width);
+        dword(&mut avih, self.height);
+        avih.extend_from_slice(&[0; 16]);
+
+        let stream_header = |kind: &[u8; 4], handler: &[u8; 4], scale: u32, rate: u32, length: u32, buffer: u32, sample_size: u32, frame: (u32, u32)| {
+            let mut strh = vec![];
+            strh.extend_from_slice(kind);
+            strh.extend_from_slice(handler);
+            // Flags, priority and language, initial frames
+            strh.extend_from_slice(&[0; 12]);
+            dword(&mut strh, scale);
+            dword(&mut strh, rate);
+            dword(&mut strh, 0);
+            dword(&mut strh, length);
+            dword(&mut strh, buffer);
+            dword(&mut strh, u32::MAX);
+            dword(&mut strh, sample_size);
+            strh.extend_from_slice(&[0; 4]);
+            strh.extend_from_slice(&(frame.0 as u16).to_le_bytes());
+            strh.extend_from_slice(&(frame.1 as u16).to_le_bytes());
+            strh
+        };
+
+        // BITMAPINFOHEADER, a positive height means the rows go bottom up
+        let mut bitmap = vec![];
+        dword(&mut bitmap, 40);
+        dword(&mut bitmap, self.width);
+        dword(&mut bitmap, self.height);
+        bitmap.extend_from_slice(&1u16.to_le_bytes());
+        bitmap.extend_from_slice(&24u16.to_le_bytes());
+        dword(&mut bitmap, 0);
+        dword(&mut bitmap, frame_size);
+        bitmap.extend_from_slice(&[0; 16]);
+
+        let mut video = vec![];
+        chunk(&mut video, b"strh", &stream_header(b"vids", b"DIB ", scale, rate, self.frames, frame_size, 0, (self.width, self.height)));
+        chunk(&mut video, b"strf", &bitmap);
+        let mut audio = vec![];
+        chunk(&mut audio, b"strh", &stream_header(b"auds", &[0; 4], 1, SAMPLE_RATE, self.samples, SAMPLE_RATE / 10, 2, (0, 0)));
+        chunk(&mut audio, b"strf", &wave_format());
+
+        let mut hdrl = vec![];
+        chunk(&mut hdrl, b"avih", &avih);
+        list(&mut hdrl, b"strl", &video);
+        list(&mut hdrl, b"strl", &audio);
+
+        let mut data = vec![];
+        data.extend_from_slice(b"RIFF");
+        dword(&mut data, riff_length);
+        data.extend_from_slice(b"AVI ");
+        list(&mut data, b"hdrl", &hdrl);
+        data.extend_from_slice(b"LIST");
+        dword(&mut data, self.movi_length + 4);
+        data.extend_from_slice(b"movi");
+        data
+    }
+
+    // DIB rows are padded to 4 bytes
+    fn row_length(&self) -> u32 {
+        (self.width * 3 + 3) & !3
+    }
+
+    pub fn frame(&mut self, rgb: &[u8], samples: &[f32]) -> Result<(), String> {
+        if rgb.len() != self.width as usize * self.height as usize * 3 {
+            return Err(format!("Every frame of the video has to be {}x{}", self.width, self.height));
+        }
+
+        let row_length = self.row_length() as usize;
+        let mut bitmap = Vec::with_capacity(row_length * self.height as usize);
+        for row in rgb.chunks(self.width as usize * 3).rev() {
+            bitmap.extend(row.chunks(3).flat_map(|pixel| [pixel[2], pixel[1], pixel[0]]));
+            bitmap.resize(bitmap.len().next_multiple_of(row_length), 0);
+        }
+        // Keyframes, every one of them
+        self.write_chunk(b"00dc", 0x10, &bitmap)?;
+        self.frames += 1;
+
+        if !samples.is_empty() {
+            let pcm: Vec<u8> = samples.iter().flat_map(|sample| to_pcm(*sample)).collect();
+            self.write_chunk(b"01wb", 0, &pcm)?;
+            self.samples += samples.len() as u32;
+        }
+        Ok(())
+    }
+
+    fn write_chunk(&mut self, kind: &[u8; 4], flags: u32, contents: &[u8]) -> Result<(), String> {
+        let padded = contents.len() as u64 + 8 + contents.len() as u64 % 2;
+        let movi_length = self.movi_length as u64 + padded;
+        // The index gets an entry for this chunk too, and has to fit as well
+        self.riff_length(movi_length, self.index.len() as u64 / 16 + 1)?;
+        let length = u32::try_from(contents.len()).map_err(|_| String::from("An AVI chunk can not be 4GB"))?;
+
+        // Offsets in the index count from the movi list's type
+        self.index.extend_from_slice(kind);
+        self.index.extend_from_slice(&flags.to_le_bytes());
+        self.index.extend_from_slice(&(self.movi_length + 4).to_le_bytes());
+        self.index.extend_from_slice(&length.to_le_bytes());
+
+        let mut data = Vec::with_capacity(padded as usize);
+        chunk(&mut data, kind, contents);
+        self.out.write_all(&data).map_err(|e| e.to_string())?;
+        self.movi_length = movi_length as u32;
+        Ok(())
+    }
+
+    // What the RIFF header will give as the file's length, once it ends with an index of `entries`
+    fn riff_length(&self, movi_length: u64, entries: u64) -> Result<u32, String> {
+        let length = self.header(0).len() as u64 - 8 + movi_length + 8 + 16 * entries;
+        u32::try_from(length).map_err(|_| String::from("The AVI file is full at 4GB, record to Y4M and WAV instead"))
+    }
+
+    pub fn finish(mut self) -> Result<W, String> {
+        let length = self.riff_length(self.movi_length as u64, self.index.len() as u64 / 16)?;
+        let mut index = vec![];
+        chunk(&mut index, b"idx1", &self.index);
+        self.out.write_all(&index).map_err(|e| e.to_string())?;
+
+        let header = self.header(length);
+        self.out.seek(SeekFrom::Start(0)).map_err(|e| e.to_string())?;
+        self.out.write_all(&header).map_err(|e| e.to_string())?;
+        self.out.seek(SeekFrom::End(0)).map_err(|e| e.to_string())?;
+        self.out.flush().map_err(|e| e.to_string())?;
+        Ok(self.out)
+    }
+}
+
+// Whichever of the three files were asked for
+pub struct Recorder {
+    video: Option<Y4mWriter<BufWriter<File>>>,
+    audio: Option<WavWriter<BufWriter<File>>>,
+    avi: Option<AviWriter<BufWriter<File>>>,
+}
+
+impl Recorder {
+    // Frames passed to frame() have to be `width` x `height`
+    pub fn create(video: Option<&str>, audio: Option<&str>, avi: Option<&str>, width: u32, height: u32, region: Region) -> Result<Recorder, String> {
+        Ok(Recorder {
+            video: video.map(|path| Y4mWriter::new(create(path)?, width, height, region)).transpose()?,
+            audio: audio.map(|path| WavWriter::new(create(path)?)).transpose()?,
+            avi: avi.map(|path| AviWriter::new(create(path)?, width, height, region)).transpose()?,
+        })
+    }
+
+    // One emulated frame as RGB, and the samples the APU put out while it ran
+    pub fn frame(&mut self, rgb: &[u8], samples: &[f32]) -> Result<(), String> {
+        if let Some(video) = self.video.as_mut() {
+            video.frame(rgb)?;
+        }
+        if let Some(audio) = self.audio.as_mut() {
+            audio.write(samples)?;
+        }
+        if let Some(avi) = self.avi.as_mut() {
+            avi.frame(rgb, samples)?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<(), String> {
+        if let Some(video) = self.video {
+            video.finish()?;
+        }
+        if let Some(audio) = self.audio {
+            audio.finish()?;
+        }
+        if let Some(avi) = self.avi {
+            avi.finish()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn u32_at(data: &[u8], position: usize) -> u32 {
+        u32::from_le_bytes([data[position], data[position + 1], data[position + 2], data[position + 3]])
+    }
+
+    #[test]
+    fn y4m_and_wav_have_every_frame_and_sample() {
+        let mut video = Y4mWriter::new(Cursor::new(vec![]), 2, 1, Region::Ntsc).unwrap();
+        video.frame(&[255, 255, 255, 0, 0, 0]).unwrap();
+        video.frame(&[0, 0, 0, 255, 255, 255]).unwrap();
+        assert!(video.frame(&[0; 3]).is_err());
+        let video = video.finish().unwrap().into_inner();
+
+        let header = b"YUV4MPEG2 W2 H1 F600988:10000 Ip A1024:7 C444\n";
+        assert_eq!(&video[..header.len()], header);
+        assert_eq!(&video[header.len()..], b"FRAME\n\xEB\x10\x80\x80\x80\x80FRAME\n\x10\xEB\x80\x80\x80\x80");
+
+        let mut audio = WavWriter::new(Cursor::new(vec![])).unwrap();
+        audio.write(&[0.0, 1.0]).unwrap();
+        audio.write(&[-1.0]).unwrap();
+        let audio = audio.finish().unwrap().into_inner();
+        assert_eq!((audio.len(), u32_at(&audio, 4), u32_at(&audio, 40)), (44 + 6, 42, 6));
+        assert_eq!(&audio[44..], &[0, 0, 0xFF, 0x7F, 0x01, 0x80]);
+    }
+
+    #[test]
+    fn wav_stops_before_its_lengths_overflow() {
+        let mut audio = WavWriter::new(Cursor::new(vec![])).unwrap();
+        // As if nearly 4GB had been recorded already, one more sample still fits
+        let last = (u32::MAX - 36) / 2;
+        audio.samples = last - 1;
+        audio.write(&[0.0]).unwrap();
+        assert_eq!(audio.write(&[0.0]).err(), Some(String::from("WAV is full at 4GB")));
+        assert_eq!(audio.write(&[]).err(), None);
+
+        let audio = audio.finish().unwrap().into_inner();
+        assert_eq!((u32_at(&audio, 4), u32_at(&audio, 40)), (36 + last * 2, last * 2));
+        assert_eq!(audio.len(), 44 + 2);
+        assert!(wav_header(last + 1).is_err());
+    }
+
+    #[test]
+    fn avi_counts_and_indexes_its_chunks() {
+        let mut avi = AviWriter::new(Cursor::new(vec![]), 3, 2, Region::Pal).unwrap();
+        let rgb: Vec<u8> = (0..18).collect();
+        avi.frame(&rgb, &[0.5; 735]).unwrap();
+        avi.frame(&rgb, &[]).unwrap();
+        let data = avi.finish().unwrap().into_inner();
+
+        assert_eq!((&data[..4], u32_at(&data, 4) as usize + 8, &data[8..12]), (&b"RIFF"[..], data.len(), &b"AVI "[..]));
+        // avih's frame count, and the lengths of the two streams
+        assert_eq!(u32_at(&data, 48), 2);
+        let streams: Vec<usize> = data.windows(4).enumerate().filter(|(_, w)| *w == b"strh").map(|(i, _)| i).collect();
+        assert_eq!((u32_at(&data, streams[0] + 8 + 32), u32_at(&data, streams[1] + 8 + 32)), (2, 735));
+
+        // Rows are bottom up in BGR and padded to 12 bytes
+        let movi = data.windows(4).position(|w| w == b"movi").unwrap();
+        assert_eq!(&data[movi + 4..movi + 12], b"00dc\x18\x00\x00\x00");
+        assert_eq!(&data[movi + 12..movi + 24], &[11, 10, 9, 14, 13, 12, 17, 16, 15, 0, 0, 0]);
+
+        let index = data.windows(4).rposition(|w| w == b"idx1").unwrap();
+        assert_eq!(u32_at(&data, index + 4), 3 * 16);
+        // The audio chunk's offset, from the movi list's type
+        assert_eq!(&data[movi + u32_at(&data, index + 8 + 16 + 8) as usize..][..4], b"01wb");
+    }
+
+    #[test]
+    fn avi_leaves_room_for_its_index_below_4gb() {
+        let mut avi = AviWriter::new(Cursor::new(vec![]), 1, 1, Region::Ntsc).unwrap();
+        // As if nearly 4GB had been recorded already, then 1x1 frames of 28 bytes with their index
+        // entries until it is full
+        avi.movi_length = u32::MAX - 0x1000;
+        let mut frames = 0u64;
+        let error = loop {
+            match avi.frame(&[0; 3], &[]) {
+                Ok(()) => frames += 1,
+                Err(e) => break e,
+            }
+        };
+        assert!(error.contains("full"));
+
+        let (header_length, movi_length) = (avi.header(0).len() as u64, avi.movi_length as u64);
+        assert!(frames > 0);
+        let data = avi.finish().unwrap().into_inner();
+        let length = header_length - 8 + movi_length + 8 + 16 * frames;
+        assert_eq!(u32_at(&data, 4) as u64, length);
+        assert!(length + 12 + 16 > u32::MAX as u64);
+    }
+}
@@ -0,0 +1,83 @@
+// The console models, which differ in clock speed, frame length and some APU tables
+
+const DOTS_PER_SCANLINE: i32 = 341;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Region {
+    Ntsc,
+    Pal,
+    // The Famiclone sold in Russia, PAL timing with the NTSC APU and a late VBlank
+    Dendy,
+}
+
+impl Region {
+    pub fn from_name(name: &str) -> Result<Region, String> {
+        match name.to_lowercase().as_str() {
+            "ntsc" => Ok(Region::Ntsc),
+            "pal" => Ok(Region::Pal),
+            "dendy" => Ok(Region::Dendy),
+            _ => Err(format!("Unknown region {}, it has to be ntsc, pal or dendy", name))
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Region::Ntsc => "ntsc",
+            Region::Pal => "pal",
+            Region::Dendy => "dendy",
+        }
+    }
+
+    // In Hz
+    pub fn cpu_clock(self) -> f64 {
+        match self {
+            Region::Ntsc => 1_789_773.0,
+            Region::Pal => 1_662_607.0,
+            Region::Dendy => 1_773_448.0,
+        }
+    }
+
+    // Frames per second, from the PPU clock and the dots in a frame. NTSC frames are half a dot
+    // shorter on average, as every other one skips a dot while rendering is on.
+    pub fn frame_rate(self) -> f64 {
+        let (dots, cycles) = self.dots_per_cycle();
+        let skipped = if self == Region::Ntsc { 0.5 } else { 0.0 };
+        let frame_dots = (self.scanlines() * DOTS_PER_SCANLINE) as f64 - skipped;
+        self.cpu_clock() * dots as f64 / cycles as f64 / frame_dots
+    }
+
+    // Scanlines per frame, including the pre-render line
+    pub fn scanlines(self) -> i32 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    // The scanline VBlank starts on. The Dendy idles for 51 lines before it, so VBlank is as long as on NTSC.
+    pub fn vblank_scanline(self) -> i32 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    // PPU dots per CPU cycle, as a fraction since a PAL PPU runs 3.2 dots per cycle
+    pub fn dots_per_cycle(self) -> (i32, i32) {
+        match self {
+            Region::Ntsc | Region::Dendy => (3, 1),
+            Region::Pal => (16, 5),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_rates_come_from_the_clocks() {
+        let rates: Vec<String> = [Region::Ntsc, Region::Pal, Region::Dendy].iter().map(|r| format!("{:.5}", r.frame_rate())).collect();
+        assert_eq!(rates, ["60.09882", "50.00698", "50.00699"]);
+    }
+}
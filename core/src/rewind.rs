@@ -0,0 +1,276 @@
+use crate::region::Region;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Keeps a history of save states for rewinding. Only the newest state is kept whole, every older
+// one is stored as the XOR against the state that followed it. Since only a few bytes of RAM
+// change from one frame to the next, those deltas are almost all zeroes and shrink to a few
+// hundred bytes with a simple run length encoding of the zero runs.
+pub struct RewindBuffer {
+    interval: u32,
+    frame_duration: Duration,
+    budget: usize,
+    newest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+    used: usize,
+    frames_since_snapshot: u32,
+    last_step: Option<Instant>,
+}
+
+impl RewindBuffer {
+    // Snapshots every `interval` frames and keeps at most `budget` bytes of history, counting the
+    // newest state along with the deltas. The region sets how long a frame is when playing the
+    // history back.
+    pub fn new(region: Region, interval: u32, budget: usize) -> RewindBuffer {
+        RewindBuffer {
+            interval: interval.max(1),
+            frame_duration: Duration::from_secs_f64(1.0 / region.frame_rate()),
+            budget,
+            newest: None,
+            deltas: VecDeque::new(),
+            used: 0,
+            frames_since_snapshot: 0,
+            last_step: None,
+        }
+    }
+
+    // Called once per emulated frame, `save` is only invoked on the frames that get a snapshot
+    pub fn frame<F: FnOnce() -> Vec<u8>>(&mut self, save: F) {
+        self.frames_since_snapshot += 1;
+        if self.frames_since_snapshot < self.interval {
+            return;
+        }
+
+        self.frames_since_snapshot = 0;
+        self.push(save());
+    }
+
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(newest) = self.newest.take() {
+            self.used -= newest.len();
+            if newest.len() == state.len() {
+                let delta = encode_delta(&newest, &state);
+                self.used += delta.len();
+                self.deltas.push_back(delta);
+            } else {
+                // A state of another shape can not be diffed against, start over from this one
+                self.deltas.clear();
+                self.used = 0;
+            }
+        }
+
+        self.used += state.len();
+        self.newest = Some(state);
+
+        while self.used > self.budget {
+            match self.deltas.pop_front() {
+                Some(delta) => self.used -= delta.len(),
+                None => break
+            }
+        }
+    }
+
+    // The state before the newest one, which becomes the newest. None once the history runs out.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let delta = self.deltas.pop_back()?;
+        self.used -= delta.len();
+
+        let newest = self.newest.as_mut()?;
+        apply_delta(newest, &delta);
+        self.frames_since_snapshot = 0;
+
+        Some(newest.clone())
+    }
+
+    // Like `pop`, but paced so that holding the rewind key plays the history back at the speed it was recorded
+    pub fn step_back(&mut self) -> Option<Vec<u8>> {
+        let now = Instant::now();
+        if let Some(last_step) = self.last_step {
+            if now.duration_since(last_step) < self.frame_duration * self.interval {
+                return None;
+            }
+        }
+
+        self.last_step = Some(now);
+        self.pop()
+    }
+
+    pub fn stop_rewinding(&mut self) {
+        self.last_step = None;
+    }
+}
+
+// The XOR of two equally long states, where runs of zeroes are written as their length.
+// The encoding is a list of (zero run, literal count, literals) with the counts as LEB128 varints.
+fn encode_delta(older: &[u8], newer: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![];
+    let mut i = 0;
+
+    while i < older.len() {
+        let zeroes_start = i;
+        while i < older.len() && older[i] == newer[i] {
+            i += 1;
+        }
+
+        // Short runs of zeroes are cheaper to keep as literals than to end the literal run for
+        let literals_start = i;
+        let mut literals_end = i;
+        while i < older.len() {
+            if older[i] != newer[i] {
+                i += 1;
+                literals_end = i;
+            } else if i + 1 < older.len() && older[i + 1] != newer[i + 1] {
+                i += 1;
+            } else {
+                break;
+            }
+        }
+        i = literals_end;
+
+        write_varint(&mut encoded, literals_start - zeroes_start);
+        write_varint(&mut encoded, literals_end - literals_start);
+        encoded.extend((literals_start..literals_end).map(|j| older[j] ^ newer[j]));
+    }
+
+    encoded
+}
+
+// Turns `state` into the state the delta was made against
+fn apply_delta(state: &mut [u8], delta: &[u8]) {
+    let mut position = 0;
+    let mut i = 0;
+
+    while i < delta.len() {
+        position += read_varint(delta, &mut i);
+        let literals = read_varint(delta, &mut i);
+        for value in &delta[i..i + literals] {
+            state[position] ^= value;
+            position += 1;
+        }
+        i += literals;
+    }
+}
+
+fn write_varint(output: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        output.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    output.push(value as u8);
+}
+
+fn read_varint(input: &[u8], position: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+
+    loop {
+        let byte = input[*position];
+        *position += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        shift += 7;
+
+        if byte & 0x80 == 0 {
+            return value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(older: &[u8], newer: &[u8]) -> Vec<u8> {
+        let delta = encode_delta(older, newer);
+        let mut state = newer.to_vec();
+        apply_delta(&mut state, &delta);
+        assert_eq!(state, older);
+        delta
+    }
+
+    #[test]
+    fn equal_states_shrink_to_one_zero_run() {
+        let state = vec![0x5A; 5000];
+        assert_eq!(round_trip(&state, &state), vec![0x88, 0x27, 0x00]);
+        assert_eq!(round_trip(&[], &[]), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn entirely_different_states_are_one_literal_run() {
+        let older: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+        let newer: Vec<u8> = older.iter().map(|b| !b).collect();
+        let delta = round_trip(&older, &newer);
+        assert_eq!(&delta[..3], &[0x00, 0xE8, 0x07]);
+        assert_eq!(delta.len(), 3 + 1000);
+    }
+
+    #[test]
+    fn runs_longer_than_a_varint_byte_round_trip() {
+        let older = vec![0u8; 70_000];
+        let mut newer = older.clone();
+        for b in &mut newer[300..500] {
+            *b = 0xFF;
+        }
+        // A single equal byte in a literal run does not end it
+        newer[400] = 0;
+        newer[69_999] = 1;
+        let delta = round_trip(&older, &newer);
+        assert_eq!(&delta[..4], &[0xAC, 0x02, 0xC8, 0x01]);
+
+        let mut scattered = older.clone();
+        for i in (0..older.len()).step_by(3) {
+            scattered[i] = i as u8 | 1;
+        }
+        round_trip(&older, &scattered);
+        round_trip(&scattered, &older);
+    }
+
+    #[test]
+    fn the_buffer_pops_states_in_reverse_and_starts_over_on_a_new_size() {
+        let mut rewind = RewindBuffer::new(Region::Ntsc, 1, 1 << 20);
+        let states: Vec<Vec<u8>> = (0..5u8).map(|n| vec![n; 64]).collect();
+        for state in &states {
+            rewind.push(state.clone());
+        }
+        assert_eq!(rewind.pop(), Some(states[3].clone()));
+        assert_eq!(rewind.pop(), Some(states[2].clone()));
+
+        rewind.push(vec![9; 32]);
+        assert_eq!(rewind.pop(), None);
+        rewind.push(vec![8; 32]);
+        assert_eq!(rewind.pop(), Some(vec![9; 32]));
+    }
+
+    #[test]
+    fn the_oldest_states_go_once_over_budget() {
+        let state = |n: u8| {
+            let mut state = vec![0; 64];
+            state[0] = n;
+            state
+        };
+        let mut rewind = RewindBuffer::new(Region::Pal, 2, 64 + 10);
+        for n in 0..8 {
+            rewind.frame(|| state(n));
+        }
+        // Snapshots on every other frame, the newest takes 64 bytes, each delta 5 and only two fit
+        assert_eq!(rewind.pop(), Some(state(5)));
+        assert_eq!(rewind.pop(), Some(state(3)));
+        assert_eq!(rewind.pop(), None);
+    }
+
+    #[test]
+    fn the_newest_state_counts_against_the_budget() {
+        let mut rewind = RewindBuffer::new(Region::Ntsc, 1, 100);
+        for n in 0..4u8 {
+            rewind.push(vec![n; 64]);
+        }
+        // One 64 byte state and a 66 byte delta do not fit in 100 bytes
+        assert_eq!(rewind.pop(), None);
+
+        let mut rewind = RewindBuffer::new(Region::Ntsc, 1, 64 + 66);
+        for n in 0..4u8 {
+            rewind.push(vec![n; 64]);
+        }
+        assert_eq!(rewind.pop(), Some(vec![2; 64]));
+        assert_eq!(rewind.pop(), None);
+    }
+}
@@ -0,0 +1,349 @@
+use crate::cpu::CPU;
+use crate::ppu::PPU;
+use std::collections::HashMap;
+
+const MAGIC: &[u8; 4] = b"RNES";
+const VERSION: u32 = 6;
+
+// A state is the magic, a version, the CRC-32 of the ROM it was taken with, the cycle counter and then
+// a list of tagged sections, one per component:
+//   CPU  - CPURegisters
+//   RAM  - RamController RAM, PRG RAM, the mapper (bank) registers and the controllers
+//   PPUR - PPURegisters and their latches
+//   PPU  - PPU frame, scanline and fetch state
+//   VRAM - VRAMController memory and OAM
+//   APU  - every APU channel and the frame counter
+// Every section carries its length and sections with an unknown tag are skipped. The layout inside
+// a section is not versioned on its own though, so VERSION goes up whenever one changes and a
+// state from any other version is refused rather than misread.
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        StateWriter {
+            data: vec![],
+        }
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_i32(&mut self, value: i32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    fn write_section<F: FnOnce(&mut StateWriter)>(&mut self, tag: &[u8; 4], write: F) {
+        let mut section = StateWriter::new();
+        write(&mut section);
+
+        self.write_bytes(tag);
+        self.write_u32(section.data.len() as u32);
+        self.write_bytes(&section.data);
+    }
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> StateReader<'a> {
+        StateReader {
+            data,
+            position: 0,
+        }
+    }
+
+    pub fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], String> {
+        if self.position + length > self.data.len() {
+            return Err(String::from("Save state is truncated"));
+        }
+
+        let bytes = &self.data[self.position..self.position + length];
+        self.position += length;
+        Ok(bytes)
+    }
+
+    pub fn read_into(&mut self, destination: &mut [u8]) -> Result<(), String> {
+        destination.copy_from_slice(self.read_bytes(destination.len())?);
+        Ok(())
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, String> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, String> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, String> {
+        let mut bytes = [0; 2];
+        self.read_into(&mut bytes)?;
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, String> {
+        let mut bytes = [0; 4];
+        self.read_into(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub fn read_i32(&mut self) -> Result<i32, String> {
+        let mut bytes = [0; 4];
+        self.read_into(&mut bytes)?;
+        Ok(i32::from_le_bytes(bytes))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, String> {
+        let mut bytes = [0; 8];
+        self.read_into(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.position == self.data.len()
+    }
+}
+
+// Snapshots the whole machine. `rom_checksum` is Cartridge::checksum of the ROM that is in it and
+// `cycles` is the CPU cycle counter kept by the main loop.
+pub fn save(cpu: &CPU, ppu: &PPU, rom_checksum: u32, cycles: u64) -> Vec<u8> {
+    let mut writer = StateWriter::new();
+    writer.write_bytes(MAGIC);
+    writer.write_u32(VERSION);
+    writer.write_u32(rom_checksum);
+    writer.write_u64(cycles);
+
+    writer.write_section(b"CPU ", |w| cpu.registers.save_state(w));
+    writer.write_section(b"RAM ", |w| cpu.memory().save_state(w));
+    writer.write_section(b"PPUR", |w| ppu.registers().get().save_state(w));
+    writer.write_section(b"PPU ", |w| ppu.save_state(w));
+    writer.write_section(b"VRAM", |w| ppu.vram().borrow().save_state(w));
+    writer.write_section(b"APU ", |w| cpu.memory().save_apu_state(w));
+
+    writer.data
+}
+
+// Restores a snapshot taken with `save` and returns the CPU cycle counter it was taken at.
+// A state that cannot be read, or was taken with another ROM, leaves the machine as it was.
+pub fn load(cpu: &mut CPU, ppu: &mut PPU, rom_checksum: u32, data: &[u8]) -> Result<u64, String> {
+    let backup = save(cpu, ppu, rom_checksum, 0);
+
+    load_sections(cpu, ppu, rom_checksum, data).inspect_err(|_| {
+        load_sections(cpu, ppu, rom_checksum, &backup).expect("Restoring the machine after a failed load");
+    })
+}
+
+fn load_sections(cpu: &mut CPU, ppu: &mut PPU, rom_checksum: u32, data: &[u8]) -> Result<u64, String> {
+    let mut reader = StateReader::new(data);
+    if reader.read_bytes(4)? != MAGIC {
+        return Err(String::from("Not a save state"));
+    }
+
+    let version = reader.read_u32()?;
+    if version != VERSION {
+        return Err(format!("Unsupported save state version {}", version));
+    }
+
+    let state_checksum = reader.read_u32()?;
+    if state_checksum != rom_checksum {
+        return Err(format!("Save state is for another ROM (CRC-32 {:08X}, this one is {:08X})", state_checksum, rom_checksum));
+    }
+
+    let cycles = reader.read_u64()?;
+
+    let mut sections = HashMap::new();
+    while !reader.is_at_end() {
+        let mut tag = [0; 4];
+        reader.read_into(&mut tag)?;
+        let length = reader.read_u32()? as usize;
+        sections.insert(tag, reader.read_bytes(length)?);
+    }
+
+    let section = |tag: &[u8; 4]| match sections.get(tag) {
+        Some(data) => Ok(StateReader::new(data)),
+        None => Err(format!("Save state has no {} section", String::from_utf8_lossy(tag).trim()))
+    };
+
+    cpu.registers.load_state(&mut section(b"CPU ")?)?;
+    cpu.clear_jam();
+    cpu.memory_mut().load_state(&mut section(b"RAM ")?)?;
+
+    let mut ppu_registers = ppu.registers().get();
+    ppu_registers.load_state(&mut section(b"PPUR")?)?;
+    ppu.registers().set(ppu_registers);
+
+    ppu.load_state(&mut section(b"PPU ")?)?;
+    ppu.vram().borrow_mut().load_state(&mut section(b"VRAM")?)?;
+    cpu.memory().load_apu_state(&mut section(b"APU ")?)?;
+
+    Ok(cycles)
+}
+
+// Quick save slots live next to the ROM, like game.nes.state1
+pub fn slot_path(rom_path: &str, slot: u32) -> String {
+    format!("{}.state{}", rom_path, slot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Cartridge;
+    use crate::nes::Nes;
+
+    // Turns on NMI, then loops writing to RAM and VRAM. The NMI handler counts frames, copies
+    // RAM to OAM and drops the status and return address to jump straight back into the loop.
+    fn test_rom() -> Cartridge {
+        let reset: &[u8] = &[
+            0xA9, 0x80, 0x8D, 0x00, 0x20, // LDA #$80, STA $2000
+            0xA2, 0x00,                   // LDX #$00
+            0xE8, 0x8A,                   // loop: INX, TXA
+            0x65, 0x10, 0x85, 0x10,       // ADC $10, STA $10
+            0x9D, 0x00, 0x03,             // STA $0300,X
+            0xA9, 0x21, 0x8D, 0x06, 0x20, // LDA #$21, STA $2006
+            0x8E, 0x06, 0x20,             // STX $2006
+            0xA5, 0x10, 0x8D, 0x07, 0x20, // LDA $10, STA $2007
+            0x4C, 0x07, 0x80,             // JMP loop
+        ];
+        let nmi: &[u8] = &[
+            0xE6, 0x11,                   // INC $11
+            0xAD, 0x02, 0x20,             // LDA $2002
+            0xA9, 0x03, 0x8D, 0x14, 0x40, // LDA #$03, STA $4014
+            0x68, 0x68, 0x68,             // PLA, PLA, PLA
+            0x4C, 0x07, 0x80,             // JMP loop
+        ];
+
+        Cartridge::nrom(&[(0x8000, reset), (0x8100, nmi)], [0x8100, 0x8000, 0x8000])
+    }
+
+    fn run(nes: &mut Nes, instructions: usize) {
+        for _ in 0..instructions {
+            nes.step();
+        }
+    }
+
+    #[test]
+    fn loading_a_state_mid_frame_continues_identically() {
+        let mut nes = Nes::new(test_rom()).unwrap();
+
+        // A couple of frames in, and then into the middle of the visible scanlines
+        run(&mut nes, 20000);
+        while nes.ppu().scanline() != 120 {
+            run(&mut nes, 1);
+        }
+        assert!(nes.cpu().memory().peek8(0x11) > 0, "the test ROM never got an NMI");
+
+        let cycles = nes.cycles();
+        let state = nes.save_state();
+        run(&mut nes, 30000);
+        let expected = nes.save_state();
+
+        let mut other = Nes::new(test_rom()).unwrap();
+        other.load_state(&state).unwrap();
+        assert_eq!(other.cycles(), cycles);
+        assert_eq!(other.save_state(), state);
+
+        run(&mut other, 30000);
+        assert_eq!(other.save_state(), expected);
+    }
+
+    #[test]
+    fn a_broken_state_leaves_the_machine_alone() {
+        let mut nes = Nes::new(test_rom()).unwrap();
+        run(&mut nes, 5000);
+
+        let before = nes.save_state();
+        let truncated = &before[..before.len() - 100];
+        assert!(nes.load_state(truncated).is_err());
+        assert!(nes.load_state(b"not a state").is_err());
+        assert_eq!(nes.save_state(), before);
+    }
+
+    #[test]
+    fn a_state_from_another_version_is_refused() {
+        let mut nes = Nes::new(test_rom()).unwrap();
+        run(&mut nes, 5000);
+        let before = nes.save_state();
+
+        for version in [VERSION - 1, VERSION + 1] {
+            let mut state = before.clone();
+            state[4..8].copy_from_slice(&version.to_le_bytes());
+            let error = nes.load_state(&state).unwrap_err();
+            assert_eq!(error, format!("Unsupported save state version {}", version));
+        }
+        assert_eq!(nes.save_state(), before);
+    }
+
+    #[test]
+    fn a_state_from_another_rom_is_refused() {
+        let mut nes = Nes::new(test_rom()).unwrap();
+        run(&mut nes, 5000);
+        let state = nes.save_state();
+
+        let mut other = Nes::new(Cartridge::nrom(&[(0x8000, &[0x4C, 0x00, 0x80])], [0x8000; 3])).unwrap();
+        assert_ne!(nes.cartridge().checksum(), other.cartridge().checksum());
+        run(&mut other, 100);
+        let before = other.save_state();
+        let error = other.load_state(&state).unwrap_err();
+        assert!(error.starts_with("Save state is for another ROM"), "{}", error);
+        assert_eq!(other.save_state(), before);
+    }
+
+    #[test]
+    fn unknown_sections_are_skipped() {
+        let mut nes = Nes::new(test_rom()).unwrap();
+        run(&mut nes, 5000);
+        let before = nes.save_state();
+
+        let mut state = before.clone();
+        state.extend_from_slice(b"NEW ");
+        state.extend_from_slice(&3u32.to_le_bytes());
+        state.extend_from_slice(&[1, 2, 3]);
+        run(&mut nes, 5000);
+        nes.load_state(&state).unwrap();
+        assert_eq!(nes.save_state(), before);
+    }
+
+    #[test]
+    fn prg_rom_writes_do_not_outlive_a_load() {
+        // loop: LDA $8020, ADC #$01, STA $8020, STA $10, JMP loop, with $33 at $8020
+        let program: &[u8] = &[0xAD, 0x20, 0x80, 0x69, 0x01, 0x8D, 0x20, 0x80, 0x85, 0x10, 0x4C, 0x00, 0x80];
+        let rom = || Cartridge::nrom(&[(0x8000, program), (0x8020, &[0x33])], [0x8000; 3]);
+        let mut nes = Nes::new(rom()).unwrap();
+        run(&mut nes, 1000);
+        assert_eq!(nes.cpu().memory().peek8(0x8020), 0x33);
+
+        let state = nes.save_state();
+        run(&mut nes, 1000);
+        let mut other = Nes::new(rom()).unwrap();
+        other.load_state(&state).unwrap();
+        run(&mut other, 1000);
+        assert_eq!(other.save_state(), nes.save_state());
+        assert_eq!(other.cpu().memory().peek8(0x10), 0x34);
+    }
+}
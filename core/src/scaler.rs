@@ -0,0 +1,254 @@
+// Fitting the picture into a window: overscan cropping, window sizes, integer scaling and aspect
+// correction, and for the software renderer nearest or bilinear filtering into an in-memory RGB buffer.
+
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// A TV shows NES pixels 8/7 as wide as they are tall
+pub const PIXEL_ASPECT: f64 = 8.0 / 7.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+}
+
+impl Filter {
+    pub fn from_name(name: &str) -> Result<Filter, String> {
+        match name.to_lowercase().as_str() {
+            "nearest" => Ok(Filter::Nearest),
+            "bilinear" => Ok(Filter::Bilinear),
+            _ => Err(format!("Unknown filter {}, it has to be nearest or bilinear", name))
+        }
+    }
+}
+
+// How many NES pixels are cut off each edge. TVs hid about 8 lines at the top and bottom, which
+// games often left full of garbage.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Overscan {
+    pub top: u32,
+    pub bottom: u32,
+    pub left: u32,
+    pub right: u32,
+}
+
+impl Overscan {
+    pub fn new() -> Overscan {
+        Overscan { top: 0, bottom: 0, left: 0, right: 0 }
+    }
+
+    // Like "top=8,bottom=8", or "all=8" for every edge
+    pub fn parse(text: &str) -> Result<Overscan, String> {
+        let mut overscan = Overscan::new();
+        for setting in text.split(',').map(|s| s.trim()).filter(|s| !s.is_empty() && *s != "none") {
+            let (name, value) = setting.split_once('=').ok_or(format!("Expected edge=pixels, got {}", setting))?;
+            let value: u32 = value.trim().parse().map_err(|_| format!("{} has to be a number of pixels", name))?;
+            match name.trim() {
+                "top" => overscan.top = value,
+                "bottom" => overscan.bottom = value,
+                "left" => overscan.left = value,
+                "right" => overscan.right = value,
+                "all" => overscan = Overscan { top: value, bottom: value, left: value, right: value },
+                other => return Err(format!("Unknown edge {}, it has to be top, bottom, left, right or all", other)),
+            }
+        }
+        if overscan.top + overscan.bottom >= SCREEN_HEIGHT as u32 || overscan.left + overscan.right >= SCREEN_WIDTH as u32 {
+            return Err(String::from("The overscan crops away the whole picture"));
+        }
+        Ok(overscan)
+    }
+
+    // What is left of the 256x240 picture
+    pub fn visible(&self) -> (u32, u32) {
+        (SCREEN_WIDTH as u32 - self.left - self.right, SCREEN_HEIGHT as u32 - self.top - self.bottom)
+    }
+
+    // Crops a 240 line RGB picture. One that is wider than 256, like the NTSC filter's, has its
+    // sides cropped by as much of its width.
+    pub fn crop(&self, rgb: &[u8], source: (u32, u32)) -> (Vec<u8>, (u32, u32)) {
+        let columns = |pixels: u32| (pixels * source.0 + SCREEN_WIDTH as u32 / 2) / SCREEN_WIDTH as u32;
+        let (left, right) = (columns(self.left), columns(self.right));
+        let width = source.0 - left - right;
+        let height = source.1 - self.top - self.bottom;
+
+        let row = source.0 as usize * 3;
+        let mut output = Vec::with_capacity(width as usize * height as usize * 3);
+        for line in rgb.chunks(row).skip(self.top as usize).take(height as usize) {
+            output.extend_from_slice(&line[left as usize * 3..(left + width) as usize * 3]);
+        }
+        (output, (width, height))
+    }
+}
+
+// Where in the window the picture goes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScaleOptions {
+    // Only whole multiples of the picture's height, so every line is as tall as the next
+    pub integer: bool,
+    // 8:7 pixels, otherwise square ones. A 602 pixel wide NTSC filtered picture gets the same shape.
+    pub aspect_correct: bool,
+    pub filter: Filter,
+    pub overscan: Overscan,
+}
+
+impl ScaleOptions {
+    pub fn new() -> ScaleOptions {
+        ScaleOptions { integer: false, aspect_correct: true, filter: Filter::Nearest, overscan: Overscan::new() }
+    }
+
+    // Width over height of the picture as it is shown
+    pub fn aspect(&self) -> f64 {
+        let (width, height) = self.overscan.visible();
+        let pixel = if self.aspect_correct { PIXEL_ASPECT } else { 1.0 };
+        width as f64 * pixel / height as f64
+    }
+
+    // A window that fits the cropped picture `scale` times over
+    pub fn window_size(&self, scale: u32) -> (u32, u32) {
+        let height = self.overscan.visible().1 * scale;
+        ((height as f64 * self.aspect()).round() as u32, height)
+    }
+
+    // The largest picture that fits, centred. A window smaller than the picture still gets it at 1x.
+    pub fn viewport(&self, picture_height: u32, window: (u32, u32)) -> Viewport {
+        let aspect = self.aspect();
+        let (mut width, mut height) = if window.0 as f64 / window.1 as f64 > aspect {
+            ((window.1 as f64 * aspect).round() as u32, window.1)
+        } else {
+            (window.0, (window.0 as f64 / aspect).round() as u32)
+        };
+
+        if self.integer {
+            let multiple = (height / picture_height).max(1);
+            height = picture_height * multiple;
+            width = (height as f64 * aspect).round() as u32;
+        }
+
+        Viewport {
+            x: window.0.saturating_sub(width) / 2,
+            y: window.1.saturating_sub(height) / 2,
+            width: width.max(1),
+            height: height.max(1),
+        }
+    }
+
+    // The picture in a `window` sized RGB buffer, with black around it
+    pub fn render(&self, rgb: &[u8], source: (u32, u32), window: (u32, u32)) -> Vec<u8> {
+        let viewport = self.viewport(source.1, window);
+        let scaled = scale(rgb, source, (viewport.width, viewport.height), self.filter);
+
+        let mut output = vec![0; window.0 as usize * window.1 as usize * 3];
+        let row = window.0 as usize * 3;
+        // Whatever of the picture is outside a window that is too small is cut off
+        let visible = (viewport.width.min(window.0) as usize * 3, viewport.height.min(window.1) as usize);
+        for (y, line) in scaled.chunks(viewport.width as usize * 3).take(visible.1).enumerate() {
+            let start = (viewport.y as usize + y) * row + viewport.x as usize * 3;
+            output[start..start + visible.0].copy_from_slice(&line[..visible.0]);
+        }
+        output
+    }
+}
+
+pub fn scale(rgb: &[u8], source: (u32, u32), target: (u32, u32), filter: Filter) -> Vec<u8> {
+    let (source_width, source_height) = (source.0 as usize, source.1 as usize);
+    let (target_width, target_height) = (target.0 as usize, target.1 as usize);
+    let mut output = Vec::with_capacity(target_width * target_height * 3);
+
+    match filter {
+        Filter::Nearest => {
+            let columns: Vec<usize> = (0..target_width).map(|x| x * source_width / target_width).collect();
+            for y in 0..target_height {
+                let line = &rgb[y * source_height / target_height * source_width * 3..];
+                for column in &columns {
+                    output.extend_from_slice(&line[column * 3..column * 3 + 3]);
+                }
+            }
+        }
+        Filter::Bilinear => {
+            // The source pixel each target pixel's centre falls on, and how far towards the next one
+            let position = |target: usize, length: usize, source_length: usize| {
+                let position = ((target as f64 + 0.5) * source_length as f64 / length as f64 - 0.5).max(0.0);
+                let first = (position as usize).min(source_length - 1);
+                (first, (first + 1).min(source_length - 1), (position - first as f64) as f32)
+            };
+            let columns: Vec<_> = (0..target_width).map(|x| position(x, target_width, source_width)).collect();
+            for y in 0..target_height {
+                let (top, bottom, fy) = position(y, target_height, source_height);
+                for (left, right, fx) in &columns {
+                    for channel in 0..3 {
+                        let pixel = |x: usize, y: usize| rgb[(y * source_width + x) * 3 + channel] as f32;
+                        let upper = pixel(*left, top) + (pixel(*right, top) - pixel(*left, top)) * fx;
+                        let lower = pixel(*left, bottom) + (pixel(*right, bottom) - pixel(*left, bottom)) * fx;
+                        output.push((upper + (lower - upper) * fy).round() as u8);
+                    }
+                }
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_picture_is_fitted_and_centred() {
+        let options = ScaleOptions { integer: false, aspect_correct: false, filter: Filter::Nearest, overscan: Overscan::new() };
+        assert_eq!(options.viewport(240, (1024, 480)), Viewport { x: 256, y: 0, width: 512, height: 480 });
+        assert_eq!(options.viewport(240, (512, 960)), Viewport { x: 0, y: 240, width: 512, height: 480 });
+
+        // 8:7 pixels make 3x 256 pixels 878 wide
+        let options = ScaleOptions { integer: true, aspect_correct: true, filter: Filter::Nearest, overscan: Overscan::new() };
+        assert_eq!(options.viewport(240, (1000, 800)), Viewport { x: 61, y: 40, width: 878, height: 720 });
+        assert_eq!(options.viewport(240, (100, 100)).height, 240);
+    }
+
+    #[test]
+    fn nearest_repeats_pixels_and_bilinear_blends_them() {
+        let rgb = [0, 0, 0, 200, 100, 50];
+        assert_eq!(scale(&rgb, (2, 1), (4, 1), Filter::Nearest), [0, 0, 0, 0, 0, 0, 200, 100, 50, 200, 100, 50]);
+        assert_eq!(scale(&rgb, (2, 1), (4, 1), Filter::Bilinear), [0, 0, 0, 50, 25, 13, 150, 75, 38, 200, 100, 50]);
+    }
+
+    #[test]
+    fn the_window_is_black_around_the_picture() {
+        let options = ScaleOptions { integer: true, aspect_correct: false, filter: Filter::Nearest, overscan: Overscan::new() };
+        let rgb = vec![255; SCREEN_WIDTH * SCREEN_HEIGHT * 3];
+        let window = options.render(&rgb, (256, 240), (600, 500));
+
+        assert_eq!(window.len(), 600 * 500 * 3);
+        // 2x is 512x480, from (44, 10)
+        let pixel = |x: usize, y: usize| window[(y * 600 + x) * 3];
+        assert_eq!((pixel(43, 100), pixel(44, 100), pixel(555, 100), pixel(556, 100)), (0, 255, 255, 0));
+        assert_eq!((pixel(300, 9), pixel(300, 10), pixel(300, 489), pixel(300, 490)), (0, 255, 255, 0));
+    }
+
+    #[test]
+    fn overscan_crops_the_picture_and_shapes_the_window() {
+        let overscan = Overscan::parse("all=8,left=0").unwrap();
+        assert_eq!(overscan, Overscan { top: 8, bottom: 8, left: 0, right: 8 });
+        assert!(Overscan::parse("top=120,bottom=120").is_err());
+
+        let rgb: Vec<u8> = (0..256 * 240).flat_map(|i| [(i % 256) as u8, (i / 256) as u8, 0]).collect();
+        let (cropped, size) = overscan.crop(&rgb, (256, 240));
+        assert_eq!(size, (248, 224));
+        assert_eq!(&cropped[..3], &[0, 8, 0]);
+        assert_eq!(&cropped[cropped.len() - 3..], &[247, 231, 0]);
+
+        // The NTSC filter's 602 pixels lose 8/256 of their width on the right
+        assert_eq!(overscan.crop(&vec![0; 602 * 240 * 3], (602, 240)).1, (583, 224));
+
+        let options = ScaleOptions { overscan, ..ScaleOptions::new() };
+        assert_eq!(options.window_size(3), (850, 672));
+        assert_eq!(ScaleOptions::new().window_size(2), (585, 480));
+    }
+}
@@ -0,0 +1,101 @@
+// Screenshots as PNG files with the ROM's name in them, in one of three kinds:
+//   raw       the PPU's 256x240 palette indices, as an indexed PNG with the NTSC colours
+//   palette   the colours the palette stage turned them into, emphasis included
+//   display   the picture as the window shows it, filtered, cropped and scaled
+
+use crate::palette;
+use crate::png;
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const DIRECTORY: &str = "screenshots";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScreenshotKind {
+    Raw,
+    Palette,
+    Display,
+}
+
+impl ScreenshotKind {
+    pub fn from_name(name: &str) -> Result<ScreenshotKind, String> {
+        match name.to_lowercase().as_str() {
+            "raw" => Ok(ScreenshotKind::Raw),
+            "palette" => Ok(ScreenshotKind::Palette),
+            "display" => Ok(ScreenshotKind::Display),
+            _ => Err(format!("Unknown screenshot kind {}, it has to be raw, palette or display", name))
+        }
+    }
+}
+
+fn text(rom_name: &str) -> [(&str, &str); 2] {
+    [("Title", rom_name), ("Software", "rustnes")]
+}
+
+pub fn raw_png(framebuffer: &[u8], rom_name: &str) -> Vec<u8> {
+    let pixels: Vec<u8> = framebuffer.iter().map(|index| index & 0x3F).collect();
+    png::encode_indexed_with_text(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32, &palette::NTSC, &pixels, &text(rom_name))
+}
+
+pub fn rgb_png(rgb: &[u8], width: u32, height: u32, rom_name: &str) -> Vec<u8> {
+    png::encode_rgb_with_text(width, height, rgb, &text(rom_name))
+}
+
+// Like screenshots/Zelda-20261018-142503-120.png, in UTC down to the millisecond
+pub fn timestamped_path(directory: &Path, rom_name: &str, time: SystemTime) -> PathBuf {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (year, month, day) = civil_date((seconds / 86400) as i64);
+    let time_of_day = seconds % 86400;
+    directory.join(format!(
+        "{}-{:04}{:02}{:02}-{:02}{:02}{:02}-{:03}.png",
+        rom_name, year, month, day, time_of_day / 3600, time_of_day / 60 % 60, time_of_day % 60, since_epoch.subsec_millis()
+    ))
+}
+
+// Writes `png` to a new timestamped file in `directory`, which is created when it is missing
+pub fn save(directory: &Path, rom_name: &str, png: &[u8]) -> Result<PathBuf, String> {
+    fs::create_dir_all(directory).map_err(|e| format!("{}: {}", directory.display(), e))?;
+    let path = timestamped_path(directory, rom_name, SystemTime::now());
+    fs::write(&path, png).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+// Year, month and day of a day counted from 1970-01-01, Howard Hinnant's days_from_civil in reverse
+fn civil_date(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month + 2) / 5 + 1) as u32;
+    let month = if month < 10 { month + 3 } else { month - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn the_file_name_has_the_rom_and_the_time() {
+        // 2024-02-29 23:59:58.042 UTC
+        let time = UNIX_EPOCH + Duration::from_millis(1709251198042);
+        assert_eq!(timestamped_path(Path::new(DIRECTORY), "smb", time), Path::new("screenshots/smb-20240229-235958-042.png"));
+        assert_eq!(civil_date(0), (1970, 1, 1));
+    }
+
+    #[test]
+    fn a_raw_screenshot_keeps_the_indices_and_the_rom_name() {
+        let framebuffer: Vec<u8> = (0..SCREEN_WIDTH * SCREEN_HEIGHT).map(|i| (i % 64) as u8).collect();
+        let image = png::decode_indexed(&raw_png(&framebuffer, "Métroid")).unwrap();
+
+        assert!(image.pixels == framebuffer);
+        assert_eq!(image.text[0], (String::from("Title"), String::from("Métroid")));
+    }
+}
@@ -0,0 +1,315 @@
+use crate::ram_controller::RamController;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+const INES_HEADER_SIZE: usize = 16;
+const PRG_BANK_SIZE: usize = 0x4000;
+
+// Labels for RAM and registers are keyed on their CPU address. Labels in PRG ROM are keyed on
+// their ROM offset instead, so that they only show up while their bank is mapped in.
+pub struct SymbolTable {
+    cpu: HashMap<u16, String>,
+    prg: HashMap<usize, String>,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable {
+            cpu: HashMap::new(),
+            prg: HashMap::new(),
+        }
+    }
+
+    // Picks up the symbol files that sit next to the ROM, named the way the tools that produce
+    // them name them: game.nes.ram.nl and game.nes.<bank>.nl (FCEUX), game.mlb (Mesen) and game.dbg (ld65)
+    pub fn load_for_rom(rom_path: &str, prg_bank_count: usize) -> Result<SymbolTable, String> {
+        let mut symbols = SymbolTable::new();
+        let stem = Path::new(rom_path).with_extension("");
+        let stem = stem.to_string_lossy();
+
+        let ram_nl = format!("{}.ram.nl", rom_path);
+        if Path::new(&ram_nl).exists() {
+            symbols.load_fceux_nl(&ram_nl, None)?;
+        }
+
+        for bank in 0..prg_bank_count {
+            let bank_nl = format!("{}.{:X}.nl", rom_path, bank);
+            if Path::new(&bank_nl).exists() {
+                symbols.load_fceux_nl(&bank_nl, Some(bank))?;
+            }
+        }
+
+        let mlb = format!("{}.mlb", stem);
+        if Path::new(&mlb).exists() {
+            symbols.load_mesen_mlb(&mlb)?;
+        }
+
+        let dbg = format!("{}.dbg", stem);
+        if Path::new(&dbg).exists() {
+            symbols.load_ld65_dbg(&dbg)?;
+        }
+
+        Ok(symbols)
+    }
+
+    pub fn load_fceux_nl(&mut self, path: &str, bank: Option<usize>) -> Result<(), String> {
+        self.parse_fceux_nl(&read_file(path)?, bank).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    // Lines look like `$C5F5#update_player#comment`. Bank files hold CPU addresses in that bank,
+    // the RAM file holds plain CPU addresses.
+    pub fn parse_fceux_nl(&mut self, text: &str, bank: Option<usize>) -> Result<(), String> {
+        for line in lines(text) {
+            let mut parts = line.split('#');
+            let address = match parts.next().and_then(|a| a.trim().strip_prefix('$')) {
+                // Arrays are written as $0200/10, only the start gets the label
+                Some(address) => address.split('/').next().unwrap_or(address),
+                None => continue
+            };
+            let name = match parts.next() {
+                Some(name) if !name.trim().is_empty() => name.trim(),
+                _ => continue
+            };
+
+            let address = u16::from_str_radix(address, 16).map_err(|_| format!("invalid address in '{}'", line))?;
+            match bank {
+                Some(bank) if address >= 0x8000 => {
+                    self.prg.insert(bank * PRG_BANK_SIZE + address as usize % PRG_BANK_SIZE, name.to_string());
+                }
+                _ => {
+                    self.cpu.insert(address, name.to_string());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn load_mesen_mlb(&mut self, path: &str) -> Result<(), String> {
+        self.parse_mesen_mlb(&read_file(path)?).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    // Lines look like `P:0C5F5:update_player:comment` where the prefix is the memory type.
+    // Both the Mesen 0.9 single letter types and the Mesen 2 names are understood.
+    pub fn parse_mesen_mlb(&mut self, text: &str) -> Result<(), String> {
+        for line in lines(text) {
+            let parts: Vec<&str> = line.splitn(4, ':').collect();
+            if parts.len() < 3 || parts[2].trim().is_empty() {
+                continue;
+            }
+
+            let address = parts[1].split('-').next().unwrap_or(parts[1]);
+            let address = usize::from_str_radix(address, 16).map_err(|_| format!("invalid address in '{}'", line))?;
+            let name = parts[2].trim().to_string();
+
+            match parts[0] {
+                "P" | "NesPrgRom" => {
+                    self.prg.insert(address, name);
+                }
+                "R" | "NesInternalRam" => {
+                    self.cpu.insert((address & 0x07FF) as u16, name);
+                }
+                "S" | "W" | "NesSaveRam" | "NesWorkRam" => {
+                    self.cpu.insert((0x6000 + (address & 0x1FFF)) as u16, name);
+                }
+                "G" | "NesMemory" => {
+                    self.cpu.insert(address as u16, name);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn load_ld65_dbg(&mut self, path: &str) -> Result<(), String> {
+        self.parse_ld65_dbg(&read_file(path)?);
+        Ok(())
+    }
+
+    // ld65 debug info (--dbgfile). Segments that are written to the ROM file carry their file
+    // offset, which is what turns a symbol in them into a PRG ROM offset.
+    pub fn parse_ld65_dbg(&mut self, text: &str) {
+        let mut segments: HashMap<String, (usize, Option<usize>)> = HashMap::new();
+        let mut symbols = vec![];
+
+        for line in lines(text) {
+            let (kind, attributes) = match line.split_once('\t') {
+                Some((kind, attributes)) => (kind, parse_dbg_attributes(attributes)),
+                None => continue
+            };
+
+            match kind {
+                "seg" => {
+                    let id = attributes.get("id").cloned().unwrap_or_default();
+                    let start = attributes.get("start").and_then(|s| parse_dbg_number(s));
+                    let file_offset = attributes.get("ooffs").and_then(|s| parse_dbg_number(s));
+                    if let Some(start) = start {
+                        segments.insert(id, (start, file_offset));
+                    }
+                }
+                "sym" => symbols.push(attributes),
+                _ => {}
+            }
+        }
+
+        for symbol in symbols {
+            let name = match symbol.get("name") {
+                Some(name) => name.clone(),
+                None => continue
+            };
+            let value = match symbol.get("val").and_then(|v| parse_dbg_number(v)) {
+                Some(value) => value,
+                None => continue
+            };
+
+            // Imports are the same symbol once more, and zero page equates are almost always plain constants
+            match (symbol.get("type").map(|t| t.as_str()), symbol.get("addrsize").map(|a| a.as_str())) {
+                (Some("lab"), _) | (Some("equ"), Some("absolute")) => {}
+                _ => continue
+            }
+
+            let segment = symbol.get("seg").and_then(|id| segments.get(id));
+            match segment {
+                Some((start, Some(file_offset))) if *file_offset >= INES_HEADER_SIZE && value >= *start => {
+                    self.prg.insert(file_offset - INES_HEADER_SIZE + (value - start), name);
+                }
+                _ => {
+                    self.cpu.insert(value as u16, name);
+                }
+            }
+        }
+    }
+
+    // CPU labels come first, a .ram.nl may name registers or mapper RAM at $8000 and up
+    pub fn label(&self, mem: &RamController, address: u16) -> Option<&str> {
+        self.cpu.get(&address)
+            .or_else(|| mem.prg_rom_offset(address).and_then(|offset| self.prg.get(&offset)))
+            .map(|name| name.as_str())
+    }
+
+    // Resolves a label to where it can be found in the CPU address space right now
+    pub fn address_of(&self, mem: &RamController, name: &str) -> Option<u16> {
+        if let Some((address, _)) = self.cpu.iter().find(|(_, n)| n.as_str() == name) {
+            return Some(*address);
+        }
+
+        self.prg.iter()
+            .filter(|(_, n)| n.as_str() == name)
+            .find_map(|(offset, _)| mem.cpu_address_of_prg_offset(*offset))
+    }
+}
+
+fn read_file(path: &str) -> Result<String, String> {
+    let bytes = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines()
+        .map(|line| line.trim_end())
+        .filter(|line| !line.is_empty())
+}
+
+// name="foo",val=0xC5F5,seg=2 -> {name: foo, val: 0xC5F5, seg: 2}
+fn parse_dbg_attributes(attributes: &str) -> HashMap<String, String> {
+    attributes.split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.trim_matches('"').to_string()))
+        .collect()
+}
+
+fn parse_dbg_number(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Cartridge;
+    use crate::nes::Nes;
+
+    // Two PRG banks, mapped at $8000 and $C000
+    fn cartridge() -> Cartridge {
+        Cartridge::nrom(&[(0xC000, &[])], [0x8000, 0x8000, 0x8000])
+    }
+
+    #[test]
+    fn fceux_nl_keys_banks_on_rom_offsets_and_ram_on_addresses() {
+        let nes = Nes::new(cartridge()).unwrap();
+        let mem = nes.cpu().memory();
+        let mut symbols = SymbolTable::new();
+        symbols.parse_fceux_nl("$C010#reset#Entry point\n$C020/10#table#\n$C030##\n", Some(1)).unwrap();
+        symbols.parse_fceux_nl("$0010#counter#\n$2000#PPUCTRL#\n\n$8000#MMC1_CTRL#Mapper register\n", None).unwrap();
+
+        assert_eq!(symbols.label(mem, 0xC010), Some("reset"));
+        assert_eq!(symbols.label(mem, 0xC020), Some("table"));
+        assert_eq!(symbols.label(mem, 0xC030), None);
+        assert_eq!(symbols.label(mem, 0x0010), Some("counter"));
+        assert_eq!(symbols.label(mem, 0x2000), Some("PPUCTRL"));
+        // A RAM file label in ROM space is still found
+        assert_eq!(symbols.label(mem, 0x8000), Some("MMC1_CTRL"));
+        assert_eq!(symbols.address_of(mem, "reset"), Some(0xC010));
+
+        assert!(symbols.parse_fceux_nl("$ZZZZ#broken#\n", None).is_err());
+    }
+
+    #[test]
+    fn mesen_mlb_understands_both_memory_type_names() {
+        let nes = Nes::new(cartridge()).unwrap();
+        let mem = nes.cpu().memory();
+        let mut symbols = SymbolTable::new();
+        symbols.parse_mesen_mlb(concat!(
+            "P:0020:irq_handler\n",
+            "R:0811:mirror_var\n",
+            "S:0005:save_slot\n",
+            "G:4016:JOYPAD1:Controller port\n",
+            "NesPrgRom:4030-4031:nmi\n",
+            "NesInternalRam:0012:frame\n",
+            "X:0001:unknown\n",
+            "P:0040:\n",
+        )).unwrap();
+
+        assert_eq!(symbols.label(mem, 0x8020), Some("irq_handler"));
+        assert_eq!(symbols.label(mem, 0x0011), Some("mirror_var"));
+        assert_eq!(symbols.label(mem, 0x6005), Some("save_slot"));
+        assert_eq!(symbols.label(mem, 0x4016), Some("JOYPAD1"));
+        assert_eq!(symbols.label(mem, 0xC030), Some("nmi"));
+        assert_eq!(symbols.label(mem, 0x0012), Some("frame"));
+        assert_eq!(symbols.label(mem, 0x0001), None);
+        assert_eq!(symbols.label(mem, 0x8040), None);
+
+        assert!(symbols.parse_mesen_mlb("P:nothex:label\n").is_err());
+    }
+
+    #[test]
+    fn ld65_dbg_places_symbols_through_their_segments() {
+        let nes = Nes::new(cartridge()).unwrap();
+        let mem = nes.cpu().memory();
+        let mut symbols = SymbolTable::new();
+        symbols.parse_ld65_dbg(concat!(
+            "version\tmajor=2,minor=0\n",
+            "seg\tid=0,name=\"ZEROPAGE\",start=0x000000,size=0x0010,addrsize=zeropage,type=rw\n",
+            "seg\tid=1,name=\"CODE\",start=0x008000,size=0x0100,addrsize=absolute,type=ro,oname=\"game.nes\",ooffs=16\n",
+            "seg\tid=2,name=\"VECTORS\",start=0x00FFFA,size=0x0006,addrsize=absolute,type=ro,oname=\"game.nes\",ooffs=32778\n",
+            "sym\tid=0,name=\"main\",addrsize=absolute,scope=0,def=1,ref=4,val=0x8005,seg=1,type=lab\n",
+            "sym\tid=1,name=\"player_x\",addrsize=zeropage,scope=0,def=2,val=0x10,seg=0,type=lab\n",
+            "sym\tid=2,name=\"PPUMASK\",addrsize=absolute,scope=0,def=3,val=0x2001,type=equ\n",
+            "sym\tid=3,name=\"SPEED\",addrsize=zeropage,scope=0,def=5,val=0x3,type=equ\n",
+            "sym\tid=4,name=\"imported\",addrsize=absolute,scope=0,def=6,val=0x8010,seg=1,type=imp\n",
+            "sym\tid=5,name=\"vectors\",addrsize=absolute,scope=0,def=7,val=0xFFFA,seg=2,type=lab\n",
+        ));
+
+        assert_eq!(symbols.label(mem, 0x8005), Some("main"));
+        assert_eq!(symbols.label(mem, 0x0010), Some("player_x"));
+        assert_eq!(symbols.label(mem, 0x2001), Some("PPUMASK"));
+        assert_eq!(symbols.label(mem, 0x0003), None);
+        assert_eq!(symbols.label(mem, 0x8010), None);
+        assert_eq!(symbols.label(mem, 0xFFFA), Some("vectors"));
+        assert_eq!(symbols.address_of(mem, "main"), Some(0x8005));
+    }
+}
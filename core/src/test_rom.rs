@@ -0,0 +1,185 @@
+use crate::cartridge::Cartridge;
+use crate::cpu::Jam;
+use crate::nes::Nes;
+
+// Test ROMs (blargg's and most newer ones) report through $6000: a status byte, the signature
+// DE B0 61 at $6001-$6003 that says the rest is valid, and a zero terminated message from $6004.
+const STATUS: u16 = 0x6000;
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const MESSAGE: u16 = 0x6004;
+
+const STATUS_RUNNING: u8 = 0x80;
+// The test wants the reset button pressed, no sooner than 100ms from now
+const STATUS_RESET: u8 = 0x81;
+const RESET_DELAY_FRAMES: u64 = 6;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TestOutcome {
+    Passed,
+    // The result code, which says which part of the test failed
+    Failed(u8),
+    TimedOut,
+    // The CPU ran into an opcode it can not run
+    Jammed(Jam),
+}
+
+#[derive(Clone, Debug)]
+pub struct TestReport {
+    pub outcome: TestOutcome,
+    // Whatever the test wrote at $6004, usually the name of the test and why it failed
+    pub message: String,
+    pub frames: u64,
+}
+
+// Runs until the test reports a result, or for `max_frames` frames
+pub fn run(nes: &mut Nes, max_frames: u64) -> TestReport {
+    let mut reset_at = None;
+
+    while nes.frame() < max_frames {
+        nes.run_frame();
+        if let Some(jam) = nes.cpu().jam() {
+            return report(nes, TestOutcome::Jammed(jam));
+        }
+
+        let status = match read_status(nes) {
+            Some(status) => status,
+            None => continue
+        };
+
+        match status {
+            STATUS_RUNNING => {}
+            STATUS_RESET => {
+                let frame = *reset_at.get_or_insert(nes.frame() + RESET_DELAY_FRAMES);
+                if nes.frame() >= frame {
+                    nes.reset();
+                    reset_at = None;
+                }
+            }
+            0 => return report(nes, TestOutcome::Passed),
+            code if code < STATUS_RUNNING => return report(nes, TestOutcome::Failed(code)),
+            _ => {}
+        }
+    }
+
+    report(nes, TestOutcome::TimedOut)
+}
+
+pub fn run_file(path: &str, max_frames: u64) -> Result<TestReport, String> {
+    let mut nes = Nes::new(Cartridge::load(path)?)?;
+    Ok(run(&mut nes, max_frames))
+}
+
+// The status, once the signature is there to show that $6000 is not just uninitialized RAM
+fn read_status(nes: &Nes) -> Option<u8> {
+    let memory = nes.cpu().memory();
+    let signature = [memory.peek8(STATUS + 1), memory.peek8(STATUS + 2), memory.peek8(STATUS + 3)];
+
+    if signature == SIGNATURE {
+        Some(memory.peek8(STATUS))
+    } else {
+        None
+    }
+}
+
+fn report(nes: &Nes, outcome: TestOutcome) -> TestReport {
+    let memory = nes.cpu().memory();
+    let message: Vec<u8> = (MESSAGE..0x8000)
+        .map(|address| memory.peek8(address))
+        .take_while(|c| *c != 0)
+        .collect();
+
+    TestReport {
+        outcome,
+        message: String::from_utf8_lossy(&message).trim_end().to_string(),
+        frames: nes.frame(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The program at $8000, with every vector pointing at it
+    fn test_rom(program: &[u8]) -> Nes {
+        Nes::new(Cartridge::nrom(&[(0x8000, program)], [0x8000, 0x8000, 0x8000])).unwrap()
+    }
+
+    // Writes the signature and a message, then `status`, and loops forever. `origin` is where the code goes.
+    fn reporting(origin: u16, status: u8, message: &str) -> Vec<u8> {
+        let mut program = vec![];
+        let bytes = SIGNATURE.iter().cloned().chain(message.bytes()).chain(std::iter::once(0));
+        for (i, byte) in bytes.enumerate() {
+            let address = STATUS + 1 + i as u16;
+            program.extend_from_slice(&[0xA9, byte, 0x8D, address as u8, (address >> 8) as u8]); // LDA #byte, STA address
+        }
+        program.extend_from_slice(&[0xA9, status, 0x8D, 0x00, 0x60]); // LDA #status, STA $6000
+
+        let end = origin + program.len() as u16;
+        program.extend_from_slice(&[0x4C, end as u8, (end >> 8) as u8]); // JMP *
+        program
+    }
+
+    #[test]
+    fn a_passing_test_reports_its_message() {
+        let report = run(&mut test_rom(&reporting(0x8000, 0, "\nPassed\n")), 60);
+        assert_eq!(report.outcome, TestOutcome::Passed);
+        assert_eq!(report.message, "\nPassed");
+    }
+
+    #[test]
+    fn a_failing_test_reports_its_code() {
+        let report = run(&mut test_rom(&reporting(0x8000, 3, "Too soon")), 60);
+        assert_eq!(report.outcome, TestOutcome::Failed(3));
+        assert_eq!(report.message, "Too soon");
+    }
+
+    #[test]
+    fn a_test_that_never_finishes_times_out() {
+        let report = run(&mut test_rom(&reporting(0x8000, STATUS_RUNNING, "")), 30);
+        assert_eq!(report.outcome, TestOutcome::TimedOut);
+        assert_eq!(report.frames, 30);
+    }
+
+    #[test]
+    fn a_test_that_asks_for_a_reset_gets_one() {
+        // $6010 survives the reset, so the second time around the test passes
+        let mut program = vec![
+            0xAD, 0x10, 0x60, // LDA $6010
+            0xD0, 0x00,       // BNE passed
+            0xEE, 0x10, 0x60, // INC $6010
+        ];
+        program.extend(reporting(0x8000 + program.len() as u16, STATUS_RESET, ""));
+        let passed = program.len();
+        program[4] = (passed - 5) as u8;
+        program.extend(reporting(0x8000 + passed as u16, 0, "ok"));
+
+        let report = run(&mut test_rom(&program), 60);
+        assert_eq!(report.outcome, TestOutcome::Passed);
+        assert_eq!(report.message, "ok");
+    }
+
+    #[test]
+    fn a_jam_stops_the_cpu_until_a_reset() {
+        // Turns NMI on and runs into JAM
+        let mut nes = test_rom(&[0xA9, 0x80, 0x8D, 0x00, 0x20, 0xEA, 0x02]);
+        let report = run(&mut nes, 60);
+        assert_eq!(report.outcome, TestOutcome::Jammed(Jam { address: 0x8006, opcode: 0x02 }));
+        assert_eq!(report.frames, 1);
+
+        // Not even the NMIs get it going
+        let stack = nes.cpu().registers.stack();
+        nes.run_frame();
+        assert_eq!((nes.cpu().registers.pc(), nes.cpu().registers.stack()), (0x8006, stack));
+        assert_eq!(nes.step().cycles, 2);
+
+        let state = nes.save_state();
+        nes.load_state(&state).unwrap();
+        assert_eq!(nes.cpu().jam(), None);
+
+        nes.step();
+        assert!(nes.cpu().jam().is_some());
+        nes.reset();
+        assert_eq!(nes.cpu().jam(), None);
+        assert_eq!(nes.cpu().registers.pc(), 0x8000);
+    }
+}
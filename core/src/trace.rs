@@ -0,0 +1,160 @@
+use crate::cpu::CPU;
+use crate::cpuregisters::CPURegisters;
+use crate::disassembler;
+use crate::symbols::SymbolTable;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+// Everything a trace line needs, captured right before the instruction at `pc` is executed.
+#[derive(Clone, Debug)]
+pub struct TraceEntry {
+    pub pc: u16,
+    pub bytes: Vec<u8>,
+    pub disassembly: String,
+    pub registers: CPURegisters,
+    pub scanline: i32,
+    pub dot: i32,
+    pub cycles: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TraceFormat {
+    Nestest,
+    Fceux,
+    Mesen,
+}
+
+pub trait TraceSink {
+    fn trace(&mut self, entry: &TraceEntry);
+}
+
+pub struct TraceLogger {
+    writer: BufWriter<File>,
+    format: TraceFormat,
+}
+
+impl TraceEntry {
+    pub fn capture(cpu: &CPU, symbols: &SymbolTable, scanline: i32, dot: i32, cycles: u64) -> TraceEntry {
+        let instruction = disassembler::disassemble_at(cpu.memory(), symbols, &cpu.registers);
+
+        TraceEntry {
+            pc: instruction.address,
+            bytes: instruction.bytes,
+            disassembly: instruction.text,
+            registers: cpu.registers.clone(),
+            scanline,
+            dot,
+            cycles,
+        }
+    }
+
+    fn hex_bytes(&self, prefix: &str) -> String {
+        self.bytes.iter()
+            .map(|b| format!("{}{:02X}", prefix, b))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+impl TraceFormat {
+    pub fn from_name(name: &str) -> Option<TraceFormat> {
+        match name.to_lowercase().as_str() {
+            "nestest" => Some(TraceFormat::Nestest),
+            "fceux" => Some(TraceFormat::Fceux),
+            "mesen" => Some(TraceFormat::Mesen),
+            _ => None
+        }
+    }
+
+    pub fn format(&self, entry: &TraceEntry) -> String {
+        let regs = &entry.registers;
+        match self {
+            // Unofficial opcodes are marked with a * in the column before the mnemonic
+            TraceFormat::Nestest => format!(
+                "{:04X}  {:<8} {:>1}{:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+                entry.pc, entry.hex_bytes(""),
+                if entry.disassembly.starts_with('*') { "*" } else { "" },
+                entry.disassembly.trim_start_matches('*'),
+                regs.accumulator(), regs.x(), regs.y(), regs.status(), regs.stack() & 0xFF,
+                entry.scanline, entry.dot, entry.cycles),
+            TraceFormat::Fceux => format!(
+                "c{:<11} A:{:02X} X:{:02X} Y:{:02X} S:{:02X} P:{}  ${:04X}:{:<9} {}",
+                entry.cycles, regs.accumulator(), regs.x(), regs.y(), regs.stack() & 0xFF,
+                status_flags(regs.status()), entry.pc, entry.hex_bytes(""), entry.disassembly),
+            TraceFormat::Mesen => format!(
+                "{:04X}  {:<15} {:<32} A:{:02X} X:{:02X} Y:{:02X} S:{:02X} P:{} V:{:<3} H:{:<3} Cycle:{}",
+                entry.pc, entry.hex_bytes("$"), entry.disassembly,
+                regs.accumulator(), regs.x(), regs.y(), regs.stack() & 0xFF, status_flags(regs.status()),
+                entry.scanline, entry.dot, entry.cycles),
+        }
+    }
+}
+
+impl TraceLogger {
+    pub fn create(path: &str, format: TraceFormat) -> Result<TraceLogger, String> {
+        let file = File::create(path).map_err(|e| e.to_string())?;
+
+        Ok(TraceLogger {
+            writer: BufWriter::new(file),
+            format,
+        })
+    }
+}
+
+impl TraceSink for TraceLogger {
+    fn trace(&mut self, entry: &TraceEntry) {
+        // A trace that can not be written is not worth stopping the emulation for
+        let _ = writeln!(self.writer, "{}", self.format.format(entry));
+    }
+}
+
+// Upper case letter for a set flag, lower case for a cleared one (NV-BDIZC), as FCEUX and Mesen print them.
+fn status_flags(status: u8) -> String {
+    "nvubdizc".chars()
+        .enumerate()
+        .map(|(i, c)| if status & (0x80 >> i) != 0 { c.to_ascii_uppercase() } else { c })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The first instruction of nestest's automated mode
+    fn entry() -> TraceEntry {
+        TraceEntry {
+            pc: 0xC000,
+            bytes: vec![0x4C, 0xF5, 0xC5],
+            disassembly: String::from("JMP $C5F5"),
+            registers: CPURegisters::new(),
+            scanline: 0,
+            dot: 21,
+            cycles: 7,
+        }
+    }
+
+    #[test]
+    fn nestest_lines_match_nestest_log() {
+        assert_eq!(TraceFormat::Nestest.format(&entry()),
+                   "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7");
+    }
+
+    #[test]
+    fn fceux_lines_lead_with_the_cycle_count_and_spell_out_the_flags() {
+        assert_eq!(TraceFormat::Fceux.format(&entry()),
+                   "c7           A:00 X:00 Y:00 S:FD P:nvUbdIzc  $C000:4C F5 C5  JMP $C5F5");
+    }
+
+    #[test]
+    fn mesen_lines_prefix_the_bytes_and_show_the_ppu_position() {
+        assert_eq!(TraceFormat::Mesen.format(&entry()),
+                   "C000  $4C $F5 $C5     JMP $C5F5                        A:00 X:00 Y:00 S:FD P:nvUbdIzc V:0   H:21  Cycle:7");
+    }
+
+    #[test]
+    fn format_names_are_case_insensitive() {
+        assert_eq!(TraceFormat::from_name("FCEUX"), Some(TraceFormat::Fceux));
+        assert_eq!(TraceFormat::from_name("Mesen"), Some(TraceFormat::Mesen));
+        assert_eq!(TraceFormat::from_name("bizhawk"), None);
+    }
+}
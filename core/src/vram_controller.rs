@@ -0,0 +1,147 @@
+use crate::cartridge::{ChrRomBank, Mirroring};
+use crate::savestate::{StateReader, StateWriter};
+
+pub struct VRAMController {
+    memory: [u8; 0x4000],
+    pub oam: [u8; 0x100],
+    mirroring: Mirroring,
+}
+
+impl VRAMController {
+    pub fn new() -> VRAMController {
+        VRAMController {
+            memory: [0; 0x4000],
+            oam: [0; 0x100],
+            mirroring: Mirroring::Horizontal,
+        }
+    }
+
+    pub fn set_mirroring(&mut self, mirroring: Mirroring) {
+        self.mirroring = mirroring;
+    }
+
+    pub fn write8(&mut self, address: u16, value: u8) {
+        self.memory[self.translate_address(address)] = value;
+    }
+
+    pub fn read8(&self, address: u16) -> u8 {
+        self.memory[self.translate_address(address)]
+    }
+
+    // For debug views. Nothing here reacts to reads yet, but mappers that latch on pattern table
+    // fetches (MMC2, MMC3) will, and looking at memory must not trip them.
+    pub fn peek8(&self, address: u16) -> u8 {
+        self.memory[self.translate_address(address)]
+    }
+
+    // DMA always copies a whole page, starting at OAMADDR and wrapping around
+    pub fn write_oam_dma(&mut self, oamaddr: u8, data: &[u8]) {
+        for (i, value) in data.iter().enumerate().take(0x100) {
+            self.oam[oamaddr.wrapping_add(i as u8) as usize] = *value;
+        }
+    }
+
+    pub fn write_oam(&mut self, index: u8, value: u8) {
+        self.oam[index as usize] = value;
+    }
+
+    pub fn read_oam(&self, index: u8) -> u8 {
+        self.oam[index as usize]
+    }
+
+    fn translate_address(&self, address: u16) -> usize {
+        let address = (address & 0x3FFF) as usize;
+
+        match address {
+            0x0000..=0x1FFF => address,
+            0x2000..=0x3EFF => {
+                // Two 1KB nametables of VRAM, mirrored to fill the four slots at $2000-$2FFF,
+                // and $3000-$3EFF mirrors that again
+                let table = ((address - 0x2000) & 0x0FFF) / 0x400;
+                let table = match self.mirroring {
+                    Mirroring::Horizontal => table / 2,
+                    Mirroring::Vertical => table % 2,
+                    Mirroring::FourScreen => table,
+                };
+                0x2000 + table * 0x400 + (address & 0x3FF)
+            }
+            _ => {
+                // 32 bytes of palette RAM, where the backdrop colour of each sprite palette
+                // is shared with the matching background palette
+                let index = address & 0x1F;
+                let index = if index & 0x13 == 0x10 { index & 0x0F } else { index };
+                0x3F00 + index
+            }
+        }
+    }
+
+    pub fn nametable0(&self) -> &[u8] {
+        &self.memory[0x2000..0x23FF]
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.memory);
+        writer.write_bytes(&self.oam);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        reader.read_into(&mut self.memory)?;
+        reader.read_into(&mut self.oam)
+    }
+
+    pub fn load_chr_rom(&mut self, rom: &ChrRomBank) {
+        /*
+        let prg_bank1 = &mut self.memory[RamController::PRG_BANK1_LOCATION..RamController::PRG_BANK1_LOCATION + RamController::PRG_BANK_SIZE];
+        prg_bank1.copy_from_slice(rom.get_data());
+        */
+
+        let pattern_tables = &mut self.memory[0..0x2000];
+        pattern_tables.copy_from_slice(rom.get_data());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vram(mirroring: Mirroring) -> VRAMController {
+        let mut vram = VRAMController::new();
+        vram.set_mirroring(mirroring);
+        vram
+    }
+
+    #[test]
+    fn nametables_follow_the_cartridge_mirroring() {
+        let mut horizontal = vram(Mirroring::Horizontal);
+        horizontal.write8(0x2005, 1);
+        horizontal.write8(0x2C06, 2);
+        assert_eq!((horizontal.read8(0x2405), horizontal.read8(0x2805)), (1, 0));
+        assert_eq!((horizontal.read8(0x2806), horizontal.read8(0x3C06)), (2, 2));
+
+        let mut vertical = vram(Mirroring::Vertical);
+        vertical.write8(0x2005, 3);
+        assert_eq!((vertical.read8(0x2805), vertical.read8(0x3005), vertical.read8(0x2405)), (3, 3, 0));
+
+        let mut four_screen = vram(Mirroring::FourScreen);
+        four_screen.write8(0x2C00, 4);
+        assert_eq!((four_screen.read8(0x2000), four_screen.read8(0x2400), four_screen.read8(0x2800)), (0, 0, 0));
+        assert_eq!(four_screen.read8(0x3C00), 4);
+    }
+
+    #[test]
+    fn sprite_backdrops_are_the_background_ones() {
+        let mut vram = VRAMController::new();
+        vram.write8(0x3F10, 0x2A);
+        vram.write8(0x3F15, 0x15);
+        assert_eq!((vram.read8(0x3F00), vram.read8(0x3F30)), (0x2A, 0x2A));
+        assert_eq!((vram.read8(0x3F05), vram.read8(0x3F35)), (0, 0x15));
+    }
+
+    #[test]
+    fn oam_dma_starts_at_oamaddr_and_wraps() {
+        let mut vram = VRAMController::new();
+        let page: Vec<u8> = (0..=255).collect();
+        vram.write_oam_dma(0xFE, &page);
+        assert_eq!((vram.read_oam(0xFE), vram.read_oam(0xFF), vram.read_oam(0x00), vram.read_oam(0xFD)), (0, 1, 2, 0xFF));
+    }
+}
@@ -0,0 +1,139 @@
+// Golden image tests, each frame is compared with core/tests/golden/<name>.png. See rustnes_core::golden
+// for writing the references, and for the .actual.png and .diff.png written when a frame differs.
+
+use std::path::PathBuf;
+
+use rustnes_core::controller::BUTTON_A;
+use rustnes_core::golden;
+use rustnes_core::headless::HeadlessOptions;
+use rustnes_core::movie::FrameInput;
+
+fn reference(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.png", name))
+}
+
+// An NROM image of `program` at $C000 with every vector pointing at it, written out for the headless runner
+fn write_rom(name: &str, program: &[u8], chr: &[u8]) -> String {
+    let mut prg = vec![0u8; 0x4000];
+    prg[..program.len()].copy_from_slice(program);
+    prg[0x3FFA..].copy_from_slice(&[0x00, 0xC0, 0x00, 0xC0, 0x00, 0xC0]);
+
+    let mut image = b"NES\x1A\x01\x01".to_vec();
+    image.resize(16, 0);
+    image.extend(prg);
+    image.extend_from_slice(chr);
+    image.resize(16 + 0x4000 + 0x2000, 0);
+
+    let path = std::env::temp_dir().join(format!("rustnes-golden-{}-{}.nes", name, std::process::id()));
+    std::fs::write(&path, image).unwrap();
+    path.to_string_lossy().to_string()
+}
+
+// Tile 1 is solid colour 1, tile 2 a diagonal in colours 1 to 3, tile 3 a checkerboard of colour 3
+fn chr() -> Vec<u8> {
+    let mut chr = vec![0u8; 0x2000];
+    for row in 0..8 {
+        chr[16 + row] = 0xFF;
+        chr[32 + row] = 0xC0 >> row;
+        chr[32 + 8 + row] = 0x03 << row;
+        chr[48 + row] = if row % 2 == 0 { 0xAA } else { 0x55 };
+        chr[48 + 8 + row] = chr[48 + row];
+    }
+    chr
+}
+
+// LDA #value, STA address
+fn store(program: &mut Vec<u8>, address: u16, value: u8) {
+    program.extend_from_slice(&[0xA9, value, 0x8D, address as u8, (address >> 8) as u8]);
+}
+
+// Fills the first nametable, palettes and OAM with rendering off, then turns on rendering and
+// loops reading the controller, scrolling right by 4 pixels while A is held
+fn scene() -> Vec<u8> {
+    let mut program = vec![
+        0x78, 0xD8, 0xA2, 0xFF, 0x9A, // SEI, CLD, LDX #$FF, TXS
+        0x2C, 0x02, 0x20, 0x10, 0xFB, // BIT $2002, BPL -5
+        0x2C, 0x02, 0x20, 0x10, 0xFB, // BIT $2002, BPL -5
+    ];
+
+    store(&mut program, 0x2006, 0x3F);
+    store(&mut program, 0x2006, 0x00);
+    for color in [0x0F, 0x16, 0x27, 0x18, 0x0F, 0x1A, 0x2A, 0x3A, 0x0F, 0x11, 0x21, 0x31, 0x0F, 0x14, 0x24, 0x34,
+                  0x0F, 0x12, 0x22, 0x32, 0x0F, 0x05, 0x15, 0x25, 0x0F, 0x09, 0x19, 0x29, 0x0F, 0x00, 0x10, 0x30] {
+        store(&mut program, 0x2007, color);
+    }
+
+    // Tiles 0 to 3 across each row, and the same bytes give the attribute table a mix of palettes
+    store(&mut program, 0x2006, 0x20);
+    store(&mut program, 0x2006, 0x00);
+    program.extend_from_slice(&[
+        0xA0, 0x04,       // LDY #4
+        0xA2, 0x00,       // LDX #0
+        0x8A,             // TXA
+        0x29, 0x03,       // AND #3
+        0x8D, 0x07, 0x20, // STA $2007
+        0xE8,             // INX
+        0xD0, 0xF7,       // BNE -9
+        0x88,             // DEY
+        0xD0, 0xF2,       // BNE -14
+    ]);
+
+    // Plain, flipped horizontally, flipped vertically, and behind the background in the second palette
+    for (i, sprite) in [[40, 2, 0x00, 40], [40, 2, 0x40, 56], [40, 2, 0x80, 72], [60, 3, 0x21, 100]].iter().enumerate() {
+        for (j, byte) in sprite.iter().enumerate() {
+            store(&mut program, 0x0200 + (i * 4 + j) as u16, *byte);
+        }
+    }
+    for i in 16..256u16 {
+        if i % 4 == 0 {
+            store(&mut program, 0x0200 + i, 0xFF);
+        }
+    }
+    store(&mut program, 0x4014, 0x02);
+
+    store(&mut program, 0x2001, 0x1E);
+    let main_loop = 0xC000 + program.len() as u16;
+    program.extend_from_slice(&[
+        0x2C, 0x02, 0x20, 0x10, 0xFB, // BIT $2002, BPL -5
+        0xA9, 0x01, 0x8D, 0x16, 0x40, // LDA #1, STA $4016
+        0xA9, 0x00, 0x8D, 0x16, 0x40, // LDA #0, STA $4016
+        0xAD, 0x16, 0x40,             // LDA $4016
+        0x29, 0x01,                   // AND #1
+        0xF0, 0x07,                   // BEQ +7
+        0xA5, 0x10,                   // LDA $10
+        0x18, 0x69, 0x04,             // CLC, ADC #4
+        0x85, 0x10,                   // STA $10
+        0xA5, 0x10,                   // LDA $10
+        0x8D, 0x05, 0x20,             // STA $2005
+        0xA9, 0x00, 0x8D, 0x05, 0x20, // LDA #0, STA $2005
+        0xA9, 0x00, 0x8D, 0x00, 0x20, // LDA #0, STA $2000
+        0x4C, main_loop as u8, (main_loop >> 8) as u8, // JMP main_loop
+    ]);
+    program
+}
+
+fn check(name: &str, frames: u64, input: Vec<FrameInput>) {
+    let rom = write_rom(name, &scene(), &chr());
+    let result = golden::check(&HeadlessOptions { rom: rom.clone(), frames, input, state: None, region: None }, &reference(name));
+    let _ = std::fs::remove_file(rom);
+    result.unwrap();
+}
+
+#[test]
+fn background_and_sprites() {
+    check("background_and_sprites", 10, vec![]);
+}
+
+#[test]
+fn scrolled_by_holding_a() {
+    let held = FrameInput { ports: [BUTTON_A, 0], ..Default::default() };
+    check("scrolled_by_holding_a", 10, vec![held; 6]);
+}
+
+// The menu is drawn from its NMI handler, so this needs NMIs to return to where they interrupted
+#[test]
+fn nestest_menu() {
+    let rom = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../roms/nestest.nes").to_string_lossy().to_string();
+    let options = HeadlessOptions { rom, frames: 60, input: vec![], state: None, region: None };
+    golden::check(&options, &reference("nestest_menu")).unwrap();
+}
@@ -0,0 +1,32 @@
+// Runs nestest from $C000, the automated mode that needs no PPU, and compares the trace with the
+// log of a known good run: registers, cycles and the PPU position before every instruction.
+
+use rustnes_core::symbols::SymbolTable;
+use rustnes_core::trace::{TraceEntry, TraceFormat};
+use rustnes_core::{Cartridge, Nes};
+use std::path::PathBuf;
+
+#[test]
+fn the_trace_matches_nestest_log() {
+    let roms = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../roms");
+    let cartridge = Cartridge::load(&roms.join("nestest.nes").to_string_lossy()).unwrap();
+    let log = std::fs::read_to_string(roms.join("nestest copy.log")).unwrap();
+
+    let mut nes = Nes::new(cartridge).unwrap();
+    nes.cpu_mut().registers.set_pc(0xC000);
+    let symbols = SymbolTable::new();
+
+    for (number, expected) in log.lines().filter(|line| !line.is_empty()).enumerate() {
+        let entry = TraceEntry::capture(nes.cpu(), &symbols, nes.ppu().scanline(), nes.ppu().pixel(), nes.cycles());
+        let line = TraceFormat::Nestest.format(&entry);
+        nes.step();
+
+        // The logging emulator shows open bus as the value of the APU registers, where the
+        // disassembly peeks at their state, so those lines skip the disassembly column
+        if expected.contains(" $40") {
+            assert_eq!((&line[..16], &line[48..]), (&expected[..16], &expected[48..]), "line {} differs", number + 1);
+            continue;
+        }
+        assert_eq!(line, expected, "line {} differs", number + 1);
+    }
+}
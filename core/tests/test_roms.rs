@@ -0,0 +1,104 @@
+// Regression suite of test ROMs that report through $6000. The ROMs are not in the repository,
+// so the suite only runs when asked for with `cargo test -- --ignored`. Put them in roms/test (as
+// laid out in blargg's archives, the paths below are the nes-test-roms collection's) or point
+// RUSTNES_TEST_ROMS at them, a missing ROM fails the run.
+
+use std::path::PathBuf;
+
+use rustnes_core::test_rom::{self, TestOutcome};
+
+const MAX_FRAMES: u64 = 60 * 60;
+
+enum Expect {
+    Pass,
+    // Fails for a reason we know about. Passing anyway is reported, so the entry gets flipped.
+    KnownFailure(&'static str),
+}
+
+use Expect::*;
+
+const SUITE: &[(&str, Expect)] = &[
+    // cpu_timing_test6 only shows its result on screen, these report through $6000
+    ("instr_timing/rom_singles/1-instr_timing.nes", KnownFailure("ANC, ALR, ARR, SBX and the other unsupported unofficial opcodes jam the CPU")),
+    ("instr_timing/rom_singles/2-branch_timing.nes", Pass),
+    ("instr_test-v5/rom_singles/01-basics.nes", Pass),
+    ("instr_test-v5/rom_singles/02-implied.nes", Pass),
+    ("instr_test-v5/rom_singles/10-branches.nes", Pass),
+    ("instr_test-v5/rom_singles/11-stack.nes", Pass),
+    ("instr_test-v5/rom_singles/12-jmp_jsr.nes", Pass),
+    ("instr_test-v5/rom_singles/13-rts.nes", Pass),
+    ("instr_test-v5/rom_singles/14-rti.nes", Pass),
+    ("instr_test-v5/rom_singles/15-brk.nes", Pass),
+    ("instr_test-v5/rom_singles/16-special.nes", Pass),
+    ("ppu_vbl_nmi/rom_singles/01-vbl_basics.nes", Pass),
+    ("ppu_vbl_nmi/rom_singles/02-vbl_set_time.nes", Pass),
+    ("ppu_vbl_nmi/rom_singles/03-vbl_clear_time.nes", Pass),
+    ("ppu_vbl_nmi/rom_singles/04-nmi_control.nes", Pass),
+    ("ppu_vbl_nmi/rom_singles/05-nmi_timing.nes", Pass),
+    ("ppu_vbl_nmi/rom_singles/06-suppression.nes", Pass),
+    ("ppu_vbl_nmi/rom_singles/07-nmi_on_timing.nes", Pass),
+    ("ppu_vbl_nmi/rom_singles/08-nmi_off_timing.nes", Pass),
+    ("ppu_vbl_nmi/rom_singles/09-even_odd_frames.nes", Pass),
+    ("ppu_vbl_nmi/rom_singles/10-even_odd_timing.nes", Pass),
+    ("apu_test/rom_singles/1-len_ctr.nes", Pass),
+    ("apu_test/rom_singles/2-len_table.nes", Pass),
+    ("apu_test/rom_singles/3-irq_flag.nes", Pass),
+    ("apu_test/rom_singles/4-jitter.nes", KnownFailure("a $4017 write resets the frame counter straight away, without the 3 or 4 cycle delay")),
+    ("apu_test/rom_singles/5-len_timing.nes", KnownFailure("the APU is clocked after each instruction, so it is a few cycles late for $4015 and $4017")),
+    ("apu_test/rom_singles/6-irq_flag_timing.nes", KnownFailure("the APU is clocked after each instruction, so it is a few cycles late for $4015 and $4017")),
+    ("apu_test/rom_singles/7-dmc_basics.nes", Pass),
+    ("apu_test/rom_singles/8-dmc_rates.nes", Pass),
+    ("mmc3_test/1-clocking.nes", KnownFailure("MMC3 (mapper 4) is not supported")),
+    ("mmc3_test/2-details.nes", KnownFailure("MMC3 (mapper 4) is not supported")),
+    ("mmc3_test/3-A12_clocking.nes", KnownFailure("MMC3 (mapper 4) is not supported")),
+    ("mmc3_test/4-scanline_timing.nes", KnownFailure("MMC3 (mapper 4) is not supported")),
+    ("mmc3_test/5-MMC3.nes", KnownFailure("MMC3 (mapper 4) is not supported")),
+    ("mmc3_test/6-MMC6.nes", KnownFailure("MMC3 (mapper 4) is not supported")),
+];
+
+fn rom_directory() -> PathBuf {
+    match std::env::var_os("RUSTNES_TEST_ROMS") {
+        Some(directory) => PathBuf::from(directory),
+        None => PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../roms/test"),
+    }
+}
+
+#[test]
+#[ignore = "needs the test ROMs, see the top of core/tests/test_roms.rs"]
+fn test_roms() {
+    let directory = rom_directory();
+    let mut problems = vec![];
+
+    for (rom, expect) in SUITE {
+        let path = directory.join(rom);
+        if !path.exists() {
+            problems.push(format!("{}: not found in {}", rom, directory.display()));
+            continue;
+        }
+
+        let outcome = match test_rom::run_file(&path.to_string_lossy(), MAX_FRAMES) {
+            Ok(report) => {
+                println!("{}: {:?} {}", rom, report.outcome, report.message.replace('\n', " "));
+                report.outcome
+            }
+            // A ROM the emulator can not even load is as much a known failure as one that fails
+            Err(e) => {
+                if !matches!(expect, KnownFailure(_)) {
+                    problems.push(format!("{}: {}", rom, e));
+                }
+                continue;
+            }
+        };
+
+        match (expect, outcome) {
+            (Pass, TestOutcome::Passed) | (KnownFailure(_), TestOutcome::Failed(_))
+            | (KnownFailure(_), TestOutcome::TimedOut) | (KnownFailure(_), TestOutcome::Jammed(_)) => {}
+            (Pass, outcome) => problems.push(format!("{}: {:?}", rom, outcome)),
+            (KnownFailure(reason), TestOutcome::Passed) => {
+                problems.push(format!("{}: passes now, it was expected to fail because {}", rom, reason))
+            }
+        }
+    }
+
+    assert!(problems.is_empty(), "\n{}", problems.join("\n"));
+}
@@ -0,0 +1,17 @@
+#version 330 core
+// Darkens the gap between the lines of the picture, the way a CRT shows them
+
+in vec2 vTexCoord;
+out vec4 FragColor;
+
+uniform sampler2D Source;
+// The emulator's picture, so the lines stay put whatever size the earlier passes scaled it to
+uniform vec2 OrigInputSize;
+
+void main()
+{
+    vec3 color = texture(Source, vTexCoord).rgb;
+    // 0 in the middle of a line and 0.5 at its edges
+    float distance = abs(fract(vTexCoord.y * OrigInputSize.y) - 0.5);
+    FragColor = vec4(color * mix(1.0, 0.55, smoothstep(0.25, 0.5, distance)), 1.0);
+}
@@ -0,0 +1,12 @@
+#version 330 core
+// Passes the picture through unchanged
+
+in vec2 vTexCoord;
+out vec4 FragColor;
+
+uniform sampler2D Source;
+
+void main()
+{
+    FragColor = texture(Source, vTexCoord);
+}
@@ -0,0 +1,267 @@
+use rustnes_core::ntsc_filter::NtscSetup;
+use rustnes_core::palette::NtscParameters;
+use rustnes_core::region::Region;
+use rustnes_core::scaler::{Filter, Overscan, ScaleOptions};
+use rustnes_core::screenshot::ScreenshotKind;
+
+pub const USAGE: &str = "\
+Usage:
+    rustnes [run] <rom> [options]     Play a ROM
+    rustnes info <rom>                Print the ROM header, mapper and checksums
+    rustnes test <rom> [--frames N]   Run a test ROM without a window and exit with its result code
+    rustnes headless <rom> [options]  Run a ROM without a window, see rustnes_core::headless
+
+Options for run:
+    --scale N                  Window size as a multiple of the picture, 1 to 8 (default 2)
+    --fullscreen               Start in borderless fullscreen, F11 toggles it
+    --region ntsc|pal|dendy    Console region (default from the ROM header, NTSC when it does not say)
+    --vsync                    Pace frames by the display refresh instead of a timer, for displays
+                               that refresh at the region's frame rate
+    --palette FILE             Colours from a .pal file of 64 or 512 colours instead of the built-in 2C02 ones
+    --ntsc-palette PARAMS      Generate the colours from the NTSC signal, PARAMS is default or any of
+                               hue=DEGREES,saturation=1,contrast=1,brightness=0,gamma=2.2
+    --ntsc-filter PRESET       Show the picture through an NTSC TV, PRESET is composite, svideo, rgb or
+                               monochrome. The picture controls come from --ntsc-palette.
+    --renderer gl|software     Draw with OpenGL 3.3 (default) or on the CPU, for machines without a GPU
+    --filter nearest|bilinear  How the picture is scaled without a shader (default nearest)
+    --integer-scale            Only scale the picture by whole multiples, however big the window is
+    --square-pixels            No 8:7 pixel aspect correction
+    --overscan EDGES           Crop pixels off the picture's edges, like top=8,bottom=8 or all=8
+    --shader FILE              Post-process the picture with a .glslp shader preset or a single .glsl
+                               shader, see shaders/scanlines.glslp
+    --screenshot-kind KIND     What F10 saves to screenshots/: raw (the palette indices), palette (the
+                               colours) or display (as shown, the default)
+    --record-video FILE        Record every emulated frame to a Y4M video
+    --record-audio FILE        Record the sound to a WAV file, in step with the video
+    --record-avi FILE          Record both into an uncompressed AVI, up to 4GB
+    --viewer-scanline N        The scanline the PPU viewer (Tab) takes its snapshot on (default the
+                               first line of VBlank)
+    --trace FILE               Write an instruction trace to FILE
+    --trace-format NAME        nestest, mesen or fceux (default nestest)
+    --paused                   Start paused, P toggles pause
+    --load-state FILE          Load a save state before starting
+    --movie FILE               Play back an FM2 movie
+    --movie-read-write         Let loading a state during playback continue recording the movie
+    --record-movie FILE        Record an FM2 movie, from the --load-state state if one is given
+    --mute                     No sound
+    --frame-limit N            Quit after N frames
+";
+
+pub enum Command {
+    Run(Box<RunOptions>),
+    Info(String),
+    Test { rom: String, frames: u64 },
+    Headless(Vec<String>),
+    Help,
+}
+
+pub enum RendererKind {
+    Gl,
+    Software,
+}
+
+pub struct RunOptions {
+    pub rom: String,
+    pub scale: u32,
+    pub fullscreen: bool,
+    pub region: Option<Region>,
+    pub vsync: bool,
+    pub palette: Option<String>,
+    pub ntsc_palette: Option<NtscParameters>,
+    pub ntsc_filter: Option<NtscSetup>,
+    pub renderer: RendererKind,
+    pub scale_options: ScaleOptions,
+    pub shader: Option<String>,
+    pub screenshot_kind: ScreenshotKind,
+    pub record_video: Option<String>,
+    pub record_audio: Option<String>,
+    pub record_avi: Option<String>,
+    pub viewer_scanline: Option<i32>,
+    pub trace: Option<String>,
+    pub trace_format: Option<String>,
+    pub paused: bool,
+    pub load_state: Option<String>,
+    pub movie: Option<String>,
+    pub movie_read_write: bool,
+    pub record_movie: Option<String>,
+    pub mute: bool,
+    pub frame_limit: Option<u64>,
+}
+
+// `args` without the program name
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    let (command, rest) = match args.first().map(|arg| arg.as_str()) {
+        None | Some("help") | Some("--help") | Some("-h") => return Ok(Command::Help),
+        Some("headless") => return Ok(Command::Headless(args[1..].to_vec())),
+        Some(command @ "run") | Some(command @ "info") | Some(command @ "test") => (command, &args[1..]),
+        Some(_) => ("run", args),
+    };
+
+    let mut options = RunOptions {
+        rom: String::new(),
+        scale: 2,
+        fullscreen: false,
+        region: None,
+        vsync: false,
+        palette: None,
+        ntsc_palette: None,
+        ntsc_filter: None,
+        renderer: RendererKind::Gl,
+        scale_options: ScaleOptions::new(),
+        shader: None,
+        screenshot_kind: ScreenshotKind::Display,
+        record_video: None,
+        record_audio: None,
+        record_avi: None,
+        viewer_scanline: None,
+        trace: None,
+        trace_format: None,
+        paused: false,
+        load_state: None,
+        movie: None,
+        movie_read_write: false,
+        record_movie: None,
+        mute: false,
+        frame_limit: None,
+    };
+    let mut rom = None;
+    let mut test_frames = 60 * 60;
+
+    let mut args = rest.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().ok_or(format!("{} needs a value", arg));
+        match (command, arg.as_str()) {
+            ("run", "--scale") => {
+                options.scale = value()?.parse().map_err(|_| String::from("--scale has to be a number"))?;
+                if !(1..=8).contains(&options.scale) {
+                    return Err(String::from("--scale has to be from 1 to 8"));
+                }
+            }
+            ("run", "--fullscreen") => options.fullscreen = true,
+            ("run", "--region") => options.region = Some(Region::from_name(&value()?)?),
+            ("run", "--vsync") => options.vsync = true,
+            ("run", "--palette") => options.palette = Some(value()?),
+            ("run", "--ntsc-palette") => options.ntsc_palette = Some(NtscParameters::parse(&value()?)?),
+            ("run", "--ntsc-filter") => options.ntsc_filter = Some(NtscSetup::from_name(&value()?)?),
+            ("run", "--renderer") => {
+                options.renderer = match value()?.as_str() {
+                    "gl" => RendererKind::Gl,
+                    "software" => RendererKind::Software,
+                    other => return Err(format!("Unknown renderer {}, it has to be gl or software", other)),
+                };
+            }
+            ("run", "--filter") => options.scale_options.filter = Filter::from_name(&value()?)?,
+            ("run", "--integer-scale") => options.scale_options.integer = true,
+            ("run", "--square-pixels") => options.scale_options.aspect_correct = false,
+            ("run", "--overscan") => options.scale_options.overscan = Overscan::parse(&value()?)?,
+            ("run", "--shader") => options.shader = Some(value()?),
+            ("run", "--screenshot-kind") => options.screenshot_kind = ScreenshotKind::from_name(&value()?)?,
+            ("run", "--record-video") => options.record_video = Some(value()?),
+            ("run", "--record-audio") => options.record_audio = Some(value()?),
+            ("run", "--record-avi") => options.record_avi = Some(value()?),
+            ("run", "--viewer-scanline") => {
+                options.viewer_scanline = Some(value()?.parse().map_err(|_| String::from("--viewer-scanline has to be a number"))?);
+            }
+            ("run", "--trace") => options.trace = Some(value()?),
+            ("run", "--trace-format") => options.trace_format = Some(value()?),
+            ("run", "--paused") => options.paused = true,
+            ("run", "--load-state") => options.load_state = Some(value()?),
+            ("run", "--movie") => options.movie = Some(value()?),
+            ("run", "--movie-read-write") => options.movie_read_write = true,
+            ("run", "--record-movie") => options.record_movie = Some(value()?),
+            ("run", "--mute") => options.mute = true,
+            ("run", "--frame-limit") => {
+                options.frame_limit = Some(value()?.parse().map_err(|_| String::from("--frame-limit has to be a number"))?);
+            }
+            ("test", "--frames") => test_frames = value()?.parse().map_err(|_| String::from("--frames has to be a number"))?,
+            (_, "--help") | (_, "-h") => return Ok(Command::Help),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {} for {}", arg, command)),
+            _ if rom.is_none() => rom = Some(arg.clone()),
+            _ => return Err(format!("Unexpected argument {}", arg))
+        }
+    }
+
+    if options.movie.is_some() && options.record_movie.is_some() {
+        return Err(String::from("--movie and --record-movie can not be used together"));
+    }
+
+    if options.palette.is_some() && options.ntsc_palette.is_some() {
+        return Err(String::from("--palette and --ntsc-palette can not be used together"));
+    }
+    if options.palette.is_some() && options.ntsc_filter.is_some() {
+        return Err(String::from("--ntsc-filter makes its own colours, it can not be used with --palette"));
+    }
+
+    if options.shader.is_some() && matches!(options.renderer, RendererKind::Software) {
+        return Err(String::from("--shader needs the gl renderer"));
+    }
+
+    let rom = rom.ok_or("No ROM given")?;
+    Ok(match command {
+        "info" => Command::Info(rom),
+        "test" => Command::Test { rom, frames: test_frames },
+        _ => Command::Run(Box::new(RunOptions { rom, ..options }))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_line(line: &str) -> Result<Command, String> {
+        let args: Vec<String> = line.split_whitespace().map(String::from).collect();
+        parse(&args)
+    }
+
+    fn run_options(line: &str) -> RunOptions {
+        match parse_line(line) {
+            Ok(Command::Run(options)) => *options,
+            Ok(_) => panic!("'{}' is not a run command", line),
+            Err(e) => panic!("'{}': {}", line, e),
+        }
+    }
+
+    #[test]
+    fn subcommands_and_the_default_run() {
+        assert!(matches!(parse_line(""), Ok(Command::Help)));
+        assert!(matches!(parse_line("--help"), Ok(Command::Help)));
+        assert!(matches!(parse_line("run game.nes -h"), Ok(Command::Help)));
+        assert!(matches!(parse_line("info game.nes"), Ok(Command::Info(rom)) if rom == "game.nes"));
+        assert!(matches!(parse_line("test game.nes"), Ok(Command::Test { rom, frames: 3600 }) if rom == "game.nes"));
+        assert!(matches!(parse_line("headless game.nes --frames 10"), Ok(Command::Headless(args)) if args == ["game.nes", "--frames", "10"]));
+
+        assert_eq!(run_options("game.nes").rom, "game.nes");
+        let options = run_options("run game.nes --fullscreen --region PAL --paused --load-state game.state1 --mute");
+        assert_eq!(options.rom, "game.nes");
+        assert!(options.fullscreen && options.paused && options.mute);
+        assert_eq!(options.region, Some(Region::Pal));
+        assert_eq!(options.load_state.as_deref(), Some("game.state1"));
+        assert_eq!(options.scale, 2);
+    }
+
+    #[test]
+    fn numbers_are_parsed_and_checked() {
+        assert_eq!(run_options("game.nes --scale 3").scale, 3);
+        assert_eq!(run_options("game.nes --frame-limit 600").frame_limit, Some(600));
+        assert!(matches!(parse_line("test game.nes --frames 120"), Ok(Command::Test { frames: 120, .. })));
+
+        assert_eq!(parse_line("game.nes --scale 0").err(), Some(String::from("--scale has to be from 1 to 8")));
+        assert_eq!(parse_line("game.nes --scale 9").err(), Some(String::from("--scale has to be from 1 to 8")));
+        assert_eq!(parse_line("game.nes --scale big").err(), Some(String::from("--scale has to be a number")));
+        assert_eq!(parse_line("game.nes --frame-limit -1").err(), Some(String::from("--frame-limit has to be a number")));
+        assert_eq!(parse_line("test game.nes --frames 1.5").err(), Some(String::from("--frames has to be a number")));
+    }
+
+    #[test]
+    fn bad_flags_are_refused() {
+        assert_eq!(parse_line("game.nes --scale").err(), Some(String::from("--scale needs a value")));
+        assert_eq!(parse_line("game.nes --turbo").err(), Some(String::from("Unknown option --turbo for run")));
+        // Options only belong to their own subcommand
+        assert_eq!(parse_line("info game.nes --scale 2").err(), Some(String::from("Unknown option --scale for info")));
+        assert_eq!(parse_line("run game.nes other.nes").err(), Some(String::from("Unexpected argument other.nes")));
+        assert_eq!(parse_line("run --mute").err(), Some(String::from("No ROM given")));
+        assert_eq!(parse_line("game.nes --region secam").err(), Some(String::from("Unknown region secam, it has to be ntsc, pal or dendy")));
+        assert_eq!(parse_line("game.nes --movie a.fm2 --record-movie b.fm2").err(),
+                   Some(String::from("--movie and --record-movie can not be used together")));
+    }
+}
@@ -0,0 +1,160 @@
+use rustnes_core::cpu::CPU;
+use rustnes_core::debug_command::{self, Command};
+use rustnes_core::debugger::{BreakReason, Debugger, PauseResult};
+use rustnes_core::disassembler::{self, DisassembledInstruction};
+use rustnes_core::ram_controller::{Bus, RamController};
+use rustnes_core::symbols::SymbolTable;
+use rustnes_core::trace::{TraceEntry, TraceFormat};
+use rustnes_core::vram_controller::VRAMController;
+use std::io::{self, BufRead, Write};
+
+// The stdin debugger console, see rustnes_core::debug_command for the commands.
+// Blocks until the user resumes execution.
+pub fn pause(debugger: &mut Debugger, cpu: &CPU, vram: &VRAMController, symbols: &SymbolTable, reason: &BreakReason, scanline: i32, dot: i32) -> PauseResult {
+    println!("{}", describe(cpu.memory(), symbols, reason));
+    print_state(cpu, symbols, scanline, dot);
+
+    let stdin = io::stdin();
+    loop {
+        print!("(rustnes) ");
+        io::stdout().flush().unwrap();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            return PauseResult::Quit;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let command = match debug_command::parse(&line, cpu.memory(), symbols) {
+            Ok(command) => command,
+            Err(message) => {
+                println!("{}", message);
+                continue;
+            }
+        };
+
+        match command {
+            Command::Continue => debugger.resume(),
+            Command::StepInto => debugger.step_into(),
+            Command::StepOver => debugger.step_over(cpu),
+            Command::StepOut => debugger.step_out(),
+            Command::RunToScanline(target) => {
+                if let Err(message) = debugger.run_to_scanline(target) {
+                    println!("{}", message);
+                    continue;
+                }
+            }
+            Command::Quit => return PauseResult::Quit,
+            command => {
+                if let Err(message) = run(command, debugger, cpu, vram, symbols, scanline, dot) {
+                    println!("{}", message);
+                }
+                continue;
+            }
+        }
+
+        return PauseResult::Resume;
+    }
+}
+
+// The commands that leave the machine paused
+fn run(command: Command, debugger: &mut Debugger, cpu: &CPU, vram: &VRAMController, symbols: &SymbolTable, scanline: i32, dot: i32) -> Result<(), String> {
+    match command {
+        Command::Break { address, condition } => debugger.add_breakpoint(address, condition),
+        Command::Watch(watchpoint) => debugger.add_watchpoint(watchpoint),
+        Command::BreakOnInterrupt(interrupt, enabled) => debugger.set_break_on_interrupt(interrupt, enabled),
+        Command::List => list(debugger, cpu.memory(), symbols),
+        Command::DeleteBreakpoint(index) => return removed(debugger.remove_breakpoint(index), index),
+        Command::DeleteWatchpoint(index) => return removed(debugger.remove_watchpoint(index), index),
+        Command::Registers => print_state(cpu, symbols, scanline, dot),
+        Command::Disassemble { start, count } => disassemble(cpu, symbols, start.unwrap_or(cpu.registers.pc()), count),
+        Command::DisassembleRange(start, end) => {
+            print_instructions(cpu, symbols, disassembler::disassemble_range(cpu.memory(), symbols, start, end));
+        }
+        Command::Memory { bus, start, length } => dump_memory(cpu, vram, bus, start, length),
+        Command::Help => println!("{}", debug_command::HELP),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn removed(removed: bool, index: usize) -> Result<(), String> {
+    if removed { Ok(()) } else { Err(format!("No such entry {}", index)) }
+}
+
+fn describe(mem: &RamController, symbols: &SymbolTable, reason: &BreakReason) -> String {
+    match reason {
+        BreakReason::Requested => String::from("Break"),
+        BreakReason::Step => String::from("Step"),
+        BreakReason::Breakpoint(address) => format!("Breakpoint at {}", describe_address(mem, symbols, *address)),
+        BreakReason::Condition(condition) => format!("Condition '{}' hit", condition),
+        BreakReason::Watchpoint(access) => format!("Watchpoint: {:?} {:?} ${:04X} = {:02X}", access.bus, access.kind, access.address, access.value),
+        BreakReason::Scanline(scanline) => format!("Reached scanline {}", scanline),
+        BreakReason::Interrupt(interrupt) => format!("{:?} taken", interrupt),
+    }
+}
+
+fn print_state(cpu: &CPU, symbols: &SymbolTable, scanline: i32, dot: i32) {
+    if let Some(label) = symbols.label(cpu.memory(), cpu.registers.pc()) {
+        println!("{}:", label);
+    }
+    println!("{}", TraceFormat::Nestest.format(&TraceEntry::capture(cpu, symbols, scanline, dot, 0)));
+}
+
+fn describe_address(mem: &RamController, symbols: &SymbolTable, address: u16) -> String {
+    match symbols.label(mem, address) {
+        Some(label) => format!("${:04X} ({})", address, label),
+        None => format!("${:04X}", address)
+    }
+}
+
+fn list(debugger: &Debugger, mem: &RamController, symbols: &SymbolTable) {
+    for (i, breakpoint) in debugger.breakpoints().iter().enumerate() {
+        let address = breakpoint.address.map(|a| describe_address(mem, symbols, a)).unwrap_or_default();
+        let condition = breakpoint.condition.as_ref().map(|c| format!(" if {}", c)).unwrap_or_default();
+        println!("b {}: {}{}", i, address, condition);
+    }
+
+    for (i, watchpoint) in debugger.watchpoints().iter().enumerate() {
+        println!("w {}: {:?} {:?} ${:04X}-${:04X}", i, watchpoint.kind, watchpoint.bus, watchpoint.start, watchpoint.end);
+    }
+}
+
+fn disassemble(cpu: &CPU, symbols: &SymbolTable, mut address: u16, count: u16) {
+    let mut instructions = vec![];
+    for _ in 0..count {
+        let instruction = disassembler::disassemble(cpu.memory(), symbols, address);
+        address = address.wrapping_add(instruction.length());
+        instructions.push(instruction);
+    }
+    print_instructions(cpu, symbols, instructions);
+}
+
+fn print_instructions(cpu: &CPU, symbols: &SymbolTable, instructions: Vec<DisassembledInstruction>) {
+    for instruction in instructions {
+        if let Some(label) = symbols.label(cpu.memory(), instruction.address) {
+            println!("{}:", label);
+        }
+        let bytes: Vec<String> = instruction.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        println!("{:04X}  {:<9} {}", instruction.address, bytes.join(" "), instruction.text);
+    }
+}
+
+fn dump_memory(cpu: &CPU, vram: &VRAMController, bus: Bus, start: u16, length: u16) {
+    for row in (0..length).step_by(16) {
+        let address = start.wrapping_add(row);
+        let values: Vec<String> = (0..16.min(length - row))
+            .map(|i| {
+                let value = match bus {
+                    Bus::Cpu => cpu.memory().peek8(address.wrapping_add(i)),
+                    Bus::Ppu => vram.read8(address.wrapping_add(i) & 0x3FFF),
+                };
+                format!("{:02X}", value)
+            })
+            .collect();
+        println!("{:04X}  {}", address, values.join(" "));
+    }
+}
@@ -0,0 +1,532 @@
+use rustnes_core::cartridge::Cartridge;
+use rustnes_core::nes::Nes;
+use rustnes_core::apu::SAMPLE_RATE;
+use crate::cli::{RendererKind, RunOptions};
+use sdl2::audio::AudioSpecDesired;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{KeyboardState, Keycode, Scancode, LSHIFTMOD, RSHIFTMOD};
+use crate::debug_console;
+use crate::window;
+use crate::memory_viewer_window::MemoryViewerWindow;
+use crate::ppu_event_window::PpuEventWindow;
+use crate::ppu_viewer_window::PpuViewerWindow;
+use crate::renderer::Renderer;
+use crate::renderer_gl::GlRenderer;
+use crate::renderer_software::SoftwareRenderer;
+use rustnes_core::trace::{TraceEntry, TraceFormat, TraceLogger, TraceSink};
+use rustnes_core::debugger::{Debugger, Interrupt, PauseResult};
+use rustnes_core::gdb_stub::GdbStub;
+use rustnes_core::symbols::SymbolTable;
+use rustnes_core::rewind::RewindBuffer;
+use rustnes_core::movie::{self, FrameInput, Movie, MovieMode, COMMAND_POWER, COMMAND_SOFT_RESET};
+use rustnes_core::ntsc_filter::{self, NtscFilter};
+use rustnes_core::palette::Palette;
+use rustnes_core::recorder::Recorder;
+use rustnes_core::screenshot::{self, ScreenshotKind};
+use rustnes_core::{controller, savestate};
+use std::time::{Duration, Instant};
+
+pub fn run(options: &RunOptions) -> Result<(), String>
+{
+    let sdl = sdl2::init()?;
+    let rom_name = std::path::Path::new(&options.rom).file_stem().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let title = format!("rustnes - {}", rom_name);
+    let window_size = options.scale_options.window_size(options.scale);
+    let mut renderer: Box<dyn Renderer> = match options.renderer {
+        RendererKind::Gl => {
+            let window = window::Window::create(&sdl, &title, window_size, options.fullscreen)?;
+            Box::new(GlRenderer::new(window, options.shader.as_deref(), options.scale_options)?)
+        }
+        RendererKind::Software => {
+            let window = window::create_plain(&sdl, &title, window_size, options.fullscreen)?;
+            Box::new(SoftwareRenderer::new(window, options.scale_options))
+        }
+    };
+    let vsync = options.vsync && renderer.set_vsync(true);
+    if options.vsync && !vsync {
+        println!("Could not turn on vsync, pacing by a timer instead");
+    }
+
+    let palette = match (&options.palette, &options.ntsc_palette) {
+        (Some(path), _) => Palette::load(path)?,
+        (None, Some(parameters)) => Palette::generate(parameters),
+        (None, None) => Palette::new(),
+    };
+    let mut ntsc_filter = options.ntsc_filter.map(|mut setup| {
+        if let Some(parameters) = options.ntsc_palette {
+            setup.picture = parameters;
+        }
+        NtscFilter::new(setup)
+    });
+
+    let rom_path = options.rom.as_str();
+    let c = Cartridge::load(rom_path)?;
+
+    // Symbol files next to the ROM (.nl, .mlb, .dbg) give the debugger and traces labels
+    let symbols = SymbolTable::load_for_rom(rom_path, c.prg_rom_banks().len()).unwrap_or_else(|e| {
+        println!("Could not load symbols: {}", e);
+        SymbolTable::new()
+    });
+
+    let region = options.region.unwrap_or_else(|| c.region());
+    let mut nes = Nes::with_region(c, region)?;
+    if let Some(path) = &options.load_state {
+        nes.load_state_from_file(path)?;
+    }
+
+    let mut tracer = match &options.trace {
+        Some(path) => {
+            let format = match &options.trace_format {
+                Some(name) => TraceFormat::from_name(name).ok_or(format!("Unknown trace format {}", name))?,
+                None => TraceFormat::Nestest
+            };
+            Some(TraceLogger::create(path, format)?)
+        }
+        None => None
+    };
+
+    // Set RUSTNES_DEBUG to start in the debugger, F12 breaks into it at any time
+    let mut debugger = Debugger::new(region);
+    if std::env::var("RUSTNES_DEBUG").is_ok() {
+        debugger.request_break();
+    }
+
+    // Set RUSTNES_GDB to a port to wait for a gdb client on localhost before starting
+    let mut gdb = match std::env::var("RUSTNES_GDB").ok().map(|port| (port.parse::<u16>(), port)) {
+        Some((Ok(port), _)) => match GdbStub::listen(port) {
+            Ok(gdb) => {
+                debugger.request_break();
+                Some(gdb)
+            }
+            Err(e) => {
+                println!("Could not start the gdb stub on port {}, running without it: {}", port, e);
+                None
+            }
+        },
+        Some((Err(_), port)) => {
+            println!("RUSTNES_GDB has to be a port number, not {}, running without the gdb stub", port);
+            None
+        }
+        None => None
+    };
+
+    // Holding backspace rewinds. RUSTNES_REWIND_INTERVAL sets how many frames apart the snapshots
+    // are and RUSTNES_REWIND_BUDGET how many megabytes of history are kept.
+    let rewind_interval = env_number("RUSTNES_REWIND_INTERVAL", "a number of frames", 1u32);
+    let rewind_budget = env_number("RUSTNES_REWIND_BUDGET", "a number of megabytes", 32usize);
+    let mut rewind = RewindBuffer::new(region, rewind_interval, rewind_budget * 1024 * 1024);
+    let mut rewinding = false;
+    let mut rewound = false;
+    let mut reported_jam = None;
+
+    // A played back movie is read-only unless --movie-read-write is given, F9 toggles it.
+    // A movie that is not read-only is written back when the emulator exits. With --load-state
+    // the movie is recorded from that state, which goes into the movie to be loaded on playback.
+    let mut movie_path = None;
+    let mut movie = if let Some(path) = &options.movie {
+        let mut movie = Movie::load_fm2(path)?;
+        if movie.region != region {
+            println!("{} was recorded on {}, it may not play back right on {}", path, movie.region.name(), region.name());
+        }
+        if let Some(state) = &movie.savestate {
+            nes.load_state(state).map_err(|e| format!("{}: the save state the movie starts from: {}", path, e))?;
+        }
+        movie.play(nes.frame());
+        movie.set_read_only(!options.movie_read_write);
+        movie_path = Some(path.clone());
+        Some(movie)
+    } else if let Some(path) = &options.record_movie {
+        let rom_filename = std::path::Path::new(rom_path).file_stem().unwrap().to_string_lossy().to_string();
+        movie_path = Some(path.clone());
+        let state = options.load_state.as_ref().map(|_| nes.save_state());
+        Some(Movie::record(&rom_filename, &movie::rom_checksum(&nes.cartridge().rom_data()), region, state, nes.frame()))
+    } else {
+        None
+    };
+
+    let audio_queue = if options.mute {
+        None
+    } else {
+        let desired = AudioSpecDesired { freq: Some(SAMPLE_RATE as i32), channels: Some(1), samples: None };
+        let queue = sdl.audio()?.open_queue::<f32, _>(None, &desired)?;
+        queue.resume();
+        Some(queue)
+    };
+    let mut paused = options.paused;
+    let mut input_frame = None;
+
+    let mut event_pump = sdl.event_pump().unwrap();
+
+    let mut take_screenshot = false;
+    let viewer_scanline = options.viewer_scanline.unwrap_or(region.vblank_scanline());
+    let mut viewer: Option<PpuViewerWindow> = None;
+    let mut event_window: Option<PpuEventWindow> = None;
+    let mut memory_window: Option<MemoryViewerWindow> = None;
+
+    // The picture as the palette or the NTSC filter makes it, before it is cropped and scaled
+    let mut recorder = match (&options.record_video, &options.record_audio, &options.record_avi) {
+        (None, None, None) => None,
+        (video, audio, avi) => {
+            let width = if ntsc_filter.is_some() { ntsc_filter::OUTPUT_WIDTH as u32 } else { 256 };
+            Some(Recorder::create(video.as_deref(), audio.as_deref(), avi.as_deref(), width, 240, region)?)
+        }
+    };
+    let mut emulated_samples = None;
+
+    // Every PPU frame is presented once. Without vsync a timer keeps them the region's frame time apart.
+    let frame_time = Duration::from_secs_f64(1.0 / region.frame_rate());
+    let mut next_frame = Instant::now() + frame_time;
+
+    'running: loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Window { window_id, win_event: WindowEvent::Close, .. } if viewer.as_ref().is_some_and(|viewer| viewer.id() == window_id) => {
+                    viewer = None;
+                }
+                Event::KeyDown { window_id, keycode: Some(keycode), repeat: false, .. } if viewer.as_ref().is_some_and(|viewer| viewer.id() == window_id) => {
+                    match keycode {
+                        Keycode::Escape => viewer = None,
+                        _ => viewer.as_mut().unwrap().key(keycode, region.scanlines(), &palette, &rom_name),
+                    }
+                }
+                Event::Window { window_id, win_event: WindowEvent::Close, .. } if event_window.as_ref().is_some_and(|window| window.id() == window_id) => {
+                    event_window = None;
+                    nes.set_ppu_event_logging(false);
+                }
+                Event::KeyDown { window_id, keycode: Some(keycode), repeat: false, .. } if event_window.as_ref().is_some_and(|window| window.id() == window_id) => {
+                    match (keycode, nes.ppu_event_log()) {
+                        (Keycode::Escape, _) => {
+                            event_window = None;
+                            nes.set_ppu_event_logging(false);
+                        }
+                        (_, Some(log)) => event_window.as_mut().unwrap().key(keycode, log, &rom_name),
+                        (_, None) => {}
+                    }
+                }
+                Event::Window { window_id, win_event: WindowEvent::Close, .. } if memory_window.as_ref().is_some_and(|window| window.id() == window_id) => {
+                    memory_window = None;
+                }
+                // Including repeats, so the cursor keeps moving while an arrow key is held
+                Event::KeyDown { window_id, keycode: Some(keycode), .. } if memory_window.as_ref().is_some_and(|window| window.id() == window_id) => {
+                    let open = memory_window.as_mut().unwrap().key(keycode, &mut nes);
+                    if !open {
+                        memory_window = None;
+                    }
+                }
+                Event::Quit { .. } |
+                Event::Window { win_event: WindowEvent::Close, .. } |
+                Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+                    break 'running;
+                }
+                Event::KeyDown { keycode: Some(Keycode::Tab), repeat: false, .. } => {
+                    viewer = match viewer {
+                        Some(_) => None,
+                        None => match PpuViewerWindow::open(&sdl, viewer_scanline) {
+                            Ok(viewer) => Some(viewer),
+                            Err(e) => {
+                                println!("Could not open the PPU viewer: {}", e);
+                                None
+                            }
+                        }
+                    };
+                }
+                Event::KeyDown { keycode: Some(Keycode::Backquote), repeat: false, .. } => {
+                    event_window = match event_window {
+                        Some(_) => None,
+                        None => match PpuEventWindow::open(&sdl, region.scanlines()) {
+                            Ok(window) => Some(window),
+                            Err(e) => {
+                                println!("Could not open the PPU event viewer: {}", e);
+                                None
+                            }
+                        }
+                    };
+                    nes.set_ppu_event_logging(event_window.is_some());
+                }
+                Event::KeyDown { keycode: Some(Keycode::M), repeat: false, .. } => {
+                    memory_window = match memory_window {
+                        Some(_) => None,
+                        None => match MemoryViewerWindow::open(&sdl, &nes) {
+                            Ok(window) => Some(window),
+                            Err(e) => {
+                                println!("Could not open the memory viewer: {}", e);
+                                None
+                            }
+                        }
+                    };
+                }
+                Event::KeyDown { keycode: Some(Keycode::P), repeat: false, .. } => {
+                    paused = !paused;
+                }
+                Event::KeyDown { keycode: Some(Keycode::F10), repeat: false, .. } => {
+                    take_screenshot = true;
+                }
+                Event::KeyDown { keycode: Some(Keycode::F11), repeat: false, .. } => {
+                    if let Err(e) = renderer.toggle_fullscreen() {
+                        println!("Could not toggle fullscreen: {}", e);
+                    }
+                }
+                Event::KeyDown { keycode: Some(Keycode::F12), .. } => {
+                    debugger.request_break();
+                }
+                Event::KeyDown { keycode: Some(Keycode::F9), .. } => {
+                    if let Some(movie) = movie.as_mut() {
+                        movie.set_read_only(!movie.is_read_only());
+                        println!("Movie is {}", if movie.is_read_only() { "read-only" } else { "read-write" });
+                    }
+                }
+                Event::KeyDown { keycode: Some(Keycode::Backspace), .. } => {
+                    rewinding = true;
+                }
+                Event::KeyUp { keycode: Some(Keycode::Backspace), .. } => {
+                    rewinding = false;
+                    rewind.stop_rewinding();
+                    // The movie picks up from wherever the rewind stopped, as one re-record
+                    if rewound {
+                        rewound = false;
+                        if let Some(movie) = movie.as_mut() {
+                            movie.state_loaded(nes.frame());
+                        }
+                    }
+                }
+                // F1-F8 loads a quick save slot, holding shift saves to it instead
+                Event::KeyDown { keycode: Some(keycode), keymod, repeat: false, .. } if quick_save_slot(keycode).is_some() => {
+                    let path = savestate::slot_path(rom_path, quick_save_slot(keycode).unwrap());
+                    if keymod.intersects(LSHIFTMOD | RSHIFTMOD) {
+                        match nes.save_state_to_file(&path) {
+                            Ok(()) => println!("Saved state to {}", path),
+                            Err(e) => println!("Could not save state: {}", e)
+                        }
+                    } else {
+                        match nes.load_state_from_file(&path) {
+                            Ok(()) => {
+                                input_frame = Some(nes.frame());
+                                if let Some(movie) = movie.as_mut() {
+                                    movie.state_loaded(nes.frame());
+                                }
+                                println!("Loaded state from {}", path);
+                            }
+                            Err(e) => println!("Could not load state: {}", e)
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if rewinding {
+            if let Some(state) = rewind.step_back() {
+                nes.load_state(&state).unwrap();
+                input_frame = Some(nes.frame());
+                rewound = true;
+            }
+        } else if !paused {
+            // Input is latched once per frame, from the movie or from the keyboard
+            if input_frame != Some(nes.frame()) {
+                input_frame = Some(nes.frame());
+
+                // The memory viewer's arrow keys are not for the game
+                let typing = memory_window.as_ref().is_some_and(|window| sdl.keyboard().focused_window_id() == Some(window.id()));
+                let live = [if typing { 0 } else { keyboard_buttons(&event_pump.keyboard_state()) }, 0];
+                let input = match movie.as_mut() {
+                    Some(movie) => {
+                        let was_playing = movie.mode() == MovieMode::Playing;
+                        let input = movie.input(nes.frame(), live);
+                        if was_playing && movie.mode() == MovieMode::Finished {
+                            println!("Movie finished");
+                        }
+                        input
+                    }
+                    None => FrameInput { commands: 0, ports: live }
+                };
+
+                if input.commands & COMMAND_POWER != 0 {
+                    nes.power_cycle();
+                } else if input.commands & COMMAND_SOFT_RESET != 0 {
+                    nes.reset();
+                }
+                nes.set_input(0, input.ports[0]);
+                nes.set_input(1, input.ports[1]);
+            }
+
+            nes.cpu().memory().set_access_logging(debugger.wants_accesses() || memory_window.is_some());
+            let frame = nes.frame();
+            while nes.frame() == frame {
+                let accesses = nes.cpu().memory().take_accesses();
+                if let Some(reason) = debugger.check(nes.cpu(), nes.ppu().scanline(), &accesses) {
+                    let (scanline, dot) = (nes.ppu().scanline(), nes.ppu().pixel());
+                    let result = match gdb.as_mut() {
+                        Some(gdb) => gdb.pause(&mut debugger, nes.cpu_mut(), &reason),
+                        None => debug_console::pause(&mut debugger, nes.cpu(), &nes.ppu().vram().borrow(), &symbols, &reason, scanline, dot)
+                    };
+                    match result {
+                        PauseResult::Quit => break 'running,
+                        PauseResult::Detach => gdb = None,
+                        PauseResult::Resume => {}
+                    }
+
+                    nes.cpu().memory().set_access_logging(debugger.wants_accesses() || memory_window.is_some());
+                }
+                if let Some(window) = memory_window.as_mut() {
+                    window.watch(&mut nes, &accesses);
+                }
+
+                if let Some(tracer) = tracer.as_mut() {
+                    tracer.trace(&TraceEntry::capture(nes.cpu(), &symbols, nes.ppu().scanline(), nes.ppu().pixel(), nes.cycles()));
+                }
+
+                let result = nes.step();
+
+                if let Some(viewer) = viewer.as_mut() {
+                    viewer.watch(nes.ppu());
+                }
+
+                if result.nmi {
+                    debugger.interrupt(Interrupt::Nmi);
+                }
+                if result.irq {
+                    debugger.interrupt(Interrupt::Irq);
+                }
+            }
+
+            // The CPU stays jammed until a reset or a loaded state, so it is only reported once
+            if nes.cpu().jam() != reported_jam {
+                reported_jam = nes.cpu().jam();
+                if let Some(jam) = reported_jam {
+                    println!("The CPU jammed on opcode ${:02X} at ${:04X}", jam.opcode, jam.address);
+                }
+            }
+
+            // The audio clock and the frame pacing drift apart slowly, so anything beyond a tenth
+            // of a second of queued audio is dropped rather than let the sound lag behind
+            let samples = nes.audio_samples();
+            if let Some(queue) = audio_queue.as_ref() {
+                if (queue.size() as usize) < SAMPLE_RATE as usize / 10 * std::mem::size_of::<f32>() {
+                    queue.queue(&samples);
+                }
+            }
+
+            rewind.frame(|| nes.save_state());
+            emulated_samples = Some(samples);
+        }
+
+        let (picture, picture_width) = match ntsc_filter.as_mut() {
+            Some(filter) => (filter.apply(nes.framebuffer(), nes.emphasis(), nes.frame()), ntsc_filter::OUTPUT_WIDTH as u32),
+            None => (palette.frame_to_rgb(nes.framebuffer(), nes.emphasis()), 256),
+        };
+
+        // Every emulated frame once, the same frame shown again while paused or rewinding is not
+        if let Some(samples) = emulated_samples.take() {
+            if let Some(Err(e)) = recorder.as_mut().map(|recorder| recorder.frame(&picture, &samples)) {
+                println!("Recording stopped: {}", e);
+                recorder.take().unwrap().finish()?;
+            }
+
+            if options.frame_limit.is_some_and(|limit| nes.frame() >= limit) {
+                break 'running;
+            }
+        }
+
+        let (picture, (picture_width, picture_height)) = options.scale_options.overscan.crop(&picture, (picture_width, 240));
+        renderer.present(&picture, picture_width, picture_height, nes.frame())?;
+        if let Some(viewer) = viewer.as_mut() {
+            viewer.show(&palette)?;
+        }
+        if let (Some(window), Some(log)) = (event_window.as_mut(), nes.ppu_event_log()) {
+            window.show(log)?;
+        }
+        if let Some(window) = memory_window.as_mut() {
+            window.show(&nes)?;
+        }
+
+        if take_screenshot {
+            take_screenshot = false;
+            let png = match options.screenshot_kind {
+                ScreenshotKind::Raw => Ok(screenshot::raw_png(nes.framebuffer(), &rom_name)),
+                ScreenshotKind::Palette => Ok(screenshot::rgb_png(&palette.frame_to_rgb(nes.framebuffer(), nes.emphasis()), 256, 240, &rom_name)),
+                ScreenshotKind::Display => renderer.capture().map(|(rgb, width, height)| screenshot::rgb_png(&rgb, width, height, &rom_name)),
+            };
+            match png.and_then(|png| screenshot::save(std::path::Path::new(screenshot::DIRECTORY), &rom_name, &png)) {
+                Ok(path) => println!("Saved a screenshot to {}", path.display()),
+                Err(e) => println!("Could not save a screenshot: {}", e),
+            }
+        }
+        if !vsync {
+            wait_for_frame(&mut next_frame, frame_time);
+        }
+
+        if let Some(gdb) = gdb.as_mut() {
+            gdb.poll_interrupt(&mut debugger);
+        }
+    }
+
+    if let Some(recorder) = recorder {
+        recorder.finish()?;
+    }
+
+    if let (Some(movie), Some(path)) = (movie, movie_path) {
+        if !movie.is_read_only() {
+            match movie.save_fm2(&path) {
+                Ok(()) => println!("Saved movie to {}", path),
+                Err(e) => println!("Could not save movie: {}", e)
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Sleeps until the next frame is due. After falling behind, by a breakpoint for instance, the
+// pacing starts over from now instead of running fast to catch up.
+fn wait_for_frame(next_frame: &mut Instant, frame_time: Duration) {
+    let now = Instant::now();
+    if *next_frame > now {
+        std::thread::sleep(*next_frame - now);
+    } else if now - *next_frame > frame_time {
+        *next_frame = now;
+    }
+    *next_frame += frame_time;
+}
+
+// Arrow keys for the d-pad, X and Z for A and B, Enter for start and right shift for select
+fn keyboard_buttons(keyboard: &KeyboardState) -> u8 {
+    let mapping = [
+        (Scancode::X, controller::BUTTON_A),
+        (Scancode::Z, controller::BUTTON_B),
+        (Scancode::RShift, controller::BUTTON_SELECT),
+        (Scancode::Return, controller::BUTTON_START),
+        (Scancode::Up, controller::BUTTON_UP),
+        (Scancode::Down, controller::BUTTON_DOWN),
+        (Scancode::Left, controller::BUTTON_LEFT),
+        (Scancode::Right, controller::BUTTON_RIGHT),
+    ];
+
+    mapping.iter()
+        .filter(|(scancode, _)| keyboard.is_scancode_pressed(*scancode))
+        .fold(0, |buttons, (_, button)| buttons | button)
+}
+
+// A number from the environment, or the default with a warning if it is set to something else
+fn env_number<T: std::str::FromStr + std::fmt::Display>(name: &str, what: &str, default: T) -> T {
+    match std::env::var(name) {
+        Ok(value) => value.parse().unwrap_or_else(|_| {
+            println!("{} has to be {}, not {}, using {}", name, what, value, default);
+            default
+        }),
+        Err(_) => default
+    }
+}
+
+fn quick_save_slot(keycode: Keycode) -> Option<u32> {
+    match keycode {
+        Keycode::F1 => Some(1),
+        Keycode::F2 => Some(2),
+        Keycode::F3 => Some(3),
+        Keycode::F4 => Some(4),
+        Keycode::F5 => Some(5),
+        Keycode::F6 => Some(6),
+        Keycode::F7 => Some(7),
+        Keycode::F8 => Some(8),
+        _ => None
+    }
+}
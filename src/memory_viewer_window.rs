@@ -0,0 +1,132 @@
+use rustnes_core::memory_viewer::{self, MemoryViewer, BYTES_PER_ROW, ROWS};
+use rustnes_core::ram_controller::MemoryAccess;
+use rustnes_core::Nes;
+use sdl2::keyboard::Keycode;
+use crate::window;
+
+// What is being typed in the title bar
+enum Prompt {
+    Find(String),
+    Goto(String),
+}
+
+// A second window with a hex view of one memory, see rustnes_core::memory_viewer. In it the arrow
+// keys and Page Up/Down move the cursor, [ and ] switch memories, hex digits overwrite the byte
+// at the cursor, Enter freezes or lets go of it, / searches for a byte sequence, N finds the next
+// one, G goes to an address and Escape closes it. Closing it lets go of the frozen bytes.
+pub struct MemoryViewerWindow {
+    window: sdl2::video::Window,
+    viewer: MemoryViewer,
+    prompt: Option<Prompt>,
+    message: Option<String>,
+}
+
+impl MemoryViewerWindow {
+    pub fn open(sdl: &sdl2::Sdl, nes: &Nes) -> Result<MemoryViewerWindow, String> {
+        let window = window::create_plain(sdl, "Memory", (700, 592), false)?;
+        Ok(MemoryViewerWindow { window, viewer: MemoryViewer::new(nes), prompt: None, message: None })
+    }
+
+    pub fn id(&self) -> u32 {
+        self.window.id()
+    }
+
+    // Returns false once Escape closes the window
+    pub fn key(&mut self, keycode: Keycode, nes: &mut Nes) -> bool {
+        self.message = None;
+        if let Some(prompt) = self.prompt.take() {
+            self.prompt_key(prompt, keycode, nes);
+            return true;
+        }
+
+        let page = (ROWS * BYTES_PER_ROW) as isize;
+        let mut result = Ok(());
+        match keycode {
+            Keycode::Escape => return false,
+            Keycode::Left => self.viewer.move_cursor(nes, -1),
+            Keycode::Right => self.viewer.move_cursor(nes, 1),
+            Keycode::Up => self.viewer.move_cursor(nes, -(BYTES_PER_ROW as isize)),
+            Keycode::Down => self.viewer.move_cursor(nes, BYTES_PER_ROW as isize),
+            Keycode::PageUp => self.viewer.move_cursor(nes, -page),
+            Keycode::PageDown => self.viewer.move_cursor(nes, page),
+            Keycode::LeftBracket => self.viewer.cycle_space(nes, -1),
+            Keycode::RightBracket => self.viewer.cycle_space(nes, 1),
+            Keycode::Return => result = self.viewer.toggle_freeze(nes),
+            Keycode::Slash => self.prompt = Some(Prompt::Find(String::new())),
+            Keycode::G => self.prompt = Some(Prompt::Goto(String::new())),
+            Keycode::N => result = self.viewer.find_next(nes).map(|_| ()),
+            _ => {
+                if let Some(digit) = hex_digit(keycode) {
+                    result = self.viewer.type_digit(nes, digit);
+                }
+            }
+        }
+        if let Err(e) = result {
+            self.message = Some(e);
+        }
+        true
+    }
+
+    fn prompt_key(&mut self, prompt: Prompt, keycode: Keycode, nes: &mut Nes) {
+        let (mut text, find) = match prompt {
+            Prompt::Find(text) => (text, true),
+            Prompt::Goto(text) => (text, false),
+        };
+        match keycode {
+            Keycode::Escape => return,
+            Keycode::Return => {
+                let result = if find {
+                    memory_viewer::parse_pattern(&text).and_then(|pattern| self.viewer.find(nes, pattern).map(|_| ()))
+                } else {
+                    usize::from_str_radix(&text, 16)
+                        .map_err(|_| format!("{} is not a hex address", text))
+                        .and_then(|address| self.viewer.goto(nes, address))
+                };
+                if let Err(e) = result {
+                    self.message = Some(e);
+                }
+                return;
+            }
+            Keycode::Backspace => {
+                text.pop();
+            }
+            Keycode::Space if find => text.push(' '),
+            _ => {
+                if let Some(digit) = hex_digit(keycode) {
+                    text.push(char::from_digit(digit as u32, 16).unwrap().to_ascii_uppercase());
+                }
+            }
+        }
+        self.prompt = Some(if find { Prompt::Find(text) } else { Prompt::Goto(text) });
+    }
+
+    // Called after every instruction with the accesses it made
+    pub fn watch(&mut self, nes: &mut Nes, accesses: &[MemoryAccess]) {
+        self.viewer.watch(nes, accesses);
+        self.viewer.apply_freezes(nes);
+    }
+
+    pub fn show(&mut self, nes: &Nes) -> Result<(), String> {
+        self.viewer.cool();
+        let title = match (&self.prompt, &self.message) {
+            (Some(Prompt::Find(text)), _) => format!("Memory - find bytes: {}_", text),
+            (Some(Prompt::Goto(text)), _) => format!("Memory - go to: {}_", text),
+            (None, Some(message)) => format!("Memory - {}", message),
+            (None, None) => format!("Memory - {}", self.viewer.status(nes)),
+        };
+        self.window.set_title(&title).map_err(|e| e.to_string())?;
+
+        let mut image = self.viewer.render(nes);
+        window::blit_to_surface(&self.window, &mut image.rgb, (image.width, image.height))
+    }
+}
+
+fn hex_digit(keycode: Keycode) -> Option<u8> {
+    let code = keycode as i32;
+    match keycode {
+        Keycode::Num0 | Keycode::Num1 | Keycode::Num2 | Keycode::Num3 | Keycode::Num4 |
+        Keycode::Num5 | Keycode::Num6 | Keycode::Num7 | Keycode::Num8 | Keycode::Num9 => Some((code - Keycode::Num0 as i32) as u8),
+        Keycode::A | Keycode::B | Keycode::C | Keycode::D | Keycode::E | Keycode::F => Some((code - Keycode::A as i32) as u8 + 10),
+        _ => None,
+    }
+}
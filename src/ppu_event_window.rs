@@ -0,0 +1,49 @@
+use rustnes_core::ppu_events::PpuEventLog;
+use rustnes_core::screenshot;
+use sdl2::keyboard::Keycode;
+use std::path::Path;
+use crate::window;
+
+// A second window plotting the last frame's PPU events on a grid of 341 dots by the region's
+// scanlines, see rustnes_core::ppu_events. In it E saves the plot as a PNG and prints every
+// event, and Escape closes it.
+pub struct PpuEventWindow {
+    window: sdl2::video::Window,
+    scanlines: i32,
+}
+
+impl PpuEventWindow {
+    pub fn open(sdl: &sdl2::Sdl, scanlines: i32) -> Result<PpuEventWindow, String> {
+        let window = window::create_plain(sdl, "PPU events", (341 * 3, scanlines as u32 * 3), false)?;
+        Ok(PpuEventWindow { window, scanlines })
+    }
+
+    pub fn id(&self) -> u32 {
+        self.window.id()
+    }
+
+    pub fn key(&mut self, keycode: Keycode, log: &PpuEventLog, rom_name: &str) {
+        if keycode == Keycode::E {
+            if let Err(e) = self.export(log, rom_name) {
+                println!("Could not save the PPU events: {}", e);
+            }
+        }
+    }
+
+    fn export(&self, log: &PpuEventLog, rom_name: &str) -> Result<(), String> {
+        let name = format!("{}-events", rom_name);
+        let path = screenshot::save(Path::new(screenshot::DIRECTORY), &name, &log.plot(self.scanlines).png(rom_name))?;
+        println!("Saved {}", path.display());
+
+        println!("{} PPU events:", log.events().len());
+        for event in log.events() {
+            println!("{}", event.describe());
+        }
+        Ok(())
+    }
+
+    pub fn show(&mut self, log: &PpuEventLog) -> Result<(), String> {
+        let mut image = log.plot(self.scanlines);
+        window::blit_to_surface(&self.window, &mut image.rgb, (image.width, image.height))
+    }
+}
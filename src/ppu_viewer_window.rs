@@ -0,0 +1,91 @@
+use rustnes_core::palette::Palette;
+use rustnes_core::ppu::PPU;
+use rustnes_core::ppu_viewer::PpuSnapshot;
+use rustnes_core::screenshot;
+use sdl2::keyboard::Keycode;
+use std::path::Path;
+use crate::window;
+
+// A second window with the nametables, pattern tables, palette RAM and sprites, see
+// rustnes_core::ppu_viewer. In it 0-7 picks the palette the pattern tables are drawn with,
+// - and = move the snapshot scanline, E saves every view as a PNG and prints the sprite list,
+// and Escape closes it.
+pub struct PpuViewerWindow {
+    window: sdl2::video::Window,
+    scanline: i32,
+    pattern_palette: u8,
+    snapshot: Option<PpuSnapshot>,
+}
+
+impl PpuViewerWindow {
+    pub fn open(sdl: &sdl2::Sdl, scanline: i32) -> Result<PpuViewerWindow, String> {
+        let window = window::create_plain(sdl, "PPU viewer", (776, 480), false)?;
+        let mut viewer = PpuViewerWindow { window, scanline, pattern_palette: 0, snapshot: None };
+        viewer.update_title();
+        Ok(viewer)
+    }
+
+    pub fn id(&self) -> u32 {
+        self.window.id()
+    }
+
+    fn update_title(&mut self) {
+        let title = format!("PPU viewer - scanline {}, pattern palette {}", self.scanline, self.pattern_palette);
+        self.window.set_title(&title).unwrap();
+    }
+
+    // Called as the PPU runs, takes a snapshot once a frame when it gets to the scanline
+    pub fn watch(&mut self, ppu: &PPU) {
+        let taken = self.snapshot.as_ref().is_some_and(|snapshot| snapshot.frame == ppu.frame());
+        if ppu.scanline() == self.scanline && !taken {
+            self.snapshot = Some(PpuSnapshot::capture(ppu));
+        }
+    }
+
+    pub fn key(&mut self, keycode: Keycode, scanlines: i32, colors: &Palette, rom_name: &str) {
+        match keycode {
+            Keycode::Num0 | Keycode::Num1 | Keycode::Num2 | Keycode::Num3 |
+            Keycode::Num4 | Keycode::Num5 | Keycode::Num6 | Keycode::Num7 => {
+                self.pattern_palette = (keycode as i32 - Keycode::Num0 as i32) as u8;
+            }
+            Keycode::Minus => self.scanline = (self.scanline - 1).rem_euclid(scanlines),
+            Keycode::Equals => self.scanline = (self.scanline + 1) % scanlines,
+            Keycode::E => {
+                if let Err(e) = self.export(colors, rom_name) {
+                    println!("Could not save the PPU views: {}", e);
+                }
+            }
+            _ => {}
+        }
+        self.update_title();
+    }
+
+    fn export(&self, colors: &Palette, rom_name: &str) -> Result<(), String> {
+        let snapshot = self.snapshot.as_ref().ok_or("The PPU has not reached the scanline yet")?;
+        let views = [
+            ("nametables", snapshot.nametables(colors)),
+            ("patterns", snapshot.pattern_tables(colors, self.pattern_palette)),
+            ("palette", snapshot.palette_ram(colors)),
+            ("sprites", snapshot.sprite_previews(colors)),
+        ];
+        for (name, image) in views {
+            let name = format!("{}-{}", rom_name, name);
+            let path = screenshot::save(Path::new(screenshot::DIRECTORY), &name, &image.png(rom_name))?;
+            println!("Saved {}", path.display());
+        }
+
+        println!("Sprites at scanline {} of frame {}:", snapshot.scanline, snapshot.frame);
+        for sprite in snapshot.sprites() {
+            println!("{}", sprite.describe());
+        }
+        Ok(())
+    }
+
+    pub fn show(&mut self, colors: &Palette) -> Result<(), String> {
+        if let Some(snapshot) = self.snapshot.as_ref() {
+            let mut image = snapshot.overview(colors, self.pattern_palette);
+            window::blit_to_surface(&self.window, &mut image.rgb, (image.width, image.height))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,16 @@
+// What the frontend draws frames with. The GL renderer runs the picture through the shader chain,
+// the software one scales it on the CPU and needs no GPU at all.
+
+pub trait Renderer {
+    // Shows a `width` x `height` RGB picture, centred in the window at the shape the scale options give
+    fn present(&mut self, rgb: &[u8], width: u32, height: u32, frame_count: u64) -> Result<(), String>;
+
+    // Whether present() now waits for the display's vertical blank. False when it can not.
+    fn set_vsync(&mut self, vsync: bool) -> bool;
+
+    // Between the window and borderless fullscreen
+    fn toggle_fullscreen(&mut self) -> Result<(), String>;
+
+    // The last presented picture as it is shown, without the black around it, as (rgb, width, height)
+    fn capture(&mut self) -> Result<(Vec<u8>, u32, u32), String>;
+}
@@ -1,6 +1,11 @@
 use gl;
 use std;
 use std::ffi::{CString, CStr};
+use crate::renderer::Renderer;
+use rustnes_core::scaler::{Filter, ScaleOptions, Viewport};
+use crate::shader_preset::{self, PassPreset};
+use crate::texture::Texture;
+use crate::window::Window;
 
 pub struct Program {
     id: gl::types::GLuint,
@@ -14,6 +19,12 @@ impl Program {
             unsafe { gl::AttachShader(program_id, shader.id()); }
         }
 
+        // Where the quad's attributes are, for shaders that do not say with layout qualifiers
+        for (location, name) in [(0, "Position"), (0, "VertexCoord"), (1, "Color"), (2, "TexCoord")] {
+            let name = CString::new(name).unwrap();
+            unsafe { gl::BindAttribLocation(program_id, location, name.as_ptr()); }
+        }
+
         unsafe { gl::LinkProgram(program_id); }
 
         let mut success: gl::types::GLint = 1;
@@ -57,6 +68,29 @@ impl Program {
             gl::UseProgram(self.id());
         }
     }
+
+    // Setting a uniform the program does not have does nothing, so shaders only declare what they use.
+    // The program has to be in use.
+    fn uniform_location(&self, name: &str) -> gl::types::GLint {
+        let name = CString::new(name).unwrap();
+        unsafe { gl::GetUniformLocation(self.id, name.as_ptr()) }
+    }
+
+    pub fn set_uniform_int(&self, name: &str, value: i32) {
+        unsafe { gl::Uniform1i(self.uniform_location(name), value); }
+    }
+
+    pub fn set_uniform_vec2(&self, name: &str, value: [f32; 2]) {
+        unsafe { gl::Uniform2f(self.uniform_location(name), value[0], value[1]); }
+    }
+
+    pub fn set_uniform_vec4(&self, name: &str, value: [f32; 4]) {
+        unsafe { gl::Uniform4f(self.uniform_location(name), value[0], value[1], value[2], value[3]); }
+    }
+
+    pub fn set_uniform_mat4(&self, name: &str, value: &[f32; 16]) {
+        unsafe { gl::UniformMatrix4fv(self.uniform_location(name), 1, gl::FALSE, value.as_ptr()); }
+    }
 }
 
 impl Drop for Program {
@@ -141,4 +175,329 @@ fn create_whitespace_cstring_with_len(len: usize) -> CString {
     buffer.extend([b' '].iter().cycle().take(len));
     // convert buffer to CString
     unsafe { CString::from_vec_unchecked(buffer) }
-}
\ No newline at end of file
+}
+
+// A texture to render into, for the passes of a ShaderChain before the last one
+pub struct Framebuffer {
+    id: gl::types::GLuint,
+    texture: gl::types::GLuint,
+    width: u32,
+    height: u32,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Result<Framebuffer, String> {
+        let mut framebuffer = Framebuffer { id: 0, texture: 0, width, height };
+        unsafe {
+            gl::GenTextures(1, &mut framebuffer.texture);
+            gl::BindTexture(gl::TEXTURE_2D, framebuffer.texture);
+            gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA8 as i32, width as i32, height as i32, 0, gl::RGBA, gl::UNSIGNED_BYTE, std::ptr::null());
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+
+            gl::GenFramebuffers(1, &mut framebuffer.id);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer.id);
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, framebuffer.texture, 0);
+            let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+
+            if status != gl::FRAMEBUFFER_COMPLETE {
+                return Err(format!("Could not create a {}x{} framebuffer ({:X})", width, height, status));
+            }
+        }
+        Ok(framebuffer)
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.id);
+            gl::DeleteTextures(1, &self.texture);
+        }
+    }
+}
+
+// For passes that are only a fragment shader. They get the texture coordinate as vTexCoord.
+const PASS_VERTEX_SHADER: &str = "\
+#version 330 core
+layout (location = 0) in vec3 Position;
+layout (location = 2) in vec2 TexCoord;
+out vec2 vTexCoord;
+void main()
+{
+    gl_Position = vec4(Position, 1.0);
+    vTexCoord = TexCoord;
+}
+";
+
+const IDENTITY: [f32; 16] = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0];
+
+struct Pass {
+    program: Program,
+    preset: PassPreset,
+    // Where the pass draws, None for the last one which draws to the window
+    target: Option<Framebuffer>,
+}
+
+// Post-processing passes, each drawing the quad with the previous pass's output as its input.
+// Every pass gets these uniforms, when it declares them:
+//   sampler2D Source (or Texture)    the input, on unit 0
+//   vec4 SourceSize, OutputSize      width, height, 1 / width, 1 / height
+//   vec2 InputSize, TextureSize      the input size again, for RetroArch shaders
+//   vec2 OrigInputSize               the size of the emulator's picture, before any pass
+//   int FrameCount                   frames since power on
+//   mat4 MVPMatrix                   the identity, the quad is already in clip space
+pub struct ShaderChain {
+    passes: Vec<Pass>,
+}
+
+impl ShaderChain {
+    // A chain of one program, like the built-in one that just shows the picture
+    pub fn from_program(program: Program, filter_linear: bool) -> ShaderChain {
+        let preset = PassPreset { filter_linear, ..PassPreset::single(std::path::Path::new("built-in")) };
+        ShaderChain { passes: vec![Pass { program, preset, target: None }] }
+    }
+
+    // A .glslp preset, or a single .glsl shader
+    pub fn load(path: &str) -> Result<ShaderChain, String> {
+        let mut passes = vec![];
+        for preset in shader_preset::load(path)? {
+            let source = std::fs::read_to_string(&preset.shader).map_err(|e| format!("{}: {}", preset.shader.display(), e))?;
+            let program = compile_pass(&source).map_err(|e| format!("{}: {}", preset.shader.display(), e))?;
+            passes.push(Pass { program, preset, target: None });
+        }
+        if passes.is_empty() {
+            return Err(format!("{} has no shaders", path));
+        }
+        Ok(ShaderChain { passes })
+    }
+
+    // Runs every pass over `source`, drawing `vao` (the full screen quad) each time, and ends up in
+    // `viewport` of a `window` sized drawable. Viewport scaled passes are a multiple of the viewport.
+    pub fn render(&mut self, source: gl::types::GLuint, source_size: (u32, u32), window: (u32, u32), viewport: Viewport, vao: gl::types::GLuint, frame_count: u64) -> Result<(), String> {
+        let (mut input, mut input_size) = (source, source_size);
+        let last = self.passes.len() - 1;
+        let viewport_size = (viewport.width, viewport.height);
+
+        for (index, pass) in self.passes.iter_mut().enumerate() {
+            let output_size = if index == last { viewport_size } else { pass.preset.output_size(input_size, viewport_size) };
+            if index != last && pass.target.as_ref().map(|target| target.size()) != Some(output_size) {
+                pass.target = Some(Framebuffer::new(output_size.0, output_size.1)?);
+            }
+
+            let filter = if pass.preset.filter_linear { gl::LINEAR } else { gl::NEAREST } as i32;
+            unsafe {
+                gl::BindFramebuffer(gl::FRAMEBUFFER, pass.target.as_ref().map_or(0, |target| target.id));
+                if index == last {
+                    // Black around the picture. GL counts y from the bottom.
+                    gl::Viewport(0, 0, window.0 as i32, window.1 as i32);
+                    gl::Clear(gl::COLOR_BUFFER_BIT);
+                    let bottom = window.1 as i32 - viewport.y as i32 - viewport.height as i32;
+                    gl::Viewport(viewport.x as i32, bottom, viewport.width as i32, viewport.height as i32);
+                } else {
+                    gl::Viewport(0, 0, output_size.0 as i32, output_size.1 as i32);
+                }
+
+                gl::ActiveTexture(gl::TEXTURE0);
+                gl::BindTexture(gl::TEXTURE_2D, input);
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter);
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter);
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            }
+
+            let size = |(width, height): (u32, u32)| [width as f32, height as f32, 1.0 / width as f32, 1.0 / height as f32];
+            pass.program.set_used();
+            pass.program.set_uniform_int("Source", 0);
+            pass.program.set_uniform_int("Texture", 0);
+            pass.program.set_uniform_vec4("SourceSize", size(input_size));
+            pass.program.set_uniform_vec4("OutputSize", size(output_size));
+            pass.program.set_uniform_vec2("InputSize", [input_size.0 as f32, input_size.1 as f32]);
+            pass.program.set_uniform_vec2("TextureSize", [input_size.0 as f32, input_size.1 as f32]);
+            pass.program.set_uniform_vec2("OrigInputSize", [source_size.0 as f32, source_size.1 as f32]);
+            pass.program.set_uniform_int("FrameCount", frame_count as i32);
+            pass.program.set_uniform_mat4("MVPMatrix", &IDENTITY);
+
+            unsafe {
+                gl::BindVertexArray(vao);
+                gl::DrawArrays(gl::TRIANGLES, 0, 6);
+                gl::BindVertexArray(0);
+            }
+
+            if let Some(target) = pass.target.as_ref() {
+                input = target.texture;
+                input_size = output_size;
+            }
+        }
+
+        unsafe { gl::BindFramebuffer(gl::FRAMEBUFFER, 0); }
+        Ok(())
+    }
+}
+
+// A shader with both stages in one file, between #if defined(VERTEX) and #elif defined(FRAGMENT) as
+// RetroArch has them, or only a fragment shader to go with PASS_VERTEX_SHADER
+fn compile_pass(source: &str) -> Result<Program, String> {
+    // The defines have to come after #version
+    let (version, body) = match source.trim_start().strip_prefix("#version") {
+        Some(rest) => {
+            let (line, body) = rest.split_once('\n').unwrap_or((rest, ""));
+            (format!("#version{}\n", line), body)
+        }
+        None => (String::from("#version 330 core\n"), source),
+    };
+    let stage = |define: &str| CString::new(format!("{}#define {}\n{}", version, define, body)).map_err(|e| e.to_string());
+
+    let shaders = if body.contains("defined(VERTEX)") || body.contains("#ifdef VERTEX") {
+        [Shader::from_vert_source(&stage("VERTEX")?)?, Shader::from_frag_source(&stage("FRAGMENT")?)?]
+    } else {
+        let vertex = CString::new(PASS_VERTEX_SHADER).unwrap();
+        [Shader::from_vert_source(&vertex)?, Shader::from_frag_source(&stage("FRAGMENT")?)?]
+    };
+    Program::from_shaders(&shaders)
+}
+
+// Draws the picture as a texture on a quad in the viewport, through the shader chain
+pub struct GlRenderer {
+    window: Window,
+    texture: Texture,
+    vao: gl::types::GLuint,
+    shader_chain: ShaderChain,
+    options: ScaleOptions,
+    // The size and frame count of the picture in the texture, for capture() to draw it again
+    last: (u32, u32, u64),
+}
+
+impl GlRenderer {
+    // `shader` is a .glslp preset or .glsl shader, without one the picture is shown as it is,
+    // filtered the way `options` says
+    pub fn new(window: Window, shader: Option<&str>, options: ScaleOptions) -> Result<GlRenderer, String> {
+        use std::ffi::CString;
+        let vert_shader = Shader::from_vert_source(&CString::new(include_str!("triangle.vert")).unwrap()).unwrap();
+        let frag_shader = Shader::from_frag_source(&CString::new(include_str!("triangle.frag")).unwrap()).unwrap();
+
+        let shader_chain = match shader {
+            Some(path) => ShaderChain::load(path)?,
+            None => ShaderChain::from_program(Program::from_shaders(&[vert_shader, frag_shader])?, options.filter == Filter::Bilinear),
+        };
+
+        let vertices: Vec<f32> = vec![
+            -1.0, 1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, // uppe vänster?
+            1.0, 1.0, 0.0, 0.0, 1.0, 0.0, 1.0, 1.0, // uppe höger
+            1.0, -1.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0, // nere höger
+
+            -1.0, 1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, // uppe vänster?
+            1.0, -1.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0, // nere höger
+            -1.0, -1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, // nere vänster
+        ];
+
+        let mut vbo: gl::types::GLuint = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut vbo);
+
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                (vertices.len() * std::mem::size_of::<f32>()) as gl::types::GLsizeiptr,
+                vertices.as_ptr() as *const gl::types::GLvoid,
+                gl::STATIC_DRAW,
+            );
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+
+        let mut vao: gl::types::GLuint = 0;
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+
+            gl::BindVertexArray(vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+
+            gl::EnableVertexAttribArray(0); // this is "layout (location = 0)" in vertex shader
+            gl::VertexAttribPointer(
+                0, // index of the generic vertex attribute ("layout (location = 0)")
+                3, // the number of components per generic vertex attribute
+                gl::FLOAT, // data type
+                gl::FALSE, // normalized (int-to-float conversion)
+                (8 * std::mem::size_of::<f32>()) as gl::types::GLint, // stride (byte offset between consecutive attributes)
+                std::ptr::null(), // offset of the first component
+            );
+
+            gl::EnableVertexAttribArray(1); // this is "layout (location = 1)" in vertex shader
+            gl::VertexAttribPointer(
+                1, // index of the generic vertex attribute ("layout (location = 1)")
+                3, // the number of components per generic vertex attribute
+                gl::FLOAT, // data type
+                gl::FALSE, // normalized (int-to-float conversion)
+                (8 * std::mem::size_of::<f32>()) as gl::types::GLint, // stride (byte offset between consecutive attributes)
+                (3 * std::mem::size_of::<f32>()) as *const gl::types::GLvoid, // offset of the first component
+            );
+
+            gl::EnableVertexAttribArray(2); // this is "layout (location = 2)" in vertex shader
+            gl::VertexAttribPointer(
+                2, // index of the generic vertex attribute ("layout (location = 1)")
+                2, // the number of components per generic vertex attribute
+                gl::FLOAT, // data type
+                gl::FALSE, // normalized (int-to-float conversion)
+                (8 * std::mem::size_of::<f32>()) as gl::types::GLint, // stride (byte offset between consecutive attributes)
+                (6 * std::mem::size_of::<f32>()) as *const gl::types::GLvoid, // offset of the first component
+            );
+
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            gl::BindVertexArray(0);
+        }
+
+        let texture = Texture::from_pixels(256, 240, vec![0; 256 * 240 * 3])?;
+
+        unsafe {
+            gl::ClearColor(0.0, 0.0, 0.0, 1.0);
+        }
+
+        Ok(GlRenderer { window, texture, vao, shader_chain, options, last: (256, 240, 0) })
+    }
+}
+
+impl Renderer for GlRenderer {
+    fn present(&mut self, rgb: &[u8], width: u32, height: u32, frame_count: u64) -> Result<(), String> {
+        self.texture.set_pixels(width as i32, height as i32, rgb.to_vec());
+        self.texture.bind();
+        // Worked out every frame, so it follows the window when it is resized
+        let window = self.window.drawable_size();
+        let viewport = self.options.viewport(height, window);
+        self.shader_chain.render(self.texture.id(), (width, height), window, viewport, self.vao, frame_count)?;
+        self.window.swap();
+        self.last = (width, height, frame_count);
+        Ok(())
+    }
+
+    fn set_vsync(&mut self, vsync: bool) -> bool {
+        self.window.set_vsync(vsync)
+    }
+
+    fn toggle_fullscreen(&mut self) -> Result<(), String> {
+        self.window.toggle_fullscreen()
+    }
+
+    // The back buffer is undefined after a swap, so the picture is drawn into it again and read back
+    fn capture(&mut self) -> Result<(Vec<u8>, u32, u32), String> {
+        let (width, height, frame_count) = self.last;
+        let window = self.window.drawable_size();
+        let viewport = self.options.viewport(height, window);
+        self.shader_chain.render(self.texture.id(), (width, height), window, viewport, self.vao, frame_count)?;
+
+        // Only what fits in the window
+        let (width, height) = (viewport.width.min(window.0), viewport.height.min(window.1));
+        let mut pixels = vec![0u8; width as usize * height as usize * 3];
+        unsafe {
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            let bottom = window.1 as i32 - viewport.y as i32 - height as i32;
+            gl::ReadPixels(viewport.x as i32, bottom, width as i32, height as i32, gl::RGB, gl::UNSIGNED_BYTE, pixels.as_mut_ptr() as *mut gl::types::GLvoid);
+        }
+        // GL reads from the bottom row up
+        let pixels = pixels.chunks(width as usize * 3).rev().flatten().copied().collect();
+        Ok((pixels, width, height))
+    }
+}
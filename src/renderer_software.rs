@@ -0,0 +1,46 @@
+use rustnes_core::scaler::{self, ScaleOptions};
+use crate::renderer::Renderer;
+use crate::window;
+
+// Scales on the CPU and copies the result into the window's surface, without a GL context
+pub struct SoftwareRenderer {
+    window: sdl2::video::Window,
+    options: ScaleOptions,
+    // The last picture presented, for capture()
+    last: (Vec<u8>, u32, u32),
+}
+
+impl SoftwareRenderer {
+    pub fn new(window: sdl2::video::Window, options: ScaleOptions) -> SoftwareRenderer {
+        SoftwareRenderer { window, options, last: (vec![], 0, 0) }
+    }
+}
+
+impl Renderer for SoftwareRenderer {
+    fn present(&mut self, rgb: &[u8], width: u32, height: u32, _frame_count: u64) -> Result<(), String> {
+        // The window's size is its surface's, and a resized window gets a new viewport
+        let size = self.window.size();
+        let mut pixels = self.options.render(rgb, (width, height), size);
+        window::blit_to_surface(&self.window, &mut pixels, size)?;
+        self.last = (rgb.to_vec(), width, height);
+        Ok(())
+    }
+
+    fn set_vsync(&mut self, _vsync: bool) -> bool {
+        false
+    }
+
+    fn toggle_fullscreen(&mut self) -> Result<(), String> {
+        window::toggle_fullscreen(&mut self.window)
+    }
+
+    fn capture(&mut self) -> Result<(Vec<u8>, u32, u32), String> {
+        let (rgb, width, height) = &self.last;
+        if rgb.is_empty() {
+            return Err(String::from("Nothing has been shown yet"));
+        }
+        let viewport = self.options.viewport(*height, self.window.size());
+        let scaled = scaler::scale(rgb, (*width, *height), (viewport.width, viewport.height), self.options.filter);
+        Ok((scaled, viewport.width, viewport.height))
+    }
+}
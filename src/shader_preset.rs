@@ -0,0 +1,151 @@
+// Shader presets, the subset of RetroArch's .glslp format that simple CRT and scanline shaders use:
+//
+//   shaders = 2
+//   shader0 = scanlines.glsl       paths are relative to the preset
+//   filter_linear0 = false         how the pass samples its input
+//   scale_type0 = source           source, viewport or absolute, or scale_type_x0 and scale_type_y0
+//   scale0 = 3.0                   or scale_x0 and scale_y0
+//   shader1 = crt.glsl
+//
+// The last pass always draws to the window. Lines starting with # are comments.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScaleType {
+    // A multiple of the pass's input
+    Source,
+    // A multiple of the window
+    Viewport,
+    // In pixels
+    Absolute,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PassPreset {
+    pub shader: PathBuf,
+    pub filter_linear: bool,
+    pub scale_type: (ScaleType, ScaleType),
+    pub scale: (f32, f32),
+}
+
+impl PassPreset {
+    // A lone shader file, as a preset of one pass
+    pub fn single(shader: &Path) -> PassPreset {
+        PassPreset { shader: shader.to_path_buf(), filter_linear: false, scale_type: (ScaleType::Source, ScaleType::Source), scale: (1.0, 1.0) }
+    }
+
+    pub fn output_size(&self, source: (u32, u32), viewport: (u32, u32)) -> (u32, u32) {
+        let size = |scale_type: ScaleType, scale: f32, source: u32, viewport: u32| {
+            let size = match scale_type {
+                ScaleType::Source => source as f32 * scale,
+                ScaleType::Viewport => viewport as f32 * scale,
+                ScaleType::Absolute => scale,
+            };
+            (size.round() as u32).max(1)
+        };
+        (
+            size(self.scale_type.0, self.scale.0, source.0, viewport.0),
+            size(self.scale_type.1, self.scale.1, source.1, viewport.1),
+        )
+    }
+}
+
+// A .glslp preset, or a single .glsl shader
+pub fn load(path: &str) -> Result<Vec<PassPreset>, String> {
+    let path = Path::new(path);
+    if path.extension().is_some_and(|extension| extension != "glslp") {
+        return Ok(vec![PassPreset::single(path)]);
+    }
+
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse(&text, path.parent().unwrap_or(Path::new(""))).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+pub fn parse(text: &str, directory: &Path) -> Result<Vec<PassPreset>, String> {
+    let mut values = HashMap::new();
+    for line in text.lines().map(|line| line.trim()).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        let (key, value) = line.split_once('=').ok_or(format!("Expected key = value, got {}", line))?;
+        values.insert(key.trim().to_string(), value.trim().trim_matches('"').to_string());
+    }
+
+    let count: usize = values.get("shaders").ok_or("The preset does not say how many shaders it has")?
+        .parse().map_err(|_| String::from("shaders has to be a number"))?;
+
+    (0..count).map(|pass| {
+        let value = |name: &str| values.get(&format!("{}{}", name, pass));
+        let shader = value("shader").ok_or(format!("shader{} is missing", pass))?;
+
+        let filter_linear = match value("filter_linear").map(|value| value.as_str()) {
+            None | Some("false") | Some("0") => false,
+            Some("true") | Some("1") => true,
+            Some(other) => return Err(format!("filter_linear{} has to be true or false, not {}", pass, other)),
+        };
+
+        let scale_type = |name: &str| -> Result<Option<ScaleType>, String> {
+            match value(name).map(|value| value.as_str()) {
+                None => Ok(None),
+                Some("source") => Ok(Some(ScaleType::Source)),
+                Some("viewport") => Ok(Some(ScaleType::Viewport)),
+                Some("absolute") => Ok(Some(ScaleType::Absolute)),
+                Some(other) => Err(format!("{}{} has to be source, viewport or absolute, not {}", name, pass, other)),
+            }
+        };
+        let both = scale_type("scale_type")?.unwrap_or(ScaleType::Source);
+        let scale_type = (scale_type("scale_type_x")?.unwrap_or(both), scale_type("scale_type_y")?.unwrap_or(both));
+
+        let scale = |name: &str| -> Result<Option<f32>, String> {
+            value(name).map(|value| value.parse().map_err(|_| format!("{}{} has to be a number", name, pass))).transpose()
+        };
+        let both = scale("scale")?.unwrap_or(1.0);
+        let scale = (scale("scale_x")?.unwrap_or(both), scale("scale_y")?.unwrap_or(both));
+
+        Ok(PassPreset { shader: directory.join(shader), filter_linear, scale_type, scale })
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passes_take_their_settings_and_default_the_rest() {
+        let passes = parse(concat!(
+            "# Two passes\n",
+            "shaders = 2\n",
+            "shader0 = \"scanlines.glsl\"\n",
+            "filter_linear0 = true\n",
+            "scale_type0 = source\n",
+            "scale0 = 3.0\n",
+            "\n",
+            "shader1 = crt/crt.glsl\n",
+            "scale_type_x1 = viewport\n",
+            "scale_type_y1 = absolute\n",
+            "scale_x1 = 0.5\n",
+            "scale_y1 = 240\n",
+        ), Path::new("shaders")).unwrap();
+
+        assert_eq!(passes, vec![
+            PassPreset { shader: PathBuf::from("shaders/scanlines.glsl"), filter_linear: true, scale_type: (ScaleType::Source, ScaleType::Source), scale: (3.0, 3.0) },
+            PassPreset { shader: PathBuf::from("shaders/crt/crt.glsl"), filter_linear: false, scale_type: (ScaleType::Viewport, ScaleType::Absolute), scale: (0.5, 240.0) },
+        ]);
+        assert_eq!(passes[0].output_size((256, 240), (1024, 768)), (768, 720));
+        assert_eq!(passes[1].output_size((768, 720), (1024, 768)), (512, 240));
+        assert_eq!(PassPreset::single(Path::new("crt.glsl")), PassPreset { shader: PathBuf::from("crt.glsl"), filter_linear: false, scale_type: (ScaleType::Source, ScaleType::Source), scale: (1.0, 1.0) });
+    }
+
+    #[test]
+    fn broken_presets_are_refused() {
+        let error = |text: &str| parse(text, Path::new("")).err();
+
+        assert_eq!(error("shader0 = a.glsl\n"), Some(String::from("The preset does not say how many shaders it has")));
+        assert_eq!(error("shaders = two\n"), Some(String::from("shaders has to be a number")));
+        assert_eq!(error("shaders = 2\nshader0 = a.glsl\n"), Some(String::from("shader1 is missing")));
+        assert_eq!(error("shaders = 1\nshader0\n"), Some(String::from("Expected key = value, got shader0")));
+        assert_eq!(error("shaders = 1\nshader0 = a.glsl\nfilter_linear0 = yes\n"), Some(String::from("filter_linear0 has to be true or false, not yes")));
+        assert_eq!(error("shaders = 1\nshader0 = a.glsl\nscale_type_x0 = window\n"), Some(String::from("scale_type_x0 has to be source, viewport or absolute, not window")));
+        assert_eq!(error("shaders = 1\nshader0 = a.glsl\nscale0 = big\n"), Some(String::from("scale0 has to be a number")));
+    }
+}
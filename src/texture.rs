@@ -6,6 +6,10 @@ pub struct Texture {
 }
 
 impl Texture {
+    pub fn id(&self) -> gl::types::GLuint {
+        self.id
+    }
+
     pub fn bind(&self) {
         unsafe {
             gl::BindTexture(gl::TEXTURE_2D, self.id)
@@ -13,6 +17,7 @@ impl Texture {
     }
 
     pub fn from_pixels(width: i32, height: i32, pixels: Vec<u8>) -> Result<Texture, String> {
+        let pixels = bottom_up(width, pixels);
         unsafe {
             let mut texture: gl::types::GLuint = 0;
             gl::GenTextures(1, &mut texture);
@@ -50,8 +55,11 @@ impl Texture {
     }
 
     pub fn set_pixels(&self, width: i32, height: i32, pixels: Vec<u8>) {
+        let pixels = bottom_up(width, pixels);
         unsafe {
             gl::BindTexture(gl::TEXTURE_2D, self.id);
+            // Rows are not padded to 4 bytes, a 602 pixel wide NTSC filtered frame would come out skewed
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
 
             gl::TexImage2D(
                 gl::TEXTURE_2D,
@@ -70,4 +78,9 @@ impl Texture {
     }
 
 
-}
\ No newline at end of file
+}
+
+// GL textures start at the bottom row, and the quad and the framebuffers of the shader passes expect that
+fn bottom_up(width: i32, pixels: Vec<u8>) -> Vec<u8> {
+    pixels.chunks(width as usize * 3).rev().flatten().copied().collect()
+}
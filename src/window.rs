@@ -1,4 +1,6 @@
-use sdl2::video::GLContext;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::surface::{Surface, SurfaceRef};
+use sdl2::video::{FullscreenType, GLContext, SwapInterval};
 
 pub struct Window {
     window: sdl2::video::Window,
@@ -6,19 +8,10 @@ pub struct Window {
 }
 
 impl Window {
-    pub fn create(sdl: &sdl2::Sdl) -> Result<Window, String> {
+    // `size` is the window's size when it is not fullscreen
+    pub fn create(sdl: &sdl2::Sdl, title: &str, size: (u32, u32), fullscreen: bool) -> Result<Window, String> {
         let video = sdl.video().unwrap();
-
-        let sdl_window2 = video
-            .window("test", 800, 600)
-            .opengl()
-            .position_centered()
-            .build();
-
-        let sdl_window = match sdl_window2 {
-            Err(e) => Err(e.to_string()),
-            Ok(w) => Ok(w)
-        }?;
+        let sdl_window = build(&video, title, size, fullscreen, true)?;
 
         let gl_attr = video.gl_attr();
         gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
@@ -27,8 +20,9 @@ impl Window {
         let gl_context = sdl_window.gl_create_context()?;
         let _gl = gl::load_with(|s| video.gl_get_proc_address(s) as *const std::os::raw::c_void);
 
+        let (width, height) = sdl_window.drawable_size();
         unsafe {
-            gl::Viewport(0, 0, 512, 512);
+            gl::Viewport(0, 0, width as i32, height as i32);
             gl::ClearColor(1.0, 0.0, 1.0, 1.0);
         }
 
@@ -39,7 +33,67 @@ impl Window {
         Ok(result)
     }
 
+    // Whether swap() waits for the display's vertical blank. False when the driver will not do it.
+    pub fn set_vsync(&self, vsync: bool) -> bool {
+        let interval = if vsync { SwapInterval::VSync } else { SwapInterval::Immediate };
+        self.window.subsystem().gl_set_swap_interval(interval)
+    }
+
+    // In pixels, which is more than the window size on high DPI displays
+    pub fn drawable_size(&self) -> (u32, u32) {
+        self.window.drawable_size()
+    }
+
     pub fn swap(&self) {
         self.window.gl_swap_window();
     }
-}
\ No newline at end of file
+
+    pub fn toggle_fullscreen(&mut self) -> Result<(), String> {
+        toggle_fullscreen(&mut self.window)
+    }
+}
+
+// A window without a GL context, for drawing into its surface
+pub fn create_plain(sdl: &sdl2::Sdl, title: &str, size: (u32, u32), fullscreen: bool) -> Result<sdl2::video::Window, String> {
+    build(&sdl.video()?, title, size, fullscreen, false)
+}
+
+// Between a window and borderless fullscreen at the desktop's resolution, which needs no mode switch
+pub fn toggle_fullscreen(window: &mut sdl2::video::Window) -> Result<(), String> {
+    let fullscreen = match window.fullscreen_state() {
+        FullscreenType::Off => FullscreenType::Desktop,
+        _ => FullscreenType::Off,
+    };
+    window.set_fullscreen(fullscreen)
+}
+
+// Stretches an RGB picture over a window without a GL context
+pub fn blit_to_surface(window: &sdl2::video::Window, rgb: &mut [u8], size: (u32, u32)) -> Result<(), String> {
+    // Window::surface() borrows the event pump, which the frontend needs, so the surface is fetched directly
+    let raw = unsafe { sdl2::sys::SDL_GetWindowSurface(window.raw()) };
+    if raw.is_null() {
+        return Err(sdl2::get_error());
+    }
+    let window_surface = unsafe { SurfaceRef::from_ll_mut(raw) };
+
+    // SDL converts to whatever format the window has while copying
+    let picture = Surface::from_data(rgb, size.0, size.1, size.0 * 3, PixelFormatEnum::RGB24)?;
+    picture.blit_scaled(None, window_surface, None)?;
+
+    if unsafe { sdl2::sys::SDL_UpdateWindowSurface(window.raw()) } != 0 {
+        return Err(sdl2::get_error());
+    }
+    Ok(())
+}
+
+fn build(video: &sdl2::VideoSubsystem, title: &str, size: (u32, u32), fullscreen: bool, opengl: bool) -> Result<sdl2::video::Window, String> {
+    let mut builder = video.window(title, size.0, size.1);
+    builder.position_centered().resizable();
+    if opengl {
+        builder.opengl();
+    }
+    if fullscreen {
+        builder.fullscreen_desktop();
+    }
+    builder.build().map_err(|e| e.to_string())
+}